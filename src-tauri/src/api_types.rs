@@ -1,4 +1,5 @@
 // API型定義 - TypeScript自動生成対応
+use crate::config::pagination;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use ts_rs::TS;
@@ -23,6 +24,14 @@ pub struct FileRecord {
     pub booth_price: Option<i64>,
     pub booth_thumbnail_path: Option<String>,
     pub encoding_info: Option<String>,
+    pub archive_path: Option<String>,
+    pub booth_category: Option<String>,
+    #[serde(default)]
+    pub is_adult: bool,
+    pub opened_at: Option<String>,
+    #[serde(default)]
+    pub is_favorite: bool,
+    pub user_note: Option<String>,
     pub created_at: Option<String>,
     pub updated_at: Option<String>,
     pub metadata: Option<String>,
@@ -49,6 +58,42 @@ pub struct FileWithTags {
     pub tags: Vec<Tag>,
 }
 
+/// ショップごとのファイル数と合計サイズ（サイドバーのショップ一覧用）
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS, JsonSchema)]
+#[ts(export)]
+#[serde(rename_all = "camelCase")]
+pub struct ShopFileSummary {
+    pub shop_name: String,
+    pub file_count: u32,
+    /// サイズ不明のファイルしかない場合は None
+    pub total_size: Option<i64>,
+}
+
+/// ライブラリ全体の統計（ダッシュボード用、ゴミ箱内のファイルは含まない）
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS, JsonSchema)]
+#[ts(export)]
+#[serde(rename_all = "camelCase")]
+pub struct LibraryStats {
+    pub total_files: u32,
+    pub total_size: i64,
+    pub total_tags: u32,
+    /// ショップ名が登録されているショップの数
+    pub shop_count: u32,
+    pub files_without_booth_url: u32,
+    /// 使用回数の多い順
+    pub top_tags: Vec<TagUsage>,
+}
+
+/// タグとその使用回数
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS, JsonSchema)]
+#[ts(export)]
+#[serde(rename_all = "camelCase")]
+pub struct TagUsage {
+    pub name: String,
+    pub color: String,
+    pub usage_count: u32,
+}
+
 // =============================================================================
 // Pagination Types
 // =============================================================================
@@ -61,6 +106,18 @@ pub struct PaginationRequest {
     pub page_size: u32,
     pub sort_by: Option<String>,
     pub sort_order: Option<String>, // "asc" or "desc"
+    /// 複数列での並び替え（先頭のキーが優先）。指定時は sort_by / sort_order より優先する
+    #[serde(default)]
+    pub sort: Vec<SortKey>,
+}
+
+/// 並び替えのキー
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS, JsonSchema)]
+#[ts(export)]
+#[serde(rename_all = "camelCase")]
+pub struct SortKey {
+    pub column: String,
+    pub order: Option<String>, // "asc" or "desc"
 }
 
 #[derive(Debug, Serialize, Deserialize, TS, JsonSchema)]
@@ -76,6 +133,53 @@ pub struct PaginationResponse<T> {
     pub has_prev_page: bool,
 }
 
+impl PaginationRequest {
+    /// ページ番号は1以上、ページサイズは許容範囲内に丸めたリクエストを返す
+    pub fn clamped(&self) -> Self {
+        Self {
+            page: self.page.max(1),
+            page_size: self
+                .page_size
+                .clamp(pagination::MIN_PAGE_SIZE, pagination::MAX_PAGE_SIZE),
+            sort_by: self.sort_by.clone(),
+            sort_order: self.sort_order.clone(),
+            sort: self.sort.clone(),
+        }
+    }
+
+    /// 並び替えのキー（sort 未指定時は sort_by / sort_order の1列）
+    pub fn sort_keys(&self) -> Vec<SortKey> {
+        if !self.sort.is_empty() {
+            return self.sort.clone();
+        }
+        vec![SortKey {
+            column: self.sort_by.clone().unwrap_or_default(),
+            order: self.sort_order.clone(),
+        }]
+    }
+}
+
+impl<T> PaginationResponse<T> {
+    /// 1ページ分の結果と全件数からページ情報を組み立てる
+    pub fn new(items: Vec<T>, total_count: u32, request: &PaginationRequest) -> Self {
+        let total_pages = if request.page_size == 0 {
+            0
+        } else {
+            total_count.div_ceil(request.page_size)
+        };
+
+        Self {
+            items,
+            total_count,
+            page: request.page,
+            page_size: request.page_size,
+            total_pages,
+            has_next_page: request.page < total_pages,
+            has_prev_page: request.page > 1,
+        }
+    }
+}
+
 // =============================================================================
 // API Request/Response Types
 // =============================================================================
@@ -93,6 +197,8 @@ pub struct SearchFilesRequest {
 pub struct SearchFilesPaginatedRequest {
     pub query: String,
     pub pagination: PaginationRequest,
+    #[serde(default)]
+    pub hide_adult: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize, TS, JsonSchema)]
@@ -108,6 +214,8 @@ pub struct SearchByTagsRequest {
 pub struct SearchByTagsPaginatedRequest {
     pub tag_names: Vec<String>,
     pub pagination: PaginationRequest,
+    #[serde(default)]
+    pub hide_adult: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize, TS, JsonSchema)]
@@ -115,6 +223,9 @@ pub struct SearchByTagsPaginatedRequest {
 #[serde(rename_all = "camelCase")]
 pub struct GetFilesPaginatedRequest {
     pub pagination: PaginationRequest,
+    /// R-18 の商品を除外する（既定では表示する）
+    #[serde(default)]
+    pub hide_adult: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize, TS, JsonSchema)]
@@ -160,6 +271,9 @@ pub struct BoothProductInfo {
     pub tags: Vec<String>,
     pub thumbnail_url: Option<String>,
     pub product_id: Option<i64>,
+    pub category: Option<String>,
+    #[serde(default)]
+    pub is_adult: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize, TS, JsonSchema)]
@@ -224,6 +338,9 @@ pub struct ApiError {
     pub message: String,
     #[ts(skip)]
     pub details: Option<serde_json::Value>,
+    /// 再試行で解決する可能性があるか（フロントエンドの再試行UI用）
+    #[serde(default)]
+    pub retryable: bool,
 }
 
 // =============================================================================
@@ -237,6 +354,9 @@ pub fn generate_typescript_bindings() -> Result<(), Box<dyn std::error::Error>>
     FileRecord::export()?;
     Tag::export()?;
     FileWithTags::export()?;
+    ShopFileSummary::export()?;
+    LibraryStats::export()?;
+    TagUsage::export()?;
     PaginationRequest::export()?;
     // Note: PaginationResponse<T> is generic, so we'll export specific instances
     SearchFilesRequest::export()?;
@@ -1,10 +1,18 @@
-use crate::config::booth;
-use crate::{booth_client::BoothProductInfo, sanitize_folder_name, AppState};
-use std::path::PathBuf;
+use crate::api_types::ApiError;
+use crate::booth_client::{
+    is_booth_shop_url, normalize_booth_url, BoothClient, BoothProductInfo, ConnectivityStatus,
+};
+use crate::config::{booth, files};
+use crate::db_pool::DatabasePool;
+use crate::{app_data_dir, sanitize_folder_name, AppError, AppResult, AppState};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 // BOOTH URL検証コマンド（商品ページ専用）
 #[tauri::command]
-pub async fn validate_booth_url(url: String) -> Result<bool, String> {
+pub async fn validate_booth_url(url: String) -> Result<bool, ApiError> {
     match url::Url::parse(&url) {
         Ok(parsed_url) => {
             let host = parsed_url.host_str().unwrap_or("");
@@ -43,90 +51,461 @@ pub async fn validate_booth_url(url: String) -> Result<bool, String> {
     }
 }
 
+// BOOTHショップURL検証コマンド（ショップトップ・商品一覧ページ）
+#[tauri::command]
+pub async fn validate_booth_shop_url(url: String) -> Result<bool, ApiError> {
+    Ok(url::Url::parse(&url)
+        .map(|parsed_url| is_booth_shop_url(&parsed_url))
+        .unwrap_or(false))
+}
+
+// BOOTHショップの商品一覧取得コマンド
+#[tauri::command]
+pub async fn fetch_shop_items(
+    shop_url: String,
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<BoothProductInfo>, ApiError> {
+    let booth_client = state.booth_client.clone();
+
+    booth_client
+        .get_shop_items(&shop_url)
+        .await
+        .map_err(|e| format!("ショップの商品一覧の取得に失敗しました: {e}").into())
+}
+
 // BOOTH商品情報取得コマンド
 #[tauri::command]
 pub async fn fetch_booth_product_info(
     url: String,
     state: tauri::State<'_, AppState>,
-) -> Result<BoothProductInfo, String> {
+) -> Result<BoothProductInfo, ApiError> {
     let booth_client = state.booth_client.clone();
 
     match booth_client.get_product_info(&url).await {
         Ok(product_info) => {
             // 商品情報の必須フィールドをチェック
-            if product_info.shop_name.trim().is_empty() || product_info.product_name.trim().is_empty() {
-                return Err("商品情報が不完全です。正しい商品ページのURLを確認してください。".to_string());
+            if product_info.shop_name.trim().is_empty()
+                || product_info.product_name.trim().is_empty()
+            {
+                return Err(
+                    "商品情報が不完全です。正しい商品ページのURLを確認してください。"
+                        .to_string()
+                        .into(),
+                );
             }
 
             // 商品名が「ページが見つかりません」等のエラーメッセージでないかチェック
             let product_name_lower = product_info.product_name.to_lowercase();
-            if product_name_lower.contains("not found") || 
-               product_name_lower.contains("404") || 
-               product_name_lower.contains("見つかりません") ||
-               product_name_lower.contains("エラー") {
-                return Err("商品ページが見つかりません。URLを確認してください。".to_string());
+            if product_name_lower.contains("not found")
+                || product_name_lower.contains("404")
+                || product_name_lower.contains("見つかりません")
+                || product_name_lower.contains("エラー")
+            {
+                return Err("商品ページが見つかりません。URLを確認してください。"
+                    .to_string()
+                    .into());
             }
 
             Ok(product_info)
         }
-        Err(e) => Err(format!("BOOTH商品情報の取得に失敗しました: {e} (購入ページやカートページではなく、商品ページのURLを入力してください)")),
+        // URLの誤り・接続できない・BOOTH側のエラー・年齢確認などを区別したエラーで返す
+        Err(e) => Err(AppError::from_booth(e).into()),
     }
 }
 
-// BOOTHサムネイルダウンロードコマンド
+// BOOTHに接続できるかを確認するコマンド（「取得に失敗する」原因が手元の回線かBOOTH側かの切り分け用）
+#[tauri::command]
+pub async fn check_booth_connectivity(
+    state: tauri::State<'_, AppState>,
+) -> Result<ConnectivityStatus, ApiError> {
+    let url = format!("https://{}/", booth::MAIN_DOMAIN);
+    Ok(state.booth_client.check_connectivity(&url).await)
+}
+
+/// ダウンロードしたサムネイルの保存先
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DownloadedThumbnail {
+    pub thumbnail_path: String,
+    /// 一覧表示用の縮小版（作成しなかった・できなかった場合は None）
+    pub thumbnail_small_path: Option<String>,
+}
+
+// BOOTHサムネイルダウンロードコマンド（generate_small 指定時は一覧用の縮小版も作る）
+// shop_name / product_name は以前の「ショップ名_商品名」形式で保存した画像を引き継ぐためのもの
 #[tauri::command]
 pub async fn download_booth_thumbnail(
     thumbnail_url: String,
-    shop_name: String,
-    product_name: String,
+    generate_small: Option<bool>,
+    shop_name: Option<String>,
+    product_name: Option<String>,
     state: tauri::State<'_, AppState>,
-) -> Result<String, String> {
-    let booth_client = state.booth_client.clone();
+) -> Result<DownloadedThumbnail, ApiError> {
+    let legacy_names = shop_name.as_deref().zip(product_name.as_deref());
+    let file_path = cached_thumbnail(
+        &state.db,
+        &state.thumbnail_downloads,
+        &state.booth_client,
+        &thumbnail_url,
+        &app_data_dir().join(files::THUMBNAIL_DIR_NAME),
+        legacy_names,
+    )
+    .await?;
+    let small_path = if generate_small.unwrap_or(false) {
+        cached_small_thumbnail(&state.db, &thumbnail_url, &file_path).await?
+    } else {
+        None
+    };
 
-    // サムネイル保存ディレクトリ
-    let app_data_dir = dirs::data_dir()
-        .unwrap_or_else(|| dirs::home_dir().unwrap_or_else(|| PathBuf::from(".")))
-        .join("BOOTH_Organizer")
-        .join("thumbnails");
+    Ok(DownloadedThumbnail {
+        thumbnail_path: file_path.to_string_lossy().to_string(),
+        thumbnail_small_path: small_path.map(|path| path.to_string_lossy().to_string()),
+    })
+}
 
-    if !app_data_dir.exists() {
-        std::fs::create_dir_all(&app_data_dir)
-            .map_err(|e| format!("サムネイルディレクトリの作成に失敗: {e}"))?;
+// 縮小版のサムネイルを返す（未作成なら元画像から作って記録する）
+// 画像として読めない場合は警告を出して None（元画像はそのまま使える）
+async fn cached_small_thumbnail(
+    db: &DatabasePool,
+    thumbnail_url: &str,
+    file_path: &Path,
+) -> AppResult<Option<PathBuf>> {
+    let cached = db.get().await?.get_cached_small_thumbnail(thumbnail_url)?;
+    if let Some(cached) = cached.map(PathBuf::from).filter(|path| path.is_file()) {
+        return Ok(Some(cached));
     }
 
-    // ファイル名生成（安全な文字のみ）
-    let safe_shop = sanitize_folder_name(&shop_name);
-    let safe_product = sanitize_folder_name(&product_name);
+    let source = file_path.to_path_buf();
+    let resized = tokio::task::spawn_blocking(move || {
+        write_small_thumbnail(&source, files::SMALL_THUMBNAIL_SIZE)
+    })
+    .await
+    .map_err(|e| AppError::custom(format!("サムネイルの縮小に失敗: {e}")))?;
 
-    // 拡張子を取得（JPGをデフォルト）
-    let extension = if thumbnail_url.contains(".png") {
-        "png"
-    } else if thumbnail_url.contains(".gif") {
-        "gif"
-    } else if thumbnail_url.contains(".webp") {
-        "webp"
+    match resized {
+        Ok(small_path) => {
+            db.get()
+                .await?
+                .set_cached_small_thumbnail(thumbnail_url, &small_path.to_string_lossy())?;
+            Ok(Some(small_path))
+        }
+        Err(e) => {
+            log::warn!("Failed to downscale {}: {e}", file_path.display());
+            Ok(None)
+        }
+    }
+}
+
+// 画像を長辺が max_size 以下になるよう縦横比を保って縮小し、元画像の隣に「名前_small」で保存する
+// 透過のある画像はPNG、それ以外はJPEGで書き出す（元から小さい画像は拡大しない）
+fn write_small_thumbnail(path: &Path, max_size: u32) -> image::ImageResult<PathBuf> {
+    let image = image::ImageReader::open(path)?
+        .with_guessed_format()?
+        .decode()?;
+    let small = if image.width() > max_size || image.height() > max_size {
+        image.thumbnail(max_size, max_size)
+    } else {
+        image
+    };
+
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let (small, extension, format) = if small.color().has_alpha() {
+        (small, "png", image::ImageFormat::Png)
     } else {
-        "jpg"
+        (
+            image::DynamicImage::ImageRgb8(small.to_rgb8()),
+            "jpg",
+            image::ImageFormat::Jpeg,
+        )
     };
+    let small_path = path.with_file_name(format!("{stem}_small.{extension}"));
+    small.save_with_format(&small_path, format)?;
+    Ok(small_path)
+}
+
+// URLに対応するサムネイルの保存先を返す（未取得ならダウンロードして対応を記録する）
+// 未登録のURLでも、以前の「ショップ名_商品名」形式の画像があればURLのハッシュ名へ移して引き継ぐ
+async fn cached_thumbnail(
+    db: &DatabasePool,
+    downloads: &ThumbnailDownloads,
+    booth_client: &BoothClient,
+    thumbnail_url: &str,
+    cache_dir: &Path,
+    legacy_names: Option<(&str, &str)>,
+) -> AppResult<PathBuf> {
+    let cached = db.get().await?.get_cached_thumbnail(thumbnail_url)?;
+    if let Some(cached) = cached.map(PathBuf::from).filter(|path| path.is_file()) {
+        return Ok(cached);
+    }
 
-    let filename = format!("{safe_shop}_{safe_product}.{extension}");
-    let file_path = app_data_dir.join(&filename);
+    let cache_base = thumbnail_cache_base(cache_dir, thumbnail_url);
+    let legacy = legacy_names.and_then(|(shop_name, product_name)| {
+        migrate_legacy_thumbnail(cache_dir, shop_name, product_name, &cache_base)
+    });
+    if let Some(file_path) = legacy {
+        db.get()
+            .await?
+            .set_cached_thumbnail(thumbnail_url, &file_path.to_string_lossy())?;
+        return Ok(file_path);
+    }
+
+    let file_path = downloads
+        .fetch(booth_client, thumbnail_url, &cache_base)
+        .await?;
+    db.get()
+        .await?
+        .set_cached_thumbnail(thumbnail_url, &file_path.to_string_lossy())?;
+    Ok(file_path)
+}
+
+// サムネイルの保存先（URLのSHA-256をファイル名にし、同名の商品でも衝突しないようにする）
+// 拡張子はダウンロードした画像の形式から決めるため、ここでは付けない
+fn thumbnail_cache_base(cache_dir: &Path, thumbnail_url: &str) -> PathBuf {
+    use sha2::{Digest, Sha256};
+
+    cache_dir.join(format!("{:x}", Sha256::digest(thumbnail_url.as_bytes())))
+}
+
+// 以前の「ショップ名_商品名.拡張子」形式の画像があれば、URLのハッシュ名（拡張子はそのまま）へ移す
+// 移せなかった場合は元の場所のまま使う（無ければ None）
+fn migrate_legacy_thumbnail(
+    cache_dir: &Path,
+    shop_name: &str,
+    product_name: &str,
+    cache_base: &Path,
+) -> Option<PathBuf> {
+    let legacy_base = cache_dir.join(format!(
+        "{}_{}",
+        sanitize_folder_name(shop_name),
+        sanitize_folder_name(product_name)
+    ));
+    THUMBNAIL_EXTENSIONS.into_iter().find_map(|extension| {
+        let legacy_path = with_image_extension(&legacy_base, extension);
+        if !legacy_path.is_file() {
+            return None;
+        }
+        let file_path = with_image_extension(cache_base, extension);
+        match std::fs::rename(&legacy_path, &file_path) {
+            Ok(()) => Some(file_path),
+            Err(e) => {
+                log::warn!("Failed to migrate {}: {e}", legacy_path.display());
+                Some(legacy_path)
+            }
+        }
+    })
+}
+
+/// サムネイルとして保存する画像の拡張子
+const THUMBNAIL_EXTENSIONS: [&str; 5] = ["jpg", "png", "gif", "webp", "avif"];
+
+// 保存先の名前に拡張子を付けたパス（商品名に "." が含まれていても置き換えない）
+fn with_image_extension(cache_base: &Path, extension: &str) -> PathBuf {
+    let file_name = cache_base
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    cache_base.with_file_name(format!("{file_name}.{extension}"))
+}
+
+// 画像の先頭バイトから形式を判定し、拡張子を返す（判定できなければ None）
+fn sniff_image_extension(bytes: &[u8]) -> Option<&'static str> {
+    match bytes {
+        [0xFF, 0xD8, 0xFF, ..] => Some("jpg"),
+        [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, ..] => Some("png"),
+        [b'G', b'I', b'F', b'8', b'7' | b'9', b'a', ..] => Some("gif"),
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Some("webp"),
+        [_, _, _, _, b'f', b't', b'y', b'p', b'a', b'v', b'i', b'f' | b's', ..] => Some("avif"),
+        _ => None,
+    }
+}
+
+// URLに含まれる文字列から拡張子を推測する（JPGをデフォルト）
+fn url_image_extension(thumbnail_url: &str) -> &'static str {
+    let thumbnail_url = thumbnail_url.to_lowercase();
+    THUMBNAIL_EXTENSIONS
+        .into_iter()
+        .find(|extension| thumbnail_url.contains(&format!(".{extension}")))
+        .unwrap_or("jpg")
+}
+
+// 画像の内容で形式を判定し、判定できない場合のみURLから推測する
+fn image_extension(bytes: &[u8], thumbnail_url: &str) -> &'static str {
+    sniff_image_extension(bytes).unwrap_or_else(|| url_image_extension(thumbnail_url))
+}
+
+/// 実行中のサムネイルのダウンロード（保存先ごと）
+///
+/// 同じ保存先へのダウンロードが同時に要求された場合は、先に始まった1件の完了を待って
+/// その結果のファイルを使う。
+#[derive(Default)]
+pub struct ThumbnailDownloads {
+    in_flight: std::sync::Mutex<HashMap<PathBuf, Arc<tokio::sync::Mutex<()>>>>,
+}
+
+impl ThumbnailDownloads {
+    /// 保存先にまだ無ければサムネイルをダウンロードして保存し、保存したファイルのパスを返す
+    ///
+    /// `cache_base` は拡張子を除いた保存先。拡張子は画像の形式から決める。
+    pub async fn fetch(
+        &self,
+        booth_client: &BoothClient,
+        thumbnail_url: &str,
+        cache_base: &Path,
+    ) -> AppResult<PathBuf> {
+        let slot = self
+            .in_flight
+            .lock()
+            .map_err(|e| AppError::custom(format!("Thumbnail download lock error: {e}")))?
+            .entry(cache_base.to_path_buf())
+            .or_default()
+            .clone();
+
+        let result = {
+            let _guard = slot.lock().await;
+            fetch_thumbnail(booth_client, thumbnail_url, cache_base).await
+        };
+
+        // 待っている取得が他になければ保存先のエントリを片付ける
+        if let Ok(mut in_flight) = self.in_flight.lock() {
+            if Arc::strong_count(&slot) == 2 {
+                in_flight.remove(cache_base);
+            }
+        }
+        result
+    }
+}
 
+// 保存先にまだ無ければサムネイルをダウンロードして保存する
+// 書き込み途中のファイルが見えないよう、一時ファイルに書いてから置き換える
+async fn fetch_thumbnail(
+    booth_client: &BoothClient,
+    thumbnail_url: &str,
+    cache_base: &Path,
+) -> AppResult<PathBuf> {
     // 既に存在する場合はスキップ
-    if file_path.exists() {
-        return Ok(file_path.to_string_lossy().to_string());
+    let cached = THUMBNAIL_EXTENSIONS
+        .into_iter()
+        .map(|extension| with_image_extension(cache_base, extension))
+        .find(|path| path.exists());
+    if let Some(cached) = cached {
+        return Ok(cached);
+    }
+
+    if let Some(dir) = cache_base.parent() {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| AppError::custom(format!("サムネイルディレクトリの作成に失敗: {e}")))?;
     }
 
     // サムネイルダウンロード
-    match booth_client.download_thumbnail(&thumbnail_url).await {
-        Ok(image_data) => {
-            std::fs::write(&file_path, image_data)
-                .map_err(|e| format!("サムネイルの保存に失敗: {e}"))?;
+    let image_data = booth_client
+        .download_thumbnail(thumbnail_url)
+        .await
+        .map_err(|e| AppError::custom(format!("サムネイルのダウンロードに失敗: {e}")))?;
+    let file_path = with_image_extension(cache_base, image_extension(&image_data, thumbnail_url));
+    let file_name = file_path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let partial_path = file_path.with_file_name(format!(".{file_name}.part"));
+    std::fs::write(&partial_path, image_data)
+        .and_then(|()| std::fs::rename(&partial_path, &file_path))
+        .map_err(|e| {
+            let _ = std::fs::remove_file(&partial_path);
+            AppError::custom(format!("サムネイルの保存に失敗: {e}"))
+        })?;
+    Ok(file_path)
+}
+
+// ファイルのサムネイルを data: URL で返すコマンド（WebViewから直接読めない場所にあっても表示できる）
+// 保存済みの画像が無く、BOOTHの画像URLが登録されている場合はダウンロードしてから返す
+#[tauri::command]
+pub async fn get_thumbnail_data_url_db(
+    state: tauri::State<'_, AppState>,
+    file_id: i64,
+) -> Result<String, ApiError> {
+    let file = {
+        let db = state
+            .db
+            .get()
+            .await
+            .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
+        db.get_file_by_id(file_id)
+            .map_err(|e| AppError::file_retrieval(format!("Failed to get file: {e}")))?
+            .ok_or_else(|| AppError::validation("file_id", format!("File {file_id} not found")))?
+    };
+    let thumbnail = file
+        .thumbnail_url
+        .filter(|thumbnail| !thumbnail.trim().is_empty())
+        .ok_or_else(|| {
+            AppError::validation("thumbnail", format!("File {file_id} has no thumbnail"))
+        })?;
 
-            Ok(file_path.to_string_lossy().to_string())
+    let local_path = Path::new(thumbnail.strip_prefix("file://").unwrap_or(&thumbnail));
+    if local_path.is_file() {
+        return Ok(thumbnail_data_url(local_path)?);
+    }
+    if !thumbnail.starts_with("http://") && !thumbnail.starts_with("https://") {
+        return Err(
+            AppError::validation("thumbnail", format!("Thumbnail not found: {thumbnail}")).into(),
+        );
+    }
+
+    let cache_path = cached_thumbnail(
+        &state.db,
+        &state.thumbnail_downloads,
+        &state.booth_client,
+        &thumbnail,
+        &app_data_dir().join(files::THUMBNAIL_DIR_NAME),
+        Some((
+            file.author_name.as_deref().unwrap_or("Unknown_Shop"),
+            file.product_name.as_deref().unwrap_or(&file.file_name),
+        )),
+    )
+    .await?;
+    Ok(thumbnail_data_url(&cache_path)?)
+}
+
+// 画像ファイルを読み込み、拡張子から決めたMIMEタイプの data: URL にする
+// 上限を超える画像・対応していない拡張子は Validation エラー
+fn thumbnail_data_url(path: &Path) -> AppResult<String> {
+    use base64::Engine;
+
+    let extension = path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let mime = match extension.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "avif" => "image/avif",
+        _ => {
+            return Err(AppError::validation(
+                "thumbnail",
+                format!("Unsupported image type: {}", path.display()),
+            ))
         }
-        Err(e) => Err(format!("サムネイルのダウンロードに失敗: {e}")),
+    };
+
+    let size = std::fs::metadata(path)?.len();
+    if size > files::MAX_THUMBNAIL_BYTES {
+        return Err(AppError::validation(
+            "thumbnail",
+            format!(
+                "Thumbnail is too large ({size} bytes, max {} bytes)",
+                files::MAX_THUMBNAIL_BYTES
+            ),
+        ));
     }
+
+    let data = std::fs::read(path)?;
+    Ok(format!(
+        "data:{mime};base64,{}",
+        base64::engine::general_purpose::STANDARD.encode(data)
+    ))
 }
 
 #[tauri::command]
@@ -134,13 +513,14 @@ pub async fn update_file_booth_url_db(
     state: tauri::State<'_, AppState>,
     file_id: i64,
     booth_url: Option<String>,
-) -> Result<(), String> {
+) -> Result<(), ApiError> {
     let db = state
         .db
-        .lock()
+        .get()
+        .await
         .map_err(|e| format!("Database lock error: {e}"))?;
 
-    // URLをproduct_urlフィールドとして更新
+    // 正規化したURLをproduct_urlフィールドとして更新
     use crate::database::FileUpdateFields;
 
     let update_fields = FileUpdateFields {
@@ -150,9 +530,280 @@ pub async fn update_file_booth_url_db(
         price: None,
         description: None,
         thumbnail_url: None,
-        product_url: booth_url,
+        product_url: booth_url.as_deref().map(normalize_booth_url),
     };
 
     db.update_file(file_id, update_fields)
-        .map_err(|e| format!("Failed to update file BOOTH URL: {e}"))
+        .map_err(|e| format!("Failed to update file BOOTH URL: {e}").into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 1x1 の透明PNG
+    const TINY_PNG: &[u8] = &[
+        0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A, 0x00, 0x00, 0x00, 0x0D, 0x49, 0x48, 0x44,
+        0x52, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x08, 0x06, 0x00, 0x00, 0x00, 0x1F,
+        0x15, 0xC4, 0x89, 0x00, 0x00, 0x00, 0x0D, 0x49, 0x44, 0x41, 0x54, 0x78, 0x9C, 0x63, 0x00,
+        0x01, 0x00, 0x00, 0x05, 0x00, 0x01, 0x0D, 0x0A, 0x2D, 0xB4, 0x00, 0x00, 0x00, 0x00, 0x49,
+        0x45, 0x4E, 0x44, 0xAE, 0x42, 0x60, 0x82,
+    ];
+
+    // 接続を数えながら、少し遅れて画像を返すローカルサーバー
+    async fn serve_thumbnail(body: &'static [u8]) -> (String, Arc<std::sync::atomic::AtomicUsize>) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let connections = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let seen = connections.clone();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                seen.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                tokio::spawn(async move {
+                    let mut buf = [0u8; 4096];
+                    let _ = socket.read(&mut buf).await;
+                    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+                    let header = format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: image/png\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                        body.len()
+                    );
+                    let _ = socket.write_all(header.as_bytes()).await;
+                    let _ = socket.write_all(body).await;
+                });
+            }
+        });
+
+        // BOOTHのCDNと同様、拡張子の無いURLでも形式を判定できること
+        (format!("http://{addr}/thumb?size=300"), connections)
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_thumbnail_downloads_share_one_fetch() {
+        let (url, connections) = serve_thumbnail(TINY_PNG).await;
+        let dir = tempfile::tempdir().unwrap();
+        let base = dir.path().join("thumbnails").join("Shop_Item");
+        let client = BoothClient::new();
+        let downloads = ThumbnailDownloads::default();
+
+        let (first, second) = tokio::join!(
+            downloads.fetch(&client, &url, &base),
+            downloads.fetch(&client, &url, &base)
+        );
+        let path = first.unwrap();
+        assert_eq!(second.unwrap(), path);
+        assert_eq!(path, dir.path().join("thumbnails").join("Shop_Item.png"));
+
+        assert_eq!(connections.load(std::sync::atomic::Ordering::SeqCst), 1);
+        assert_eq!(std::fs::read(&path).unwrap(), TINY_PNG);
+        // 一時ファイルは残らず、実行中のエントリも片付いている
+        let entries: Vec<_> = std::fs::read_dir(path.parent().unwrap())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(entries, vec![std::ffi::OsString::from("Shop_Item.png")]);
+        assert!(downloads.in_flight.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_thumbnails_are_cached_per_url() {
+        let (url, connections) = serve_thumbnail(TINY_PNG).await;
+        let dir = tempfile::tempdir().unwrap();
+        let db = DatabasePool::new(":memory:").unwrap();
+        let client = BoothClient::new();
+        let downloads = ThumbnailDownloads::default();
+        let fetch = |thumbnail_url: String| {
+            let (db, client, downloads) = (&db, &client, &downloads);
+            let cache_dir = dir.path().to_path_buf();
+            async move {
+                cached_thumbnail(db, downloads, client, &thumbnail_url, &cache_dir, None)
+                    .await
+                    .unwrap()
+            }
+        };
+
+        // 同じショップ名・商品名の別商品でも、URLが違えば別のファイルになる
+        let first = fetch(format!("{url}&item=1")).await;
+        let second = fetch(format!("{url}&item=2")).await;
+        assert_ne!(first, second);
+        assert_eq!(std::fs::read(&first).unwrap(), TINY_PNG);
+        assert_eq!(std::fs::read(&second).unwrap(), TINY_PNG);
+
+        // 取得済みのURLは記録済みのファイルを使い、再ダウンロードしない
+        assert_eq!(fetch(format!("{url}&item=1")).await, first);
+        assert_eq!(connections.load(std::sync::atomic::Ordering::SeqCst), 2);
+        assert_eq!(
+            db.get()
+                .await
+                .unwrap()
+                .get_cached_thumbnail(&format!("{url}&item=2"))
+                .unwrap(),
+            Some(second.to_string_lossy().to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_legacy_thumbnails_are_migrated_without_downloading() {
+        let (url, connections) = serve_thumbnail(TINY_PNG).await;
+        let dir = tempfile::tempdir().unwrap();
+        let db = DatabasePool::new(":memory:").unwrap();
+        let client = BoothClient::new();
+        let downloads = ThumbnailDownloads::default();
+        let legacy_path = dir.path().join("Shop_Item.gif");
+        std::fs::write(&legacy_path, b"legacy").unwrap();
+
+        // 以前の名前で保存した画像をURLのハッシュ名へ移して記録する（拡張子はそのまま）
+        let migrated = cached_thumbnail(
+            &db,
+            &downloads,
+            &client,
+            &url,
+            dir.path(),
+            Some(("Shop", "Item")),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            migrated,
+            with_image_extension(&thumbnail_cache_base(dir.path(), &url), "gif")
+        );
+        assert_eq!(std::fs::read(&migrated).unwrap(), b"legacy");
+        assert!(!legacy_path.exists());
+        assert_eq!(
+            db.get().await.unwrap().get_cached_thumbnail(&url).unwrap(),
+            Some(migrated.to_string_lossy().to_string())
+        );
+
+        // 同じ名前の別商品は移した画像を取り合わず、自分のURLからダウンロードする
+        let other = cached_thumbnail(
+            &db,
+            &downloads,
+            &client,
+            &format!("{url}&item=2"),
+            dir.path(),
+            Some(("Shop", "Item")),
+        )
+        .await
+        .unwrap();
+        assert_ne!(other, migrated);
+        assert_eq!(std::fs::read(&other).unwrap(), TINY_PNG);
+        assert_eq!(connections.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_small_thumbnail_keeps_aspect_ratio() {
+        let dir = tempfile::tempdir().unwrap();
+        let wide = image::RgbaImage::from_pixel(1024, 256, image::Rgba([200, 100, 50, 255]));
+        let formats = [
+            ("wide.png", image::ImageFormat::Png),
+            ("wide.jpg", image::ImageFormat::Jpeg),
+            ("wide.gif", image::ImageFormat::Gif),
+            ("wide.webp", image::ImageFormat::WebP),
+        ];
+
+        for (name, format) in formats {
+            let path = dir.path().join(name);
+            let source = if format == image::ImageFormat::Jpeg {
+                image::DynamicImage::ImageRgb8(
+                    image::DynamicImage::ImageRgba8(wide.clone()).to_rgb8(),
+                )
+            } else {
+                image::DynamicImage::ImageRgba8(wide.clone())
+            };
+            source.save_with_format(&path, format).unwrap();
+
+            let small_path = write_small_thumbnail(&path, 256).unwrap();
+            assert_ne!(small_path, path);
+            let small = image::open(&small_path).unwrap();
+            assert_eq!((small.width(), small.height()), (256, 64), "{name}");
+        }
+
+        // 元から小さい画像は拡大しない
+        let tiny = dir.path().join("tiny.png");
+        image::RgbaImage::new(40, 10).save(&tiny).unwrap();
+        let small = image::open(write_small_thumbnail(&tiny, 256).unwrap()).unwrap();
+        assert_eq!((small.width(), small.height()), (40, 10));
+
+        // 画像でなければエラー
+        let text = dir.path().join("thumb.png");
+        std::fs::write(&text, b"not an image").unwrap();
+        assert!(write_small_thumbnail(&text, 256).is_err());
+    }
+
+    #[test]
+    fn test_image_extension_from_magic_numbers() {
+        let cases: [(&[u8], &str); 6] = [
+            (&[0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x10], "jpg"),
+            (TINY_PNG, "png"),
+            (b"GIF89a\x01\x00", "gif"),
+            (b"GIF87a\x01\x00", "gif"),
+            (b"RIFF\x24\x00\x00\x00WEBPVP8 ", "webp"),
+            (b"\x00\x00\x00\x1cftypavif\x00\x00\x00\x00", "avif"),
+        ];
+        for (bytes, expected) in cases {
+            // URLの拡張子より内容を優先する
+            assert_eq!(
+                image_extension(bytes, "https://example.com/a.gif?x"),
+                expected
+            );
+        }
+        assert_eq!(
+            sniff_image_extension(b"\x00\x00\x00\x1cftypavis"),
+            Some("avif")
+        );
+
+        // 判定できない場合はURLから推測し、それも無ければJPG
+        assert_eq!(
+            image_extension(b"????", "https://example.com/a.WEBP"),
+            "webp"
+        );
+        assert_eq!(
+            image_extension(b"RIFF", "https://example.com/a.png?v=1"),
+            "png"
+        );
+        assert_eq!(image_extension(&[], "https://example.com/thumb"), "jpg");
+    }
+
+    #[test]
+    fn test_with_image_extension_keeps_dots_in_name() {
+        assert_eq!(
+            with_image_extension(Path::new("/cache/Shop_Item v1.2"), "png"),
+            Path::new("/cache/Shop_Item v1.2.png")
+        );
+    }
+
+    #[test]
+    fn test_thumbnail_data_url_uses_mime_from_extension() {
+        let dir = tempfile::tempdir().unwrap();
+        let png = dir.path().join("thumb.png");
+        std::fs::write(&png, TINY_PNG).unwrap();
+        let jpeg = dir.path().join("thumb.JPEG");
+        std::fs::write(&jpeg, b"jpeg").unwrap();
+
+        let url = thumbnail_data_url(&png).unwrap();
+        assert!(
+            url.starts_with("data:image/png;base64,iVBORw0KGgo"),
+            "{url}"
+        );
+        assert_eq!(
+            thumbnail_data_url(&jpeg).unwrap(),
+            "data:image/jpeg;base64,anBlZw=="
+        );
+    }
+
+    #[test]
+    fn test_thumbnail_data_url_rejects_oversized_and_unknown_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let large = dir.path().join("large.png");
+        let file = std::fs::File::create(&large).unwrap();
+        file.set_len(files::MAX_THUMBNAIL_BYTES + 1).unwrap();
+        let text = dir.path().join("thumb.txt");
+        std::fs::write(&text, b"not an image").unwrap();
+
+        for path in [large, text] {
+            let err = thumbnail_data_url(&path).unwrap_err();
+            assert!(matches!(err, AppError::Validation { .. }), "{err:?}");
+        }
+    }
 }
@@ -0,0 +1,1219 @@
+use crate::api_types::ApiError;
+use crate::database::{Database, FileRecord, FileWithTags, Tag};
+use crate::file_commands::{copy_recursively, remove_path};
+use crate::tag_validator::normalize_tag_name;
+use crate::{AppError, AppResult, AppState};
+use log::{error, warn};
+use serde::ser::{Error as _, SerializeSeq};
+use serde::{Deserialize, Serialize, Serializer};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// カタログのエクスポート形式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CatalogFormat {
+    Json,
+    Csv,
+}
+
+impl CatalogFormat {
+    pub fn parse(format: &str) -> AppResult<Self> {
+        match format.trim().to_lowercase().as_str() {
+            "json" => Ok(Self::Json),
+            "csv" => Ok(Self::Csv),
+            other => Err(AppError::validation(
+                "format",
+                format!("Unsupported catalog format: {other}"),
+            )),
+        }
+    }
+}
+
+/// インポート時に既存ファイル（同じ file_path）とどう統合するか
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergeStrategy {
+    /// 既存ファイルはそのまま残し、インポート側を無視する
+    Skip,
+    /// 既存ファイルのメタデータとタグをインポート側で置き換える
+    Overwrite,
+    /// 既存ファイルを「名前 (2)」形式の別名でコピーし、新しい行として追加する
+    /// （ディスク上に無いファイルはコピーできないためスキップする）
+    Duplicate,
+}
+
+impl MergeStrategy {
+    pub fn parse(strategy: &str) -> AppResult<Self> {
+        match strategy.trim().to_lowercase().as_str() {
+            "skip" => Ok(Self::Skip),
+            "overwrite" => Ok(Self::Overwrite),
+            "duplicate" => Ok(Self::Duplicate),
+            other => Err(AppError::validation(
+                "merge_strategy",
+                format!("Unsupported merge strategy: {other}"),
+            )),
+        }
+    }
+}
+
+/// インポート結果の件数
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct ImportSummary {
+    pub inserted: usize,
+    pub updated: usize,
+    pub skipped: usize,
+}
+
+/// CSVからのタグ一括付与の結果
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct CsvTagReport {
+    /// 新たに作成したファイルとタグの関連付けの数
+    pub associations: usize,
+    /// 一致するファイルが見つからなかった行のパス（またはID）
+    pub unmatched: Vec<String>,
+}
+
+/// ライブラリバックアップの形式バージョン
+pub const LIBRARY_FORMAT_VERSION: u32 = 1;
+
+/// ライブラリ全体のバックアップ（ファイル・タグ・関連付け）
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LibraryExport {
+    pub version: u32,
+    pub exported_at: String,
+    pub files: Vec<FileRecord>,
+    pub tags: Vec<Tag>,
+    pub file_tags: Vec<FileTagLink>,
+}
+
+/// バックアップ内のファイルとタグの関連付け（エクスポート元のID）
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct FileTagLink {
+    pub file_id: i64,
+    pub tag_id: i64,
+}
+
+/// CSVのタグ列で使用する区切り文字
+pub const CSV_TAG_SEPARATOR: &str = ";";
+
+/// Excel が UTF-8 と認識するための BOM
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+const FILE_LIST_CSV_HEADER: [&str; 7] = [
+    "file_name",
+    "shop_name",
+    "product_name",
+    "price",
+    "booth_url",
+    "tags",
+    "created_at",
+];
+
+const CATALOG_CSV_HEADER: [&str; 15] = [
+    "id",
+    "file_path",
+    "file_name",
+    "file_size",
+    "modified_time",
+    "created_at",
+    "updated_at",
+    "product_id",
+    "product_name",
+    "author_name",
+    "price",
+    "description",
+    "thumbnail_url",
+    "product_url",
+    "tags",
+];
+
+#[tauri::command]
+pub async fn export_catalog_db(
+    state: tauri::State<'_, AppState>,
+    format: String,
+    output_path: String,
+) -> Result<usize, ApiError> {
+    let format = CatalogFormat::parse(&format)?;
+
+    let db = state
+        .db
+        .get()
+        .await
+        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
+
+    export_catalog(&db, format, Path::new(&output_path))
+        .map_err(|e| AppError::custom(format!("Failed to export catalog: {e}")).into())
+}
+
+#[tauri::command]
+pub async fn export_csv(
+    state: tauri::State<'_, AppState>,
+    path: String,
+) -> Result<usize, ApiError> {
+    let db = state
+        .db
+        .get()
+        .await
+        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
+
+    export_file_list_csv(&db, Path::new(&path))
+        .map_err(|e| AppError::custom(format!("Failed to export CSV: {e}")).into())
+}
+
+#[tauri::command]
+pub async fn import_catalog_db(
+    state: tauri::State<'_, AppState>,
+    input_path: String,
+    merge_strategy: String,
+) -> Result<ImportSummary, ApiError> {
+    let strategy = MergeStrategy::parse(&merge_strategy)?;
+
+    let db = state
+        .db
+        .get()
+        .await
+        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
+
+    import_catalog(&db, Path::new(&input_path), strategy)
+        .map_err(|e| AppError::custom(format!("Failed to import catalog: {e}")).into())
+}
+
+#[tauri::command]
+pub async fn apply_tags_from_csv_db(
+    state: tauri::State<'_, AppState>,
+    csv_path: String,
+) -> Result<CsvTagReport, ApiError> {
+    let db = state
+        .db
+        .get()
+        .await
+        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
+
+    apply_tags_from_csv(&db, Path::new(&csv_path))
+        .map_err(|e| AppError::custom(format!("Failed to apply tags from CSV: {e}")).into())
+}
+
+#[tauri::command]
+pub async fn export_library_db(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<Option<String>, ApiError> {
+    use tauri_plugin_dialog::DialogExt;
+
+    // ダイアログの結果はランタイムのスレッドを塞がずに待つ
+    let (tx, rx) = tokio::sync::oneshot::channel();
+
+    app.dialog()
+        .file()
+        .add_filter("JSON", &["json"])
+        .set_file_name("booth_organizer_library.json")
+        .set_title("ライブラリのバックアップ先を選択")
+        .save_file(move |file_path| {
+            if tx.send(file_path).is_err() {
+                error!("Failed to send file path: receiver dropped");
+            }
+        });
+
+    let file_path = rx
+        .await
+        .map_err(|e| AppError::custom(format!("ファイル選択エラー: {e}")))?;
+
+    let Some(file_path) = file_path else {
+        return Ok(None);
+    };
+    let file_path = file_path.to_string();
+
+    let json = {
+        let db = state
+            .db
+            .get()
+            .await
+            .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
+        export_library(&db)
+            .map_err(|e| AppError::custom(format!("Failed to export library: {e}")))?
+    };
+
+    std::fs::write(&file_path, json)
+        .map_err(|e| AppError::custom(format!("Failed to write library: {e}")))?;
+
+    Ok(Some(file_path))
+}
+
+#[tauri::command]
+pub async fn import_library_db(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<Option<ImportSummary>, ApiError> {
+    use tauri_plugin_dialog::DialogExt;
+
+    let (tx, rx) = tokio::sync::oneshot::channel();
+
+    app.dialog()
+        .file()
+        .add_filter("JSON", &["json"])
+        .set_title("復元するライブラリのバックアップを選択")
+        .pick_file(move |file_path| {
+            if tx.send(file_path).is_err() {
+                error!("Failed to send file path: receiver dropped");
+            }
+        });
+
+    let file_path = rx
+        .await
+        .map_err(|e| AppError::custom(format!("ファイル選択エラー: {e}")))?;
+
+    let Some(file_path) = file_path else {
+        return Ok(None);
+    };
+
+    let json = std::fs::read_to_string(file_path.to_string())
+        .map_err(|e| AppError::custom(format!("Failed to read library: {e}")))?;
+
+    let db = state
+        .db
+        .get()
+        .await
+        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
+
+    import_library(&db, &json)
+        .map(Some)
+        .map_err(|e| AppError::custom(format!("Failed to import library: {e}")).into())
+}
+
+/// ライブラリ全体をバージョン付きJSONとしてシリアライズ
+pub fn export_library(db: &Database) -> AppResult<String> {
+    let library = LibraryExport {
+        version: LIBRARY_FORMAT_VERSION,
+        exported_at: chrono::Utc::now().to_rfc3339(),
+        files: db.get_all_files_including_deleted()?,
+        tags: db.get_all_tags()?,
+        file_tags: db
+            .get_all_file_tag_links()?
+            .into_iter()
+            .map(|(file_id, tag_id)| FileTagLink { file_id, tag_id })
+            .collect(),
+    };
+
+    Ok(serde_json::to_string_pretty(&library)?)
+}
+
+/// export_library のJSONを復元する
+///
+/// IDは振り直し、同じ file_path が既にあるファイルは追加せずスキップする
+/// （関連付けは既存ファイルに対して復元する）。
+pub fn import_library(db: &Database, json: &str) -> AppResult<ImportSummary> {
+    let library: LibraryExport = serde_json::from_str(json)?;
+
+    if library.version > LIBRARY_FORMAT_VERSION {
+        return Err(AppError::validation(
+            "version",
+            format!(
+                "Unsupported library version: {} (supported: {LIBRARY_FORMAT_VERSION})",
+                library.version
+            ),
+        ));
+    }
+
+    db.in_transaction(|db| {
+        let mut summary = ImportSummary::default();
+
+        let mut tag_ids = HashMap::new();
+        for tag in &library.tags {
+            // タグ名として使えないタグは関連付けごと取り込まない
+            let Some(tag_name) = importable_tag_name(&tag.name) else {
+                continue;
+            };
+            let new_id = db.add_tag(&tag_name)?;
+            if let Some(old_id) = tag.id {
+                tag_ids.insert(old_id, new_id);
+            }
+        }
+
+        let mut file_ids = HashMap::new();
+        for file in &library.files {
+            let new_id = match db.get_file_by_path(&file.file_path)?.and_then(|f| f.id) {
+                Some(existing_id) => {
+                    summary.skipped += 1;
+                    existing_id
+                }
+                None => {
+                    summary.inserted += 1;
+                    db.import_file_record(file)?
+                }
+            };
+            if let Some(old_id) = file.id {
+                file_ids.insert(old_id, new_id);
+            }
+        }
+
+        for link in &library.file_tags {
+            if let (Some(&file_id), Some(&tag_id)) =
+                (file_ids.get(&link.file_id), tag_ids.get(&link.tag_id))
+            {
+                db.add_file_tag(file_id, tag_id)?;
+            }
+        }
+
+        db.recalculate_usage_counts()?;
+        Ok(summary)
+    })
+}
+
+/// ライブラリ全体を指定形式でファイルに書き出し、書き出した件数を返す
+pub fn export_catalog(
+    db: &Database,
+    format: CatalogFormat,
+    output_path: &Path,
+) -> AppResult<usize> {
+    let files = db.get_all_files_ordered_by_id()?;
+    let mut writer = BufWriter::new(File::create(output_path)?);
+
+    match format {
+        CatalogFormat::Json => {
+            serde_json::to_writer_pretty(&mut writer, &CatalogJson { db, files: &files })?;
+            writeln!(writer)?;
+        }
+        CatalogFormat::Csv => write_catalog_csv(db, &files, &mut writer)?,
+    }
+
+    writer.flush()?;
+    Ok(files.len())
+}
+
+/// export_catalog で書き出したJSONカタログを読み込み、ファイルとタグを登録する
+///
+/// 登録に失敗した場合は、Duplicate で作成したコピーも削除する。
+pub fn import_catalog(
+    db: &Database,
+    input_path: &Path,
+    strategy: MergeStrategy,
+) -> AppResult<ImportSummary> {
+    let reader = BufReader::new(File::open(input_path)?);
+    let entries: Vec<FileWithTags> = serde_json::from_reader(reader)?;
+
+    let mut copies = Vec::new();
+    let result = import_catalog_entries(db, entries, strategy, &mut copies);
+    if result.is_err() {
+        for copy in &copies {
+            if let Err(e) = remove_path(copy) {
+                warn!(
+                    "Failed to remove {} after a failed import: {e}",
+                    copy.display()
+                );
+            }
+        }
+    }
+    result
+}
+
+// 作成したコピーのパスは copies に記録する
+fn import_catalog_entries(
+    db: &Database,
+    entries: Vec<FileWithTags>,
+    strategy: MergeStrategy,
+    copies: &mut Vec<PathBuf>,
+) -> AppResult<ImportSummary> {
+    db.in_transaction(|db| {
+        let mut summary = ImportSummary::default();
+
+        for entry in entries {
+            let existing = db.get_file_by_path(&entry.file.file_path)?;
+
+            let file_id = match (existing.and_then(|f| f.id), strategy) {
+                (None, _) => {
+                    summary.inserted += 1;
+                    db.add_file(FileRecord {
+                        id: None,
+                        deleted_at: None,
+                        file_hash: None,
+                        ..entry.file
+                    })?
+                }
+                (Some(_), MergeStrategy::Skip) => {
+                    summary.skipped += 1;
+                    continue;
+                }
+                (Some(existing_id), MergeStrategy::Overwrite) => {
+                    summary.updated += 1;
+                    db.overwrite_file(existing_id, &entry.file)?;
+                    db.clear_file_tags(existing_id)?;
+                    existing_id
+                }
+                (Some(_), MergeStrategy::Duplicate) => {
+                    let source = PathBuf::from(&entry.file.file_path);
+                    if std::fs::symlink_metadata(&source).is_err() {
+                        warn!("Skipping duplicate of missing file {}", source.display());
+                        summary.skipped += 1;
+                        continue;
+                    }
+                    let copy = duplicate_path(db, &source)?;
+                    if let Err(e) = copy_recursively(&source, &copy) {
+                        // 途中までコピーしたものは残さない
+                        let _ = remove_path(&copy);
+                        return Err(e.into());
+                    }
+                    copies.push(copy.clone());
+
+                    summary.inserted += 1;
+                    db.add_file(FileRecord {
+                        id: None,
+                        file_path: copy.to_string_lossy().to_string(),
+                        file_name: copy
+                            .file_name()
+                            .map(|name| name.to_string_lossy().to_string())
+                            .unwrap_or_default(),
+                        deleted_at: None,
+                        file_hash: None,
+                        ..entry.file
+                    })?
+                }
+            };
+
+            for tag in &entry.tags {
+                let Some(tag_name) = importable_tag_name(&tag.name) else {
+                    continue;
+                };
+                // タグを取得または作成
+                let tag_id = db.add_tag(&tag_name)?;
+                db.add_file_tag(file_id, tag_id)?;
+            }
+        }
+
+        db.recalculate_usage_counts()?;
+        Ok(summary)
+    })
+}
+
+// 取り込むタグ名を正規化する（検証に通らない名前は警告を出して None）
+fn importable_tag_name(tag_name: &str) -> Option<String> {
+    match normalize_tag_name(tag_name) {
+        Ok(tag_name) => Some(tag_name),
+        Err(e) => {
+            warn!("Skipping invalid tag {tag_name:?}: {e}");
+            None
+        }
+    }
+}
+
+// コピー先として、ディスク上にも既存行にも無いパスを「名前 (2)」形式で生成する
+// ファイルは拡張子の前に番号を入れる（"avatar (2).zip"）
+fn duplicate_path(db: &Database, source: &Path) -> AppResult<PathBuf> {
+    let name = |n: usize| {
+        let file_name = source
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        match (source.is_file(), source.file_stem(), source.extension()) {
+            (true, Some(stem), Some(extension)) => format!(
+                "{} ({n}).{}",
+                stem.to_string_lossy(),
+                extension.to_string_lossy()
+            ),
+            _ => format!("{file_name} ({n})"),
+        }
+    };
+
+    let mut n = 2;
+    loop {
+        let candidate = source.with_file_name(name(n));
+        if std::fs::symlink_metadata(&candidate).is_err()
+            && db.get_file_by_path(&candidate.to_string_lossy())?.is_none()
+        {
+            return Ok(candidate);
+        }
+        n += 1;
+    }
+}
+
+// CSVの文字コードを判定して文字列にする
+// BOM 付き・UTF-8 として読めるものはそのまま、それ以外（Excel の Shift_JIS など）は推定した文字コードで読む
+fn decode_csv(bytes: &[u8]) -> std::borrow::Cow<'_, str> {
+    if let Some(bytes) = bytes.strip_prefix(UTF8_BOM) {
+        return String::from_utf8_lossy(bytes);
+    }
+    if let Ok(content) = std::str::from_utf8(bytes) {
+        return content.into();
+    }
+
+    let mut detector = chardetng::EncodingDetector::new();
+    detector.feed(bytes, true);
+    let (content, _, _) = detector.guess(None, false).decode(bytes);
+    content
+}
+
+/// `file_path,tag1;tag2;...`（または `file_id,...`）形式のCSVを読み、該当ファイルにタグを付ける
+///
+/// タグは必要に応じて作成する。一致するファイルがない行は `unmatched` に記録して処理を続ける。
+pub fn apply_tags_from_csv(db: &Database, csv_path: &Path) -> AppResult<CsvTagReport> {
+    let bytes = std::fs::read(csv_path)?;
+    let content = decode_csv(&bytes);
+
+    db.in_transaction(|db| {
+        let mut report = CsvTagReport::default();
+
+        for (index, row) in parse_csv_rows(&content).into_iter().enumerate() {
+            let Some(key) = row.first().map(|key| key.trim()) else {
+                continue;
+            };
+            if key.is_empty() || (index == 0 && CSV_TAG_KEY_HEADERS.contains(&key)) {
+                continue;
+            }
+
+            let file = match key.parse::<i64>() {
+                Ok(file_id) => db.get_file_by_id(file_id)?,
+                Err(_) => db.get_file_by_path(key)?,
+            };
+            let Some(file_id) = file.filter(|f| f.deleted_at.is_none()).and_then(|f| f.id) else {
+                report.unmatched.push(key.to_string());
+                continue;
+            };
+
+            let mut existing: Vec<i64> = db
+                .get_tags_for_file(file_id)?
+                .into_iter()
+                .filter_map(|tag| tag.id)
+                .collect();
+            let tag_names = row.get(1).map(String::as_str).unwrap_or_default();
+            for tag_name in tag_names.split(CSV_TAG_SEPARATOR) {
+                if tag_name.trim().is_empty() {
+                    continue;
+                }
+                let Some(tag_name) = importable_tag_name(tag_name) else {
+                    continue;
+                };
+                let tag_id = db.add_tag(&tag_name)?;
+                if !existing.contains(&tag_id) {
+                    db.add_file_tag(file_id, tag_id)?;
+                    existing.push(tag_id);
+                    report.associations += 1;
+                }
+            }
+        }
+
+        Ok(report)
+    })
+}
+
+/// CSVタグ付与で先頭行を見出しとして読み飛ばす列名
+const CSV_TAG_KEY_HEADERS: [&str; 2] = ["file_path", "file_id"];
+
+// CSV全体を行ごとのフィールドに分割する（引用符内の区切り文字・改行と "" のエスケープに対応）
+fn parse_csv_rows(content: &str) -> Vec<Vec<String>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = content.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (c, in_quotes) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            ('"', true) => in_quotes = false,
+            ('"', false) if field.is_empty() => in_quotes = true,
+            (',', false) => row.push(std::mem::take(&mut field)),
+            ('\r', false) if chars.peek() == Some(&'\n') => {}
+            ('\n' | '\r', false) => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            _ => field.push(c),
+        }
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    rows
+}
+
+/// 表計算ソフト向けにファイル一覧をCSV（UTF-8 BOM付き）で書き出し、件数を返す
+pub fn export_file_list_csv(db: &Database, output_path: &Path) -> AppResult<usize> {
+    let files = db.get_files_with_tags()?;
+    let mut writer = BufWriter::new(File::create(output_path)?);
+
+    writer.write_all(UTF8_BOM)?;
+    write_csv_row(&mut writer, FILE_LIST_CSV_HEADER)?;
+
+    for file_with_tags in &files {
+        let file = &file_with_tags.file;
+        let tag_names = file_with_tags
+            .tags
+            .iter()
+            .map(|tag| tag.name.as_str())
+            .collect::<Vec<_>>()
+            .join(CSV_TAG_SEPARATOR);
+
+        write_csv_row(
+            &mut writer,
+            [
+                file.file_name.clone(),
+                file.author_name.clone().unwrap_or_default(),
+                file.product_name.clone().unwrap_or_default(),
+                file.price.map(|p| p.to_string()).unwrap_or_default(),
+                file.product_url.clone().unwrap_or_default(),
+                tag_names,
+                file.created_at.clone(),
+            ],
+        )?;
+    }
+
+    writer.flush()?;
+    Ok(files.len())
+}
+
+// ファイルごとにタグを取得しながらJSON配列として逐次書き出す
+struct CatalogJson<'a> {
+    db: &'a Database,
+    files: &'a [FileRecord],
+}
+
+impl Serialize for CatalogJson<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.files.len()))?;
+        for file in self.files {
+            let tags = match file.id {
+                Some(id) => self.db.get_tags_for_file(id).map_err(S::Error::custom)?,
+                None => Vec::new(),
+            };
+            seq.serialize_element(&FileWithTags {
+                file: file.clone(),
+                tags,
+            })?;
+        }
+        seq.end()
+    }
+}
+
+fn write_catalog_csv<W: Write>(
+    db: &Database,
+    files: &[FileRecord],
+    writer: &mut W,
+) -> AppResult<()> {
+    write_csv_row(writer, CATALOG_CSV_HEADER)?;
+
+    for file in files {
+        let tags = match file.id {
+            Some(id) => db.get_tags_for_file(id)?,
+            None => Vec::new(),
+        };
+        let tag_names = tags
+            .iter()
+            .map(|tag| tag.name.as_str())
+            .collect::<Vec<_>>()
+            .join(CSV_TAG_SEPARATOR);
+
+        write_csv_row(
+            writer,
+            [
+                file.id.map(|id| id.to_string()).unwrap_or_default(),
+                file.file_path.clone(),
+                file.file_name.clone(),
+                file.file_size.to_string(),
+                file.modified_time.to_string(),
+                file.created_at.clone(),
+                file.updated_at.clone(),
+                file.product_id.clone().unwrap_or_default(),
+                file.product_name.clone().unwrap_or_default(),
+                file.author_name.clone().unwrap_or_default(),
+                file.price.map(|p| p.to_string()).unwrap_or_default(),
+                file.description.clone().unwrap_or_default(),
+                file.thumbnail_url.clone().unwrap_or_default(),
+                file.product_url.clone().unwrap_or_default(),
+                tag_names,
+            ],
+        )?;
+    }
+
+    Ok(())
+}
+
+/// CSVの1行を書き出す（区切り文字・引用符・改行を含むフィールドは引用符で囲む）
+pub fn write_csv_row<W, I, S>(writer: &mut W, fields: I) -> std::io::Result<()>
+where
+    W: Write,
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    let line = fields
+        .into_iter()
+        .map(|field| escape_csv_field(field.as_ref()))
+        .collect::<Vec<_>>()
+        .join(",");
+    writer.write_all(line.as_bytes())?;
+    writer.write_all(b"\r\n")
+}
+
+fn escape_csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    fn sample_file(path: &str, product_name: &str) -> FileRecord {
+        FileRecord {
+            file_name: path.rsplit('/').next().unwrap_or(path).to_string(),
+            file_size: 2048,
+            modified_time: 1_700_000_000,
+            product_id: Some("5840141".to_string()),
+            product_name: Some(product_name.to_string()),
+            author_name: Some("Extension Shop".to_string()),
+            price: Some(800),
+            description: Some("説明, カンマ入り".to_string()),
+            product_url: Some("https://extension.booth.pm/items/5840141".to_string()),
+            ..test_support::sample_file(path)
+        }
+    }
+
+    fn sample_db() -> Database {
+        let db = Database::new(":memory:").unwrap();
+        let first = db.add_file(sample_file("/lib/yukata", "YUKATA")).unwrap();
+        let second = db.add_file(sample_file("/lib/hair", "Hair")).unwrap();
+        for tag_name in ["VRChat", "浴衣"] {
+            let tag_id = db.add_tag(tag_name).unwrap();
+            db.add_file_tag(first, tag_id).unwrap();
+        }
+        let tag_id = db.add_tag("VRChat").unwrap();
+        db.add_file_tag(second, tag_id).unwrap();
+        db
+    }
+
+    #[test]
+    fn test_catalog_format_parse() {
+        assert_eq!(CatalogFormat::parse("JSON").unwrap(), CatalogFormat::Json);
+        assert_eq!(CatalogFormat::parse(" csv ").unwrap(), CatalogFormat::Csv);
+        assert!(CatalogFormat::parse("xml").is_err());
+    }
+
+    #[test]
+    fn test_export_json_round_trip() {
+        let db = sample_db();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("catalog.json");
+
+        let count = export_catalog(&db, CatalogFormat::Json, &path).unwrap();
+        assert_eq!(count, 2);
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert!(content.contains("\n  {"), "JSON should be pretty-printed");
+
+        let parsed: Vec<FileWithTags> = serde_json::from_str(&content).unwrap();
+        assert_eq!(parsed.len(), 2);
+        assert!(parsed[0].file.id < parsed[1].file.id);
+        assert_eq!(parsed[0].file.product_name.as_deref(), Some("YUKATA"));
+        assert_eq!(
+            parsed[0].file.description.as_deref(),
+            Some("説明, カンマ入り")
+        );
+        let tag_names: Vec<_> = parsed[0].tags.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(tag_names, vec!["VRChat", "浴衣"]);
+        assert_eq!(parsed[1].tags.len(), 1);
+    }
+
+    #[test]
+    fn test_export_csv_header_and_tags() {
+        let db = sample_db();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("catalog.csv");
+
+        export_catalog(&db, CatalogFormat::Csv, &path).unwrap();
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], CATALOG_CSV_HEADER.join(","));
+        assert!(lines[1].starts_with("1,/lib/yukata,yukata,2048,"));
+        assert!(lines[1].contains("\"説明, カンマ入り\""));
+        assert!(lines[1].ends_with(",VRChat;浴衣"));
+        assert!(lines[2].ends_with(",VRChat"));
+    }
+
+    fn exported_catalog(dir: &Path) -> std::path::PathBuf {
+        let path = dir.join("catalog.json");
+        export_catalog(&sample_db(), CatalogFormat::Json, &path).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_merge_strategy_parse() {
+        assert_eq!(MergeStrategy::parse("Skip").unwrap(), MergeStrategy::Skip);
+        assert_eq!(
+            MergeStrategy::parse("overwrite").unwrap(),
+            MergeStrategy::Overwrite
+        );
+        assert_eq!(
+            MergeStrategy::parse("duplicate").unwrap(),
+            MergeStrategy::Duplicate
+        );
+        assert!(MergeStrategy::parse("merge").is_err());
+    }
+
+    #[test]
+    fn test_import_into_empty_db() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = exported_catalog(dir.path());
+        let db = Database::new(":memory:").unwrap();
+
+        let summary = import_catalog(&db, &path, MergeStrategy::Skip).unwrap();
+        assert_eq!(
+            summary,
+            ImportSummary {
+                inserted: 2,
+                updated: 0,
+                skipped: 0
+            }
+        );
+
+        let files = db.get_files_with_tags().unwrap();
+        assert_eq!(files.len(), 2);
+        let yukata = files
+            .iter()
+            .find(|f| f.file.file_path == "/lib/yukata")
+            .unwrap();
+        assert_eq!(yukata.tags.len(), 2);
+
+        let tags = db.get_all_tags().unwrap();
+        assert_eq!(tags.len(), 2);
+        assert_eq!(
+            tags.iter()
+                .find(|t| t.name == "VRChat")
+                .unwrap()
+                .usage_count,
+            2
+        );
+    }
+
+    #[test]
+    fn test_reimport_with_skip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = exported_catalog(dir.path());
+        let db = Database::new(":memory:").unwrap();
+        import_catalog(&db, &path, MergeStrategy::Skip).unwrap();
+
+        let summary = import_catalog(&db, &path, MergeStrategy::Skip).unwrap();
+        assert_eq!(summary.skipped, 2);
+        assert_eq!(summary.inserted + summary.updated, 0);
+        assert_eq!(db.get_all_files().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_reimport_with_overwrite() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = exported_catalog(dir.path());
+        let db = Database::new(":memory:").unwrap();
+        import_catalog(&db, &path, MergeStrategy::Skip).unwrap();
+        let original_id = db.get_file_by_path("/lib/hair").unwrap().unwrap().id;
+
+        // 既存側を変更してからインポートで上書きする
+        let hair_id = original_id.unwrap();
+        let extra_tag = db.add_tag("Extra").unwrap();
+        db.add_file_tag(hair_id, extra_tag).unwrap();
+        db.soft_delete_file(hair_id).unwrap();
+
+        let summary = import_catalog(&db, &path, MergeStrategy::Overwrite).unwrap();
+        assert_eq!(summary.updated, 2);
+        assert_eq!(summary.inserted + summary.skipped, 0);
+
+        let hair = db.get_file_by_path("/lib/hair").unwrap().unwrap();
+        assert_eq!(hair.id, original_id);
+        assert_eq!(hair.deleted_at, None);
+        assert_eq!(hair.product_name.as_deref(), Some("Hair"));
+        let tag_names: Vec<_> = db
+            .get_tags_for_file(hair_id)
+            .unwrap()
+            .into_iter()
+            .map(|t| t.name)
+            .collect();
+        assert_eq!(tag_names, vec!["VRChat"]);
+        assert_eq!(db.get_all_files().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_reimport_with_duplicate_copies_files_on_disk() {
+        let dir = tempfile::tempdir().unwrap();
+        let yukata = dir.path().join("yukata");
+        std::fs::create_dir(&yukata).unwrap();
+        std::fs::write(yukata.join("model.fbx"), b"fbx").unwrap();
+        let hair = dir.path().join("hair.zip");
+        std::fs::write(&hair, b"zip").unwrap();
+        let path_of = |path: &Path| path.to_string_lossy().to_string();
+
+        let source = Database::new(":memory:").unwrap();
+        let yukata_id = source
+            .add_file(sample_file(&path_of(&yukata), "YUKATA"))
+            .unwrap();
+        for tag_name in ["VRChat", "浴衣"] {
+            let tag_id = source.add_tag(tag_name).unwrap();
+            source.add_file_tag(yukata_id, tag_id).unwrap();
+        }
+        source
+            .add_file(sample_file(&path_of(&hair), "Hair"))
+            .unwrap();
+        source
+            .add_file(sample_file(&path_of(&dir.path().join("gone")), "Gone"))
+            .unwrap();
+        let path = dir.path().join("catalog.json");
+        export_catalog(&source, CatalogFormat::Json, &path).unwrap();
+
+        let db = Database::new(":memory:").unwrap();
+        import_catalog(&db, &path, MergeStrategy::Skip).unwrap();
+
+        // ディスク上のファイルは別名でコピーして登録し、無いファイルはスキップする
+        let summary = import_catalog(&db, &path, MergeStrategy::Duplicate).unwrap();
+        assert_eq!(summary.inserted, 2);
+        assert_eq!(summary.skipped, 1);
+        assert_eq!(summary.updated, 0);
+        assert_eq!(db.get_all_files().unwrap().len(), 5);
+
+        let copied_folder = dir.path().join("yukata (2)");
+        let copy = db
+            .get_file_by_path(&path_of(&copied_folder))
+            .unwrap()
+            .unwrap();
+        assert_eq!(copy.file_name, "yukata (2)");
+        assert_eq!(copy.product_name.as_deref(), Some("YUKATA"));
+        assert_eq!(db.get_tags_for_file(copy.id.unwrap()).unwrap().len(), 2);
+        assert_eq!(
+            std::fs::read(copied_folder.join("model.fbx")).unwrap(),
+            b"fbx"
+        );
+        let copied_file = dir.path().join("hair (2).zip");
+        assert!(db
+            .get_file_by_path(&path_of(&copied_file))
+            .unwrap()
+            .is_some());
+        assert_eq!(std::fs::read(&copied_file).unwrap(), b"zip");
+
+        import_catalog(&db, &path, MergeStrategy::Duplicate).unwrap();
+        assert!(dir.path().join("yukata (3)/model.fbx").exists());
+        assert!(db
+            .get_file_by_path(&path_of(&dir.path().join("yukata (3)")))
+            .unwrap()
+            .is_some());
+    }
+
+    #[test]
+    fn test_library_round_trip() {
+        let source = sample_db();
+        // IDが振り直されることを確認するため、未使用タグと欠番を作っておく
+        source.add_tag("未使用").unwrap();
+        let gone = source.add_file(sample_file("/lib/gone", "Gone")).unwrap();
+        source.delete_file(gone).unwrap();
+        source.add_file(sample_file("/lib/last", "Last")).unwrap();
+        // ゴミ箱内のファイルもゴミ箱に入ったまま書き出し・復元する
+        let trashed = source
+            .add_file(sample_file("/lib/trashed", "Trashed"))
+            .unwrap();
+        source.soft_delete_file(trashed).unwrap();
+
+        let json = export_library(&source).unwrap();
+        let parsed: LibraryExport = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.version, LIBRARY_FORMAT_VERSION);
+        assert_eq!(parsed.files.len(), 4);
+        assert_eq!(parsed.file_tags.len(), 3);
+
+        let target = Database::new(":memory:").unwrap();
+        let summary = import_library(&target, &json).unwrap();
+        assert_eq!(summary.inserted, 4);
+        assert_eq!(summary.skipped, 0);
+        let deleted = target.get_deleted_files().unwrap();
+        assert_eq!(deleted.len(), 1);
+        assert_eq!(deleted[0].file_path, "/lib/trashed");
+        assert_eq!(
+            deleted[0].deleted_at,
+            source.get_deleted_files().unwrap()[0].deleted_at
+        );
+
+        let snapshot = |db: &Database| {
+            let mut files: Vec<_> = db
+                .get_files_with_tags()
+                .unwrap()
+                .into_iter()
+                .map(|f| {
+                    let tags: Vec<_> = f.tags.into_iter().map(|t| t.name).collect();
+                    (FileRecord { id: None, ..f.file }, tags)
+                })
+                .collect();
+            files.sort_by(|a, b| a.0.file_path.cmp(&b.0.file_path));
+            let tags: Vec<_> = db
+                .get_all_tags()
+                .unwrap()
+                .into_iter()
+                .map(|t| (t.name, t.usage_count))
+                .collect();
+            (format!("{files:?}"), tags)
+        };
+        assert_eq!(snapshot(&source), snapshot(&target));
+
+        // 再インポートは既存パスとしてスキップされる
+        let summary = import_library(&target, &json).unwrap();
+        assert_eq!(summary.inserted, 0);
+        assert_eq!(summary.skipped, 4);
+        assert_eq!(target.get_all_files().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_library_import_skips_invalid_tag_names() {
+        let source = sample_db();
+        let mut library: LibraryExport =
+            serde_json::from_str(&export_library(&source).unwrap()).unwrap();
+        let file_id = library.files[0].id.unwrap();
+        let template = library.tags[0].clone();
+        for (id, name) in [
+            (100, "  3D   衣装 ".to_string()),
+            (101, "   ".to_string()),
+            (102, "x".repeat(500)),
+            (103, "bad\nname".to_string()),
+        ] {
+            library.tags.push(Tag {
+                id: Some(id),
+                name,
+                ..template.clone()
+            });
+            library.file_tags.push(FileTagLink {
+                file_id,
+                tag_id: id,
+            });
+        }
+
+        let target = Database::new(":memory:").unwrap();
+        import_library(&target, &serde_json::to_string(&library).unwrap()).unwrap();
+
+        let mut names: Vec<_> = target
+            .get_all_tags()
+            .unwrap()
+            .into_iter()
+            .map(|t| t.name)
+            .collect();
+        names.sort();
+        assert_eq!(names, vec!["3D 衣装", "VRChat", "浴衣"]);
+    }
+
+    #[test]
+    fn test_library_rejects_newer_version() {
+        let json = format!(
+            r#"{{"version": {}, "exported_at": "", "files": [], "tags": [], "file_tags": []}}"#,
+            LIBRARY_FORMAT_VERSION + 1
+        );
+        let db = Database::new(":memory:").unwrap();
+        assert!(import_library(&db, &json).is_err());
+    }
+
+    #[test]
+    fn test_export_file_list_csv() {
+        let db = Database::new(":memory:").unwrap();
+        let mut file = sample_file("/lib/yukata", "YUKATA, 浴衣");
+        file.description = None;
+        let file_id = db.add_file(file).unwrap();
+        for tag_name in ["VRChat", "浴衣"] {
+            let tag_id = db.add_tag(tag_name).unwrap();
+            db.add_file_tag(file_id, tag_id).unwrap();
+        }
+        let created_at = db.get_all_files().unwrap()[0].created_at.clone();
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("files.csv");
+        assert_eq!(export_file_list_csv(&db, &path).unwrap(), 1);
+
+        let bytes = std::fs::read(&path).unwrap();
+        assert!(bytes.starts_with(UTF8_BOM));
+
+        let content = String::from_utf8(bytes[UTF8_BOM.len()..].to_vec()).unwrap();
+        let lines: Vec<&str> = content.split("\r\n").collect();
+        assert_eq!(
+            lines[0],
+            "file_name,shop_name,product_name,price,booth_url,tags,created_at"
+        );
+        assert_eq!(
+            lines[1],
+            format!(
+                "yukata,Extension Shop,\"YUKATA, 浴衣\",800,\
+                 https://extension.booth.pm/items/5840141,VRChat;浴衣,{created_at}"
+            )
+        );
+        assert_eq!(lines[2], "");
+    }
+
+    #[test]
+    fn test_parse_csv_rows_handles_quotes() {
+        let rows = parse_csv_rows("a,\"b,c\"\r\n\"say \"\"hi\"\"\",\"x\ny\"\nlast");
+        assert_eq!(
+            rows,
+            vec![
+                vec!["a".to_string(), "b,c".to_string()],
+                vec!["say \"hi\"".to_string(), "x\ny".to_string()],
+                vec!["last".to_string()],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_apply_tags_from_csv() {
+        let db = Database::new(":memory:").unwrap();
+        let file_id = db.add_file(sample_file("/lib/yukata", "浴衣")).unwrap();
+        db.add_file_tag(file_id, db.add_tag("VRChat").unwrap())
+            .unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tags.csv");
+        std::fs::write(
+            &path,
+            "file_path,tags\r\n/lib/yukata,VRChat;浴衣; 夏 \r\n/lib/missing,衣装\r\n",
+        )
+        .unwrap();
+
+        let report = apply_tags_from_csv(&db, &path).unwrap();
+        // 既に付いている VRChat は数えない
+        assert_eq!(report.associations, 2);
+        assert_eq!(report.unmatched, vec!["/lib/missing".to_string()]);
+
+        let mut names: Vec<String> = db
+            .get_tags_for_file(file_id)
+            .unwrap()
+            .into_iter()
+            .map(|tag| tag.name)
+            .collect();
+        names.sort();
+        assert_eq!(names, vec!["VRChat", "夏", "浴衣"]);
+
+        // ファイルIDでも指定できる
+        std::fs::write(&path, format!("{file_id},衣装\n999,衣装")).unwrap();
+        let report = apply_tags_from_csv(&db, &path).unwrap();
+        assert_eq!(report.associations, 1);
+        assert_eq!(report.unmatched, vec!["999".to_string()]);
+
+        // Excel が書き出す Shift_JIS のCSVも読める
+        let (sjis, _, _) = encoding_rs::SHIFT_JIS
+            .encode("file_path,tags\r\n/lib/yukata,和服;夏祭り\r\n/lib/存在しない,衣装\r\n");
+        std::fs::write(&path, &sjis).unwrap();
+        let report = apply_tags_from_csv(&db, &path).unwrap();
+        assert_eq!(report.associations, 2);
+        assert_eq!(report.unmatched, vec!["/lib/存在しない".to_string()]);
+        let names: Vec<String> = db
+            .get_tags_for_file(file_id)
+            .unwrap()
+            .into_iter()
+            .map(|tag| tag.name)
+            .collect();
+        assert!(names.contains(&"和服".to_string()), "{names:?}");
+        assert!(names.contains(&"夏祭り".to_string()), "{names:?}");
+    }
+
+    #[test]
+    fn test_escape_csv_field() {
+        assert_eq!(escape_csv_field("plain"), "plain");
+        assert_eq!(escape_csv_field("a,b"), "\"a,b\"");
+        assert_eq!(escape_csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(escape_csv_field("line\nbreak"), "\"line\nbreak\"");
+    }
+}
@@ -0,0 +1,87 @@
+use crate::api_types::ApiError;
+use crate::database::{Collection, FileWithTags};
+use crate::{AppError, AppState};
+
+#[tauri::command]
+pub async fn create_collection_db(
+    state: tauri::State<'_, AppState>,
+    name: String,
+) -> Result<i64, ApiError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(AppError::validation("name", "コレクション名を入力してください").into());
+    }
+
+    let db = state
+        .db
+        .get()
+        .await
+        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
+
+    db.create_collection(name)
+        .map_err(|e| AppError::custom(format!("Failed to create collection: {e}")).into())
+}
+
+#[tauri::command]
+pub async fn list_collections_db(
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<Collection>, ApiError> {
+    let db = state
+        .db
+        .get()
+        .await
+        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
+
+    db.list_collections()
+        .map_err(|e| AppError::custom(format!("Failed to get collections: {e}")).into())
+}
+
+#[tauri::command]
+pub async fn add_files_to_collection_db(
+    state: tauri::State<'_, AppState>,
+    collection_id: i64,
+    file_ids: Vec<i64>,
+) -> Result<usize, ApiError> {
+    let db = state
+        .db
+        .get()
+        .await
+        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
+
+    db.add_files_to_collection(collection_id, &file_ids)
+        .map_err(|e| AppError::custom(format!("Failed to add files to collection: {e}")).into())
+}
+
+#[tauri::command]
+pub async fn remove_files_from_collection_db(
+    state: tauri::State<'_, AppState>,
+    collection_id: i64,
+    file_ids: Vec<i64>,
+) -> Result<usize, ApiError> {
+    let db = state
+        .db
+        .get()
+        .await
+        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
+
+    db.remove_files_from_collection(collection_id, &file_ids)
+        .map_err(|e| {
+            AppError::custom(format!("Failed to remove files from collection: {e}")).into()
+        })
+}
+
+#[tauri::command]
+pub async fn get_collection_files_db(
+    state: tauri::State<'_, AppState>,
+    collection_id: i64,
+) -> Result<Vec<FileWithTags>, ApiError> {
+    let db = state
+        .db
+        .get()
+        .await
+        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
+
+    db.get_collection_files(collection_id).map_err(|e| {
+        AppError::file_retrieval(format!("Failed to get collection files: {e}")).into()
+    })
+}
@@ -8,6 +8,27 @@ pub mod app {
 
     /// Database file name
     pub const DATABASE_FILENAME: &str = "booth_organizer.db";
+
+    /// Settings file name inside the application data directory
+    pub const CONFIG_FILENAME: &str = "config.json";
+}
+
+/// Log file configuration constants
+pub mod logging {
+    /// Directory for log files inside the application data directory
+    pub const LOG_DIR_NAME: &str = "logs";
+
+    /// Base name of the log files; the file being written carries the `rCURRENT` infix
+    pub const LOG_FILE_BASENAME: &str = "booth_organizer";
+
+    /// Log level used when `RUST_LOG` is not set
+    pub const DEFAULT_LEVEL: &str = "info";
+
+    /// Size at which the current log file is rotated (5 MiB)
+    pub const MAX_LOG_FILE_BYTES: u64 = 5 * 1024 * 1024;
+
+    /// Number of rotated log files kept next to the current one
+    pub const KEEP_LOG_FILES: usize = 5;
 }
 
 /// Tag-related configuration constants
@@ -15,14 +36,22 @@ pub mod tags {
     /// Maximum allowed length for tag names
     pub const MAX_TAG_LENGTH: usize = 50;
 
-    /// Default tag color (blue) - Reserved for future UI implementation
-    #[allow(dead_code)]
+    /// Default tag color (blue), used until a color is set on the tag
     pub const DEFAULT_TAG_COLOR: &str = "#007ACC";
 
     /// Alternative default color (blue) - Reserved for future UI implementation
     #[allow(dead_code)]
     pub const ALTERNATIVE_DEFAULT_COLOR: &str = "#3498db";
 
+    /// Number of most-used tags included in the library statistics by default
+    pub const STATS_TOP_TAGS: u32 = 10;
+
+    /// Number of tags suggested for a file by default
+    pub const SUGGESTED_TAGS: u32 = 5;
+
+    /// Maximum nesting of parentheses and NOT in a tag search expression
+    pub const MAX_QUERY_NESTING: usize = 64;
+
     /// Initial usage count for new tags - Reserved for future feature
     #[allow(dead_code)]
     pub const INITIAL_USAGE_COUNT: i64 = 0;
@@ -33,11 +62,85 @@ pub mod files {
     /// Maximum filename length before truncation
     pub const MAX_FILENAME_LENGTH: usize = 200;
 
+    /// Maximum UTF-8 byte length of a folder name; most filesystems allow 255 bytes per
+    /// component, and the rest is left for suffixes such as " (12)" added on name collisions
+    pub const MAX_FILENAME_BYTES: usize = 240;
+
+    /// Maximum length (in characters) of the note a user can attach to a file
+    pub const MAX_USER_NOTE_LENGTH: usize = 4000;
+
+    /// Directory name used for trashed files, created next to the deleted item
+    pub const TRASH_DIR_NAME: &str = ".trash";
+
+    /// Characters replaced in folder names by default (invalid on Windows, macOS or Linux)
+    pub const INVALID_FILENAME_CHARS: &[char] = &['<', '>', ':', '"', '|', '?', '*', '/', '\\'];
+
+    /// Default replacement for invalid folder name characters
+    pub const DEFAULT_REPLACEMENT_CHAR: char = '_';
+
+    /// Device names Windows refuses as file or folder names, with or without an extension
+    pub const WINDOWS_RESERVED_NAMES: &[&str] = &[
+        "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+        "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9", "COM¹",
+        "COM²", "COM³", "LPT¹", "LPT²", "LPT³",
+    ];
+
+    /// Output folder layout used when no naming template is configured
+    pub const DEFAULT_FOLDER_TEMPLATE: &str = "{shop}/{product}";
+
+    /// Directory for downloaded thumbnails inside the application data directory
+    pub const THUMBNAIL_DIR_NAME: &str = "thumbnails";
+
+    /// Longest side of the downscaled thumbnail variant used in the grid view
+    pub const SMALL_THUMBNAIL_SIZE: u32 = 256;
+
+    /// Largest thumbnail returned to the frontend as a data URL (5 MiB before encoding)
+    pub const MAX_THUMBNAIL_BYTES: u64 = 5 * 1024 * 1024;
+
     /// Default file size for unknown files - Reserved for future feature
     #[allow(dead_code)]
     pub const UNKNOWN_FILE_SIZE: i64 = 0;
 }
 
+/// ZIP processing configuration constants
+pub mod processing {
+    /// Upper bound on archives extracted concurrently in a batch
+    pub const MAX_BATCH_WORKERS: usize = 8;
+
+    /// Event emitted after each archive in a batch finishes
+    pub const BATCH_PROGRESS_EVENT: &str = "zip-batch-progress";
+
+    /// Event emitted before and after each archive in a sequential import
+    pub const PROCESSING_STATUS_EVENT: &str = "zip-processing-status";
+
+    /// Default cap on the total uncompressed size of one archive (20 GiB)
+    pub const MAX_TOTAL_UNCOMPRESSED_BYTES: u64 = 20 * 1024 * 1024 * 1024;
+
+    /// Default cap on the uncompressed size of a single entry (4 GiB)
+    pub const MAX_ENTRY_UNCOMPRESSED_BYTES: u64 = 4 * 1024 * 1024 * 1024;
+
+    /// Default maximum uncompressed/compressed ratio for a single entry
+    pub const MAX_COMPRESSION_RATIO: u64 = 200;
+
+    /// Entries smaller than this are exempt from the compression ratio check
+    pub const RATIO_CHECK_MIN_BYTES: u64 = 1024 * 1024;
+
+    /// Default limit on how many levels of zip-in-zip are extracted in recursive mode
+    pub const MAX_NESTED_ARCHIVE_DEPTH: usize = 3;
+}
+
+/// Inbox watcher configuration constants
+pub mod watcher {
+    /// Event emitted after the watcher imports an archive from the inbox
+    pub const INBOX_IMPORT_EVENT: &str = "inbox-import";
+
+    /// How long a new archive's size must stay unchanged before it is imported
+    pub const SETTLE_DURATION_MS: u64 = 3000;
+
+    /// Interval between size checks on pending archives
+    pub const POLL_INTERVAL_MS: u64 = 500;
+}
+
 /// BOOTH-related configuration constants
 pub mod booth {
     /// Main BOOTH domain
@@ -46,6 +149,49 @@ pub mod booth {
     /// BOOTH subdomain suffix pattern
     pub const SUBDOMAIN_SUFFIX: &str = ".booth.pm";
 
+    /// Subdomains of booth.pm that are not shops
+    pub const NON_SHOP_SUBDOMAINS: &[&str] = &["www", "accounts", "manage", "asset"];
+
+    /// Upper bound on listing pages fetched for one shop
+    pub const MAX_SHOP_PAGES: u32 = 50;
+
+    /// Upper bound on how long a 429 Retry-After header can make us wait
+    pub const MAX_RETRY_AFTER_SECS: u64 = 60;
+
+    /// Attempts per request (including the first) unless configured otherwise
+    pub const DEFAULT_MAX_RETRIES: u32 = 3;
+
+    /// Wait before the first retry; doubles on each further attempt
+    pub const DEFAULT_RETRY_BACKOFF_MS: u64 = 500;
+
+    /// Upper bound on a single retry backoff unless configured otherwise
+    pub const DEFAULT_MAX_RETRY_BACKOFF_MS: u64 = 10_000;
+
+    /// User-Agent identifying this app to BOOTH
+    pub const DEFAULT_USER_AGENT: &str = concat!(
+        "BoothFileOrganizer/",
+        env!("CARGO_PKG_VERSION"),
+        " (+https://github.com/yoshiken/Booth_File_Organizer)"
+    );
+
+    /// Markers of the age confirmation page BOOTH serves instead of an R-18 item
+    pub const AGE_GATE_MARKERS: &[&str] = &[
+        "adult-check",
+        "adult_check",
+        "age-confirmation",
+        "age_confirmation",
+        "18歳以上ですか",
+    ];
+
+    /// Environment variables checked, in order, for a proxy when none is configured
+    pub const PROXY_ENV_VARS: &[&str] = &["HTTPS_PROXY", "https_proxy"];
+
+    /// Proxy URL schemes accepted for BOOTH requests
+    pub const PROXY_SCHEMES: &[&str] = &["http", "https", "socks5", "socks5h"];
+
+    /// Browser-like User-Agent for when BOOTH blocks non-browser clients
+    pub const BROWSER_USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/91.0.4472.124 Safari/537.36";
+
     /// Default shop name for unknown shops - Reserved for future feature
     #[allow(dead_code)]
     pub const DEFAULT_SHOP_NAME: &str = "Unknown_Shop";
@@ -57,7 +203,7 @@ pub mod booth {
     pub const LANG_ENGLISH: &str = "en";
 }
 
-/// Database schema constants
+/// Database schema and connection constants
 pub mod database {
     /// Files table name - Reserved for database abstraction
     #[allow(dead_code)]
@@ -78,6 +224,36 @@ pub mod database {
     /// Datetime now function - Reserved for database abstraction
     #[allow(dead_code)]
     pub const DATETIME_NOW: &str = "datetime('now')";
+
+    /// Schema version stored in `PRAGMA user_version`; bump when `migrate_schema` changes
+    pub const SCHEMA_VERSION: i32 = 3;
+
+    /// Maximum number of pooled SQLite connections
+    pub const POOL_MAX_SIZE: usize = 8;
+
+    /// Seconds to wait for a free pooled connection
+    pub const POOL_TIMEOUT_SECS: u64 = 30;
+
+    /// Seconds SQLite waits on a locked database before failing
+    pub const BUSY_TIMEOUT_SECS: u64 = 10;
+
+    /// Folder (next to the database file) that automatic backups are written to
+    pub const BACKUP_DIR_NAME: &str = "backups";
+
+    /// Number of most recent backups kept in a backup folder
+    pub const BACKUP_KEEP_COUNT: usize = 5;
+}
+
+/// Pagination limits applied to paginated commands
+pub mod pagination {
+    /// Smallest page size returned to the UI
+    pub const MIN_PAGE_SIZE: u32 = 1;
+
+    /// Largest page size returned to the UI
+    pub const MAX_PAGE_SIZE: u32 = 500;
+
+    /// Number of files returned by the recent files lists when no limit is given
+    pub const DEFAULT_RECENT_LIMIT: u32 = 20;
 }
 
 /// Regex group indices
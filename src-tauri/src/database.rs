@@ -1,7 +1,10 @@
+use crate::api_types::{LibraryStats, PaginationRequest, ShopFileSummary, SortKey, TagUsage};
+use crate::tag_query::TagExpr;
+use crate::{AppError, AppResult};
 use rusqlite::{Connection, Result};
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
 pub struct FileRecord {
     pub id: Option<i64>,
     pub file_path: String,
@@ -17,6 +20,34 @@ pub struct FileRecord {
     pub description: Option<String>,
     pub thumbnail_url: Option<String>,
     pub product_url: Option<String>,
+    #[serde(default)]
+    pub deleted_at: Option<String>,
+    #[serde(default)]
+    pub file_hash: Option<String>,
+    /// アーカイブ内のファイル名の文字コード（例: "Shift_JIS", "UTF-8"）
+    #[serde(default)]
+    pub encoding_info: Option<String>,
+    /// 取り込み元のZIPの場所（file_path は展開先フォルダ）
+    #[serde(default)]
+    pub archive_path: Option<String>,
+    /// BOOTHの商品カテゴリ（最下層の名前、例: "3D衣装"）
+    #[serde(default)]
+    pub booth_category: Option<String>,
+    /// BOOTHで R-18 指定されている商品
+    #[serde(default)]
+    pub is_adult: bool,
+    /// 最後にフォルダを開いた日時（未オープンは None）
+    #[serde(default)]
+    pub opened_at: Option<String>,
+    /// お気に入り（スター付き）
+    #[serde(default)]
+    pub is_favorite: bool,
+    /// ユーザーが書き込んだメモ（例: "Unity 2022 が必要"）
+    #[serde(default)]
+    pub user_note: Option<String>,
+    /// 任意の追加情報（JSON、例: {"unity_version": "2022"}）
+    #[serde(default)]
+    pub metadata: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -26,6 +57,25 @@ pub struct Tag {
     pub usage_count: i32,
     pub created_at: String,
     pub updated_at: String,
+    #[serde(default = "default_tag_color")]
+    pub color: String,
+    #[serde(default)]
+    pub category: Option<String>,
+    #[serde(default)]
+    pub parent_tag_id: Option<i64>,
+}
+
+fn default_tag_color() -> String {
+    crate::config::tags::DEFAULT_TAG_COLOR.to_string()
+}
+
+/// タグの表示設定の変更内容（None の項目は変更しない）
+#[derive(Debug, Clone, Default)]
+pub struct TagUpdateFields {
+    pub color: Option<String>,
+    /// 空文字列を指定するとカテゴリを解除する
+    pub category: Option<String>,
+    pub parent_tag_id: Option<i64>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -34,6 +84,14 @@ pub struct FileWithTags {
     pub tags: Vec<Tag>,
 }
 
+// hide_adult が true の場合に R-18 のファイルを取り除く
+pub fn exclude_adult_files(files: Vec<FileWithTags>, hide_adult: bool) -> Vec<FileWithTags> {
+    if !hide_adult {
+        return files;
+    }
+    files.into_iter().filter(|f| !f.file.is_adult).collect()
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct FileUpdateFields {
     pub product_id: Option<String>,
@@ -52,18 +110,236 @@ pub struct BatchStatistics {
     pub errors: usize,
 }
 
+// データベース修復の結果（種類ごとの件数と整合性チェックの結果）
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct RepairReport {
+    /// 存在しないファイル・タグを指していたため削除した関連付けの数
+    pub dangling_file_tags_removed: usize,
+    /// usage_count を補正したタグ数
+    pub usage_counts_corrected: usize,
+    /// どのファイルにも付いていなかったため削除したタグ数
+    pub unused_tags_removed: usize,
+    /// PRAGMA integrity_check で問題が見つからなかった
+    pub integrity_ok: bool,
+    /// integrity_check が報告した問題（正常なら空）
+    pub integrity_errors: Vec<String>,
+}
+
+// 取り込みの履歴（取り消し用）。展開しなかった場合 output_path は None
+// file_path は登録中のファイルの現在のパス（取り込み後に移動されていれば移動先）
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct ImportRecord {
+    pub file_id: i64,
+    pub file_path: String,
+    pub output_path: Option<String>,
+    /// 展開先フォルダを取り込みで新規作成した（既存のフォルダに展開した場合は false）
+    pub output_created: bool,
+}
+
+// ショップ（author_name）ごとの購入数・合計金額・合計サイズ
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ShopSummary {
+    pub shop_name: String,
+    pub item_count: i64,
+    pub total_price: i64,
+    pub total_size: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Collection {
+    pub id: Option<i64>,
+    pub name: String,
+    pub file_count: i64,
+    pub created_at: String,
+}
+
+// 詳細検索の条件（未指定の項目では絞り込まない）
+// 日時は SQLite の julianday() で解釈できる形式（コマンド側で RFC3339 から変換済み）
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct AdvancedSearchFilter {
+    pub query: Option<String>,
+    pub tag_names: Vec<String>,
+    pub match_all_tags: bool,
+    /// 下限（バイト、この値を含む）
+    pub min_size: Option<i64>,
+    /// 上限（バイト、この値を含む）
+    pub max_size: Option<i64>,
+    /// この日時以降に登録（含む）
+    pub created_after: Option<String>,
+    /// この日時より前に登録（含まない）
+    pub created_before: Option<String>,
+    /// R-18 の商品を除外する
+    pub hide_adult: bool,
+}
+
+fn sort_direction(order: Option<&str>) -> &'static str {
+    match order.map(str::to_lowercase).as_deref() {
+        Some("asc") => "ASC",
+        _ => "DESC",
+    }
+}
+
+// 並び替えのキーを「列 方向」の並びにする（許可されていない列名は sort_by の検証エラー）
+// 列名が空のキー（sort_by 未指定）は既定の並び順に任せる
+fn sort_terms(
+    keys: &[SortKey],
+    column_for: fn(&str) -> Option<&'static str>,
+) -> AppResult<Vec<String>> {
+    keys.iter()
+        .filter(|key| !key.column.is_empty())
+        .map(|key| {
+            let column = column_for(&key.column).ok_or_else(|| {
+                AppError::validation(
+                    "sort_by",
+                    format!("Unsupported sort column: {}", key.column),
+                )
+            })?;
+            Ok(format!("{column} {}", sort_direction(key.order.as_deref())))
+        })
+        .collect()
+}
+
+fn file_sort_column(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "created_at" | "createdAt" => "f.created_at",
+        "file_name" | "fileName" => "f.file_name",
+        "file_size" | "fileSize" => "f.file_size",
+        "modified_time" | "modifiedTime" => "f.modified_time",
+        "updated_at" | "updatedAt" => "f.updated_at",
+        "product_name" | "productName" => "f.product_name",
+        "author_name" | "authorName" => "f.author_name",
+        _ => return None,
+    })
+}
+
+// ページ取得時の並び順。列の指定がなければ作成日時順として扱う
+fn file_order_clause(keys: &[SortKey]) -> AppResult<String> {
+    let terms = sort_terms(keys, file_sort_column)?;
+    if terms.is_empty() {
+        let order = keys.first().and_then(|key| key.order.as_deref());
+        return Ok(format!("f.created_at {}", sort_direction(order)));
+    }
+    Ok(terms.join(", "))
+}
+
+fn tag_sort_column(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "name" => "name",
+        "created_at" | "createdAt" => "created_at",
+        "usage_count" | "usageCount" => "usage_count",
+        _ => return None,
+    })
+}
+
+// タグ一覧の ORDER BY 句（既定は get_all_tags と同じく使用回数の多い順）
+fn tag_order_clause(keys: &[SortKey]) -> AppResult<String> {
+    let terms = sort_terms(keys, tag_sort_column)?;
+    if terms.is_empty() {
+        return Ok("usage_count DESC, name ASC".to_string());
+    }
+    Ok(format!("{}, name ASC", terms.join(", ")))
+}
+
+// LIKE のワイルドカードをエスケープ（ESCAPE '\' と組み合わせて使用）
+fn escape_like(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+// files テーブル（別名 f）の SELECT 列（file_from_row と順序を合わせること）
+const FILE_COLUMNS: &str = "f.id, f.file_path, f.file_name, f.file_size, f.modified_time,
+    f.created_at, f.updated_at, f.product_id, f.product_name,
+    f.author_name, f.price, f.description, f.thumbnail_url, f.product_url, f.deleted_at,
+    f.file_hash, f.encoding_info, f.archive_path, f.booth_category, f.is_adult,
+    f.opened_at, f.is_favorite, f.user_note, f.metadata";
+
+// tags テーブル（別名 t）の SELECT 列（tag_from_row と順序を合わせること）
+const TAG_COLUMNS: &str =
+    "t.id, t.name, t.usage_count, t.created_at, t.updated_at, t.color, t.category, t.parent_tag_id";
+
+fn tag_from_row(row: &rusqlite::Row) -> Result<Tag> {
+    Ok(Tag {
+        id: Some(row.get(0)?),
+        name: row.get(1)?,
+        usage_count: row.get(2)?,
+        created_at: row.get(3)?,
+        updated_at: row.get(4)?,
+        color: row.get(5)?,
+        category: row.get(6)?,
+        parent_tag_id: row.get(7)?,
+    })
+}
+
+fn file_from_row(row: &rusqlite::Row) -> Result<FileRecord> {
+    Ok(FileRecord {
+        id: Some(row.get(0)?),
+        file_path: row.get(1)?,
+        file_name: row.get(2)?,
+        file_size: row.get(3)?,
+        modified_time: row.get(4)?,
+        created_at: row.get(5)?,
+        updated_at: row.get(6)?,
+        product_id: row.get(7)?,
+        product_name: row.get(8)?,
+        author_name: row.get(9)?,
+        price: row.get(10)?,
+        description: row.get(11)?,
+        thumbnail_url: row.get(12)?,
+        product_url: row.get(13)?,
+        deleted_at: row.get(14)?,
+        file_hash: row.get(15)?,
+        encoding_info: row.get(16)?,
+        archive_path: row.get(17)?,
+        booth_category: row.get(18)?,
+        is_adult: row.get(19)?,
+        opened_at: row.get(20)?,
+        is_favorite: row.get(21)?,
+        user_note: row.get(22)?,
+        metadata: row.get(23)?,
+    })
+}
+
 pub struct Database {
     conn: Connection,
 }
 
 impl Database {
     pub fn new(db_path: &str) -> Result<Self> {
-        let conn = Connection::open(db_path)?;
-        let db = Database { conn };
+        let existed = crate::db_backup::is_existing_database(db_path);
+        let db = Self::open(db_path)?;
+        // スキーマの古い既存のデータベースだけ、作成・移行前にバックアップしておく
+        let outdated = existed && db.schema_version()? < crate::config::database::SCHEMA_VERSION;
+        crate::db_backup::backup_before_migration(&db, db_path, outdated);
         db.initialize_schema()?;
         Ok(db)
     }
 
+    /// 記録済みのスキーマのバージョン（PRAGMA user_version、未記録なら 0）
+    pub fn schema_version(&self) -> Result<i32> {
+        self.conn
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+    }
+
+    // スキーマ初期化を行わずに接続する（プールの2本目以降の接続用）
+    pub fn open(db_path: &str) -> Result<Self> {
+        let conn = Connection::open(db_path)?;
+        // ON DELETE CASCADE を有効にする（SQLiteは接続ごとに既定で無効）
+        // INSERT OR REPLACE による削除でも FTS 同期トリガーを発火させる
+        // WAL で書き込み中も読み取りを可能にし、ロック競合時は待機する
+        // WAL では synchronous = NORMAL でもコミット済みのデータは壊れない（電源断時に直近のみ失われうる）
+        conn.execute_batch(
+            "PRAGMA foreign_keys = ON; PRAGMA recursive_triggers = ON;
+             PRAGMA journal_mode = WAL; PRAGMA synchronous = NORMAL;",
+        )?;
+        conn.busy_timeout(std::time::Duration::from_secs(
+            crate::config::database::BUSY_TIMEOUT_SECS,
+        ))?;
+        Ok(Database { conn })
+    }
+
     fn initialize_schema(&self) -> Result<()> {
         self.conn.execute(
             "CREATE TABLE IF NOT EXISTS files (
@@ -80,19 +356,37 @@ impl Database {
                 price INTEGER,
                 description TEXT,
                 thumbnail_url TEXT,
-                product_url TEXT
+                product_url TEXT,
+                deleted_at DATETIME,
+                trash_path TEXT,
+                file_hash TEXT,
+                encoding_info TEXT,
+                archive_path TEXT,
+                booth_category TEXT,
+                is_adult INTEGER NOT NULL DEFAULT 0,
+                opened_at DATETIME,
+                is_favorite INTEGER NOT NULL DEFAULT 0,
+                favorited_at DATETIME,
+                user_note TEXT,
+                metadata TEXT
             )",
             [],
         )?;
 
         self.conn.execute(
-            "CREATE TABLE IF NOT EXISTS tags (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                name TEXT UNIQUE NOT NULL,
-                usage_count INTEGER DEFAULT 0,
-                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-                updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
-            )",
+            &format!(
+                "CREATE TABLE IF NOT EXISTS tags (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    name TEXT UNIQUE NOT NULL,
+                    usage_count INTEGER DEFAULT 0,
+                    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    color TEXT NOT NULL DEFAULT '{}',
+                    category TEXT,
+                    parent_tag_id INTEGER REFERENCES tags (id) ON DELETE SET NULL
+                )",
+                crate::config::tags::DEFAULT_TAG_COLOR
+            ),
             [],
         )?;
 
@@ -109,6 +403,166 @@ impl Database {
             [],
         )?;
 
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS collections (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name TEXT UNIQUE NOT NULL,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+            )",
+            [],
+        )?;
+
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS collection_files (
+                collection_id INTEGER NOT NULL,
+                file_id INTEGER NOT NULL,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                PRIMARY KEY (collection_id, file_id),
+                FOREIGN KEY (collection_id) REFERENCES collections (id) ON DELETE CASCADE,
+                FOREIGN KEY (file_id) REFERENCES files (id) ON DELETE CASCADE
+            )",
+            [],
+        )?;
+
+        // サムネイルのURLとダウンロード済みの画像の対応
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS thumbnail_cache (
+                url TEXT PRIMARY KEY,
+                local_path TEXT NOT NULL,
+                small_path TEXT,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+            )",
+            [],
+        )?;
+
+        // 取り込みの履歴（ファイルを削除すると履歴も消える）
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS import_history (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                file_id INTEGER NOT NULL UNIQUE,
+                output_path TEXT,
+                output_created INTEGER NOT NULL DEFAULT 0,
+                imported_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                FOREIGN KEY (file_id) REFERENCES files (id) ON DELETE CASCADE
+            )",
+            [],
+        )?;
+
+        self.migrate_schema()?;
+        self.initialize_fts()?;
+        // 移行が済んだバージョンを記録し、次回以降の起動ではバックアップしない
+        self.conn.execute_batch(&format!(
+            "PRAGMA user_version = {}",
+            crate::config::database::SCHEMA_VERSION
+        ))?;
+
+        Ok(())
+    }
+
+    // 全文検索用の FTS5 テーブル（files を外部コンテンツとして参照）と同期トリガー
+    fn initialize_fts(&self) -> Result<()> {
+        let exists: bool = self.conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'files_fts')",
+            [],
+            |row| row.get(0),
+        )?;
+
+        self.conn.execute_batch(
+            "CREATE VIRTUAL TABLE IF NOT EXISTS files_fts USING fts5(
+                file_name, product_name, author_name, description,
+                content = 'files', content_rowid = 'id', tokenize = 'trigram'
+            );
+
+            CREATE TRIGGER IF NOT EXISTS files_fts_insert AFTER INSERT ON files BEGIN
+                INSERT INTO files_fts (rowid, file_name, product_name, author_name, description)
+                VALUES (new.id, new.file_name, new.product_name, new.author_name, new.description);
+            END;
+
+            CREATE TRIGGER IF NOT EXISTS files_fts_delete AFTER DELETE ON files BEGIN
+                INSERT INTO files_fts (files_fts, rowid, file_name, product_name, author_name, description)
+                VALUES ('delete', old.id, old.file_name, old.product_name, old.author_name, old.description);
+            END;
+
+            CREATE TRIGGER IF NOT EXISTS files_fts_update AFTER UPDATE ON files BEGIN
+                INSERT INTO files_fts (files_fts, rowid, file_name, product_name, author_name, description)
+                VALUES ('delete', old.id, old.file_name, old.product_name, old.author_name, old.description);
+                INSERT INTO files_fts (rowid, file_name, product_name, author_name, description)
+                VALUES (new.id, new.file_name, new.product_name, new.author_name, new.description);
+            END;",
+        )?;
+
+        // 既存データベースでは作成直後に索引を構築する
+        if !exists {
+            self.conn
+                .execute("INSERT INTO files_fts (files_fts) VALUES ('rebuild')", [])?;
+        }
+        Ok(())
+    }
+
+    // 既存データベースに不足している列を追加
+    fn migrate_schema(&self) -> Result<()> {
+        self.add_column_if_missing("files", "deleted_at", "DATETIME")?;
+        self.add_column_if_missing("files", "trash_path", "TEXT")?;
+        self.add_column_if_missing("files", "file_hash", "TEXT")?;
+        self.add_column_if_missing("files", "encoding_info", "TEXT")?;
+        self.add_column_if_missing("files", "archive_path", "TEXT")?;
+        self.add_column_if_missing("files", "booth_category", "TEXT")?;
+        self.add_column_if_missing("files", "is_adult", "INTEGER NOT NULL DEFAULT 0")?;
+        self.add_column_if_missing("files", "opened_at", "DATETIME")?;
+        self.add_column_if_missing("files", "is_favorite", "INTEGER NOT NULL DEFAULT 0")?;
+        self.add_column_if_missing("files", "favorited_at", "DATETIME")?;
+        self.add_column_if_missing("files", "user_note", "TEXT")?;
+        self.add_column_if_missing("files", "metadata", "TEXT")?;
+        self.add_column_if_missing(
+            "tags",
+            "color",
+            &format!(
+                "TEXT NOT NULL DEFAULT '{}'",
+                crate::config::tags::DEFAULT_TAG_COLOR
+            ),
+        )?;
+        self.add_column_if_missing("tags", "category", "TEXT")?;
+        self.add_column_if_missing("thumbnail_cache", "small_path", "TEXT")?;
+        self.add_column_if_missing(
+            "import_history",
+            "output_created",
+            "INTEGER NOT NULL DEFAULT 0",
+        )?;
+        self.add_column_if_missing(
+            "tags",
+            "parent_tag_id",
+            "INTEGER REFERENCES tags (id) ON DELETE SET NULL",
+        )?;
+        self.create_indexes()?;
+        Ok(())
+    }
+
+    // 絞り込み・並び替え・結合で使う列のインデックス
+    // tags.name と file_tags(file_id, ...) は UNIQUE 制約の自動インデックスを利用する
+    fn create_indexes(&self) -> Result<()> {
+        self.conn.execute_batch(
+            "CREATE INDEX IF NOT EXISTS idx_files_author_name ON files (author_name);
+             CREATE INDEX IF NOT EXISTS idx_files_created_at ON files (created_at);
+             CREATE INDEX IF NOT EXISTS idx_files_file_name ON files (file_name);
+             CREATE INDEX IF NOT EXISTS idx_files_file_hash ON files (file_hash);
+             CREATE INDEX IF NOT EXISTS idx_files_product_id ON files (product_id);
+             CREATE INDEX IF NOT EXISTS idx_file_tags_tag_id ON file_tags (tag_id);",
+        )
+    }
+
+    fn add_column_if_missing(&self, table: &str, column: &str, definition: &str) -> Result<()> {
+        let mut stmt = self.conn.prepare(&format!("PRAGMA table_info({table})"))?;
+        let exists = stmt
+            .query_map([], |row| row.get::<_, String>(1))?
+            .filter_map(|name| name.ok())
+            .any(|name| name == column);
+
+        if !exists {
+            self.conn.execute(
+                &format!("ALTER TABLE {table} ADD COLUMN {column} {definition}"),
+                [],
+            )?;
+        }
         Ok(())
     }
 
@@ -116,8 +570,9 @@ impl Database {
         let mut stmt = self.conn.prepare(
             "INSERT OR REPLACE INTO files 
              (file_path, file_name, file_size, modified_time, product_id, product_name, 
-              author_name, price, description, thumbnail_url, product_url)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+              author_name, price, description, thumbnail_url, product_url, file_hash,
+              encoding_info, archive_path, booth_category, is_adult)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16)",
         )?;
 
         stmt.execute(rusqlite::params![
@@ -132,37 +587,111 @@ impl Database {
             file.description,
             file.thumbnail_url,
             file.product_url,
+            file.file_hash,
+            file.encoding_info,
+            file.archive_path,
+            file.booth_category,
+            file.is_adult,
         ])?;
 
         Ok(self.conn.last_insert_rowid())
     }
 
-    pub fn get_all_files(&self) -> Result<Vec<FileRecord>> {
-        let mut stmt = self.conn.prepare(
-            "SELECT id, file_path, file_name, file_size, modified_time, 
-                    created_at, updated_at, product_id, product_name, 
-                    author_name, price, description, thumbnail_url, product_url
-             FROM files ORDER BY created_at DESC",
+    // バックアップからの復元用に作成・更新日時とゴミ箱の状態を保持したまま追加
+    pub fn import_file_record(&self, file: &FileRecord) -> Result<i64> {
+        self.conn.execute(
+            "INSERT INTO files
+             (file_path, file_name, file_size, modified_time, created_at, updated_at,
+              product_id, product_name, author_name, price, description,
+              thumbnail_url, product_url, file_hash, encoding_info, archive_path,
+              booth_category, is_adult, is_favorite, user_note, metadata, deleted_at)
+             VALUES (?1, ?2, ?3, ?4,
+                     COALESCE(NULLIF(?5, ''), CURRENT_TIMESTAMP),
+                     COALESCE(NULLIF(?6, ''), CURRENT_TIMESTAMP),
+                     ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21, ?22)",
+            rusqlite::params![
+                file.file_path,
+                file.file_name,
+                file.file_size,
+                file.modified_time,
+                file.created_at,
+                file.updated_at,
+                file.product_id,
+                file.product_name,
+                file.author_name,
+                file.price,
+                file.description,
+                file.thumbnail_url,
+                file.product_url,
+                file.file_hash,
+                file.encoding_info,
+                file.archive_path,
+                file.booth_category,
+                file.is_adult,
+                file.is_favorite,
+                file.user_note,
+                file.metadata,
+                file.deleted_at,
+            ],
         )?;
 
-        let file_iter = stmt.query_map([], |row| {
-            Ok(FileRecord {
-                id: Some(row.get(0)?),
-                file_path: row.get(1)?,
-                file_name: row.get(2)?,
-                file_size: row.get(3)?,
-                modified_time: row.get(4)?,
-                created_at: row.get(5)?,
-                updated_at: row.get(6)?,
-                product_id: row.get(7)?,
-                product_name: row.get(8)?,
-                author_name: row.get(9)?,
-                price: row.get(10)?,
-                description: row.get(11)?,
-                thumbnail_url: row.get(12)?,
-                product_url: row.get(13)?,
-            })
-        })?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    pub fn get_all_files(&self) -> Result<Vec<FileRecord>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {FILE_COLUMNS} FROM files f
+             WHERE f.deleted_at IS NULL
+             ORDER BY f.created_at DESC"
+        ))?;
+
+        let file_iter = stmt.query_map([], file_from_row)?;
+
+        let mut files = Vec::new();
+        for file in file_iter {
+            files.push(file?);
+        }
+        Ok(files)
+    }
+
+    // エクスポート用にID順でファイルを取得
+    // ライブラリ全体のバックアップ用（ゴミ箱内のファイルを含む、ID順）
+    pub fn get_all_files_including_deleted(&self) -> Result<Vec<FileRecord>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {FILE_COLUMNS} FROM files f ORDER BY f.id ASC"
+        ))?;
+
+        let files = stmt
+            .query_map([], file_from_row)?
+            .collect::<Result<Vec<_>>>()?;
+        Ok(files)
+    }
+
+    pub fn get_all_files_ordered_by_id(&self) -> Result<Vec<FileRecord>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {FILE_COLUMNS} FROM files f
+             WHERE f.deleted_at IS NULL
+             ORDER BY f.id ASC"
+        ))?;
+
+        let file_iter = stmt.query_map([], file_from_row)?;
+
+        let mut files = Vec::new();
+        for file in file_iter {
+            files.push(file?);
+        }
+        Ok(files)
+    }
+
+    // ゴミ箱内（論理削除済み）のファイルを取得
+    pub fn get_deleted_files(&self) -> Result<Vec<FileRecord>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {FILE_COLUMNS} FROM files f
+             WHERE f.deleted_at IS NOT NULL
+             ORDER BY f.deleted_at DESC"
+        ))?;
+
+        let file_iter = stmt.query_map([], file_from_row)?;
 
         let mut files = Vec::new();
         for file in file_iter {
@@ -171,6 +700,88 @@ impl Database {
         Ok(files)
     }
 
+    // 論理削除済みを含めてIDでファイルを取得
+    pub fn get_file_by_id(&self, id: i64) -> Result<Option<FileRecord>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {FILE_COLUMNS} FROM files f WHERE f.id = ?1"
+        ))?;
+
+        let mut rows = stmt.query_map([id], file_from_row)?;
+        rows.next().transpose()
+    }
+
+    // IDでファイルとそのタグを取得（論理削除済みを含む）
+    pub fn get_file_with_tags(&self, id: i64) -> Result<Option<FileWithTags>> {
+        let Some(file) = self.get_file_by_id(id)? else {
+            return Ok(None);
+        };
+        let tags = self.get_tags_for_file(id)?;
+        Ok(Some(FileWithTags { file, tags }))
+    }
+
+    // 論理削除済みを含めてパスでファイルを取得
+    pub fn get_file_by_path(&self, file_path: &str) -> Result<Option<FileRecord>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {FILE_COLUMNS} FROM files f WHERE f.file_path = ?1"
+        ))?;
+
+        let mut rows = stmt.query_map([file_path], file_from_row)?;
+        rows.next().transpose()
+    }
+
+    // アーカイブのハッシュで取り込み済みのファイルを取得（ゴミ箱内は除く）
+    pub fn get_file_by_hash(&self, file_hash: &str) -> Result<Option<FileRecord>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {FILE_COLUMNS} FROM files f
+             WHERE f.file_hash = ?1 AND f.deleted_at IS NULL
+             ORDER BY f.id LIMIT 1"
+        ))?;
+
+        let mut rows = stmt.query_map([file_hash], file_from_row)?;
+        rows.next().transpose()
+    }
+
+    // IDを維持したままレコード全体を上書き（ゴミ箱内の場合は復元される）
+    pub fn overwrite_file(&self, id: i64, file: &FileRecord) -> Result<()> {
+        self.conn.execute(
+            "UPDATE files SET
+             file_path = ?1, file_name = ?2, file_size = ?3, modified_time = ?4,
+             product_id = ?5, product_name = ?6, author_name = ?7, price = ?8,
+             description = ?9, thumbnail_url = ?10, product_url = ?11, file_hash = ?12,
+             encoding_info = ?13, archive_path = ?14, booth_category = ?15, is_adult = ?16,
+             deleted_at = NULL, updated_at = CURRENT_TIMESTAMP
+             WHERE id = ?17",
+            rusqlite::params![
+                file.file_path,
+                file.file_name,
+                file.file_size,
+                file.modified_time,
+                file.product_id,
+                file.product_name,
+                file.author_name,
+                file.price,
+                file.description,
+                file.thumbnail_url,
+                file.product_url,
+                file.file_hash,
+                file.encoding_info,
+                file.archive_path,
+                file.booth_category,
+                file.is_adult,
+                id
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn set_file_hash(&self, id: i64, file_hash: &str) -> Result<()> {
+        self.conn.execute(
+            "UPDATE files SET file_hash = ?1, updated_at = CURRENT_TIMESTAMP WHERE id = ?2",
+            rusqlite::params![file_hash, id],
+        )?;
+        Ok(())
+    }
+
     pub fn update_file(&self, id: i64, updates: FileUpdateFields) -> Result<()> {
         self.conn.execute(
             "UPDATE files SET 
@@ -192,123 +803,2606 @@ impl Database {
         Ok(())
     }
 
+    // ディスク上のサイズ・更新日時を反映。対象が存在しない場合は false
+    pub fn update_file_stats(&self, id: i64, file_size: i64, modified_time: i64) -> Result<bool> {
+        let affected = self.conn.execute(
+            "UPDATE files SET file_size = ?1, modified_time = ?2, updated_at = CURRENT_TIMESTAMP
+             WHERE id = ?3",
+            rusqlite::params![file_size, modified_time, id],
+        )?;
+        Ok(affected > 0)
+    }
+
+    // 管理場所（file_path）を変更。対象が存在しない・ゴミ箱内の場合は false
+    pub fn update_file_path(&self, id: i64, file_path: &str) -> Result<bool> {
+        let affected = self.conn.execute(
+            "UPDATE files SET file_path = ?1, updated_at = CURRENT_TIMESTAMP
+             WHERE id = ?2 AND deleted_at IS NULL",
+            rusqlite::params![file_path, id],
+        )?;
+        Ok(affected > 0)
+    }
+
     pub fn delete_file(&self, id: i64) -> Result<()> {
         self.conn.execute("DELETE FROM files WHERE id = ?1", [id])?;
         Ok(())
     }
 
-    pub fn add_tag(&self, name: &str) -> Result<i64> {
+    // 論理削除（ゴミ箱へ移動）。対象が存在しない場合は false
+    pub fn soft_delete_file(&self, id: i64) -> Result<bool> {
+        let affected = self.conn.execute(
+            "UPDATE files SET deleted_at = CURRENT_TIMESTAMP
+             WHERE id = ?1 AND deleted_at IS NULL",
+            [id],
+        )?;
+        Ok(affected > 0)
+    }
+
+    // 物理ファイル・フォルダを trash_path へ移動済みとして論理削除する。対象が存在しない場合は false
+    pub fn soft_delete_file_to_trash(&self, id: i64, trash_path: &str) -> Result<bool> {
+        let affected = self.conn.execute(
+            "UPDATE files SET deleted_at = CURRENT_TIMESTAMP, trash_path = ?1
+             WHERE id = ?2 AND deleted_at IS NULL",
+            rusqlite::params![trash_path, id],
+        )?;
+        Ok(affected > 0)
+    }
+
+    // ゴミ箱へ移動した物理ファイル・フォルダの場所（移動していない場合は None）
+    pub fn get_trash_path(&self, id: i64) -> Result<Option<String>> {
         let mut stmt = self
             .conn
-            .prepare("INSERT OR IGNORE INTO tags (name) VALUES (?1)")?;
-        stmt.execute([name])?;
-
-        let mut stmt = self.conn.prepare("SELECT id FROM tags WHERE name = ?1")?;
-        let tag_id: i64 = stmt.query_row([name], |row| row.get(0))?;
+            .prepare("SELECT trash_path FROM files WHERE id = ?1")?;
+        let mut rows = stmt.query_map([id], |row| row.get::<_, Option<String>>(0))?;
+        Ok(rows.next().transpose()?.flatten())
+    }
 
-        Ok(tag_id)
+    // フォルダを開いた日時を記録。対象が存在しない・ゴミ箱内の場合は false
+    // 続けて開いた順序を区別できるようミリ秒まで記録する
+    pub fn mark_file_opened(&self, id: i64) -> Result<bool> {
+        let affected = self.conn.execute(
+            "UPDATE files SET opened_at = strftime('%Y-%m-%d %H:%M:%f', 'now')
+             WHERE id = ?1 AND deleted_at IS NULL",
+            [id],
+        )?;
+        Ok(affected > 0)
     }
 
-    pub fn get_all_tags(&self) -> Result<Vec<Tag>> {
-        let mut stmt = self.conn.prepare(
-            "SELECT id, name, usage_count, created_at, updated_at 
-             FROM tags ORDER BY usage_count DESC, name ASC",
+    // お気に入りを設定・解除。対象が存在しない・ゴミ箱内の場合は false
+    // 登録日時（favorited_at）は並び替え用で、登録済みのファイルを再度登録しても変わらない
+    pub fn set_favorite(&self, id: i64, value: bool) -> Result<bool> {
+        let affected = self.conn.execute(
+            "UPDATE files SET
+                 is_favorite = ?1,
+                 favorited_at = CASE
+                     WHEN NOT ?1 THEN NULL
+                     WHEN is_favorite THEN favorited_at
+                     ELSE strftime('%Y-%m-%d %H:%M:%f', 'now')
+                 END,
+                 updated_at = CURRENT_TIMESTAMP
+             WHERE id = ?2 AND deleted_at IS NULL",
+            rusqlite::params![value, id],
         )?;
+        Ok(affected > 0)
+    }
 
-        let tag_iter = stmt.query_map([], |row| {
-            Ok(Tag {
-                id: Some(row.get(0)?),
-                name: row.get(1)?,
-                usage_count: row.get(2)?,
-                created_at: row.get(3)?,
-                updated_at: row.get(4)?,
-            })
-        })?;
+    // お気に入りを切り替えて新しい状態を返す。対象が存在しない・ゴミ箱内の場合は None
+    pub fn toggle_favorite(&self, id: i64) -> Result<Option<bool>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT is_favorite FROM files WHERE id = ?1 AND deleted_at IS NULL")?;
+        let current: Option<bool> = stmt.query_map([id], |row| row.get(0))?.next().transpose()?;
 
-        let mut tags = Vec::new();
-        for tag in tag_iter {
-            tags.push(tag?);
+        match current {
+            Some(is_favorite) => {
+                self.set_favorite(id, !is_favorite)?;
+                Ok(Some(!is_favorite))
+            }
+            None => Ok(None),
         }
-        Ok(tags)
     }
 
-    pub fn add_file_tag(&self, file_id: i64, tag_id: i64) -> Result<()> {
-        self.conn.execute(
-            "INSERT OR IGNORE INTO file_tags (file_id, tag_id) VALUES (?1, ?2)",
-            [file_id, tag_id],
+    // メモを設定（None で削除）。対象が存在しない・ゴミ箱内の場合は false
+    pub fn set_user_note(&self, id: i64, note: Option<&str>) -> Result<bool> {
+        let affected = self.conn.execute(
+            "UPDATE files SET user_note = ?1, updated_at = CURRENT_TIMESTAMP
+             WHERE id = ?2 AND deleted_at IS NULL",
+            rusqlite::params![note, id],
         )?;
+        Ok(affected > 0)
+    }
 
-        // Update usage count
-        self.conn.execute(
-            "UPDATE tags SET usage_count = (
-                SELECT COUNT(*) FROM file_tags WHERE tag_id = ?1
-            ) WHERE id = ?1",
-            [tag_id],
+    // 追加情報（JSON）を設定（None で削除）。対象が存在しない・ゴミ箱内の場合は false
+    pub fn set_file_metadata(&self, id: i64, metadata: Option<&str>) -> Result<bool> {
+        let affected = self.conn.execute(
+            "UPDATE files SET metadata = ?1, updated_at = CURRENT_TIMESTAMP
+             WHERE id = ?2 AND deleted_at IS NULL",
+            rusqlite::params![metadata, id],
         )?;
-
-        Ok(())
+        Ok(affected > 0)
     }
 
-    pub fn get_files_with_tags(&self) -> Result<Vec<FileWithTags>> {
-        let files = self.get_all_files()?;
-        let mut files_with_tags = Vec::new();
+    // 追加情報（JSON）の json_path の値が value と一致するファイル
+    // 数値・真偽値も文字列として比較する（"2022" は 2022 にも一致する）
+    pub fn search_files_by_metadata(
+        &self,
+        json_path: &str,
+        value: &str,
+    ) -> Result<Vec<FileWithTags>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {FILE_COLUMNS} FROM files f
+             WHERE f.deleted_at IS NULL
+               AND json_valid(f.metadata)
+               AND CAST(json_extract(f.metadata, ?1) AS TEXT) = ?2
+             ORDER BY f.created_at DESC, f.id DESC"
+        ))?;
+
+        let files = stmt
+            .query_map(rusqlite::params![json_path, value], file_from_row)?
+            .collect::<Result<Vec<_>>>()?;
 
+        let mut files_with_tags = Vec::with_capacity(files.len());
         for file in files {
             let file_id = file.id.unwrap();
             let tags = self.get_tags_for_file(file_id)?;
             files_with_tags.push(FileWithTags { file, tags });
         }
-
         Ok(files_with_tags)
     }
 
-    pub fn get_tags_for_file(&self, file_id: i64) -> Result<Vec<Tag>> {
+    // パス指定版（open_folder から呼ばれる。登録されていないパスは false）
+    pub fn mark_file_opened_by_path(&self, file_path: &str) -> Result<bool> {
+        let affected = self.conn.execute(
+            "UPDATE files SET opened_at = strftime('%Y-%m-%d %H:%M:%f', 'now')
+             WHERE file_path = ?1 AND deleted_at IS NULL",
+            [file_path],
+        )?;
+        Ok(affected > 0)
+    }
+
+    // パスに指定した名前を含むファイルのIDとパス（表記の異なるパスを照合する候補）
+    pub fn find_file_paths_containing(&self, name: &str) -> Result<Vec<(i64, String)>> {
         let mut stmt = self.conn.prepare(
-            "SELECT t.id, t.name, t.usage_count, t.created_at, t.updated_at
-             FROM tags t
-             JOIN file_tags ft ON t.id = ft.tag_id
-             WHERE ft.file_id = ?1
-             ORDER BY t.name",
+            "SELECT id, file_path FROM files
+             WHERE deleted_at IS NULL AND file_path LIKE ?1 ESCAPE '\\'",
         )?;
+        let rows = stmt
+            .query_map([format!("%{}%", escape_like(name))], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })?
+            .collect::<Result<Vec<_>>>()?;
+        Ok(rows)
+    }
 
-        let tag_iter = stmt.query_map([file_id], |row| {
-            Ok(Tag {
-                id: Some(row.get(0)?),
-                name: row.get(1)?,
-                usage_count: row.get(2)?,
-                created_at: row.get(3)?,
-                updated_at: row.get(4)?,
-            })
-        })?;
+    // 指定したパス自身またはその配下に登録されている（ゴミ箱外の）ファイルがあるか
+    pub fn has_files_under(&self, path: &str) -> Result<bool> {
+        let path = path.trim_end_matches(['/', '\\']);
+        let escaped = escape_like(path);
+        self.conn.query_row(
+            "SELECT EXISTS(
+                SELECT 1 FROM files
+                WHERE deleted_at IS NULL
+                  AND (file_path = ?1 OR file_path LIKE ?2 ESCAPE '\\' OR file_path LIKE ?3 ESCAPE '\\')
+            )",
+            rusqlite::params![path, format!("{escaped}/%"), format!("{escaped}\\\\%")],
+            |row| row.get(0),
+        )
+    }
 
-        let mut tags = Vec::new();
-        for tag in tag_iter {
-            tags.push(tag?);
+    // ゴミ箱から復元。対象が存在しない場合は false
+    pub fn restore_file(&self, id: i64) -> Result<bool> {
+        let affected = self.conn.execute(
+            "UPDATE files SET deleted_at = NULL, trash_path = NULL, updated_at = CURRENT_TIMESTAMP
+             WHERE id = ?1 AND deleted_at IS NOT NULL",
+            [id],
+        )?;
+        Ok(affected > 0)
+    }
+
+    // 指定日数以上前に論理削除されたファイルを完全に削除し、削除したレコードとゴミ箱内の場所を返す
+    pub fn purge_deleted_files(
+        &self,
+        older_than_days: u32,
+    ) -> Result<Vec<(FileRecord, Option<String>)>> {
+        let cutoff = format!("-{older_than_days} days");
+
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {FILE_COLUMNS}, f.trash_path FROM files f
+             WHERE f.deleted_at IS NOT NULL AND f.deleted_at <= datetime('now', ?1)"
+        ))?;
+        let purged = stmt
+            .query_map([&cutoff], |row| Ok((file_from_row(row)?, row.get(24)?)))?
+            .collect::<Result<Vec<_>>>()?;
+
+        for (file, _) in &purged {
+            if let Some(id) = file.id {
+                self.conn
+                    .execute("DELETE FROM file_tags WHERE file_id = ?1", [id])?;
+                self.conn.execute("DELETE FROM files WHERE id = ?1", [id])?;
+            }
         }
-        Ok(tags)
+
+        self.recalculate_usage_counts()?;
+        Ok(purged)
     }
 
-    pub fn recalculate_usage_counts(&self) -> Result<()> {
-        self.conn.execute(
-            "UPDATE tags SET usage_count = (
-                SELECT COUNT(*) FROM file_tags WHERE tag_id = tags.id
-            )",
-            [],
-        )?;
-        Ok(())
+    pub fn add_tag(&self, name: &str) -> Result<i64> {
+        self.add_tag_with_color(name, crate::config::tags::DEFAULT_TAG_COLOR)
     }
 
-    pub fn get_file_count(&self) -> Result<usize> {
-        let count: i64 = self
+    // 色は新規作成時のみ設定する（既存タグの色は変更しない）
+    pub fn add_tag_with_color(&self, name: &str, color: &str) -> Result<i64> {
+        let mut stmt = self
             .conn
-            .query_row("SELECT COUNT(*) FROM files", [], |row| row.get(0))?;
-        Ok(count as usize)
+            .prepare("INSERT OR IGNORE INTO tags (name, color) VALUES (?1, ?2)")?;
+        stmt.execute([name, color])?;
+
+        let mut stmt = self.conn.prepare("SELECT id FROM tags WHERE name = ?1")?;
+        let tag_id: i64 = stmt.query_row([name], |row| row.get(0))?;
+
+        Ok(tag_id)
     }
 
-    pub fn get_tag_count(&self) -> Result<usize> {
-        let count: i64 = self
-            .conn
+    pub fn get_all_tags(&self) -> Result<Vec<Tag>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {TAG_COLUMNS}
+             FROM tags t ORDER BY t.usage_count DESC, t.name ASC"
+        ))?;
+
+        let tag_iter = stmt.query_map([], tag_from_row)?;
+
+        let mut tags = Vec::new();
+        for tag in tag_iter {
+            tags.push(tag?);
+        }
+        Ok(tags)
+    }
+
+    /// タグ一覧の1ページ分と全件数を取得する
+    /// 並び替えの列が許可されていない場合は sort_by の検証エラー
+    pub fn get_tags_page(&self, pagination: &PaginationRequest) -> AppResult<(Vec<Tag>, u32)> {
+        let order_clause = tag_order_clause(&pagination.sort_keys())?;
+        let total_count: u32 = self
+            .conn
+            .query_row("SELECT COUNT(*) FROM tags", [], |row| row.get(0))?;
+
+        let offset = pagination.page.saturating_sub(1) as u64 * pagination.page_size as u64;
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {TAG_COLUMNS}
+             FROM tags t
+             ORDER BY {order_clause}, t.id
+             LIMIT {} OFFSET {offset}",
+            pagination.page_size,
+        ))?;
+
+        let tags = stmt
+            .query_map([], tag_from_row)?
+            .collect::<Result<Vec<_>>>()?;
+        Ok((tags, total_count))
+    }
+
+    pub fn get_tag_by_id(&self, tag_id: i64) -> Result<Option<Tag>> {
+        let mut stmt = self
+            .conn
+            .prepare(&format!("SELECT {TAG_COLUMNS} FROM tags t WHERE t.id = ?1"))?;
+
+        let mut rows = stmt.query_map([tag_id], tag_from_row)?;
+        rows.next().transpose()
+    }
+
+    // parent_tag_id から親をたどった祖先タグのID（自身は含まない）
+    pub fn get_tag_ancestor_ids(&self, tag_id: i64) -> Result<Vec<i64>> {
+        let mut stmt = self.conn.prepare(
+            "WITH RECURSIVE ancestors (id, depth) AS (
+                 SELECT parent_tag_id, 1 FROM tags WHERE id = ?1 AND parent_tag_id IS NOT NULL
+                 UNION
+                 SELECT t.parent_tag_id, a.depth + 1 FROM tags t
+                 JOIN ancestors a ON t.id = a.id
+                 WHERE t.parent_tag_id IS NOT NULL
+             )
+             SELECT id FROM ancestors ORDER BY depth",
+        )?;
+        let ids = stmt
+            .query_map([tag_id], |row| row.get(0))?
+            .collect::<Result<Vec<_>>>()?;
+        Ok(ids)
+    }
+
+    // 指定された項目のみ更新し、更新された行数を返す（検証は呼び出し側で行う）
+    pub fn update_tag(&self, tag_id: i64, fields: &TagUpdateFields) -> Result<usize> {
+        let category = fields
+            .category
+            .as_deref()
+            .map(|category| (!category.trim().is_empty()).then(|| category.trim()));
+        self.conn.execute(
+            "UPDATE tags SET
+                 color = COALESCE(?1, color),
+                 category = CASE WHEN ?2 THEN ?3 ELSE category END,
+                 parent_tag_id = COALESCE(?4, parent_tag_id),
+                 updated_at = CURRENT_TIMESTAMP
+             WHERE id = ?5",
+            rusqlite::params![
+                fields.color,
+                category.is_some(),
+                category.flatten(),
+                fields.parent_tag_id,
+                tag_id
+            ],
+        )
+    }
+
+    pub fn add_file_tag(&self, file_id: i64, tag_id: i64) -> Result<()> {
+        self.conn.execute(
+            "INSERT OR IGNORE INTO file_tags (file_id, tag_id) VALUES (?1, ?2)",
+            [file_id, tag_id],
+        )?;
+
+        // Update usage count
+        self.conn.execute(
+            "UPDATE tags SET usage_count = (
+                SELECT COUNT(*) FROM file_tags ft
+                JOIN files f ON f.id = ft.file_id
+                WHERE ft.tag_id = ?1 AND f.deleted_at IS NULL
+            ) WHERE id = ?1",
+            [tag_id],
+        )?;
+
+        Ok(())
+    }
+
+    // 全ファイルとタグの関連付けを (file_id, tag_id) で取得
+    pub fn get_all_file_tag_links(&self) -> Result<Vec<(i64, i64)>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT file_id, tag_id FROM file_tags ORDER BY file_id, tag_id")?;
+
+        let links = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<Vec<_>>>()?;
+        Ok(links)
+    }
+
+    // ファイルに付いているタグをすべて外す（usage_count の再計算は呼び出し側で行う）
+    pub fn clear_file_tags(&self, file_id: i64) -> Result<()> {
+        self.conn
+            .execute("DELETE FROM file_tags WHERE file_id = ?1", [file_id])?;
+        Ok(())
+    }
+
+    pub fn get_files_with_tags(&self) -> Result<Vec<FileWithTags>> {
+        let files = self.get_all_files()?;
+        let mut files_with_tags = Vec::new();
+
+        for file in files {
+            let file_id = file.id.unwrap();
+            let tags = self.get_tags_for_file(file_id)?;
+            files_with_tags.push(FileWithTags { file, tags });
+        }
+
+        Ok(files_with_tags)
+    }
+
+    // 指定したタグをすべて持つファイルを取得（AND検索）
+    pub fn get_files_with_all_tags(&self, tag_names: &[String]) -> Result<Vec<FileWithTags>> {
+        let mut unique_names: Vec<&str> = tag_names.iter().map(|name| name.as_str()).collect();
+        unique_names.sort_unstable();
+        unique_names.dedup();
+
+        if unique_names.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let placeholders = (1..=unique_names.len())
+            .map(|i| format!("?{i}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let count_param = unique_names.len() + 1;
+
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {FILE_COLUMNS} FROM files f
+             JOIN file_tags ft ON ft.file_id = f.id
+             JOIN tags t ON t.id = ft.tag_id
+             WHERE f.deleted_at IS NULL AND t.name IN ({placeholders})
+             GROUP BY f.id
+             HAVING COUNT(DISTINCT t.name) = ?{count_param}
+             ORDER BY f.created_at DESC"
+        ))?;
+
+        let tag_count = unique_names.len() as i64;
+        let mut params: Vec<&dyn rusqlite::ToSql> = unique_names
+            .iter()
+            .map(|name| name as &dyn rusqlite::ToSql)
+            .collect();
+        params.push(&tag_count);
+
+        let files = stmt
+            .query_map(params.as_slice(), file_from_row)?
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut files_with_tags = Vec::new();
+        for file in files {
+            let file_id = file.id.unwrap();
+            let tags = self.get_tags_for_file(file_id)?;
+            files_with_tags.push(FileWithTags { file, tags });
+        }
+        Ok(files_with_tags)
+    }
+
+    // BOOTHの商品カテゴリで絞り込んだファイルを登録が新しい順に取得
+    // category が None の場合はカテゴリ未取得のファイルを返す
+    pub fn get_files_by_category(&self, category: Option<&str>) -> Result<Vec<FileWithTags>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {FILE_COLUMNS} FROM files f
+             WHERE f.deleted_at IS NULL AND f.booth_category IS ?1
+             ORDER BY f.created_at DESC, f.id DESC"
+        ))?;
+
+        let files = stmt
+            .query_map([category], file_from_row)?
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut files_with_tags = Vec::with_capacity(files.len());
+        for file in files {
+            let file_id = file.id.unwrap();
+            let tags = self.get_tags_for_file(file_id)?;
+            files_with_tags.push(FileWithTags { file, tags });
+        }
+        Ok(files_with_tags)
+    }
+
+    // お気に入りのファイルを登録が新しい順に取得（登録日時のない既存データは最後）
+    pub fn get_favorites(&self) -> Result<Vec<FileWithTags>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {FILE_COLUMNS} FROM files f
+             WHERE f.deleted_at IS NULL AND f.is_favorite = 1
+             ORDER BY f.favorited_at IS NULL, f.favorited_at DESC, f.id DESC"
+        ))?;
+
+        let files = stmt
+            .query_map([], file_from_row)?
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut files_with_tags = Vec::with_capacity(files.len());
+        for file in files {
+            let file_id = file.id.unwrap();
+            let tags = self.get_tags_for_file(file_id)?;
+            files_with_tags.push(FileWithTags { file, tags });
+        }
+        Ok(files_with_tags)
+    }
+
+    // 最近取り込んだ順に取得
+    pub fn get_recent_files(&self, limit: u32) -> Result<Vec<FileWithTags>> {
+        self.get_files_in_order("f.created_at DESC, f.id DESC", limit)
+    }
+
+    // 最近開いた順に取得（一度も開いていないファイルは最後）
+    pub fn get_recently_opened(&self, limit: u32) -> Result<Vec<FileWithTags>> {
+        self.get_files_in_order(
+            "f.opened_at IS NULL, f.opened_at DESC, f.created_at DESC, f.id DESC",
+            limit,
+        )
+    }
+
+    fn get_files_in_order(&self, order_by: &str, limit: u32) -> Result<Vec<FileWithTags>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {FILE_COLUMNS} FROM files f
+             WHERE f.deleted_at IS NULL
+             ORDER BY {order_by}
+             LIMIT ?1"
+        ))?;
+
+        let files = stmt
+            .query_map([limit], file_from_row)?
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut files_with_tags = Vec::with_capacity(files.len());
+        for file in files {
+            let file_id = file.id.unwrap();
+            let tags = self.get_tags_for_file(file_id)?;
+            files_with_tags.push(FileWithTags { file, tags });
+        }
+        Ok(files_with_tags)
+    }
+
+    // 指定したファイルと共通するタグの多い順に他のファイルを取得（同数なら最近取り込んだものが先）
+    // 共通するタグが1つもないファイル・ゴミ箱内のファイルは含めない
+    pub fn get_related_files(&self, file_id: i64, limit: u32) -> Result<Vec<FileWithTags>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {FILE_COLUMNS} FROM files f
+             JOIN (
+                 SELECT other.file_id, COUNT(*) AS shared
+                 FROM file_tags mine
+                 JOIN file_tags other
+                   ON other.tag_id = mine.tag_id AND other.file_id != mine.file_id
+                 WHERE mine.file_id = ?1
+                 GROUP BY other.file_id
+             ) related ON related.file_id = f.id
+             WHERE f.deleted_at IS NULL
+             ORDER BY related.shared DESC, f.created_at DESC, f.id DESC
+             LIMIT ?2"
+        ))?;
+
+        let files = stmt
+            .query_map(rusqlite::params![file_id, limit], file_from_row)?
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut files_with_tags = Vec::with_capacity(files.len());
+        for file in files {
+            let file_id = file.id.unwrap();
+            let tags = self.get_tags_for_file(file_id)?;
+            files_with_tags.push(FileWithTags { file, tags });
+        }
+        Ok(files_with_tags)
+    }
+
+    // 同じショップ、またはタグを共有するファイルに多く付いているタグのうち、
+    // 対象ファイルにまだ付いていないものを多い順に返す（usage_count は類似ファイルでの件数）
+    pub fn suggest_tags_for_file(&self, file_id: i64, limit: u32) -> Result<Vec<TagUsage>> {
+        let mut stmt = self.conn.prepare(
+            "WITH similar AS (
+                 SELECT f.id FROM files f
+                 JOIN files target ON target.id = ?1
+                 WHERE f.id != target.id AND f.deleted_at IS NULL
+                   AND ((NULLIF(TRIM(target.author_name), '') IS NOT NULL
+                         AND f.author_name = target.author_name)
+                        OR EXISTS (
+                            SELECT 1 FROM file_tags mine
+                            JOIN file_tags other ON other.tag_id = mine.tag_id
+                            WHERE mine.file_id = target.id AND other.file_id = f.id))
+             )
+             SELECT t.name, t.color, COUNT(*) AS frequency
+             FROM similar s
+             JOIN file_tags ft ON ft.file_id = s.id
+             JOIN tags t ON t.id = ft.tag_id
+             WHERE NOT EXISTS (
+                 SELECT 1 FROM file_tags own WHERE own.file_id = ?1 AND own.tag_id = t.id)
+             GROUP BY t.id
+             ORDER BY frequency DESC, t.name ASC
+             LIMIT ?2",
+        )?;
+        let suggestions = stmt
+            .query_map(rusqlite::params![file_id, limit], |row| {
+                Ok(TagUsage {
+                    name: row.get(0)?,
+                    color: row.get(1)?,
+                    usage_count: row.get(2)?,
+                })
+            })?
+            .collect::<Result<Vec<_>>>()?;
+        Ok(suggestions)
+    }
+
+    // BOOTHの商品URLが未設定（NULLまたは空文字）のファイルを登録順に取得
+    // APIの booth_url はこのスキーマでは product_url 列に保存されている
+    pub fn get_unlinked_files(&self) -> Result<Vec<FileWithTags>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {FILE_COLUMNS} FROM files f
+             WHERE f.deleted_at IS NULL
+               AND (f.product_url IS NULL OR TRIM(f.product_url) = '')
+             ORDER BY f.created_at ASC, f.id ASC"
+        ))?;
+
+        let files = stmt
+            .query_map([], file_from_row)?
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut files_with_tags = Vec::new();
+        for file in files {
+            let file_id = file.id.unwrap();
+            let tags = self.get_tags_for_file(file_id)?;
+            files_with_tags.push(FileWithTags { file, tags });
+        }
+        Ok(files_with_tags)
+    }
+
+    // 全文検索（関連度順）。fts_query は build_fts_query で生成したもの
+    pub fn search_files_fts(&self, fts_query: &str) -> Result<Vec<FileWithTags>> {
+        // 列の重み: file_name, product_name, author_name, description
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {FILE_COLUMNS} FROM files_fts
+             JOIN files f ON f.id = files_fts.rowid
+             WHERE files_fts MATCH ?1 AND f.deleted_at IS NULL
+             ORDER BY bm25(files_fts, 5.0, 10.0, 3.0, 1.0)"
+        ))?;
+
+        let files = stmt
+            .query_map([fts_query], file_from_row)?
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut files_with_tags = Vec::new();
+        for file in files {
+            let file_id = file.id.unwrap();
+            let tags = self.get_tags_for_file(file_id)?;
+            files_with_tags.push(FileWithTags { file, tags });
+        }
+        Ok(files_with_tags)
+    }
+
+    // テキスト・タグ・サイズ・登録日時の条件をすべて満たすファイルを新しい順に取得
+    // file_size が NULL の行はサイズ条件を指定した場合に除外される（0扱いしない）
+    pub fn search_files_advanced(
+        &self,
+        filter: &AdvancedSearchFilter,
+    ) -> Result<Vec<FileWithTags>> {
+        use rusqlite::types::Value;
+
+        let mut conditions = vec!["f.deleted_at IS NULL".to_string()];
+        let mut params: Vec<Value> = Vec::new();
+
+        if filter.hide_adult {
+            conditions.push("f.is_adult = 0".to_string());
+        }
+
+        if let Some(query) = filter.query.as_deref().filter(|q| !q.trim().is_empty()) {
+            params.push(Value::Text(format!("%{}%", escape_like(query))));
+            let n = params.len();
+            conditions.push(format!(
+                "(f.file_name LIKE ?{n} ESCAPE '\\' OR f.file_path LIKE ?{n} ESCAPE '\\'
+                  OR f.product_name LIKE ?{n} ESCAPE '\\' OR f.author_name LIKE ?{n} ESCAPE '\\')"
+            ));
+        }
+
+        if !filter.tag_names.is_empty() {
+            let tag_exists = |n: usize| {
+                format!(
+                    "EXISTS (SELECT 1 FROM file_tags ft JOIN tags t ON t.id = ft.tag_id
+                             WHERE ft.file_id = f.id AND t.name = ?{n})"
+                )
+            };
+            let mut tag_conditions = Vec::new();
+            for tag_name in &filter.tag_names {
+                params.push(Value::Text(tag_name.clone()));
+                tag_conditions.push(tag_exists(params.len()));
+            }
+            let joiner = if filter.match_all_tags {
+                " AND "
+            } else {
+                " OR "
+            };
+            conditions.push(format!("({})", tag_conditions.join(joiner)));
+        }
+
+        let bounds = [
+            (filter.min_size.map(Value::Integer), "f.file_size >= ?"),
+            (filter.max_size.map(Value::Integer), "f.file_size <= ?"),
+            (
+                filter.created_after.clone().map(Value::Text),
+                "julianday(f.created_at) >= julianday(?)",
+            ),
+            (
+                filter.created_before.clone().map(Value::Text),
+                "julianday(f.created_at) < julianday(?)",
+            ),
+        ];
+        for (value, predicate) in bounds {
+            if let Some(value) = value {
+                params.push(value);
+                conditions.push(predicate.replace('?', &format!("?{}", params.len())));
+            }
+        }
+
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {FILE_COLUMNS} FROM files f
+             WHERE {}
+             ORDER BY f.created_at DESC, f.id DESC",
+            conditions.join(" AND ")
+        ))?;
+
+        let files = stmt
+            .query_map(rusqlite::params_from_iter(params), file_from_row)?
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut files_with_tags = Vec::with_capacity(files.len());
+        for file in files {
+            let file_id = file.id.unwrap();
+            let tags = self.get_tags_for_file(file_id)?;
+            files_with_tags.push(FileWithTags { file, tags });
+        }
+        Ok(files_with_tags)
+    }
+
+    // タグの論理式に一致するファイルを新しい順に取得
+    pub fn search_files_by_tag_expr(&self, expr: &TagExpr) -> Result<Vec<FileWithTags>> {
+        let mut params = Vec::new();
+        let condition = expr.to_sql(&mut params);
+
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {FILE_COLUMNS} FROM files f
+             WHERE f.deleted_at IS NULL AND {condition}
+             ORDER BY f.created_at DESC, f.id DESC"
+        ))?;
+
+        let files = stmt
+            .query_map(rusqlite::params_from_iter(params), file_from_row)?
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut files_with_tags = Vec::with_capacity(files.len());
+        for file in files {
+            let file_id = file.id.unwrap();
+            let tags = self.get_tags_for_file(file_id)?;
+            files_with_tags.push(FileWithTags { file, tags });
+        }
+        Ok(files_with_tags)
+    }
+
+    // 1ページ分のファイルと、条件に一致する全件数を取得
+    pub fn get_files_page(
+        &self,
+        pagination: &PaginationRequest,
+        hide_adult: bool,
+    ) -> AppResult<(Vec<FileWithTags>, u32)> {
+        self.query_file_page("f.deleted_at IS NULL", &[], pagination, hide_adult)
+    }
+
+    // ファイル名・パス・商品名・ショップ名の部分一致検索（ページ単位）
+    pub fn search_files_page(
+        &self,
+        query: &str,
+        pagination: &PaginationRequest,
+        hide_adult: bool,
+    ) -> AppResult<(Vec<FileWithTags>, u32)> {
+        let pattern = format!("%{}%", escape_like(query));
+        self.query_file_page(
+            "f.deleted_at IS NULL AND (
+                f.file_name LIKE ?1 ESCAPE '\\' OR f.file_path LIKE ?1 ESCAPE '\\'
+                OR f.product_name LIKE ?1 ESCAPE '\\' OR f.author_name LIKE ?1 ESCAPE '\\'
+            )",
+            &[&pattern],
+            pagination,
+            hide_adult,
+        )
+    }
+
+    // 指定したタグのいずれかを持つファイルを取得（OR検索、ページ単位）
+    pub fn get_files_with_any_tags_page(
+        &self,
+        tag_names: &[String],
+        pagination: &PaginationRequest,
+        hide_adult: bool,
+    ) -> AppResult<(Vec<FileWithTags>, u32)> {
+        if tag_names.is_empty() {
+            return Ok((Vec::new(), 0));
+        }
+
+        let placeholders = (1..=tag_names.len())
+            .map(|i| format!("?{i}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let params: Vec<&dyn rusqlite::ToSql> = tag_names
+            .iter()
+            .map(|name| name as &dyn rusqlite::ToSql)
+            .collect();
+
+        self.query_file_page(
+            &format!(
+                "f.deleted_at IS NULL AND EXISTS (
+                    SELECT 1 FROM file_tags ft JOIN tags t ON t.id = ft.tag_id
+                    WHERE ft.file_id = f.id AND t.name IN ({placeholders})
+                )"
+            ),
+            &params,
+            pagination,
+            hide_adult,
+        )
+    }
+
+    // hide_adult の場合は R-18 のファイルを件数にも含めない
+    // 並び替えの列が許可されていない場合は sort_by の検証エラー
+    fn query_file_page(
+        &self,
+        condition: &str,
+        params: &[&dyn rusqlite::ToSql],
+        pagination: &PaginationRequest,
+        hide_adult: bool,
+    ) -> AppResult<(Vec<FileWithTags>, u32)> {
+        let order_clause = file_order_clause(&pagination.sort_keys())?;
+        let condition = if hide_adult {
+            format!("({condition}) AND f.is_adult = 0")
+        } else {
+            condition.to_string()
+        };
+        let total_count: u32 = self.conn.query_row(
+            &format!("SELECT COUNT(*) FROM files f WHERE {condition}"),
+            params,
+            |row| row.get(0),
+        )?;
+
+        let offset = pagination.page.saturating_sub(1) as u64 * pagination.page_size as u64;
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {FILE_COLUMNS} FROM files f
+             WHERE {condition}
+             ORDER BY {order_clause}, f.id
+             LIMIT {} OFFSET {offset}",
+            pagination.page_size,
+        ))?;
+
+        let files = stmt
+            .query_map(params, file_from_row)?
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut files_with_tags = Vec::with_capacity(files.len());
+        for file in files {
+            let file_id = file.id.unwrap();
+            let tags = self.get_tags_for_file(file_id)?;
+            files_with_tags.push(FileWithTags { file, tags });
+        }
+        Ok((files_with_tags, total_count))
+    }
+
+    pub fn get_tags_for_file(&self, file_id: i64) -> Result<Vec<Tag>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {TAG_COLUMNS}
+             FROM tags t
+             JOIN file_tags ft ON t.id = ft.tag_id
+             WHERE ft.file_id = ?1
+             ORDER BY t.name"
+        ))?;
+
+        let tag_iter = stmt.query_map([file_id], tag_from_row)?;
+
+        let mut tags = Vec::new();
+        for tag in tag_iter {
+            tags.push(tag?);
+        }
+        Ok(tags)
+    }
+
+    // 全タグの usage_count を再計算し、値が変わったタグの数を返す
+    pub fn recalculate_usage_counts(&self) -> Result<usize> {
+        self.conn.execute(
+            "UPDATE tags SET usage_count = (
+                SELECT COUNT(*) FROM file_tags ft
+                JOIN files f ON f.id = ft.file_id
+                WHERE ft.tag_id = tags.id AND f.deleted_at IS NULL
+            )
+            WHERE usage_count IS NOT (
+                SELECT COUNT(*) FROM file_tags ft
+                JOIN files f ON f.id = ft.file_id
+                WHERE ft.tag_id = tags.id AND f.deleted_at IS NULL
+            )",
+            [],
+        )
+    }
+
+    // 存在しないファイル・タグを指す関連付けを削除し、削除数を返す
+    // （外部キーを有効にする前に作られたデータベースに残っている場合がある）
+    pub fn delete_dangling_file_tags(&self) -> Result<usize> {
+        self.conn.execute(
+            "DELETE FROM file_tags
+             WHERE NOT EXISTS (SELECT 1 FROM files f WHERE f.id = file_tags.file_id)
+                OR NOT EXISTS (SELECT 1 FROM tags t WHERE t.id = file_tags.tag_id)",
+            [],
+        )
+    }
+
+    // PRAGMA integrity_check の結果（問題がなければ空）
+    pub fn integrity_check(&self) -> Result<Vec<String>> {
+        let mut stmt = self.conn.prepare("PRAGMA integrity_check")?;
+        let messages = stmt
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>>>()?;
+        Ok(messages
+            .into_iter()
+            .filter(|message| message != "ok")
+            .collect())
+    }
+
+    // SQLite のオンラインバックアップで dest にコピーを書き出す（使用中でも整合したコピーになる）
+    pub fn backup_to(&self, dest: &std::path::Path) -> Result<()> {
+        self.conn.backup(rusqlite::DatabaseName::Main, dest, None)
+    }
+
+    // WAL の内容を本体に書き戻して WAL ファイルを空にする（ファイルサイズの計測前に呼ぶ）
+    pub fn checkpoint(&self) -> Result<()> {
+        self.conn
+            .query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))
+    }
+
+    // 空き領域を解放してファイルを詰め直す
+    // VACUUM はトランザクション内では実行できず、実行中は他の書き込みと衝突するため
+    // 呼び出し側で排他的に借り出した接続を使うこと
+    pub fn vacuum(&self) -> Result<()> {
+        self.conn.execute_batch("VACUUM")?;
+        self.checkpoint()
+    }
+
+    // 関連付け・タグの不整合を1トランザクションで修復し、その後に整合性チェックを行う
+    pub fn repair(&self) -> Result<RepairReport> {
+        let mut report = self.in_transaction(|db| {
+            Ok::<_, rusqlite::Error>(RepairReport {
+                dangling_file_tags_removed: db.delete_dangling_file_tags()?,
+                usage_counts_corrected: db.recalculate_usage_counts()?,
+                unused_tags_removed: db.delete_orphaned_tags()?,
+                ..RepairReport::default()
+            })
+        })?;
+        report.integrity_errors = self.integrity_check()?;
+        report.integrity_ok = report.integrity_errors.is_empty();
+        Ok(report)
+    }
+
+    // どのファイルにも付いていないタグを削除し、削除数を返す
+    // ゴミ箱内のファイルに付いたタグは復元に備えて残す
+    pub fn delete_orphaned_tags(&self) -> Result<usize> {
+        self.conn.execute(
+            "DELETE FROM tags
+             WHERE NOT EXISTS (SELECT 1 FROM file_tags ft WHERE ft.tag_id = tags.id)",
+            [],
+        )
+    }
+
+    // ショップ別の集計を合計金額の多い順に取得（ショップ名が未設定のファイルは "Unknown" にまとめる）
+    pub fn get_shop_breakdown(&self) -> Result<Vec<ShopSummary>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT COALESCE(NULLIF(TRIM(author_name), ''), 'Unknown') AS shop,
+                    COUNT(*), COALESCE(SUM(price), 0) AS total_price, COALESCE(SUM(file_size), 0)
+             FROM files
+             WHERE deleted_at IS NULL
+             GROUP BY shop
+             ORDER BY total_price DESC, shop ASC",
+        )?;
+
+        let summaries = stmt
+            .query_map([], |row| {
+                Ok(ShopSummary {
+                    shop_name: row.get(0)?,
+                    item_count: row.get(1)?,
+                    total_price: row.get(2)?,
+                    total_size: row.get(3)?,
+                })
+            })?
+            .collect::<Result<Vec<_>>>()?;
+        Ok(summaries)
+    }
+
+    // ショップごとのファイル数と合計サイズ（ファイル数の多い順、ショップ不明は "Unknown_Shop"）
+    pub fn get_shop_summary(&self) -> Result<Vec<ShopFileSummary>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT COALESCE(NULLIF(TRIM(author_name), ''), 'Unknown_Shop') AS shop,
+                    COUNT(*) AS file_count, SUM(file_size)
+             FROM files
+             WHERE deleted_at IS NULL
+             GROUP BY shop
+             ORDER BY file_count DESC, shop ASC",
+        )?;
+
+        let summaries = stmt
+            .query_map([], |row| {
+                Ok(ShopFileSummary {
+                    shop_name: row.get(0)?,
+                    file_count: row.get(1)?,
+                    total_size: row.get(2)?,
+                })
+            })?
+            .collect::<Result<Vec<_>>>()?;
+        Ok(summaries)
+    }
+
+    // ライブラリ全体の件数・合計サイズと使用回数上位のタグ（行を読み込まず集計する）
+    pub fn get_library_stats(&self, top_tags: u32) -> Result<LibraryStats> {
+        let (total_files, total_size, shop_count, files_without_booth_url) = self.conn.query_row(
+            "SELECT COUNT(*), COALESCE(SUM(file_size), 0),
+                        COUNT(DISTINCT NULLIF(TRIM(author_name), '')),
+                        COUNT(*) - COUNT(NULLIF(TRIM(product_url), ''))
+                 FROM files
+                 WHERE deleted_at IS NULL",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        )?;
+        let total_tags = self
+            .conn
+            .query_row("SELECT COUNT(*) FROM tags", [], |row| row.get(0))?;
+
+        let mut stmt = self.conn.prepare(
+            "SELECT t.name, t.color, COUNT(f.id) AS usage
+             FROM tags t
+             JOIN file_tags ft ON ft.tag_id = t.id
+             JOIN files f ON f.id = ft.file_id AND f.deleted_at IS NULL
+             GROUP BY t.id
+             ORDER BY usage DESC, t.name ASC
+             LIMIT ?1",
+        )?;
+        let top_tags = stmt
+            .query_map([top_tags], |row| {
+                Ok(TagUsage {
+                    name: row.get(0)?,
+                    color: row.get(1)?,
+                    usage_count: row.get(2)?,
+                })
+            })?
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(LibraryStats {
+            total_files,
+            total_size,
+            total_tags,
+            shop_count,
+            files_without_booth_url,
+            top_tags,
+        })
+    }
+
+    // 取り込みを履歴に記録する（同じファイルを取り込み直した場合は最新として記録し直す）
+    pub fn record_import(
+        &self,
+        file_id: i64,
+        output_path: Option<&str>,
+        output_created: bool,
+    ) -> Result<()> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO import_history (file_id, output_path, output_created)
+             VALUES (?1, ?2, ?3)",
+            rusqlite::params![file_id, output_path, output_created],
+        )?;
+        Ok(())
+    }
+
+    // 最後に取り込んだファイル（ゴミ箱内のものは対象外）
+    pub fn get_last_import(&self) -> Result<Option<ImportRecord>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT h.file_id, f.file_path, h.output_path, h.output_created
+             FROM import_history h
+             JOIN files f ON f.id = h.file_id
+             WHERE f.deleted_at IS NULL
+             ORDER BY h.id DESC
+             LIMIT 1",
+        )?;
+        let mut rows = stmt.query_map([], |row| {
+            Ok(ImportRecord {
+                file_id: row.get(0)?,
+                file_path: row.get(1)?,
+                output_path: row.get(2)?,
+                output_created: row.get(3)?,
+            })
+        })?;
+        rows.next().transpose()
+    }
+
+    // サムネイルのURLに対応するダウンロード済みの画像のパス
+    pub fn get_cached_thumbnail(&self, url: &str) -> Result<Option<String>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT local_path FROM thumbnail_cache WHERE url = ?1")?;
+        let mut rows = stmt.query_map([url], |row| row.get(0))?;
+        rows.next().transpose()
+    }
+
+    // サムネイルのURLに対応する縮小版の画像のパス
+    pub fn get_cached_small_thumbnail(&self, url: &str) -> Result<Option<String>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT small_path FROM thumbnail_cache WHERE url = ?1")?;
+        let mut rows = stmt.query_map([url], |row| row.get::<_, Option<String>>(0))?;
+        Ok(rows.next().transpose()?.flatten())
+    }
+
+    pub fn set_cached_small_thumbnail(&self, url: &str, small_path: &str) -> Result<bool> {
+        let affected = self.conn.execute(
+            "UPDATE thumbnail_cache SET small_path = ?1 WHERE url = ?2",
+            [small_path, url],
+        )?;
+        Ok(affected > 0)
+    }
+
+    pub fn set_cached_thumbnail(&self, url: &str, local_path: &str) -> Result<()> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO thumbnail_cache (url, local_path) VALUES (?1, ?2)",
+            [url, local_path],
+        )?;
+        Ok(())
+    }
+
+    pub fn create_collection(&self, name: &str) -> Result<i64> {
+        self.conn
+            .execute("INSERT INTO collections (name) VALUES (?1)", [name])?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    pub fn list_collections(&self) -> Result<Vec<Collection>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT c.id, c.name, COUNT(f.id), c.created_at
+             FROM collections c
+             LEFT JOIN collection_files cf ON cf.collection_id = c.id
+             LEFT JOIN files f ON f.id = cf.file_id AND f.deleted_at IS NULL
+             GROUP BY c.id
+             ORDER BY c.name ASC",
+        )?;
+
+        let collections = stmt
+            .query_map([], |row| {
+                Ok(Collection {
+                    id: Some(row.get(0)?),
+                    name: row.get(1)?,
+                    file_count: row.get(2)?,
+                    created_at: row.get(3)?,
+                })
+            })?
+            .collect::<Result<Vec<_>>>()?;
+        Ok(collections)
+    }
+
+    // コレクションにファイルを追加し、新たに追加された件数を返す
+    pub fn add_files_to_collection(&self, collection_id: i64, file_ids: &[i64]) -> Result<usize> {
+        let mut added = 0;
+        for file_id in file_ids {
+            added += self.conn.execute(
+                "INSERT OR IGNORE INTO collection_files (collection_id, file_id)
+                 VALUES (?1, ?2)",
+                [collection_id, *file_id],
+            )?;
+        }
+        Ok(added)
+    }
+
+    // コレクションからファイルを外し、外した件数を返す
+    pub fn remove_files_from_collection(
+        &self,
+        collection_id: i64,
+        file_ids: &[i64],
+    ) -> Result<usize> {
+        let mut removed = 0;
+        for file_id in file_ids {
+            removed += self.conn.execute(
+                "DELETE FROM collection_files WHERE collection_id = ?1 AND file_id = ?2",
+                [collection_id, *file_id],
+            )?;
+        }
+        Ok(removed)
+    }
+
+    pub fn get_collection_files(&self, collection_id: i64) -> Result<Vec<FileWithTags>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {FILE_COLUMNS} FROM files f
+             JOIN collection_files cf ON cf.file_id = f.id
+             WHERE cf.collection_id = ?1 AND f.deleted_at IS NULL
+             ORDER BY cf.created_at ASC, f.id ASC"
+        ))?;
+
+        let files = stmt
+            .query_map([collection_id], file_from_row)?
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut files_with_tags = Vec::new();
+        for file in files {
+            let file_id = file.id.unwrap();
+            let tags = self.get_tags_for_file(file_id)?;
+            files_with_tags.push(FileWithTags { file, tags });
+        }
+        Ok(files_with_tags)
+    }
+
+    // クロージャ内の操作を1つのトランザクションで実行（エラー時はロールバック）
+    pub fn in_transaction<T, E>(
+        &self,
+        f: impl FnOnce(&Self) -> std::result::Result<T, E>,
+    ) -> std::result::Result<T, E>
+    where
+        E: From<rusqlite::Error>,
+    {
+        let tx = self.conn.unchecked_transaction()?;
+        let result = f(self)?;
+        tx.commit()?;
+        Ok(result)
+    }
+
+    pub fn get_file_count(&self) -> Result<usize> {
+        let count: i64 = self.conn.query_row(
+            "SELECT COUNT(*) FROM files WHERE deleted_at IS NULL",
+            [],
+            |row| row.get(0),
+        )?;
+        Ok(count as usize)
+    }
+
+    pub fn get_tag_count(&self) -> Result<usize> {
+        let count: i64 = self
+            .conn
             .query_row("SELECT COUNT(*) FROM tags", [], |row| row.get(0))?;
         Ok(count as usize)
     }
 }
+
+/// trigram トークナイザーが扱える最小の語の長さ（文字数）
+pub const FTS_MIN_TERM_CHARS: usize = 3;
+
+/// 検索語を FTS5 のクエリに変換（空白区切りの語をすべて含むもの＝AND）
+///
+/// trigram では3文字未満の語を照合できないため、その場合は None を返す。
+pub fn build_fts_query(query: &str) -> Option<String> {
+    let terms: Vec<&str> = query.split_whitespace().collect();
+    if terms.is_empty()
+        || terms
+            .iter()
+            .any(|term| term.chars().count() < FTS_MIN_TERM_CHARS)
+    {
+        return None;
+    }
+
+    Some(
+        terms
+            .iter()
+            .map(|term| format!("\"{}\"", term.replace('"', "\"\"")))
+            .collect::<Vec<_>>()
+            .join(" "),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{self, tagged_db};
+
+    fn sample_file(path: &str) -> FileRecord {
+        FileRecord {
+            file_name: path.rsplit('/').next().unwrap_or(path).to_string(),
+            file_size: 1024,
+            product_name: Some("Product".to_string()),
+            author_name: Some("Shop".to_string()),
+            ..test_support::sample_file(path)
+        }
+    }
+
+    #[test]
+    fn test_connection_pragmas_are_applied() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Database::new(&dir.path().join("library.db").to_string_lossy()).unwrap();
+        let pragma = |name: &str| -> String {
+            db.conn
+                .query_row(&format!("PRAGMA {name}"), [], |row| {
+                    row.get::<_, rusqlite::types::Value>(0)
+                })
+                .map(|value| match value {
+                    rusqlite::types::Value::Integer(n) => n.to_string(),
+                    rusqlite::types::Value::Text(s) => s,
+                    other => format!("{other:?}"),
+                })
+                .unwrap()
+        };
+
+        assert_eq!(pragma("foreign_keys"), "1");
+        assert_eq!(pragma("journal_mode"), "wal");
+        // NORMAL
+        assert_eq!(pragma("synchronous"), "1");
+        assert_eq!(
+            pragma("busy_timeout"),
+            (crate::config::database::BUSY_TIMEOUT_SECS * 1000).to_string()
+        );
+    }
+
+    #[test]
+    fn test_delete_file_cascades_to_file_tags() {
+        let db = Database::new(":memory:").unwrap();
+        let id = db.add_file(sample_file("/tmp/a")).unwrap();
+        let kept = db.add_file(sample_file("/tmp/b")).unwrap();
+        let tag = db.add_tag("VRChat").unwrap();
+        db.add_file_tag(id, tag).unwrap();
+        db.add_file_tag(kept, tag).unwrap();
+
+        db.delete_file(id).unwrap();
+
+        let file_tag_rows = |file_id: i64| -> i64 {
+            db.conn
+                .query_row(
+                    "SELECT COUNT(*) FROM file_tags WHERE file_id = ?1",
+                    [file_id],
+                    |row| row.get(0),
+                )
+                .unwrap()
+        };
+        assert_eq!(file_tag_rows(id), 0);
+        assert_eq!(file_tag_rows(kept), 1);
+    }
+
+    #[test]
+    fn test_get_file_with_tags_by_id() {
+        let db = Database::new(":memory:").unwrap();
+        let id = db.add_file(sample_file("/tmp/a")).unwrap();
+        db.add_file(sample_file("/tmp/b")).unwrap();
+        for name in ["衣装", "VRChat"] {
+            let tag_id = db.add_tag(name).unwrap();
+            db.add_file_tag(id, tag_id).unwrap();
+        }
+
+        let found = db.get_file_with_tags(id).unwrap().unwrap();
+        assert_eq!(found.file.file_path, "/tmp/a");
+        let names: Vec<_> = found.tags.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, vec!["VRChat", "衣装"]);
+
+        assert!(db.get_file_with_tags(id + 100).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_soft_delete_hides_file() {
+        let db = Database::new(":memory:").unwrap();
+        let id = db.add_file(sample_file("/tmp/a")).unwrap();
+        db.add_file(sample_file("/tmp/b")).unwrap();
+
+        assert!(db.soft_delete_file(id).unwrap());
+
+        let files = db.get_all_files().unwrap();
+        assert_eq!(files.len(), 1);
+        assert!(files.iter().all(|f| f.id != Some(id)));
+        assert!(db
+            .get_files_with_tags()
+            .unwrap()
+            .iter()
+            .all(|f| f.file.id != Some(id)));
+
+        let deleted = db.get_deleted_files().unwrap();
+        assert_eq!(deleted.len(), 1);
+        assert!(deleted[0].deleted_at.is_some());
+
+        // 二重の論理削除は対象なし
+        assert!(!db.soft_delete_file(id).unwrap());
+    }
+
+    #[test]
+    fn test_restore_brings_file_back() {
+        let db = Database::new(":memory:").unwrap();
+        let id = db.add_file(sample_file("/tmp/a")).unwrap();
+        let tag_id = db.add_tag("VRChat").unwrap();
+        db.add_file_tag(id, tag_id).unwrap();
+
+        db.soft_delete_file(id).unwrap();
+        db.recalculate_usage_counts().unwrap();
+        assert_eq!(db.get_all_tags().unwrap()[0].usage_count, 0);
+
+        assert!(db.restore_file(id).unwrap());
+        db.recalculate_usage_counts().unwrap();
+
+        let files = db.get_all_files().unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].deleted_at, None);
+        assert_eq!(db.get_tags_for_file(id).unwrap().len(), 1);
+        assert_eq!(db.get_all_tags().unwrap()[0].usage_count, 1);
+
+        // 削除されていないファイルの復元は対象なし
+        assert!(!db.restore_file(id).unwrap());
+    }
+
+    #[test]
+    fn test_get_unlinked_files_returns_only_files_without_url() {
+        let db = Database::new(":memory:").unwrap();
+        for (path, url) in [
+            ("/tmp/linked_a", Some("https://shop.booth.pm/items/1")),
+            ("/tmp/unlinked", None),
+            ("/tmp/linked_b", Some("https://shop.booth.pm/items/2")),
+        ] {
+            let mut file = sample_file(path);
+            file.product_url = url.map(str::to_string);
+            db.add_file(file).unwrap();
+        }
+
+        let unlinked = db.get_unlinked_files().unwrap();
+        assert_eq!(unlinked.len(), 1);
+        assert_eq!(unlinked[0].file.file_path, "/tmp/unlinked");
+
+        // 空文字のURLも未設定として扱う
+        let linked_b = db.get_file_by_path("/tmp/linked_b").unwrap().unwrap();
+        db.update_file(
+            linked_b.id.unwrap(),
+            FileUpdateFields {
+                product_id: None,
+                product_name: None,
+                author_name: None,
+                price: None,
+                description: None,
+                thumbnail_url: None,
+                product_url: Some(" ".to_string()),
+            },
+        )
+        .unwrap();
+
+        let paths: Vec<_> = db
+            .get_unlinked_files()
+            .unwrap()
+            .into_iter()
+            .map(|f| f.file.file_path)
+            .collect();
+        assert_eq!(paths, vec!["/tmp/unlinked", "/tmp/linked_b"]);
+    }
+
+    #[test]
+    fn test_search_excludes_trashed_files_until_restored() {
+        let db = Database::new(":memory:").unwrap();
+        let mut file = sample_file("/tmp/milk_avatar");
+        file.product_name = Some("ミルク アバター".to_string());
+        let id = db.add_file(file).unwrap();
+        let tag_id = db.add_tag("VRChat").unwrap();
+        db.add_file_tag(id, tag_id).unwrap();
+
+        let pagination = PaginationRequest {
+            page: 1,
+            page_size: 10,
+            sort_by: None,
+            sort_order: None,
+            sort: Vec::new(),
+        };
+        let tags = vec!["VRChat".to_string()];
+        let visible_counts = |db: &Database| {
+            (
+                db.search_files_fts(&build_fts_query("アバター").unwrap())
+                    .unwrap()
+                    .len(),
+                db.search_files_page("milk", &pagination, false).unwrap().1,
+                db.get_files_with_any_tags_page(&tags, &pagination, false)
+                    .unwrap()
+                    .1,
+                db.get_files_with_all_tags(&tags).unwrap().len(),
+            )
+        };
+
+        assert_eq!(visible_counts(&db), (1, 1, 1, 1));
+
+        db.soft_delete_file(id).unwrap();
+        assert_eq!(visible_counts(&db), (0, 0, 0, 0));
+
+        db.restore_file(id).unwrap();
+        assert_eq!(visible_counts(&db), (1, 1, 1, 1));
+    }
+
+    #[test]
+    fn test_purge_removes_permanently() {
+        let db = Database::new(":memory:").unwrap();
+        let old_id = db.add_file(sample_file("/tmp/old")).unwrap();
+        let recent_id = db.add_file(sample_file("/tmp/recent")).unwrap();
+        let live_id = db.add_file(sample_file("/tmp/live")).unwrap();
+        let tag_id = db.add_tag("VRChat").unwrap();
+        db.add_file_tag(old_id, tag_id).unwrap();
+
+        db.soft_delete_file(old_id).unwrap();
+        db.soft_delete_file(recent_id).unwrap();
+        db.conn
+            .execute(
+                "UPDATE files SET deleted_at = datetime('now', '-40 days') WHERE id = ?1",
+                [old_id],
+            )
+            .unwrap();
+
+        let purged = db.purge_deleted_files(30).unwrap();
+        assert_eq!(purged.len(), 1);
+        assert_eq!(purged[0].0.id, Some(old_id));
+        assert!(db.get_file_by_id(old_id).unwrap().is_none());
+        assert!(db.get_tags_for_file(old_id).unwrap().is_empty());
+
+        // 期限内のゴミ箱と通常ファイルは残る
+        assert!(db.get_file_by_id(recent_id).unwrap().is_some());
+        assert!(db.get_file_by_id(live_id).unwrap().is_some());
+
+        let purged = db.purge_deleted_files(0).unwrap();
+        assert_eq!(purged.len(), 1);
+        assert!(db.get_file_by_id(recent_id).unwrap().is_none());
+        assert!(db.get_deleted_files().unwrap().is_empty());
+        assert_eq!(db.get_all_files().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_get_files_with_all_tags() {
+        let (db, both, vrchat_only, _) = tagged_db();
+        let names = |tags: &[&str]| tags.iter().map(|t| t.to_string()).collect::<Vec<_>>();
+
+        let files = db
+            .get_files_with_all_tags(&names(&["VRChat", "有料"]))
+            .unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].file.id, Some(both));
+        assert_eq!(files[0].tags.len(), 2);
+
+        // 重複指定は1つとして扱う
+        let files = db
+            .get_files_with_all_tags(&names(&["VRChat", "VRChat"]))
+            .unwrap();
+        let mut ids: Vec<_> = files.iter().filter_map(|f| f.file.id).collect();
+        ids.sort_unstable();
+        assert_eq!(ids, vec![both, vrchat_only]);
+
+        assert!(db
+            .get_files_with_all_tags(&names(&["VRChat", "存在しない"]))
+            .unwrap()
+            .is_empty());
+        assert!(db.get_files_with_all_tags(&[]).unwrap().is_empty());
+
+        // ゴミ箱内のファイルは除外
+        db.soft_delete_file(both).unwrap();
+        assert!(db
+            .get_files_with_all_tags(&names(&["VRChat", "有料"]))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_create_and_list_collections() {
+        let db = Database::new(":memory:").unwrap();
+        let project = db.create_collection("Current VRChat Project").unwrap();
+        db.create_collection("Archive").unwrap();
+
+        // 同名のコレクションは作成できない
+        assert!(db.create_collection("Archive").is_err());
+
+        let file = db.add_file(sample_file("/tmp/a")).unwrap();
+        db.add_files_to_collection(project, &[file]).unwrap();
+
+        let collections = db.list_collections().unwrap();
+        let names: Vec<_> = collections.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, vec!["Archive", "Current VRChat Project"]);
+        assert_eq!(collections[0].file_count, 0);
+        assert_eq!(collections[1].file_count, 1);
+    }
+
+    #[test]
+    fn test_collection_membership() {
+        let db = Database::new(":memory:").unwrap();
+        let project = db.create_collection("Project").unwrap();
+        let favorites = db.create_collection("Favorites").unwrap();
+        let a = db.add_file(sample_file("/tmp/a")).unwrap();
+        let b = db.add_file(sample_file("/tmp/b")).unwrap();
+        let tag_id = db.add_tag("VRChat").unwrap();
+        db.add_file_tag(a, tag_id).unwrap();
+
+        assert_eq!(db.add_files_to_collection(project, &[a, b]).unwrap(), 2);
+        // 追加済みのファイルは数えない
+        assert_eq!(db.add_files_to_collection(project, &[a]).unwrap(), 0);
+        // 1つのファイルを複数のコレクションに入れられる
+        assert_eq!(db.add_files_to_collection(favorites, &[a]).unwrap(), 1);
+
+        let files = db.get_collection_files(project).unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].file.id, Some(a));
+        assert_eq!(files[0].tags.len(), 1);
+        assert_eq!(files[0].tags[0].name, "VRChat");
+
+        assert_eq!(db.remove_files_from_collection(project, &[a]).unwrap(), 1);
+        let files = db.get_collection_files(project).unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].file.id, Some(b));
+        assert_eq!(db.get_collection_files(favorites).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_deleting_file_removes_collection_membership() {
+        let db = Database::new(":memory:").unwrap();
+        let project = db.create_collection("Project").unwrap();
+        let a = db.add_file(sample_file("/tmp/a")).unwrap();
+        let b = db.add_file(sample_file("/tmp/b")).unwrap();
+        db.add_files_to_collection(project, &[a, b]).unwrap();
+
+        // ゴミ箱内のファイルは一覧に出ないが、所属は残る
+        db.soft_delete_file(b).unwrap();
+        assert_eq!(db.get_collection_files(project).unwrap().len(), 1);
+        db.restore_file(b).unwrap();
+        assert_eq!(db.get_collection_files(project).unwrap().len(), 2);
+
+        db.delete_file(a).unwrap();
+        let remaining: i64 = db
+            .conn
+            .query_row(
+                "SELECT COUNT(*) FROM collection_files WHERE file_id = ?1",
+                [a],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(remaining, 0);
+        assert_eq!(db.list_collections().unwrap()[0].file_count, 1);
+    }
+
+    fn product_file(path: &str, product_name: &str, description: Option<&str>) -> FileRecord {
+        FileRecord {
+            product_name: Some(product_name.to_string()),
+            description: description.map(|d| d.to_string()),
+            ..sample_file(path)
+        }
+    }
+
+    #[test]
+    fn test_build_fts_query() {
+        assert_eq!(
+            build_fts_query("YUKATA 祭囃子").as_deref(),
+            Some("\"YUKATA\" \"祭囃子\"")
+        );
+        assert_eq!(build_fts_query("say\"hi").as_deref(), Some("\"say\"\"hi\""));
+        assert_eq!(build_fts_query("浴衣"), None);
+        assert_eq!(build_fts_query("   "), None);
+    }
+
+    #[test]
+    fn test_fts_matches_product_name_token() {
+        let db = Database::new(":memory:").unwrap();
+        let yukata = db
+            .add_file(product_file(
+                "/tmp/yukata",
+                "YUKATA 祭囃子 - Matsuribayashi -",
+                None,
+            ))
+            .unwrap();
+        db.add_file(product_file("/tmp/hair", "Short Hair", None))
+            .unwrap();
+
+        let query = build_fts_query("matsuribayashi").unwrap();
+        let files = db.search_files_fts(&query).unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].file.id, Some(yukata));
+
+        let query = build_fts_query("祭囃子").unwrap();
+        assert_eq!(db.search_files_fts(&query).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_fts_ranks_title_above_description() {
+        let db = Database::new(":memory:").unwrap();
+        let description_only = db
+            .add_file(product_file(
+                "/tmp/desc",
+                "Outfit Set",
+                Some("Compatible with the Milltina avatar and others"),
+            ))
+            .unwrap();
+        let title = db
+            .add_file(product_file("/tmp/title", "Milltina", None))
+            .unwrap();
+
+        let query = build_fts_query("Milltina").unwrap();
+        let files = db.search_files_fts(&query).unwrap();
+        let ids: Vec<_> = files.iter().filter_map(|f| f.file.id).collect();
+        assert_eq!(ids, vec![title, description_only]);
+    }
+
+    #[test]
+    fn test_fts_stays_in_sync() {
+        let db = Database::new(":memory:").unwrap();
+        let id = db
+            .add_file(product_file("/tmp/a", "Original Name", None))
+            .unwrap();
+
+        db.update_file(
+            id,
+            FileUpdateFields {
+                product_id: None,
+                product_name: Some("Renamed Product".to_string()),
+                author_name: None,
+                price: None,
+                description: None,
+                thumbnail_url: None,
+                product_url: None,
+            },
+        )
+        .unwrap();
+        let original = build_fts_query("Original").unwrap();
+        let renamed = build_fts_query("Renamed").unwrap();
+        assert!(db.search_files_fts(&original).unwrap().is_empty());
+        assert_eq!(db.search_files_fts(&renamed).unwrap().len(), 1);
+
+        // 同じパスへの INSERT OR REPLACE で古い索引が残らない
+        db.add_file(product_file("/tmp/a", "Replaced Product", None))
+            .unwrap();
+        assert!(db.search_files_fts(&renamed).unwrap().is_empty());
+
+        // ゴミ箱内は検索対象外、完全削除で索引からも消える
+        let replaced = build_fts_query("Replaced").unwrap();
+        let id = db.get_file_by_path("/tmp/a").unwrap().unwrap().id.unwrap();
+        db.soft_delete_file(id).unwrap();
+        assert!(db.search_files_fts(&replaced).unwrap().is_empty());
+        db.delete_file(id).unwrap();
+        let indexed: i64 = db
+            .conn
+            .query_row(
+                "SELECT COUNT(*) FROM files_fts WHERE files_fts MATCH ?1",
+                [&replaced],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(indexed, 0);
+    }
+
+    fn query_plan(db: &Database, sql: &str) -> String {
+        let mut stmt = db
+            .conn
+            .prepare(&format!("EXPLAIN QUERY PLAN {sql}"))
+            .unwrap();
+        stmt.query_map([], |row| row.get::<_, String>(3))
+            .unwrap()
+            .collect::<Result<Vec<_>>>()
+            .unwrap()
+            .join("\n")
+    }
+
+    #[test]
+    fn test_tag_search_uses_indexes() {
+        let db = Database::new(":memory:").unwrap();
+
+        let plan = query_plan(
+            &db,
+            "SELECT f.id FROM files f
+             JOIN file_tags ft ON ft.file_id = f.id
+             JOIN tags t ON t.id = ft.tag_id
+             WHERE f.deleted_at IS NULL AND t.name IN ('VRChat', '有料')",
+        );
+
+        assert!(plan.contains("idx_file_tags_tag_id"), "{plan}");
+        assert!(!plan.contains("SCAN ft"), "{plan}");
+        assert!(
+            !plan.contains("SCAN f\n") && !plan.ends_with("SCAN f"),
+            "{plan}"
+        );
+    }
+
+    // テーブルのインデックスごとの先頭列
+    fn indexed_columns(db: &Database, table: &str) -> Vec<String> {
+        let mut stmt = db
+            .conn
+            .prepare(&format!("PRAGMA index_list({table})"))
+            .unwrap();
+        let indexes = stmt
+            .query_map([], |row| row.get::<_, String>(1))
+            .unwrap()
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        indexes
+            .iter()
+            .map(|index| {
+                db.conn
+                    .query_row(
+                        &format!("SELECT name FROM pragma_index_info('{index}') WHERE seqno = 0"),
+                        [],
+                        |row| row.get(0),
+                    )
+                    .unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_files_page_sorts_by_multiple_columns() {
+        let db = Database::new(":memory:").unwrap();
+        for (path, shop) in [
+            ("/tmp/b_dress", "Bravo"),
+            ("/tmp/c_hair", "Alpha"),
+            ("/tmp/a_shoes", "Bravo"),
+            ("/tmp/a_avatar", "Alpha"),
+            ("/tmp/b_coat", "Alpha"),
+        ] {
+            db.add_file(FileRecord {
+                author_name: Some(shop.to_string()),
+                ..sample_file(path)
+            })
+            .unwrap();
+        }
+        let key = |column: &str, order: &str| SortKey {
+            column: column.to_string(),
+            order: Some(order.to_string()),
+        };
+        let page = |sort: Vec<SortKey>| {
+            let pagination = PaginationRequest {
+                page: 1,
+                page_size: 10,
+                sort_by: Some("file_name".to_string()),
+                sort_order: Some("desc".to_string()),
+                sort,
+            };
+            db.get_files_page(&pagination, false)
+                .unwrap()
+                .0
+                .into_iter()
+                .map(|f| f.file.file_name)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            page(vec![key("author_name", "asc"), key("fileName", "asc")]),
+            vec!["a_avatar", "b_coat", "c_hair", "a_shoes", "b_dress"]
+        );
+        // 許可されていない列は検証エラー
+        let pagination = PaginationRequest {
+            page: 1,
+            page_size: 10,
+            sort_by: None,
+            sort_order: None,
+            sort: vec![
+                key("author_name", "desc"),
+                key("file_name; DROP TABLE files", "asc"),
+            ],
+        };
+        assert!(matches!(
+            db.get_files_page(&pagination, false),
+            Err(AppError::Validation { field, .. }) if field == "sort_by"
+        ));
+        assert!(matches!(
+            db.get_tags_page(&pagination),
+            Err(AppError::Validation { field, .. }) if field == "sort_by"
+        ));
+        // sort 未指定時は sort_by / sort_order の1列
+        assert_eq!(
+            page(Vec::new()),
+            vec!["c_hair", "b_dress", "b_coat", "a_shoes", "a_avatar"]
+        );
+    }
+
+    #[test]
+    fn test_hot_query_columns_are_indexed() {
+        let db = Database::new(":memory:").unwrap();
+
+        let files = indexed_columns(&db, "files");
+        for column in ["product_id", "file_hash", "author_name"] {
+            assert!(files.iter().any(|c| c == column), "{column}: {files:?}");
+        }
+        // tags.name は UNIQUE 制約の自動インデックス
+        assert!(indexed_columns(&db, "tags").iter().any(|c| c == "name"));
+        assert!(indexed_columns(&db, "file_tags")
+            .iter()
+            .any(|c| c == "tag_id"));
+    }
+
+    #[test]
+    fn test_shop_filter_and_date_sort_use_indexes() {
+        let db = Database::new(":memory:").unwrap();
+
+        let plan = query_plan(
+            &db,
+            "SELECT id FROM files WHERE author_name = 'shop' ORDER BY created_at DESC",
+        );
+        assert!(plan.contains("idx_files_author_name"), "{plan}");
+
+        let plan = query_plan(
+            &db,
+            "SELECT id FROM files ORDER BY created_at DESC LIMIT 10",
+        );
+        assert!(plan.contains("idx_files_created_at"), "{plan}");
+    }
+
+    #[test]
+    fn test_migration_adds_deleted_at_column() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("legacy.db");
+        let db_path = db_path.to_string_lossy();
+
+        {
+            let conn = Connection::open(db_path.as_ref()).unwrap();
+            conn.execute(
+                "CREATE TABLE files (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    file_path TEXT UNIQUE NOT NULL,
+                    file_name TEXT NOT NULL,
+                    file_size INTEGER NOT NULL,
+                    modified_time INTEGER NOT NULL,
+                    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    product_id TEXT,
+                    product_name TEXT,
+                    author_name TEXT,
+                    price INTEGER,
+                    description TEXT,
+                    thumbnail_url TEXT,
+                    product_url TEXT
+                )",
+                [],
+            )
+            .unwrap();
+            conn.execute(
+                "INSERT INTO files (file_path, file_name, file_size, modified_time)
+                 VALUES ('/tmp/legacy', 'legacy', 1, 0)",
+                [],
+            )
+            .unwrap();
+        }
+
+        let db = Database::new(&db_path).unwrap();
+        let files = db.get_all_files().unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].deleted_at, None);
+
+        // 既存の行も全文検索の索引に入る
+        let query = build_fts_query("legacy").unwrap();
+        assert_eq!(db.search_files_fts(&query).unwrap().len(), 1);
+    }
+
+    fn advanced_paths(db: &Database, filter: &AdvancedSearchFilter) -> Vec<String> {
+        let mut paths: Vec<String> = db
+            .search_files_advanced(filter)
+            .unwrap()
+            .into_iter()
+            .map(|f| f.file.file_path)
+            .collect();
+        paths.sort();
+        paths
+    }
+
+    #[test]
+    fn test_advanced_search_size_bounds_are_inclusive() {
+        let db = Database::new(":memory:").unwrap();
+        for (path, size) in [("/tmp/s100", 100), ("/tmp/s200", 200), ("/tmp/s300", 300)] {
+            db.add_file(FileRecord {
+                file_size: size,
+                ..sample_file(path)
+            })
+            .unwrap();
+        }
+
+        let min = AdvancedSearchFilter {
+            min_size: Some(200),
+            ..Default::default()
+        };
+        assert_eq!(advanced_paths(&db, &min), vec!["/tmp/s200", "/tmp/s300"]);
+
+        let max = AdvancedSearchFilter {
+            max_size: Some(200),
+            ..Default::default()
+        };
+        assert_eq!(advanced_paths(&db, &max), vec!["/tmp/s100", "/tmp/s200"]);
+
+        let exact = AdvancedSearchFilter {
+            min_size: Some(200),
+            max_size: Some(200),
+            ..Default::default()
+        };
+        assert_eq!(advanced_paths(&db, &exact), vec!["/tmp/s200"]);
+
+        let empty_range = AdvancedSearchFilter {
+            min_size: Some(201),
+            max_size: Some(299),
+            ..Default::default()
+        };
+        assert!(advanced_paths(&db, &empty_range).is_empty());
+    }
+
+    #[test]
+    fn test_advanced_search_excludes_null_size_from_size_filters() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("nullable.db");
+        let db_path = db_path.to_string_lossy();
+
+        // file_size に NOT NULL 制約のない古いスキーマ
+        {
+            let conn = Connection::open(db_path.as_ref()).unwrap();
+            conn.execute(
+                "CREATE TABLE files (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    file_path TEXT UNIQUE NOT NULL,
+                    file_name TEXT NOT NULL,
+                    file_size INTEGER,
+                    modified_time INTEGER NOT NULL,
+                    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    product_id TEXT,
+                    product_name TEXT,
+                    author_name TEXT,
+                    price INTEGER,
+                    description TEXT,
+                    thumbnail_url TEXT,
+                    product_url TEXT
+                )",
+                [],
+            )
+            .unwrap();
+            conn.execute(
+                "INSERT INTO files (file_path, file_name, file_size, modified_time)
+                 VALUES ('/tmp/unknown', 'unknown', NULL, 0), ('/tmp/zero', 'zero', 0, 0)",
+                [],
+            )
+            .unwrap();
+        }
+
+        let db = Database::new(&db_path).unwrap();
+
+        let max = AdvancedSearchFilter {
+            max_size: Some(10),
+            ..Default::default()
+        };
+        assert_eq!(advanced_paths(&db, &max), vec!["/tmp/zero"]);
+
+        let min = AdvancedSearchFilter {
+            min_size: Some(0),
+            ..Default::default()
+        };
+        assert_eq!(advanced_paths(&db, &min), vec!["/tmp/zero"]);
+    }
+
+    #[test]
+    fn test_advanced_search_created_range_is_half_open() {
+        let db = Database::new(":memory:").unwrap();
+        for (path, created_at) in [
+            ("/tmp/d09", "2024-01-09 23:59:59"),
+            ("/tmp/d10", "2024-01-10 00:00:00"),
+            ("/tmp/d17", "2024-01-17 00:00:00"),
+        ] {
+            db.import_file_record(&FileRecord {
+                created_at: created_at.to_string(),
+                ..sample_file(path)
+            })
+            .unwrap();
+        }
+
+        let after = AdvancedSearchFilter {
+            created_after: Some("2024-01-10 00:00:00".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(advanced_paths(&db, &after), vec!["/tmp/d10", "/tmp/d17"]);
+
+        let before = AdvancedSearchFilter {
+            created_before: Some("2024-01-10 00:00:00".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(advanced_paths(&db, &before), vec!["/tmp/d09"]);
+
+        let week = AdvancedSearchFilter {
+            created_after: Some("2024-01-10 00:00:00".to_string()),
+            created_before: Some("2024-01-17 00:00:00".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(advanced_paths(&db, &week), vec!["/tmp/d10"]);
+    }
+
+    #[test]
+    fn test_advanced_search_composes_with_text_and_tags() {
+        let db = Database::new(":memory:").unwrap();
+        let big_avatar = db
+            .add_file(FileRecord {
+                file_size: 600,
+                product_name: Some("Avatar".to_string()),
+                ..sample_file("/tmp/big_avatar")
+            })
+            .unwrap();
+        let small_avatar = db
+            .add_file(FileRecord {
+                file_size: 100,
+                product_name: Some("Avatar".to_string()),
+                ..sample_file("/tmp/small_avatar")
+            })
+            .unwrap();
+        db.add_file(FileRecord {
+            file_size: 600,
+            product_name: Some("Shader".to_string()),
+            ..sample_file("/tmp/big_shader")
+        })
+        .unwrap();
+
+        let tag_id = db.add_tag("vrchat").unwrap();
+        db.add_file_tag(big_avatar, tag_id).unwrap();
+        db.add_file_tag(small_avatar, tag_id).unwrap();
+
+        let filter = AdvancedSearchFilter {
+            query: Some("avatar".to_string()),
+            tag_names: vec!["vrchat".to_string()],
+            min_size: Some(500),
+            ..Default::default()
+        };
+        assert_eq!(advanced_paths(&db, &filter), vec!["/tmp/big_avatar"]);
+    }
+
+    #[test]
+    fn test_get_files_by_category() {
+        let db = Database::new(":memory:").unwrap();
+        let with_category = |path: &str, category: Option<&str>| {
+            db.add_file(FileRecord {
+                booth_category: category.map(str::to_string),
+                ..sample_file(path)
+            })
+            .unwrap()
+        };
+        with_category("/tmp/dress", Some("3D衣装"));
+        let trashed = with_category("/tmp/old_dress", Some("3D衣装"));
+        with_category("/tmp/avatar", Some("3Dキャラクター"));
+        with_category("/tmp/unknown", None);
+        db.soft_delete_file(trashed).unwrap();
+
+        let paths = |category: Option<&str>| {
+            db.get_files_by_category(category)
+                .unwrap()
+                .into_iter()
+                .map(|f| f.file.file_path)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(paths(Some("3D衣装")), vec!["/tmp/dress"]);
+        // カテゴリ未取得のファイルは None で取得する
+        assert_eq!(paths(None), vec!["/tmp/unknown"]);
+        assert!(paths(Some("存在しない")).is_empty());
+    }
+
+    fn set_file_timestamp(db: &Database, id: i64, column: &str, value: &str) {
+        db.conn
+            .execute(
+                &format!("UPDATE files SET {column} = ?1 WHERE id = ?2"),
+                rusqlite::params![value, id],
+            )
+            .unwrap();
+    }
+
+    fn paths_of(files: Vec<FileWithTags>) -> Vec<String> {
+        files.into_iter().map(|f| f.file.file_path).collect()
+    }
+
+    #[test]
+    fn test_recent_files_are_newest_first() {
+        let db = Database::new(":memory:").unwrap();
+        let old = db.add_file(sample_file("/tmp/old")).unwrap();
+        let new = db.add_file(sample_file("/tmp/new")).unwrap();
+        let same_time = db.add_file(sample_file("/tmp/same_time")).unwrap();
+        let trashed = db.add_file(sample_file("/tmp/trashed")).unwrap();
+        set_file_timestamp(&db, old, "created_at", "2024-01-01 00:00:00");
+        set_file_timestamp(&db, new, "created_at", "2024-03-01 00:00:00");
+        set_file_timestamp(&db, same_time, "created_at", "2024-03-01 00:00:00");
+        set_file_timestamp(&db, trashed, "created_at", "2024-06-01 00:00:00");
+        db.soft_delete_file(trashed).unwrap();
+
+        // 同じ日時の場合は後から登録したものが先
+        assert_eq!(
+            paths_of(db.get_recent_files(10).unwrap()),
+            vec!["/tmp/same_time", "/tmp/new", "/tmp/old"]
+        );
+        assert_eq!(
+            paths_of(db.get_recent_files(1).unwrap()),
+            vec!["/tmp/same_time"]
+        );
+    }
+
+    #[test]
+    fn test_related_files_rank_by_shared_tags() {
+        let db = Database::new(":memory:").unwrap();
+        let tag = |name: &str| db.add_tag(name).unwrap();
+        let (avatar, outfit, vrchat, pbr) =
+            (tag("アバター"), tag("衣装"), tag("VRChat"), tag("PBR"));
+
+        let base = db.add_file(sample_file("/tmp/base")).unwrap();
+        let close = db.add_file(sample_file("/tmp/close")).unwrap();
+        let older = db.add_file(sample_file("/tmp/older")).unwrap();
+        let newer = db.add_file(sample_file("/tmp/newer")).unwrap();
+        let unrelated = db.add_file(sample_file("/tmp/unrelated")).unwrap();
+        let trashed = db.add_file(sample_file("/tmp/trashed")).unwrap();
+        let links = [
+            (base, vec![avatar, outfit, vrchat]),
+            (close, vec![avatar, outfit, vrchat, pbr]),
+            (older, vec![vrchat]),
+            (newer, vec![outfit]),
+            (unrelated, vec![pbr]),
+            (trashed, vec![avatar, outfit, vrchat]),
+        ];
+        for (file_id, tag_ids) in links {
+            for tag_id in tag_ids {
+                db.add_file_tag(file_id, tag_id).unwrap();
+            }
+        }
+        set_file_timestamp(&db, older, "created_at", "2024-01-01 00:00:00");
+        set_file_timestamp(&db, newer, "created_at", "2024-03-01 00:00:00");
+        db.soft_delete_file(trashed).unwrap();
+
+        assert_eq!(
+            paths_of(db.get_related_files(base, 10).unwrap()),
+            vec!["/tmp/close", "/tmp/newer", "/tmp/older"]
+        );
+        assert_eq!(
+            paths_of(db.get_related_files(base, 1).unwrap()),
+            vec!["/tmp/close"]
+        );
+        assert!(db.get_related_files(base + 100, 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_suggest_tags_from_same_shop_and_shared_tags() {
+        let db = Database::new(":memory:").unwrap();
+        let tag = |name: &str| db.add_tag(name).unwrap();
+        let (kimono, vrchat, wafu, pbr, quest) = (
+            tag("着物"),
+            tag("VRChat"),
+            tag("和風"),
+            tag("PBR"),
+            tag("Quest"),
+        );
+        let file = |path: &str, shop: &str, tag_ids: &[i64]| {
+            let id = db
+                .add_file(FileRecord {
+                    author_name: Some(shop.to_string()),
+                    ..sample_file(path)
+                })
+                .unwrap();
+            for &tag_id in tag_ids {
+                db.add_file_tag(id, tag_id).unwrap();
+            }
+            id
+        };
+
+        let target = file("/tmp/target", "Kimono Shop", &[kimono]);
+        file("/tmp/a1", "Kimono Shop", &[kimono, vrchat, wafu]);
+        file("/tmp/a2", "Kimono Shop", &[vrchat, wafu]);
+        file("/tmp/a3", "Kimono Shop", &[vrchat]);
+        // 別ショップでも同じタグを持つファイルは対象
+        file("/tmp/b1", "Other Shop", &[kimono, pbr]);
+        // 共通点のないファイル・ゴミ箱内のファイルは対象外
+        file("/tmp/c1", "Third Shop", &[quest]);
+        let trashed = file("/tmp/trashed", "Kimono Shop", &[quest]);
+        db.soft_delete_file(trashed).unwrap();
+
+        let suggested = db.suggest_tags_for_file(target, 10).unwrap();
+        let names: Vec<_> = suggested
+            .iter()
+            .map(|t| (t.name.as_str(), t.usage_count))
+            .collect();
+        assert_eq!(names, vec![("VRChat", 3), ("和風", 2), ("PBR", 1)]);
+
+        assert_eq!(db.suggest_tags_for_file(target, 1).unwrap().len(), 1);
+        assert!(db
+            .suggest_tags_for_file(target + 100, 10)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_recently_opened_puts_never_opened_last() {
+        let db = Database::new(":memory:").unwrap();
+        let never = db.add_file(sample_file("/tmp/never")).unwrap();
+        let earlier = db.add_file(sample_file("/tmp/earlier")).unwrap();
+        let latest = db.add_file(sample_file("/tmp/latest")).unwrap();
+
+        assert!(db.mark_file_opened(earlier).unwrap());
+        assert!(db.mark_file_opened_by_path("/tmp/latest").unwrap());
+        assert!(!db.mark_file_opened(never + 100).unwrap());
+        assert!(!db.mark_file_opened_by_path("/tmp/unknown").unwrap());
+        assert!(db
+            .get_file_by_id(latest)
+            .unwrap()
+            .unwrap()
+            .opened_at
+            .is_some());
+
+        set_file_timestamp(&db, earlier, "opened_at", "2024-01-01 00:00:00");
+        set_file_timestamp(&db, latest, "opened_at", "2024-02-01 00:00:00");
+        assert_eq!(
+            paths_of(db.get_recently_opened(10).unwrap()),
+            vec!["/tmp/latest", "/tmp/earlier", "/tmp/never"]
+        );
+        assert_eq!(db.get_file_by_id(never).unwrap().unwrap().opened_at, None);
+    }
+
+    #[test]
+    fn test_set_favorite_toggles_favorites_list() {
+        let db = Database::new(":memory:").unwrap();
+        let avatar = db
+            .add_file(FileRecord {
+                product_name: Some("Avatar".to_string()),
+                ..sample_file("/tmp/avatar")
+            })
+            .unwrap();
+        let cloth = db
+            .add_file(FileRecord {
+                product_name: Some("Cloth".to_string()),
+                ..sample_file("/tmp/cloth")
+            })
+            .unwrap();
+        let trashed = db.add_file(sample_file("/tmp/trashed")).unwrap();
+        assert!(db.get_favorites().unwrap().is_empty());
+
+        assert!(db.set_favorite(cloth, true).unwrap());
+        assert!(db.set_favorite(avatar, true).unwrap());
+        assert!(db.set_favorite(trashed, true).unwrap());
+        db.soft_delete_file(trashed).unwrap();
+        set_file_timestamp(&db, cloth, "favorited_at", "2024-01-01 00:00:00.000");
+        set_file_timestamp(&db, avatar, "favorited_at", "2024-01-01 00:00:00.500");
+        assert_eq!(
+            paths_of(db.get_favorites().unwrap()),
+            vec!["/tmp/avatar", "/tmp/cloth"]
+        );
+        // 登録済みのファイルを再度登録しても順序は変わらない
+        assert!(db.set_favorite(cloth, true).unwrap());
+        assert_eq!(
+            paths_of(db.get_favorites().unwrap()),
+            vec!["/tmp/avatar", "/tmp/cloth"]
+        );
+        assert!(db.get_file_by_id(avatar).unwrap().unwrap().is_favorite);
+
+        assert!(db.set_favorite(avatar, false).unwrap());
+        assert_eq!(paths_of(db.get_favorites().unwrap()), vec!["/tmp/cloth"]);
+        assert!(!db.get_file_by_id(avatar).unwrap().unwrap().is_favorite);
+        assert!(!db.set_favorite(trashed, false).unwrap());
+        assert!(!db.set_favorite(cloth + 100, true).unwrap());
+    }
+
+    #[test]
+    fn test_toggle_favorite_lists_most_recent_first() {
+        let db = Database::new(":memory:").unwrap();
+        let first = db.add_file(sample_file("/tmp/first")).unwrap();
+        let second = db.add_file(sample_file("/tmp/second")).unwrap();
+
+        assert_eq!(db.toggle_favorite(first).unwrap(), Some(true));
+        set_file_timestamp(&db, first, "favorited_at", "2024-01-01 00:00:00.000");
+        assert_eq!(db.toggle_favorite(second).unwrap(), Some(true));
+        assert_eq!(
+            paths_of(db.get_favorites().unwrap()),
+            vec!["/tmp/second", "/tmp/first"]
+        );
+
+        // 解除して再登録すると先頭に戻る
+        assert_eq!(db.toggle_favorite(first).unwrap(), Some(false));
+        assert_eq!(paths_of(db.get_favorites().unwrap()), vec!["/tmp/second"]);
+        assert!(!db.get_file_by_id(first).unwrap().unwrap().is_favorite);
+        set_file_timestamp(&db, second, "favorited_at", "2024-01-01 00:00:00.000");
+        assert_eq!(db.toggle_favorite(first).unwrap(), Some(true));
+        assert_eq!(
+            paths_of(db.get_favorites().unwrap()),
+            vec!["/tmp/first", "/tmp/second"]
+        );
+
+        assert_eq!(db.toggle_favorite(second + 100).unwrap(), None);
+        db.soft_delete_file(second).unwrap();
+        assert_eq!(db.toggle_favorite(second).unwrap(), None);
+    }
+
+    #[test]
+    fn test_hide_adult_excludes_flagged_files() {
+        let db = Database::new(":memory:").unwrap();
+        db.add_file(FileRecord {
+            is_adult: true,
+            ..sample_file("/tmp/r18")
+        })
+        .unwrap();
+        db.add_file(sample_file("/tmp/all_ages")).unwrap();
+
+        // 既定では R-18 のファイルも表示する
+        let pagination = PaginationRequest {
+            page: 1,
+            page_size: 10,
+            sort_by: None,
+            sort_order: None,
+            sort: Vec::new(),
+        };
+        assert_eq!(db.get_files_page(&pagination, false).unwrap().1, 2);
+
+        let (items, total) = db.get_files_page(&pagination, true).unwrap();
+        assert_eq!(total, 1);
+        assert_eq!(items[0].file.file_path, "/tmp/all_ages");
+        assert_eq!(
+            exclude_adult_files(db.get_files_with_tags().unwrap(), true).len(),
+            1
+        );
+
+        let filter = AdvancedSearchFilter {
+            hide_adult: true,
+            ..Default::default()
+        };
+        assert_eq!(advanced_paths(&db, &filter), vec!["/tmp/all_ages"]);
+        assert!(db.get_file_by_path("/tmp/r18").unwrap().unwrap().is_adult);
+    }
+
+    #[test]
+    fn test_tag_expr_search_mixes_and_or_not() {
+        let db = Database::new(":memory:").unwrap();
+        let tag = |name: &str| db.add_tag(name).unwrap();
+        let (vrchat, avatar, cloth, wip) = (tag("VRChat"), tag("Avatar"), tag("Cloth"), tag("WIP"));
+
+        let files = [
+            ("/tmp/avatar", vec![vrchat, avatar]),
+            ("/tmp/cloth", vec![vrchat, cloth]),
+            ("/tmp/cloth_wip", vec![vrchat, cloth, wip]),
+            ("/tmp/other_avatar", vec![avatar]),
+            ("/tmp/untagged", vec![]),
+        ];
+        for (path, tag_ids) in files {
+            let id = db.add_file(sample_file(path)).unwrap();
+            for tag_id in tag_ids {
+                db.add_file_tag(id, tag_id).unwrap();
+            }
+        }
+
+        let paths = |expr: &str| {
+            let mut paths: Vec<String> = db
+                .search_files_by_tag_expr(&crate::tag_query::parse(expr).unwrap())
+                .unwrap()
+                .into_iter()
+                .map(|f| f.file.file_path)
+                .collect();
+            paths.sort();
+            paths
+        };
+
+        assert_eq!(
+            paths("VRChat AND (Avatar OR Cloth) AND NOT WIP"),
+            vec!["/tmp/avatar", "/tmp/cloth"]
+        );
+        assert_eq!(
+            paths("Avatar OR Cloth AND WIP"),
+            vec!["/tmp/avatar", "/tmp/cloth_wip", "/tmp/other_avatar"]
+        );
+        // 存在しないタグは常に偽
+        assert!(paths("VRChat AND Unknown").is_empty());
+        assert_eq!(paths("NOT Unknown").len(), 5);
+    }
+
+    #[test]
+    fn test_shop_summary_counts_files_per_shop() {
+        let db = Database::new(":memory:").unwrap();
+        let files = [
+            ("/tmp/a1", Some("Alpha"), 100),
+            ("/tmp/b1", Some("Beta"), 1000),
+            ("/tmp/b2", Some("Beta"), 2000),
+            ("/tmp/n1", None, 10),
+        ];
+        for (path, shop, size) in files {
+            db.add_file(FileRecord {
+                author_name: shop.map(str::to_string),
+                file_size: size,
+                ..sample_file(path)
+            })
+            .unwrap();
+        }
+        let trashed = db.add_file(sample_file("/tmp/trashed")).unwrap();
+        db.soft_delete_file(trashed).unwrap();
+
+        let summary = |shop: &str, file_count: u32, total_size: i64| ShopFileSummary {
+            shop_name: shop.to_string(),
+            file_count,
+            total_size: Some(total_size),
+        };
+        assert_eq!(
+            db.get_shop_summary().unwrap(),
+            vec![
+                summary("Beta", 2, 3000),
+                summary("Alpha", 1, 100),
+                summary("Unknown_Shop", 1, 10),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_repair_removes_dangling_file_tags() {
+        let db = Database::new(":memory:").unwrap();
+        let file_id = db.add_file(sample_file("/tmp/kept")).unwrap();
+        let gone_id = db.add_file(sample_file("/tmp/gone")).unwrap();
+        let kept = db.add_tag("VRChat").unwrap();
+        let dangling_only = db.add_tag("衣装").unwrap();
+        db.add_file_tag(file_id, kept).unwrap();
+        db.add_file_tag(gone_id, kept).unwrap();
+        db.add_file_tag(gone_id, dangling_only).unwrap();
+
+        // 外部キーが無効だった頃と同様に、関連付けを残したままファイルを消す
+        db.conn.execute_batch("PRAGMA foreign_keys = OFF").unwrap();
+        db.conn
+            .execute("DELETE FROM files WHERE id = ?1", [gone_id])
+            .unwrap();
+        db.conn.execute_batch("PRAGMA foreign_keys = ON").unwrap();
+
+        let report = db.repair().unwrap();
+        assert_eq!(
+            report,
+            RepairReport {
+                dangling_file_tags_removed: 2,
+                usage_counts_corrected: 2,
+                unused_tags_removed: 1,
+                integrity_ok: true,
+                integrity_errors: vec![],
+            }
+        );
+
+        let count: i64 = db
+            .conn
+            .query_row("SELECT COUNT(*) FROM file_tags", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 1);
+        let tags = db.get_all_tags().unwrap();
+        assert_eq!(tags.len(), 1);
+        assert_eq!((tags[0].name.as_str(), tags[0].usage_count), ("VRChat", 1));
+
+        // 2回目は修復するものがない
+        let again = db.repair().unwrap();
+        assert_eq!(
+            (
+                again.dangling_file_tags_removed,
+                again.usage_counts_corrected,
+                again.unused_tags_removed
+            ),
+            (0, 0, 0)
+        );
+    }
+
+    #[test]
+    fn test_library_stats_aggregates() {
+        let db = Database::new(":memory:").unwrap();
+        let files = [
+            (
+                "/tmp/a1",
+                Some("Alpha"),
+                Some("https://alpha.booth.pm/items/1"),
+                100,
+            ),
+            ("/tmp/a2", Some("Alpha"), None, 200),
+            ("/tmp/b1", Some("Beta"), Some(" "), 1000),
+            ("/tmp/n1", None, None, 10),
+        ];
+        let mut ids = Vec::new();
+        for (path, shop, url, size) in files {
+            ids.push(
+                db.add_file(FileRecord {
+                    author_name: shop.map(str::to_string),
+                    product_url: url.map(str::to_string),
+                    file_size: size,
+                    ..sample_file(path)
+                })
+                .unwrap(),
+            );
+        }
+        let trashed = db
+            .add_file(FileRecord {
+                author_name: Some("Gamma".to_string()),
+                ..sample_file("/tmp/trashed")
+            })
+            .unwrap();
+
+        let vrchat = db.add_tag("VRChat").unwrap();
+        let outfit = db.add_tag("衣装").unwrap();
+        db.add_tag("未使用").unwrap();
+        for &id in &ids {
+            db.add_file_tag(id, vrchat).unwrap();
+        }
+        db.add_file_tag(ids[0], outfit).unwrap();
+        db.add_file_tag(trashed, outfit).unwrap();
+        db.add_file_tag(trashed, outfit).unwrap();
+        db.soft_delete_file(trashed).unwrap();
+
+        let stats = db.get_library_stats(1).unwrap();
+        assert_eq!(stats.total_files, 4);
+        assert_eq!(stats.total_size, 1310);
+        assert_eq!(stats.total_tags, 3);
+        assert_eq!(stats.shop_count, 2);
+        assert_eq!(stats.files_without_booth_url, 3);
+        assert_eq!(
+            stats.top_tags,
+            vec![TagUsage {
+                name: "VRChat".to_string(),
+                color: crate::config::tags::DEFAULT_TAG_COLOR.to_string(),
+                usage_count: 4,
+            }]
+        );
+
+        // ゴミ箱内のファイルへの関連付けは数えない
+        let top = db.get_library_stats(10).unwrap().top_tags;
+        assert_eq!(top.len(), 2);
+        assert_eq!((top[1].name.as_str(), top[1].usage_count), ("衣装", 1));
+    }
+
+    #[test]
+    fn test_shop_breakdown_groups_and_orders_by_total_price() {
+        let db = Database::new(":memory:").unwrap();
+        let purchases = [
+            ("/tmp/a1", Some("Alpha"), Some(500), 100),
+            ("/tmp/a2", Some("Alpha"), Some(700), 200),
+            ("/tmp/b1", Some("Beta"), Some(3000), 1000),
+            ("/tmp/n1", None, None, 10),
+            ("/tmp/n2", Some(" "), Some(100), 20),
+        ];
+        for (path, shop, price, size) in purchases {
+            db.add_file(FileRecord {
+                author_name: shop.map(str::to_string),
+                price,
+                file_size: size,
+                ..sample_file(path)
+            })
+            .unwrap();
+        }
+        let trashed = db
+            .add_file(FileRecord {
+                author_name: Some("Beta".to_string()),
+                price: Some(9999),
+                ..sample_file("/tmp/b2")
+            })
+            .unwrap();
+        db.soft_delete_file(trashed).unwrap();
+
+        let breakdown = db.get_shop_breakdown().unwrap();
+
+        let rows: Vec<(&str, i64, i64, i64)> = breakdown
+            .iter()
+            .map(|s| {
+                (
+                    s.shop_name.as_str(),
+                    s.item_count,
+                    s.total_price,
+                    s.total_size,
+                )
+            })
+            .collect();
+        assert_eq!(
+            rows,
+            vec![
+                ("Beta", 1, 3000, 1000),
+                ("Alpha", 2, 1200, 300),
+                ("Unknown", 2, 100, 30),
+            ]
+        );
+    }
+}
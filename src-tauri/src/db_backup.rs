@@ -0,0 +1,211 @@
+use crate::config::database;
+use crate::database::Database;
+use crate::{AppError, AppResult};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// データベースのバックアップを作成し、書き出したファイルのパスを返す
+///
+/// SQLite のオンラインバックアップを使うため、他の接続が使用中でも整合したコピーになる。
+/// `dest` 未指定時はデータベースと同じフォルダの `backups/` に `{ファイル名}-{日時}.db` で書き出し、
+/// そこでは直近 `BACKUP_KEEP_COUNT` 件だけを残して古いものを削除する。
+/// 利用者が指定した `dest` には他のファイルもあり得るため、古いものの削除はしない。
+pub fn backup_database(db: &Database, db_path: &str, dest: Option<&Path>) -> AppResult<PathBuf> {
+    if db_path == ":memory:" {
+        return Err(AppError::validation(
+            "db_path",
+            "In-memory database cannot be backed up",
+        ));
+    }
+
+    let db_path = Path::new(db_path);
+    let dir = match dest {
+        Some(dest) => dest.to_path_buf(),
+        None => db_path
+            .parent()
+            .unwrap_or(Path::new(""))
+            .join(database::BACKUP_DIR_NAME),
+    };
+    fs::create_dir_all(&dir)?;
+
+    let prefix = backup_prefix(db_path);
+    let target = dir.join(format!(
+        "{prefix}{}.db",
+        chrono::Local::now().format("%Y%m%d-%H%M%S%3f")
+    ));
+    db.backup_to(&target)?;
+    if dest.is_none() {
+        prune_backups(&dir, &prefix, database::BACKUP_KEEP_COUNT)?;
+    }
+
+    Ok(target)
+}
+
+/// 一括削除・最適化など元に戻せない操作の前にバックアップを作成する
+///
+/// 失敗した場合は操作を中止できるようエラーを返す（":memory:" では何もしない）。
+pub fn backup_before_destructive(db: &Database, db_path: &str, operation: &str) -> AppResult<()> {
+    if db_path == ":memory:" {
+        return Ok(());
+    }
+    let backup = backup_database(db, db_path, None)?;
+    log::info!(
+        "Backed up database before {operation}: {}",
+        backup.display()
+    );
+    Ok(())
+}
+
+/// スキーマの移行が必要な既存のデータベースであれば、移行前にバックアップを作成する
+///
+/// 移行不要（記録済みのバージョンが最新）・新規作成・":memory:" では何もしない。
+/// 失敗しても起動は続ける（警告のみ記録）。
+pub fn backup_before_migration(db: &Database, db_path: &str, outdated: bool) {
+    if !outdated || db_path == ":memory:" {
+        return;
+    }
+    if let Err(e) = backup_database(db, db_path, None) {
+        log::warn!("Failed to back up {db_path} before migration: {e}");
+    }
+}
+
+/// バックアップ対象となる既存のデータベースファイルか（開く前に判定する）
+pub fn is_existing_database(db_path: &str) -> bool {
+    db_path != ":memory:"
+        && fs::metadata(db_path)
+            .map(|metadata| metadata.is_file() && metadata.len() > 0)
+            .unwrap_or(false)
+}
+
+// バックアップファイル名の接頭辞（例: "booth_organizer-"）
+fn backup_prefix(db_path: &Path) -> String {
+    let stem = db_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "database".to_string());
+    format!("{stem}-")
+}
+
+// 日時入りのファイル名は名前順が作成順になるため、名前順で古いものから削除する
+fn prune_backups(dir: &Path, prefix: &str, keep: usize) -> AppResult<usize> {
+    let mut backups = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.is_file()
+                && path.file_name().is_some_and(|name| {
+                    let name = name.to_string_lossy();
+                    name.starts_with(prefix) && name.ends_with(".db")
+                })
+        })
+        .collect::<Vec<_>>();
+    backups.sort();
+
+    let excess = backups.len().saturating_sub(keep);
+    for path in &backups[..excess] {
+        fs::remove_file(path)?;
+    }
+    Ok(excess)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::sample_file;
+
+    #[test]
+    fn test_backup_is_openable_copy() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("library.db");
+        let db_path = db_path.to_string_lossy();
+        let db = Database::new(&db_path).unwrap();
+        db.add_file(sample_file("/tmp/avatar")).unwrap();
+
+        let backup = backup_database(&db, &db_path, None).unwrap();
+        assert_eq!(backup.parent().unwrap(), dir.path().join("backups"));
+        assert!(backup
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .starts_with("library-"));
+
+        let restored = Database::open(&backup.to_string_lossy()).unwrap();
+        assert!(restored.integrity_check().unwrap().is_empty());
+        let files = restored.get_all_files().unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].file_path, "/tmp/avatar");
+    }
+
+    #[test]
+    fn test_outdated_database_is_backed_up_once_on_open() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("library.db");
+        let db_path = db_path.to_string_lossy();
+        let backups = dir.path().join("backups");
+
+        // 新規作成時はバックアップしない
+        drop(Database::new(&db_path).unwrap());
+        assert!(!backups.exists());
+
+        // スキーマが最新なら開き直してもバックアップしない
+        let db = Database::new(&db_path).unwrap();
+        assert_eq!(db.schema_version().unwrap(), database::SCHEMA_VERSION);
+        drop(db);
+        assert!(!backups.exists());
+
+        // バージョンを記録していない（移行前の）データベースは1回だけバックアップする
+        rusqlite::Connection::open(&*db_path)
+            .unwrap()
+            .execute_batch("PRAGMA user_version = 0")
+            .unwrap();
+        drop(Database::new(&db_path).unwrap());
+        assert_eq!(fs::read_dir(&backups).unwrap().count(), 1);
+        drop(Database::new(&db_path).unwrap());
+        assert_eq!(fs::read_dir(&backups).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_backup_to_custom_dest_keeps_existing_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("library.db");
+        let db_path = db_path.to_string_lossy();
+        let db = Database::new(&db_path).unwrap();
+
+        // 利用者のフォルダにある同じ接頭辞のファイルは消さない
+        let dest = dir.path().join("mine");
+        fs::create_dir_all(&dest).unwrap();
+        for i in 0..database::BACKUP_KEEP_COUNT + 2 {
+            fs::write(dest.join(format!("library-own{i}.db")), "").unwrap();
+        }
+        backup_database(&db, &db_path, Some(&dest)).unwrap();
+        assert_eq!(
+            fs::read_dir(&dest).unwrap().count(),
+            database::BACKUP_KEEP_COUNT + 3
+        );
+    }
+
+    #[test]
+    fn test_prune_backups_keeps_most_recent() {
+        let dir = tempfile::tempdir().unwrap();
+        for stamp in ["20240101", "20240103", "20240102", "20240104"] {
+            fs::write(dir.path().join(format!("library-{stamp}.db")), "").unwrap();
+        }
+        fs::write(dir.path().join("other-20240101.db"), "").unwrap();
+
+        assert_eq!(prune_backups(dir.path(), "library-", 2).unwrap(), 2);
+
+        let mut remaining = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+        remaining.sort();
+        assert_eq!(
+            remaining,
+            vec![
+                "library-20240103.db",
+                "library-20240104.db",
+                "other-20240101.db"
+            ]
+        );
+    }
+}
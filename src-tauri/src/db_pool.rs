@@ -0,0 +1,379 @@
+use crate::config::database;
+use crate::database::Database;
+use crate::{AppError, AppResult};
+use std::ops::Deref;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// SQLite接続のプール
+///
+/// 接続ごとに `Database` を保持し、コマンドは使用中のみ1本を借り出す。
+/// ファイルDBでは WAL により読み取りが並行して進み、書き込み同士は
+/// SQLite のロック（busy_timeout で待機）で直列化される。
+/// ":memory:" は接続ごとに別DBになるため、常に1本だけで運用する。
+/// VACUUM などの排他的な操作は `get_exclusive` で他の接続がすべて返却されてから行う。
+///
+/// 非同期のコマンドからは `get` / `get_exclusive` を使う（返却待ちは別スレッドで行い、
+/// tokio のワーカーを止めない）。スレッドや同期処理からは `*_blocking` を使う。
+/// `clone` は同じプールを共有する（別スレッドへ渡すときに使う）。
+#[derive(Clone)]
+pub struct DatabasePool {
+    inner: Arc<PoolInner>,
+}
+
+struct PoolInner {
+    db_path: String,
+    max_size: usize,
+    timeout: Duration,
+    state: Mutex<PoolState>,
+    available: Condvar,
+}
+
+struct PoolState {
+    idle: Vec<Database>,
+    open: usize,
+    /// 排他的に借り出されている間は他の借り出しを待たせる
+    exclusive: bool,
+}
+
+impl DatabasePool {
+    pub fn new(db_path: &str) -> AppResult<Self> {
+        let max_size = if db_path == ":memory:" {
+            1
+        } else {
+            database::POOL_MAX_SIZE
+        };
+        Self::with_max_size(db_path, max_size)
+    }
+
+    pub fn with_max_size(db_path: &str, max_size: usize) -> AppResult<Self> {
+        // 最初の接続でスキーマの作成・移行を済ませておく
+        let first = Database::new(db_path)?;
+
+        Ok(Self {
+            inner: Arc::new(PoolInner {
+                db_path: db_path.to_string(),
+                max_size: max_size.max(1),
+                timeout: Duration::from_secs(database::POOL_TIMEOUT_SECS),
+                state: Mutex::new(PoolState {
+                    idle: vec![first],
+                    open: 1,
+                    exclusive: false,
+                }),
+                available: Condvar::new(),
+            }),
+        })
+    }
+
+    /// 接続を1本借り出す。上限まで使用中の場合は返却を待つ
+    ///
+    /// 空いている接続があればそのまま返し、待つ必要がある場合は別スレッドで待つ。
+    pub async fn get(&self) -> AppResult<PooledDatabase> {
+        if let Some(db) = self.inner.try_take_idle()? {
+            return Ok(db);
+        }
+        let inner = Arc::clone(&self.inner);
+        tokio::task::spawn_blocking(move || inner.checkout())
+            .await
+            .map_err(|e| AppError::database_lock(e.to_string()))?
+    }
+
+    /// 他の接続がすべて返却されるのを待ってから1本を借り出す
+    ///
+    /// 返却されるまでの間、他の借り出しは待たされる（書き込み中の処理と並行させたくない操作用）。
+    pub async fn get_exclusive(&self) -> AppResult<PooledDatabase> {
+        let inner = Arc::clone(&self.inner);
+        tokio::task::spawn_blocking(move || inner.checkout_exclusive())
+            .await
+            .map_err(|e| AppError::database_lock(e.to_string()))?
+    }
+
+    /// `get` の同期版（呼び出したスレッドで返却を待つ）
+    pub fn get_blocking(&self) -> AppResult<PooledDatabase> {
+        self.inner.checkout()
+    }
+
+    /// `get_exclusive` の同期版（呼び出したスレッドで返却を待つ）
+    pub fn get_exclusive_blocking(&self) -> AppResult<PooledDatabase> {
+        self.inner.checkout_exclusive()
+    }
+
+    /// 現在のデータベースファイルのパス（":memory:" の場合はそのまま）
+    pub fn path(&self) -> &str {
+        &self.inner.db_path
+    }
+}
+
+impl PoolInner {
+    // 待たずに借り出せる接続があれば返す
+    fn try_take_idle(self: &Arc<Self>) -> AppResult<Option<PooledDatabase>> {
+        let mut state = self.lock_state()?;
+        if state.exclusive {
+            return Ok(None);
+        }
+        Ok(state.idle.pop().map(|db| self.lend(db, false)))
+    }
+
+    fn checkout(self: &Arc<Self>) -> AppResult<PooledDatabase> {
+        let deadline = Instant::now() + self.timeout;
+        let mut state = self.lock_state()?;
+
+        loop {
+            if state.exclusive {
+                state = self.wait_until(state, deadline)?;
+                continue;
+            }
+
+            if let Some(db) = state.idle.pop() {
+                return Ok(self.lend(db, false));
+            }
+
+            if state.open < self.max_size {
+                state.open += 1;
+                drop(state);
+
+                return match Database::open(&self.db_path) {
+                    Ok(db) => Ok(self.lend(db, false)),
+                    Err(e) => {
+                        self.lock_state()?.open -= 1;
+                        self.available.notify_one();
+                        Err(e.into())
+                    }
+                };
+            }
+
+            state = self.wait_until(state, deadline)?;
+        }
+    }
+
+    fn checkout_exclusive(self: &Arc<Self>) -> AppResult<PooledDatabase> {
+        let deadline = Instant::now() + self.timeout;
+        let mut state = self.lock_state()?;
+
+        loop {
+            if !state.exclusive && state.idle.len() == state.open {
+                let db = match state.idle.pop() {
+                    Some(db) => db,
+                    None => {
+                        let db = Database::open(&self.db_path)?;
+                        state.open += 1;
+                        db
+                    }
+                };
+                state.exclusive = true;
+                return Ok(self.lend(db, true));
+            }
+
+            state = self.wait_until(state, deadline)?;
+        }
+    }
+
+    fn lend(self: &Arc<Self>, db: Database, exclusive: bool) -> PooledDatabase {
+        PooledDatabase {
+            pool: Arc::clone(self),
+            db: Some(db),
+            exclusive,
+        }
+    }
+
+    // 接続の返却を期限まで待つ
+    fn wait_until<'a>(
+        &self,
+        state: std::sync::MutexGuard<'a, PoolState>,
+        deadline: Instant,
+    ) -> AppResult<std::sync::MutexGuard<'a, PoolState>> {
+        let now = Instant::now();
+        if now >= deadline {
+            return Err(AppError::database_lock(
+                "Timed out waiting for a database connection",
+            ));
+        }
+
+        Ok(self
+            .available
+            .wait_timeout(state, deadline - now)
+            .map_err(|e| AppError::database_lock(e.to_string()))?
+            .0)
+    }
+
+    fn lock_state(&self) -> AppResult<std::sync::MutexGuard<'_, PoolState>> {
+        self.state
+            .lock()
+            .map_err(|e| AppError::database_lock(e.to_string()))
+    }
+}
+
+/// プールから借り出した接続。drop 時にプールへ返却される
+///
+/// 借り出しを待つタスクが取り消されても、受け取り損ねた接続は drop で返却される。
+pub struct PooledDatabase {
+    pool: Arc<PoolInner>,
+    db: Option<Database>,
+    exclusive: bool,
+}
+
+impl Deref for PooledDatabase {
+    type Target = Database;
+
+    fn deref(&self) -> &Database {
+        self.db
+            .as_ref()
+            .expect("pooled connection is present until drop")
+    }
+}
+
+impl Drop for PooledDatabase {
+    fn drop(&mut self) {
+        if let Some(db) = self.db.take() {
+            match self.pool.state.lock() {
+                Ok(mut state) => {
+                    state.idle.push(db);
+                    state.exclusive &= !self.exclusive;
+                }
+                Err(mut poisoned) => {
+                    let state = poisoned.get_mut();
+                    state.open -= 1;
+                    state.exclusive &= !self.exclusive;
+                }
+            }
+            // 排他的な借り出しは全接続の返却を待つため、待機中のすべてを起こす
+            self.pool.available.notify_all();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::sample_file;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Barrier};
+
+    #[test]
+    fn test_reads_proceed_while_write_transaction_is_open() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("pool.db");
+        let pool = Arc::new(DatabasePool::with_max_size(&path.to_string_lossy(), 4).unwrap());
+        pool.get_blocking()
+            .unwrap()
+            .add_file(sample_file("/existing"))
+            .unwrap();
+
+        let write_started = Arc::new(Barrier::new(4));
+        let reads_done = Arc::new(Barrier::new(4));
+
+        let writer = {
+            let pool = pool.clone();
+            let write_started = write_started.clone();
+            let reads_done = reads_done.clone();
+            std::thread::spawn(move || {
+                let db = pool.get_blocking().unwrap();
+                db.in_transaction(|db| {
+                    db.add_file(sample_file("/new"))?;
+                    write_started.wait();
+                    // 書き込み中のまま読み取りスレッドの完了を待つ
+                    reads_done.wait();
+                    Ok::<_, rusqlite::Error>(())
+                })
+                .unwrap();
+            })
+        };
+
+        let readers: Vec<_> = (0..3)
+            .map(|_| {
+                let pool = pool.clone();
+                let write_started = write_started.clone();
+                let reads_done = reads_done.clone();
+                std::thread::spawn(move || {
+                    write_started.wait();
+                    let count = pool.get_blocking().unwrap().get_all_files().unwrap().len();
+                    reads_done.wait();
+                    count
+                })
+            })
+            .collect();
+
+        // 未コミットの書き込みは見えず、読み取りはブロックされない
+        for reader in readers {
+            assert_eq!(reader.join().unwrap(), 1);
+        }
+        writer.join().unwrap();
+
+        assert_eq!(
+            pool.get_blocking().unwrap().get_all_files().unwrap().len(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_memory_pool_reuses_single_connection() {
+        let pool = DatabasePool::new(":memory:").unwrap();
+
+        pool.get_blocking()
+            .unwrap()
+            .add_file(sample_file("/a"))
+            .unwrap();
+        assert_eq!(
+            pool.get_blocking().unwrap().get_all_files().unwrap().len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_exclusive_waits_for_other_connections() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("pool.db");
+        let pool = DatabasePool::with_max_size(&path.to_string_lossy(), 4).unwrap();
+        let released = AtomicBool::new(false);
+
+        std::thread::scope(|scope| {
+            // 借り出し中の接続が返却されるまで待つ
+            let held = pool.get_blocking().unwrap();
+            scope.spawn(|| {
+                std::thread::sleep(Duration::from_millis(50));
+                released.store(true, Ordering::SeqCst);
+                drop(held);
+            });
+            let exclusive = pool.get_exclusive_blocking().unwrap();
+            assert!(released.load(Ordering::SeqCst));
+
+            // 排他的に借り出している間は他の借り出しを待たせる
+            released.store(false, Ordering::SeqCst);
+            let waiter = scope.spawn(|| {
+                pool.get_blocking().unwrap().get_all_files().unwrap();
+                released.load(Ordering::SeqCst)
+            });
+            std::thread::sleep(Duration::from_millis(50));
+            released.store(true, Ordering::SeqCst);
+            drop(exclusive);
+            assert!(waiter.join().unwrap());
+        });
+    }
+
+    #[tokio::test]
+    async fn test_async_get_waits_without_blocking_the_runtime() {
+        let pool = Arc::new(DatabasePool::new(":memory:").unwrap());
+        let held = pool.get().await.unwrap();
+
+        let waiter = {
+            let pool = pool.clone();
+            tokio::spawn(
+                async move { pool.get().await.map(|db| db.get_all_files().unwrap().len()) },
+            )
+        };
+        // 単一スレッドのランタイムでも、返却を待つ間に他のタスク（ここでは返却）が進む
+        tokio::task::yield_now().await;
+        drop(held);
+
+        assert_eq!(waiter.await.unwrap().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_get_times_out_when_exhausted() {
+        let mut pool = DatabasePool::new(":memory:").unwrap();
+        Arc::get_mut(&mut pool.inner).unwrap().timeout = Duration::from_millis(50);
+
+        let _held = pool.get_blocking().unwrap();
+        assert!(pool.get_blocking().is_err());
+    }
+}
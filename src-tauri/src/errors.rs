@@ -1,3 +1,5 @@
+use crate::api_types::ApiError;
+use crate::booth_client::BoothClientError;
 use thiserror::Error;
 
 /// アプリケーション全体で使用する統一エラー型
@@ -7,7 +9,7 @@ pub enum AppError {
     Database(#[from] rusqlite::Error),
 
     #[error("BOOTH client error: {0}")]
-    BoothClient(#[from] crate::booth_client::BoothClientError),
+    BoothClient(#[source] BoothClientError),
 
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
@@ -33,6 +35,15 @@ pub enum AppError {
     #[error("Validation error: {field} - {message}")]
     Validation { field: String, message: String },
 
+    #[error("Archive is password protected: {0}")]
+    PasswordRequired(String),
+
+    #[error("Incorrect archive password: {0}")]
+    InvalidPassword(String),
+
+    #[error("BOOTH item is behind an age confirmation page: {0}")]
+    AgeRestricted(String),
+
     #[error("Configuration error: {0}")]
     Configuration(String),
 
@@ -127,6 +138,16 @@ impl AppError {
         Self::TagCreation(message.into())
     }
 
+    /// BOOTHクライアントのエラーを変換（年齢確認が必要な商品はフロントエンドで案内できるよう専用のエラーにする）
+    /// プロキシに接続できなかった場合は、フロントエンドのネットワークエラーの扱いに合わせて Network にする
+    pub fn from_booth(err: BoothClientError) -> Self {
+        match err {
+            BoothClientError::AgeRestricted { url } => Self::AgeRestricted(url),
+            BoothClientError::ProxyError { source, .. } => Self::Network(source),
+            other => Self::BoothClient(other),
+        }
+    }
+
     /// カスタムエラーを作成 (任意の文字列エラーメッセージ用)
     pub fn custom(message: impl Into<String>) -> Self {
         Self::Custom(message.into())
@@ -155,6 +176,10 @@ impl AppError {
         match self {
             AppError::Validation { .. } => ErrorCategory::UserError,
             AppError::UrlParse(_) => ErrorCategory::UserError,
+            AppError::BoothClient(BoothClientError::InvalidUrl { .. }) => ErrorCategory::UserError,
+            AppError::PasswordRequired(_) => ErrorCategory::UserError,
+            AppError::InvalidPassword(_) => ErrorCategory::UserError,
+            AppError::AgeRestricted(_) => ErrorCategory::UserError,
             AppError::Configuration(_) => ErrorCategory::ConfigError,
             AppError::Database(_) => ErrorCategory::SystemError,
             AppError::Network(_) => ErrorCategory::SystemError,
@@ -183,9 +208,7 @@ impl AppError {
                 "データベースエラーが発生しました。アプリケーションを再起動してください。"
                     .to_string()
             }
-            AppError::BoothClient(_) => {
-                "BOOTH商品情報の取得に失敗しました。URLを確認してください。".to_string()
-            }
+            AppError::BoothClient(err) => err.user_message(),
             AppError::Network(_) => {
                 "ネットワークエラーが発生しました。インターネット接続を確認してください。"
                     .to_string()
@@ -203,6 +226,13 @@ impl AppError {
             }
             AppError::Internal(err) => format!("内部エラーが発生しました: {err}"),
             AppError::Validation { field, message } => format!("{field}: {message}"),
+            AppError::PasswordRequired(name) => {
+                format!("{name} はパスワードで保護されています。パスワードを入力してください。")
+            }
+            AppError::InvalidPassword(name) => format!("{name} のパスワードが正しくありません。"),
+            AppError::AgeRestricted(url) => {
+                BoothClientError::AgeRestricted { url: url.clone() }.user_message()
+            }
             AppError::Configuration(msg) => format!("設定エラー: {msg}"),
             AppError::Encoding { message } => format!("文字エンコーディングエラー: {message}"),
             AppError::Serialization(_) => "データの変換中にエラーが発生しました。".to_string(),
@@ -223,6 +253,42 @@ impl AppError {
     pub fn is_retryable(&self) -> bool {
         matches!(self.category(), ErrorCategory::SystemError)
     }
+
+    /// フロントエンドで分岐に使うエラーコード（バリアント名）
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::Database(_) => "Database",
+            AppError::BoothClient(_) => "BoothClient",
+            AppError::Io(_) => "Io",
+            AppError::Serialization(_) => "Serialization",
+            AppError::Network(_) => "Network",
+            AppError::UrlParse(_) => "UrlParse",
+            AppError::ZipExtraction(_) => "ZipExtraction",
+            AppError::Internal(_) => "Internal",
+            AppError::Encoding { .. } => "Encoding",
+            AppError::Validation { .. } => "Validation",
+            AppError::PasswordRequired(_) => "PasswordRequired",
+            AppError::InvalidPassword(_) => "InvalidPassword",
+            AppError::AgeRestricted(_) => "AgeRestricted",
+            AppError::Configuration(_) => "Configuration",
+            AppError::Unknown(_) => "Unknown",
+            AppError::DatabaseLock(_) => "DatabaseLock",
+            AppError::FileSave(_) => "FileSave",
+            AppError::FileRetrieval(_) => "FileRetrieval",
+            AppError::FileDeletion(_) => "FileDeletion",
+            AppError::FileUpdate(_) => "FileUpdate",
+            AppError::TagOperation(_) => "TagOperation",
+            AppError::TagCreation(_) => "TagCreation",
+            AppError::Custom(_) => "Custom",
+        }
+    }
+}
+
+/// BoothClientError から AppError への変換（from_booth と同じ振り分け）
+impl From<BoothClientError> for AppError {
+    fn from(err: BoothClientError) -> Self {
+        Self::from_booth(err)
+    }
 }
 
 /// String から AppError への変換 (既存のエラーメッセージを保持)
@@ -239,6 +305,31 @@ impl From<&str> for AppError {
     }
 }
 
+/// AppError を Tauri コマンドの構造化エラーに変換
+/// ユーザー操作以外のエラーはログファイルにも error レベルで記録する
+impl From<AppError> for ApiError {
+    fn from(err: AppError) -> Self {
+        match err.category() {
+            ErrorCategory::UserError => log::warn!("{}: {err}", err.code()),
+            _ => log::error!("{}: {err}", err.code()),
+        }
+
+        ApiError {
+            code: err.code().to_string(),
+            message: err.user_message(),
+            details: Some(serde_json::Value::String(err.to_string())),
+            retryable: err.is_retryable(),
+        }
+    }
+}
+
+/// 文字列のエラーは Custom として変換 (既存のエラーメッセージを保持)
+impl From<String> for ApiError {
+    fn from(msg: String) -> Self {
+        AppError::Custom(msg).into()
+    }
+}
+
 /// AppError を String に変換 (エラーメッセージの表示)
 impl From<AppError> for String {
     fn from(err: AppError) -> Self {
@@ -294,3 +385,40 @@ impl AppError {
 pub fn to_tauri_result<T>(result: AppResult<T>) -> Result<T, String> {
     result.map_err(|e| e.to_string())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_database_lock_error_is_retryable() {
+        let err: ApiError = AppError::database_lock("Database lock error: poisoned").into();
+
+        assert_eq!(err.code, "DatabaseLock");
+        assert_eq!(err.message, "Database lock error: poisoned");
+        assert!(err.retryable);
+    }
+
+    #[test]
+    fn test_validation_error_is_not_retryable() {
+        let err: ApiError = AppError::validation("overwrite_policy", "Unsupported").into();
+
+        assert_eq!(err.code, "Validation");
+        assert_eq!(err.message, "overwrite_policy: Unsupported");
+        assert_eq!(
+            err.details,
+            Some(serde_json::Value::String(
+                "Validation error: overwrite_policy - Unsupported".to_string()
+            ))
+        );
+        assert!(!err.retryable);
+    }
+
+    #[test]
+    fn test_string_error_becomes_custom() {
+        let err = ApiError::from("予期しない状態".to_string());
+
+        assert_eq!(err.code, "Custom");
+        assert_eq!(err.message, "予期しない状態");
+    }
+}
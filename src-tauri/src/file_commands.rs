@@ -1,5 +1,10 @@
-use crate::database::{FileRecord, FileUpdateFields, FileWithTags};
-use crate::{AppError, AppState};
+use crate::api_types::{ApiError, GetFilesPaginatedRequest, PaginationResponse};
+use crate::booth_client::{is_valid_booth_url, normalize_booth_url};
+use crate::config::{files, pagination};
+use crate::database::{exclude_adult_files, Database, FileRecord, FileUpdateFields, FileWithTags};
+use crate::db_backup;
+use crate::{AppError, AppResult, AppState};
+use std::path::{Path, PathBuf};
 
 // データベース関連のTauriコマンド
 #[tauri::command]
@@ -15,11 +20,12 @@ pub async fn save_file_to_db(
     price: Option<i32>,
     description: Option<String>,
     thumbnail_url: Option<String>,
-) -> Result<i64, String> {
+) -> Result<i64, ApiError> {
     let db = state
         .db
-        .lock()
-        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")).to_string())?;
+        .get()
+        .await
+        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
 
     let modified_time = std::fs::metadata(&file_path)
         .map(|meta| {
@@ -50,54 +56,403 @@ pub async fn save_file_to_db(
         price,
         description,
         thumbnail_url,
-        product_url,
+        product_url: product_url.as_deref().map(normalize_booth_url),
+        deleted_at: None,
+        file_hash: None,
+        encoding_info: None,
+        archive_path: None,
+        booth_category: None,
+        is_adult: false,
+        opened_at: None,
+        is_favorite: false,
+        user_note: None,
+        metadata: None,
     };
 
-    db.add_file(file_record).map_err(|e| {
-        AppError::file_save(format!("Failed to save file to database: {e}")).to_string()
-    })
+    db.add_file(file_record)
+        .map_err(|e| AppError::file_save(format!("Failed to save file to database: {e}")).into())
 }
 
 #[tauri::command]
 pub async fn get_all_files_from_db(
     state: tauri::State<'_, AppState>,
-) -> Result<Vec<FileRecord>, String> {
+) -> Result<Vec<FileRecord>, ApiError> {
     let db = state
         .db
-        .lock()
-        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")).to_string())?;
+        .get()
+        .await
+        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
 
     db.get_all_files().map_err(|e| {
-        AppError::file_retrieval(format!("Failed to get files from database: {e}")).to_string()
+        AppError::file_retrieval(format!("Failed to get files from database: {e}")).into()
     })
 }
 
 #[tauri::command]
 pub async fn get_files_with_tags_from_db(
     state: tauri::State<'_, AppState>,
-) -> Result<Vec<FileWithTags>, String> {
+    hide_adult: Option<bool>,
+) -> Result<Vec<FileWithTags>, ApiError> {
+    let db = state
+        .db
+        .get()
+        .await
+        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
+
+    db.get_files_with_tags()
+        .map(|files| exclude_adult_files(files, hide_adult.unwrap_or(false)))
+        .map_err(|e| AppError::file_retrieval(format!("Failed to get files with tags: {e}")).into())
+}
+
+#[tauri::command]
+pub async fn get_files_paginated_db(
+    state: tauri::State<'_, AppState>,
+    request: GetFilesPaginatedRequest,
+) -> Result<PaginationResponse<FileWithTags>, ApiError> {
+    let pagination = request.pagination.clamped();
+    let db = state
+        .db
+        .get()
+        .await
+        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
+
+    let (items, total_count) = db
+        .get_files_page(&pagination, request.hide_adult)
+        .map_err(|e| match e {
+            AppError::Database(e) => AppError::file_retrieval(format!("Failed to get files: {e}")),
+            e => e,
+        })?;
+
+    Ok(PaginationResponse::new(items, total_count, &pagination))
+}
+
+#[tauri::command]
+pub async fn mark_file_opened_db(
+    state: tauri::State<'_, AppState>,
+    file_id: i64,
+) -> Result<bool, ApiError> {
+    let db = state
+        .db
+        .get()
+        .await
+        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
+
+    db.mark_file_opened(file_id)
+        .map_err(|e| AppError::file_update(format!("Failed to mark file as opened: {e}")).into())
+}
+
+#[tauri::command]
+pub async fn set_favorite_db(
+    state: tauri::State<'_, AppState>,
+    file_id: i64,
+    value: bool,
+) -> Result<bool, ApiError> {
+    let db = state
+        .db
+        .get()
+        .await
+        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
+
+    db.set_favorite(file_id, value)
+        .map_err(|e| AppError::file_update(format!("Failed to update favorite: {e}")).into())
+}
+
+#[tauri::command]
+pub async fn update_file_note_db(
+    state: tauri::State<'_, AppState>,
+    file_id: i64,
+    note: Option<String>,
+) -> Result<bool, ApiError> {
+    let db = state
+        .db
+        .get()
+        .await
+        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
+
+    Ok(update_file_note(&db, file_id, note.as_deref())?)
+}
+
+// メモを検証して保存する（空白のみのメモは削除扱い）
+fn update_file_note(db: &Database, file_id: i64, note: Option<&str>) -> AppResult<bool> {
+    let note = note.filter(|note| !note.trim().is_empty());
+    if let Some(note) = note {
+        let length = note.chars().count();
+        if length > files::MAX_USER_NOTE_LENGTH {
+            return Err(AppError::validation(
+                "note",
+                format!(
+                    "Note must be at most {} characters ({length} given)",
+                    files::MAX_USER_NOTE_LENGTH
+                ),
+            ));
+        }
+    }
+
+    db.set_user_note(file_id, note)
+        .map_err(|e| AppError::file_update(format!("Failed to update note: {e}")))
+}
+
+// 追加情報（JSONオブジェクト）を設定するコマンド（None・空文字で削除）
+#[tauri::command]
+pub async fn update_file_metadata_db(
+    state: tauri::State<'_, AppState>,
+    file_id: i64,
+    metadata: Option<String>,
+) -> Result<bool, ApiError> {
     let db = state
         .db
-        .lock()
-        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")).to_string())?;
+        .get()
+        .await
+        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
+
+    Ok(update_file_metadata(&db, file_id, metadata.as_deref())?)
+}
+
+fn update_file_metadata(db: &Database, file_id: i64, metadata: Option<&str>) -> AppResult<bool> {
+    let metadata = metadata.filter(|metadata| !metadata.trim().is_empty());
+    if let Some(metadata) = metadata {
+        match serde_json::from_str::<serde_json::Value>(metadata) {
+            Ok(serde_json::Value::Object(_)) => {}
+            Ok(_) => {
+                return Err(AppError::validation(
+                    "metadata",
+                    "Metadata must be a JSON object",
+                ))
+            }
+            Err(e) => {
+                return Err(AppError::validation(
+                    "metadata",
+                    format!("Invalid JSON: {e}"),
+                ))
+            }
+        }
+    }
+
+    db.set_file_metadata(file_id, metadata)
+        .map_err(|e| AppError::file_update(format!("Failed to update metadata: {e}")))
+}
+
+// 追加情報（JSON）の json_path の値が value と一致するファイルを検索するコマンド
+// 例: json_path = "$.unity_version", value = "2022"
+#[tauri::command]
+pub async fn search_metadata(
+    state: tauri::State<'_, AppState>,
+    json_path: String,
+    value: String,
+) -> Result<Vec<FileWithTags>, ApiError> {
+    let db = state
+        .db
+        .get()
+        .await
+        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
+
+    Ok(search_files_by_metadata(&db, &json_path, &value)?)
+}
+
+fn search_files_by_metadata(
+    db: &Database,
+    json_path: &str,
+    value: &str,
+) -> AppResult<Vec<FileWithTags>> {
+    validate_json_path(json_path)?;
+    db.search_files_by_metadata(json_path, value)
+        .map_err(|e| AppError::file_retrieval(format!("Failed to search metadata: {e}")))
+}
+
+// "$" に続けて .key / ."quoted key" / [添字] を並べた形式だけを受け付ける
+fn validate_json_path(json_path: &str) -> AppResult<()> {
+    let invalid = || AppError::validation("json_path", format!("Invalid JSON path: {json_path}"));
+
+    let mut rest = json_path.strip_prefix('$').ok_or_else(invalid)?;
+    if rest.is_empty() {
+        return Err(invalid());
+    }
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix(".\"") {
+            let end = after.find('"').filter(|&end| end > 0).ok_or_else(invalid)?;
+            rest = &after[end + 1..];
+        } else if let Some(after) = rest.strip_prefix('.') {
+            let end = after.find(['.', '[']).unwrap_or(after.len());
+            let key = &after[..end];
+            if key.is_empty()
+                || !key
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+            {
+                return Err(invalid());
+            }
+            rest = &after[end..];
+        } else if let Some(after) = rest.strip_prefix('[') {
+            let end = after.find(']').ok_or_else(invalid)?;
+            let index = &after[..end];
+            if index.is_empty() || !index.bytes().all(|b| b.is_ascii_digit()) {
+                return Err(invalid());
+            }
+            rest = &after[end + 1..];
+        } else {
+            return Err(invalid());
+        }
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn open_booth_page_db(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+    file_id: i64,
+) -> Result<String, ApiError> {
+    use tauri_plugin_opener::OpenerExt;
+
+    let db = state
+        .db
+        .get()
+        .await
+        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
+
+    Ok(open_booth_page(&db, file_id, |url| {
+        app.opener()
+            .open_url(url, None::<&str>)
+            .map_err(|e| AppError::custom(format!("Failed to open BOOTH page: {e}")))
+    })?)
+}
+
+// ファイルに登録されたBOOTH URLを open に渡し、開いたURLを返す
+// http(s) の BOOTH 以外のURL（file: やカスタムスキームなど）は開かない
+fn open_booth_page(
+    db: &Database,
+    file_id: i64,
+    open: impl FnOnce(&str) -> AppResult<()>,
+) -> AppResult<String> {
+    let file = db
+        .get_file_by_id(file_id)
+        .map_err(|e| AppError::file_retrieval(format!("Failed to get file: {e}")))?
+        .ok_or_else(|| AppError::validation("file_id", format!("File {file_id} not found")))?;
+    let url = file
+        .product_url
+        .filter(|url| !url.trim().is_empty())
+        .ok_or_else(|| {
+            AppError::validation("booth_url", format!("File {file_id} has no BOOTH URL"))
+        })?;
+    if !url::Url::parse(url.trim()).is_ok_and(|parsed| is_valid_booth_url(&parsed)) {
+        return Err(AppError::validation(
+            "booth_url",
+            format!("Not a BOOTH URL: {url}"),
+        ));
+    }
 
-    db.get_files_with_tags().map_err(|e| {
-        AppError::file_retrieval(format!("Failed to get files with tags: {e}")).to_string()
+    open(&url)?;
+    Ok(url)
+}
+
+#[tauri::command]
+pub async fn toggle_favorite_db(
+    state: tauri::State<'_, AppState>,
+    file_id: i64,
+) -> Result<bool, ApiError> {
+    let db = state
+        .db
+        .get()
+        .await
+        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
+
+    db.toggle_favorite(file_id)
+        .map_err(|e| AppError::file_update(format!("Failed to update favorite: {e}")))?
+        .ok_or_else(|| AppError::file_update(format!("File not found: {file_id}")).into())
+}
+
+#[tauri::command]
+pub async fn get_favorites_db(
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<FileWithTags>, ApiError> {
+    let db = state
+        .db
+        .get()
+        .await
+        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
+
+    db.get_favorites()
+        .map_err(|e| AppError::file_retrieval(format!("Failed to get favorites: {e}")).into())
+}
+
+#[tauri::command]
+pub async fn get_recent_files_db(
+    state: tauri::State<'_, AppState>,
+    limit: Option<u32>,
+) -> Result<Vec<FileWithTags>, ApiError> {
+    let db = state
+        .db
+        .get()
+        .await
+        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
+
+    db.get_recent_files(recent_limit(limit))
+        .map_err(|e| AppError::file_retrieval(format!("Failed to get recent files: {e}")).into())
+}
+
+// 共通するタグの多いファイル（「こちらもおすすめ」用）
+#[tauri::command]
+pub async fn get_related_files_db(
+    state: tauri::State<'_, AppState>,
+    file_id: i64,
+    limit: Option<u32>,
+) -> Result<Vec<FileWithTags>, ApiError> {
+    let db = state
+        .db
+        .get()
+        .await
+        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
+
+    db.get_related_files(file_id, recent_limit(limit))
+        .map_err(|e| AppError::file_retrieval(format!("Failed to get related files: {e}")).into())
+}
+
+#[tauri::command]
+pub async fn get_recently_opened_db(
+    state: tauri::State<'_, AppState>,
+    limit: Option<u32>,
+) -> Result<Vec<FileWithTags>, ApiError> {
+    let db = state
+        .db
+        .get()
+        .await
+        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
+
+    db.get_recently_opened(recent_limit(limit)).map_err(|e| {
+        AppError::file_retrieval(format!("Failed to get recently opened files: {e}")).into()
     })
 }
 
+// 最近のファイル一覧の件数（未指定時は既定値、ページサイズと同じ範囲に丸める）
+fn recent_limit(limit: Option<u32>) -> u32 {
+    limit
+        .unwrap_or(pagination::DEFAULT_RECENT_LIMIT)
+        .clamp(pagination::MIN_PAGE_SIZE, pagination::MAX_PAGE_SIZE)
+}
+
 #[tauri::command]
 pub async fn delete_file_db(
     state: tauri::State<'_, AppState>,
     file_id: i64,
-) -> Result<Option<String>, String> {
+    permanent: Option<bool>,
+) -> Result<Option<String>, ApiError> {
     let db = state
         .db
-        .lock()
-        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")).to_string())?;
+        .get()
+        .await
+        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
 
-    db.delete_file(file_id)
-        .map_err(|e| AppError::file_deletion(format!("Failed to delete file: {e}")).to_string())?;
+    // 既定ではゴミ箱へ移動（論理削除）し、permanent 指定時のみ完全削除
+    if permanent.unwrap_or(false) {
+        db.delete_file(file_id)
+            .map_err(|e| AppError::file_deletion(format!("Failed to delete file: {e}")))?;
+    } else {
+        db.soft_delete_file(file_id)
+            .map_err(|e| AppError::file_deletion(format!("Failed to move file to trash: {e}")))?;
+    }
+
+    db.recalculate_usage_counts()
+        .map_err(|e| AppError::custom(format!("Failed to recalculate tag usage count: {e}")))?;
     Ok(None)
 }
 
@@ -105,48 +460,53 @@ pub async fn delete_file_db(
 pub async fn delete_file_and_folder(
     state: tauri::State<'_, AppState>,
     file_id: i64,
-) -> Result<bool, String> {
+    move_to_trash: Option<bool>,
+) -> Result<bool, ApiError> {
     let db = state
         .db
-        .lock()
-        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")).to_string())?;
+        .get()
+        .await
+        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
 
     // まずファイル情報を取得
-    let files = db
-        .get_all_files()
-        .map_err(|e| AppError::file_retrieval(format!("Failed to get files: {e}")).to_string())?;
-
-    let file_info = files.iter().find(|f| f.id == Some(file_id));
+    let file_info = db
+        .get_file_by_id(file_id)
+        .map_err(|e| AppError::file_retrieval(format!("Failed to get files: {e}")))?;
 
     if let Some(file) = file_info {
         let file_path = file.file_path.clone();
+        let path = Path::new(&file_path);
+
+        if move_to_trash.unwrap_or(false) {
+            trash_file(&db, file_id, path)?;
+            db.recalculate_usage_counts().map_err(|e| {
+                AppError::custom(format!("Failed to recalculate tag usage count: {e}"))
+            })?;
+
+            return Ok(true);
+        }
 
         // データベースから削除
         db.delete_file(file_id).map_err(|e| {
-            AppError::file_deletion(format!("Failed to delete file from database: {e}")).to_string()
+            AppError::file_deletion(format!("Failed to delete file from database: {e}"))
         })?;
 
         // 物理ファイル・フォルダを削除
-        let path = std::path::Path::new(&file_path);
-
         if path.exists() {
             if path.is_dir() {
-                std::fs::remove_dir_all(path).map_err(|e| {
-                    AppError::custom(format!("Failed to delete directory: {e}")).to_string()
-                })?;
+                std::fs::remove_dir_all(path)
+                    .map_err(|e| AppError::custom(format!("Failed to delete directory: {e}")))?;
             } else {
-                std::fs::remove_file(path).map_err(|e| {
-                    AppError::custom(format!("Failed to delete file: {e}")).to_string()
-                })?;
+                std::fs::remove_file(path)
+                    .map_err(|e| AppError::custom(format!("Failed to delete file: {e}")))?;
             }
         }
 
         // サムネイルファイルも削除（新しいスキーマでは thumbnail_url フィールド）
         if let Some(thumbnail_url) = &file.thumbnail_url {
             // もしローカルパスの場合は削除を試行
-            if thumbnail_url.starts_with("file://") || std::path::Path::new(thumbnail_url).exists()
-            {
-                let thumb_path = std::path::Path::new(thumbnail_url);
+            if thumbnail_url.starts_with("file://") || Path::new(thumbnail_url).exists() {
+                let thumb_path = Path::new(thumbnail_url);
                 if thumb_path.exists() {
                     let _ = std::fs::remove_file(thumb_path);
                 }
@@ -159,34 +519,438 @@ pub async fn delete_file_and_folder(
     }
 }
 
+#[tauri::command]
+pub async fn get_deleted_files_db(
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<FileRecord>, ApiError> {
+    let db = state
+        .db
+        .get()
+        .await
+        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
+
+    db.get_deleted_files()
+        .map_err(|e| AppError::file_retrieval(format!("Failed to get deleted files: {e}")).into())
+}
+
+// BOOTHの商品URLが未設定のファイル一覧（紐付け漏れの確認用）
+#[tauri::command]
+pub async fn get_unlinked_files_db(
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<FileWithTags>, ApiError> {
+    let db = state
+        .db
+        .get()
+        .await
+        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
+
+    db.get_unlinked_files()
+        .map_err(|e| AppError::file_retrieval(format!("Failed to get unlinked files: {e}")).into())
+}
+
+// BOOTHのカテゴリ（最下層の名前）で絞り込む。未指定・空文字はカテゴリ不明のファイル
+#[tauri::command]
+pub async fn get_files_by_category_db(
+    state: tauri::State<'_, AppState>,
+    category: Option<String>,
+) -> Result<Vec<FileWithTags>, ApiError> {
+    let category = category.filter(|c| !c.trim().is_empty());
+    let db = state
+        .db
+        .get()
+        .await
+        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
+
+    db.get_files_by_category(category.as_deref().map(str::trim))
+        .map_err(|e| {
+            AppError::file_retrieval(format!("Failed to get files by category: {e}")).into()
+        })
+}
+
+#[tauri::command]
+pub async fn restore_file_db(
+    state: tauri::State<'_, AppState>,
+    file_id: i64,
+) -> Result<bool, ApiError> {
+    let db = state
+        .db
+        .get()
+        .await
+        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
+
+    if !restore_from_trash(&db, file_id)? {
+        return Ok(false);
+    }
+
+    db.recalculate_usage_counts()
+        .map_err(|e| AppError::custom(format!("Failed to recalculate tag usage count: {e}")))?;
+
+    Ok(true)
+}
+
+#[tauri::command]
+pub async fn purge_deleted_db(
+    state: tauri::State<'_, AppState>,
+    older_than_days: u32,
+) -> Result<usize, ApiError> {
+    let db = state
+        .db
+        .get()
+        .await
+        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
+
+    let purged = db
+        .purge_deleted_files(older_than_days)
+        .map_err(|e| AppError::file_deletion(format!("Failed to purge deleted files: {e}")))?;
+
+    // .trash に残っている物理ファイル・フォルダも削除
+    for (file, trash_path) in &purged {
+        let trash_path = trash_path
+            .as_ref()
+            .map(PathBuf::from)
+            .or_else(|| legacy_trash_path(Path::new(&file.file_path)));
+        if let Some(trash_path) = trash_path.filter(|path| path.exists()) {
+            let _ = remove_path(&trash_path);
+        }
+    }
+
+    Ok(purged.len())
+}
+
+// 物理ファイル・フォルダを .trash へ移動し、移動先を記録してレコードを論理削除する
+// 論理削除に失敗した場合は移動を元に戻す。ゴミ箱内のレコードは false
+fn trash_file(db: &Database, file_id: i64, path: &Path) -> AppResult<bool> {
+    let soft_delete_error =
+        |e| AppError::file_deletion(format!("Failed to move file to trash: {e}"));
+    if !path.exists() {
+        return db.soft_delete_file(file_id).map_err(soft_delete_error);
+    }
+
+    let trash_path = move_path_to_trash(path, file_id)
+        .map_err(|e| AppError::custom(format!("Failed to move to trash: {e}")))?;
+
+    match db.soft_delete_file_to_trash(file_id, &trash_path.to_string_lossy()) {
+        Ok(true) => Ok(true),
+        result => {
+            if let Err(e) = std::fs::rename(&trash_path, path) {
+                log::error!(
+                    "Failed to put {} back after a failed delete: {e}",
+                    path.display()
+                );
+            }
+            result.map_err(soft_delete_error)
+        }
+    }
+}
+
+// ゴミ箱の物理ファイル・フォルダを元の場所へ戻してから論理削除を取り消す
+// 取り消しに失敗した場合はゴミ箱へ戻す。ゴミ箱にないレコードは false
+fn restore_from_trash(db: &Database, file_id: i64) -> AppResult<bool> {
+    let Some(file) = db
+        .get_file_by_id(file_id)
+        .map_err(|e| AppError::file_retrieval(format!("Failed to get file: {e}")))?
+        .filter(|file| file.deleted_at.is_some())
+    else {
+        return Ok(false);
+    };
+
+    let path = PathBuf::from(&file.file_path);
+    let trash_path = db
+        .get_trash_path(file_id)
+        .map_err(|e| AppError::file_retrieval(format!("Failed to get file: {e}")))?
+        .map(PathBuf::from)
+        .or_else(|| legacy_trash_path(&path))
+        .filter(|trash_path| trash_path.exists());
+
+    if let Some(trash_path) = &trash_path {
+        if path.exists() {
+            return Err(AppError::validation(
+                "file_id",
+                format!("Restore destination already exists: {}", file.file_path),
+            ));
+        }
+        std::fs::rename(trash_path, &path)
+            .map_err(|e| AppError::custom(format!("Failed to restore from trash: {e}")))?;
+    }
+
+    match db.restore_file(file_id) {
+        Ok(restored) => Ok(restored),
+        Err(e) => {
+            if let Some(trash_path) = &trash_path {
+                if let Err(e) = std::fs::rename(&path, trash_path) {
+                    log::error!(
+                        "Failed to move {} back to trash after a failed restore: {e}",
+                        path.display()
+                    );
+                }
+            }
+            Err(AppError::file_update(format!(
+                "Failed to restore file: {e}"
+            )))
+        }
+    }
+}
+
+// 削除対象と同じ階層の .trash ディレクトリ内の移動先パス
+// 同名のファイルを何度削除しても上書きしないよう、ファイルIDと連番で一意にする
+fn trash_path_for(path: &Path, file_id: i64) -> Option<PathBuf> {
+    let trash_dir = path.parent()?.join(files::TRASH_DIR_NAME);
+    let name = path.file_name()?.to_string_lossy();
+    (0..)
+        .map(|n| match n {
+            0 => trash_dir.join(format!("{file_id}_{name}")),
+            n => trash_dir.join(format!("{file_id}_{n}_{name}")),
+        })
+        .find(|candidate| !candidate.exists())
+}
+
+// 移動先を記録していなかった頃のゴミ箱内のパス（元の名前のまま）
+fn legacy_trash_path(path: &Path) -> Option<PathBuf> {
+    let parent = path.parent()?;
+    let name = path.file_name()?;
+    Some(parent.join(files::TRASH_DIR_NAME).join(name))
+}
+
+// ファイル・フォルダを同じ階層の .trash へ移動し、移動先を返す
+pub(crate) fn move_path_to_trash(path: &Path, file_id: i64) -> std::io::Result<PathBuf> {
+    let trash_path = trash_path_for(path, file_id).ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("Invalid file path: {}", path.display()),
+        )
+    })?;
+    if let Some(trash_dir) = trash_path.parent() {
+        std::fs::create_dir_all(trash_dir)?;
+    }
+    std::fs::rename(path, &trash_path)?;
+    Ok(trash_path)
+}
+
+// ファイル・フォルダを別の場所へ移動し、データベースの file_path も合わせて更新するコマンド
+// コピーが必要な移動もあるため、ディスク上の移動中は接続を返してから別スレッドで行う
+#[tauri::command]
+pub async fn move_file(
+    state: tauri::State<'_, AppState>,
+    file_id: i64,
+    new_path: String,
+) -> Result<FileRecord, ApiError> {
+    let new_path = PathBuf::from(new_path);
+    let old_path = {
+        let db = state
+            .db
+            .get()
+            .await
+            .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
+        check_move(&db, file_id, &new_path)?
+    };
+
+    let (from, to) = (old_path.clone(), new_path.clone());
+    let placement = tokio::task::spawn_blocking(move || place_path(&from, &to))
+        .await
+        .map_err(|e| AppError::file_update(format!("Failed to move file: {e}")))?
+        .map_err(|e| AppError::file_update(format!("Failed to move file: {e}")))?;
+
+    let db = state
+        .db
+        .get()
+        .await
+        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
+    Ok(finish_move(&db, file_id, &old_path, &new_path, placement)?)
+}
+
+// 移動できるか検証し、移動元のパスを返す
+fn check_move(db: &Database, file_id: i64, new_path: &Path) -> AppResult<PathBuf> {
+    let file = db
+        .get_file_by_id(file_id)?
+        .filter(|file| file.deleted_at.is_none())
+        .ok_or_else(|| AppError::validation("file_id", format!("File {file_id} not found")))?;
+    let old_path = PathBuf::from(&file.file_path);
+    if !old_path.exists() {
+        return Err(AppError::validation(
+            "file_id",
+            format!("File not found on disk: {}", file.file_path),
+        ));
+    }
+    if new_path.exists() {
+        return Err(AppError::validation(
+            "new_path",
+            format!("Destination already exists: {}", new_path.display()),
+        ));
+    }
+    // 自分自身の中へ移動するとコピーが終わらないため拒否する
+    if resolve_path(new_path).starts_with(old_path.canonicalize()?) {
+        return Err(AppError::validation(
+            "new_path",
+            format!("Destination is inside the source: {}", new_path.display()),
+        ));
+    }
+    Ok(old_path)
+}
+
+// 存在しない部分を含むパスを、存在する最も深い祖先を正規化して解決する
+fn resolve_path(path: &Path) -> PathBuf {
+    path.ancestors()
+        .find_map(|ancestor| {
+            let resolved = ancestor.canonicalize().ok()?;
+            let rest = path.strip_prefix(ancestor).ok()?;
+            Some(resolved.join(rest))
+        })
+        .unwrap_or_else(|| path.to_path_buf())
+}
+
+// ディスク上の移動方法（失敗時の戻し方と、成功後に移動元を消すかが変わる）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Placement {
+    Renamed,
+    Copied,
+}
+
+// データベースの file_path を更新する。更新に失敗したらディスク上の移動を元に戻し、
+// 成功したらコピーで移動した場合の移動元を削除する（削除の失敗は警告のみ）
+fn finish_move(
+    db: &Database,
+    file_id: i64,
+    old_path: &Path,
+    new_path: &Path,
+    placement: Placement,
+) -> AppResult<FileRecord> {
+    if let Err(e) = db.update_file_path(file_id, &new_path.to_string_lossy()) {
+        let restored = match placement {
+            Placement::Renamed => std::fs::rename(new_path, old_path),
+            Placement::Copied => remove_path(new_path),
+        };
+        if let Err(restore_error) = restored {
+            log::error!(
+                "Failed to restore {} after a failed move: {restore_error}",
+                old_path.display()
+            );
+        }
+        return Err(AppError::file_update(format!(
+            "Failed to update file path: {e}"
+        )));
+    }
+
+    if placement == Placement::Copied {
+        if let Err(e) = remove_path(old_path) {
+            log::warn!("Failed to delete {} after moving: {e}", old_path.display());
+        }
+    }
+
+    db.get_file_by_id(file_id)?
+        .ok_or_else(|| AppError::validation("file_id", format!("File {file_id} not found")))
+}
+
+// ファイル・フォルダを移動先に配置する。別のファイルシステムへは rename できないためコピーする
+// （コピーした場合の移動元は、データベースの更新後に finish_move が削除する）
+fn place_path(from: &Path, to: &Path) -> std::io::Result<Placement> {
+    if let Some(parent) = to.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let rename_error = match std::fs::rename(from, to) {
+        Ok(()) => return Ok(Placement::Renamed),
+        Err(e) => e,
+    };
+    if !from.exists() || to.exists() {
+        return Err(rename_error);
+    }
+
+    if let Err(e) = copy_recursively(from, to) {
+        // 途中までコピーしたものは残さない
+        let _ = remove_path(to);
+        return Err(e);
+    }
+    Ok(Placement::Copied)
+}
+
+// シンボリックリンクはリンク先をたどらず、リンク自体を削除する
+pub(crate) fn remove_path(path: &Path) -> std::io::Result<()> {
+    if std::fs::symlink_metadata(path)?.is_dir() {
+        std::fs::remove_dir_all(path)
+    } else {
+        std::fs::remove_file(path)
+    }
+}
+
+// シンボリックリンクはリンク先をたどらず、リンクとしてコピーする
+// （リンク先がフォルダ外でも中身を複製せず、循環するリンクでも止まる）
+pub(crate) fn copy_recursively(from: &Path, to: &Path) -> std::io::Result<()> {
+    let metadata = std::fs::symlink_metadata(from)?;
+    if metadata.is_symlink() {
+        return copy_symlink(from, to);
+    }
+    if !metadata.is_dir() {
+        return std::fs::copy(from, to).map(|_| ());
+    }
+
+    std::fs::create_dir(to)?;
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        copy_recursively(&entry.path(), &to.join(entry.file_name()))?;
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn copy_symlink(from: &Path, to: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(std::fs::read_link(from)?, to)
+}
+
+// Windows ではリンクの作成に権限が要るため、コピーせず警告のみ
+#[cfg(not(unix))]
+fn copy_symlink(from: &Path, _to: &Path) -> std::io::Result<()> {
+    log::warn!("Skipped copying symbolic link {}", from.display());
+    Ok(())
+}
+
 #[tauri::command]
 pub async fn batch_delete_files_db(
     state: tauri::State<'_, AppState>,
     file_ids: Vec<i64>,
-) -> Result<Vec<String>, String> {
+) -> Result<Vec<String>, ApiError> {
     let db = state
         .db
-        .lock()
-        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")).to_string())?;
+        .get()
+        .await
+        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
+
+    Ok(batch_delete_files(&db, state.db.path(), &file_ids)?)
+}
+
+// 一括削除の前にデータベースをバックアップする（バックアップできなければ何も削除しない）
+fn batch_delete_files(db: &Database, db_path: &str, file_ids: &[i64]) -> AppResult<Vec<String>> {
+    if !file_ids.is_empty() {
+        db_backup::backup_before_destructive(db, db_path, "batch delete")?;
+    }
 
     let mut result = Vec::new();
 
-    for file_id in file_ids {
-        match db.delete_file(file_id) {
-            Ok(_) => {
-                // 削除成功時の処理
-                result.push(format!("File {file_id} deleted successfully"));
+    // 単体の削除と同じく .trash へ移動して論理削除し、後から復元できるようにする
+    for &file_id in file_ids {
+        let trashed = db
+            .get_file_by_id(file_id)
+            .map_err(|e| AppError::file_retrieval(format!("Failed to get file: {e}")))
+            .and_then(|file| match file {
+                Some(file) => trash_file(db, file_id, Path::new(&file.file_path)),
+                None => Ok(false),
+            });
+        match trashed {
+            Ok(true) => {
+                result.push(format!("File {file_id} moved to trash"));
+            }
+            Ok(false) => {
+                result.push(format!("File {file_id} not found"));
             }
             Err(e) => {
+                log::error!("Failed to delete file {file_id}: {e}");
                 result.push(format!("Failed to delete file {file_id}: {e}"));
             }
         }
     }
 
     // バッチ削除後にタグのカウントを再計算
-    db.recalculate_usage_counts().map_err(|e| {
-        AppError::custom(format!("Failed to recalculate tag usage count: {e}")).to_string()
-    })?;
+    db.recalculate_usage_counts()
+        .map_err(|e| AppError::custom(format!("Failed to recalculate tag usage count: {e}")))?;
 
     Ok(result)
 }
@@ -196,36 +960,52 @@ pub async fn batch_update_files_db(
     state: tauri::State<'_, AppState>,
     file_ids: Vec<i64>,
     update_fields: FileUpdateFields,
-) -> Result<(), String> {
+) -> Result<(), ApiError> {
     let db = state
         .db
-        .lock()
-        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")).to_string())?;
+        .get()
+        .await
+        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
 
     // バッチ更新の実装（新しいスキーマでは個別更新）
     for file_id in file_ids {
         db.update_file(file_id, update_fields.clone())
-            .map_err(|e| {
-                AppError::file_update(format!("Failed to update file {file_id}: {e}")).to_string()
-            })?;
+            .map_err(|e| AppError::file_update(format!("Failed to update file {file_id}: {e}")))?;
     }
     Ok(())
 }
 
+// IDで1件取得する（存在しない場合は None）
+#[tauri::command]
+pub async fn get_file_by_id_db(
+    state: tauri::State<'_, AppState>,
+    file_id: i64,
+) -> Result<Option<FileWithTags>, ApiError> {
+    let db = state
+        .db
+        .get()
+        .await
+        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
+
+    db.get_file_with_tags(file_id)
+        .map_err(|e| AppError::file_retrieval(format!("Failed to get file: {e}")).into())
+}
+
 #[tauri::command]
 pub async fn get_files_with_tags_by_ids_db(
     state: tauri::State<'_, AppState>,
     file_ids: Vec<i64>,
-) -> Result<Vec<FileWithTags>, String> {
+) -> Result<Vec<FileWithTags>, ApiError> {
     let db = state
         .db
-        .lock()
-        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")).to_string())?;
+        .get()
+        .await
+        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
 
     // IDでファイルをフィルタリング
-    let all_files_with_tags = db.get_files_with_tags().map_err(|e| {
-        AppError::file_retrieval(format!("Failed to get files with tags: {e}")).to_string()
-    })?;
+    let all_files_with_tags = db
+        .get_files_with_tags()
+        .map_err(|e| AppError::file_retrieval(format!("Failed to get files with tags: {e}")))?;
 
     let filtered_files = all_files_with_tags
         .into_iter()
@@ -240,3 +1020,398 @@ pub async fn get_files_with_tags_by_ids_db(
 
     Ok(filtered_files)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::sample_file;
+
+    fn db_with_file() -> (Database, i64) {
+        let db = Database::new(":memory:").unwrap();
+        let file_id = db
+            .add_file(FileRecord {
+                file_name: "item".to_string(),
+                ..sample_file("/tmp/item")
+            })
+            .unwrap();
+        (db, file_id)
+    }
+
+    // 実在するフォルダを指すファイルを登録する
+    fn db_with_folder(dir: &Path) -> (Database, i64, PathBuf) {
+        let folder = dir.join("Shop").join("Item");
+        std::fs::create_dir_all(folder.join("Assets")).unwrap();
+        std::fs::write(folder.join("Assets/model.fbx"), b"fbx").unwrap();
+
+        let (db, file_id) = db_with_file();
+        db.update_file_path(file_id, &folder.to_string_lossy())
+            .unwrap();
+        (db, file_id, folder)
+    }
+
+    #[test]
+    fn test_move_file_renames_folder_and_updates_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let (db, file_id, folder) = db_with_folder(dir.path());
+        let new_path = dir.path().join("Moved").join("Item");
+
+        let old_path = check_move(&db, file_id, &new_path).unwrap();
+        assert_eq!(old_path, folder);
+        let placement = place_path(&old_path, &new_path).unwrap();
+        assert_eq!(placement, Placement::Renamed);
+        let moved = finish_move(&db, file_id, &old_path, &new_path, placement).unwrap();
+
+        assert_eq!(moved.file_path, new_path.to_string_lossy());
+        assert!(!folder.exists());
+        assert_eq!(
+            std::fs::read(new_path.join("Assets/model.fbx")).unwrap(),
+            b"fbx"
+        );
+    }
+
+    #[test]
+    fn test_move_file_failure_leaves_disk_and_db_unchanged() {
+        let dir = tempfile::tempdir().unwrap();
+        let (db, file_id, folder) = db_with_folder(dir.path());
+        let path_of = |db: &Database| db.get_file_by_id(file_id).unwrap().unwrap().file_path;
+
+        // 移動先の親がファイルだと配置に失敗し、移動元はそのまま残る
+        let blocker = dir.path().join("blocker");
+        std::fs::write(&blocker, b"").unwrap();
+        let old_path = check_move(&db, file_id, &blocker.join("Item")).unwrap();
+        assert!(place_path(&old_path, &blocker.join("Item")).is_err());
+        assert!(folder.join("Assets/model.fbx").exists());
+
+        // データベースの更新に失敗した場合（別のファイルが登録済みのパス）は移動しない
+        let taken = dir.path().join("Taken");
+        db.add_file(FileRecord {
+            file_path: taken.to_string_lossy().to_string(),
+            ..db.get_file_by_id(file_id).unwrap().unwrap()
+        })
+        .unwrap();
+        let placement = place_path(&folder, &taken).unwrap();
+        let err = finish_move(&db, file_id, &folder, &taken, placement).unwrap_err();
+        assert!(matches!(err, AppError::FileUpdate(_)), "{err:?}");
+        assert_eq!(path_of(&db), folder.to_string_lossy());
+        assert!(folder.exists() && !taken.exists());
+
+        // 既存のパスへの移動は検証エラー
+        let err = check_move(&db, file_id, &blocker).unwrap_err();
+        assert!(matches!(err, AppError::Validation { .. }), "{err:?}");
+
+        // 自分自身の中への移動も検証エラー
+        let err = check_move(&db, file_id, &folder.join("Assets/Item")).unwrap_err();
+        assert!(matches!(err, AppError::Validation { .. }), "{err:?}");
+        assert!(!folder.join("Assets/Item").exists());
+        assert_eq!(path_of(&db), folder.to_string_lossy());
+    }
+
+    #[test]
+    fn test_copied_move_deletes_source_only_after_db_update() {
+        let dir = tempfile::tempdir().unwrap();
+        let (db, file_id, folder) = db_with_folder(dir.path());
+        let copy = dir.path().join("Copy");
+
+        // データベースの更新に失敗したらコピーを消し、移動元は残す
+        let taken = dir.path().join("Taken");
+        db.add_file(FileRecord {
+            file_path: copy.to_string_lossy().to_string(),
+            ..db.get_file_by_id(file_id).unwrap().unwrap()
+        })
+        .unwrap();
+        copy_recursively(&folder, &copy).unwrap();
+        let err = finish_move(&db, file_id, &folder, &copy, Placement::Copied).unwrap_err();
+        assert!(matches!(err, AppError::FileUpdate(_)), "{err:?}");
+        assert!(!copy.exists());
+        assert!(folder.join("Assets/model.fbx").exists());
+
+        // 更新に成功したら移動元を削除する
+        copy_recursively(&folder, &taken).unwrap();
+        let moved = finish_move(&db, file_id, &folder, &taken, Placement::Copied).unwrap();
+        assert_eq!(moved.file_path, taken.to_string_lossy());
+        assert!(!folder.exists());
+        assert!(taken.join("Assets/model.fbx").exists());
+    }
+
+    #[test]
+    fn test_copy_recursively_copies_nested_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let (_db, _file_id, folder) = db_with_folder(dir.path());
+        let copy = dir.path().join("Copy");
+
+        copy_recursively(&folder, &copy).unwrap();
+
+        assert_eq!(
+            std::fs::read(copy.join("Assets/model.fbx")).unwrap(),
+            b"fbx"
+        );
+        assert!(folder.join("Assets/model.fbx").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_copy_recursively_copies_symlinks_as_links() {
+        let dir = tempfile::tempdir().unwrap();
+        let (_db, _file_id, folder) = db_with_folder(dir.path());
+        let outside = dir.path().join("Outside");
+        std::fs::create_dir(&outside).unwrap();
+        std::fs::write(outside.join("large.bin"), b"large").unwrap();
+        std::os::unix::fs::symlink(&outside, folder.join("Linked")).unwrap();
+        // 自分自身を指す循環リンク
+        std::os::unix::fs::symlink(&folder, folder.join("Assets/Loop")).unwrap();
+        let copy = dir.path().join("Copy");
+
+        copy_recursively(&folder, &copy).unwrap();
+
+        // リンク先の中身は複製せず、同じ場所を指すリンクになる
+        let linked = copy.join("Linked");
+        assert!(std::fs::symlink_metadata(&linked).unwrap().is_symlink());
+        assert_eq!(std::fs::read_link(&linked).unwrap(), outside);
+        assert_eq!(
+            std::fs::read_link(copy.join("Assets/Loop")).unwrap(),
+            folder
+        );
+
+        // コピーを消してもリンク先は残る
+        remove_path(&copy).unwrap();
+        assert!(outside.join("large.bin").exists());
+        assert!(folder.join("Assets/model.fbx").exists());
+    }
+
+    fn note_of(db: &Database, file_id: i64) -> Option<String> {
+        db.get_file_by_id(file_id).unwrap().unwrap().user_note
+    }
+
+    #[test]
+    fn test_update_file_note_sets_and_clears() {
+        let (db, file_id) = db_with_file();
+
+        assert!(update_file_note(&db, file_id, Some("Unity 2022 が必要")).unwrap());
+        assert_eq!(note_of(&db, file_id).as_deref(), Some("Unity 2022 が必要"));
+
+        // 空白のみ・None はメモの削除
+        assert!(update_file_note(&db, file_id, Some("  ")).unwrap());
+        assert_eq!(note_of(&db, file_id), None);
+        update_file_note(&db, file_id, Some("memo")).unwrap();
+        assert!(update_file_note(&db, file_id, None).unwrap());
+        assert_eq!(note_of(&db, file_id), None);
+
+        assert!(!update_file_note(&db, file_id + 1, Some("memo")).unwrap());
+    }
+
+    #[test]
+    fn test_trash_never_overwrites_and_restores_from_recorded_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let (db, file_id, folder) = db_with_folder(dir.path());
+        let trash_dir = dir.path().join("Shop").join(files::TRASH_DIR_NAME);
+
+        // 同名のゴミ箱エントリがあっても上書きせず別の名前で移動する
+        let existing = trash_dir.join(format!("{file_id}_Item"));
+        std::fs::create_dir_all(&existing).unwrap();
+        std::fs::write(existing.join("keep.txt"), b"keep").unwrap();
+
+        assert!(trash_file(&db, file_id, &folder).unwrap());
+        assert!(!folder.exists());
+        assert_eq!(std::fs::read(existing.join("keep.txt")).unwrap(), b"keep");
+        let trash_path = PathBuf::from(db.get_trash_path(file_id).unwrap().unwrap());
+        assert_ne!(trash_path, existing);
+        assert!(trash_path.join("Assets/model.fbx").exists());
+
+        // 元の場所が埋まっている間は復元せず、レコードもゴミ箱に残す
+        std::fs::create_dir_all(&folder).unwrap();
+        let err = restore_from_trash(&db, file_id).unwrap_err();
+        assert!(matches!(err, AppError::Validation { .. }), "{err:?}");
+        assert!(db
+            .get_file_by_id(file_id)
+            .unwrap()
+            .unwrap()
+            .deleted_at
+            .is_some());
+        assert!(trash_path.exists());
+
+        std::fs::remove_dir(&folder).unwrap();
+        assert!(restore_from_trash(&db, file_id).unwrap());
+        assert!(folder.join("Assets/model.fbx").exists());
+        assert!(!trash_path.exists());
+        assert_eq!(db.get_trash_path(file_id).unwrap(), None);
+        assert!(db
+            .get_file_by_id(file_id)
+            .unwrap()
+            .unwrap()
+            .deleted_at
+            .is_none());
+        assert!(!restore_from_trash(&db, file_id).unwrap());
+    }
+
+    #[test]
+    fn test_batch_delete_backs_up_and_moves_files_to_trash() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("library.db");
+        let db = Database::new(&db_path.to_string_lossy()).unwrap();
+        let folder = dir.path().join("Shop").join("Item");
+        std::fs::create_dir_all(&folder).unwrap();
+        let file_id = db.add_file(sample_file(&folder.to_string_lossy())).unwrap();
+
+        let result = batch_delete_files(&db, &db_path.to_string_lossy(), &[file_id]).unwrap();
+        assert_eq!(result, vec![format!("File {file_id} moved to trash")]);
+        assert!(!folder.exists());
+        assert_eq!(
+            std::fs::read_dir(dir.path().join("backups"))
+                .unwrap()
+                .count(),
+            1
+        );
+
+        // 復元できる
+        assert!(restore_from_trash(&db, file_id).unwrap());
+        assert!(folder.exists());
+    }
+
+    #[test]
+    fn test_search_metadata_matches_json_value() {
+        let (db, file_id) = db_with_file();
+        let add = |path: &str, metadata: Option<&str>| {
+            let id = db
+                .add_file(FileRecord {
+                    file_path: path.to_string(),
+                    ..db.get_file_by_id(file_id).unwrap().unwrap()
+                })
+                .unwrap();
+            db.set_file_metadata(id, metadata).unwrap();
+            id
+        };
+        update_file_metadata(
+            &db,
+            file_id,
+            Some(r#"{"unity_version": "2022", "shader": {"name": "lilToon"}}"#),
+        )
+        .unwrap();
+        let numeric = add("/tmp/numeric", Some(r#"{"unity_version": 2022}"#));
+        add("/tmp/older", Some(r#"{"unity_version": "2019"}"#));
+        add("/tmp/broken", Some("not json"));
+        add("/tmp/none", None);
+
+        let ids = |path: &str, value: &str| -> Vec<i64> {
+            let mut ids: Vec<_> = search_files_by_metadata(&db, path, value)
+                .unwrap()
+                .into_iter()
+                .map(|f| f.file.id.unwrap())
+                .collect();
+            ids.sort_unstable();
+            ids
+        };
+        assert_eq!(ids("$.unity_version", "2022"), vec![file_id, numeric]);
+        assert_eq!(ids("$.shader.name", "lilToon"), vec![file_id]);
+        assert!(ids("$.unity_version", "2021").is_empty());
+
+        // JSONオブジェクト以外は保存しない
+        assert!(update_file_metadata(&db, file_id, Some("[1, 2]")).is_err());
+        assert!(update_file_metadata(&db, file_id, Some("{")).is_err());
+        assert_eq!(ids("$.shader.name", "lilToon"), vec![file_id]);
+    }
+
+    #[test]
+    fn test_validate_json_path() {
+        for path in [
+            "$.unity_version",
+            "$.shader.name",
+            "$.versions[0]",
+            r#"$."unity version""#,
+        ] {
+            assert!(validate_json_path(path).is_ok(), "{path}");
+        }
+        for path in [
+            "",
+            "$",
+            "unity_version",
+            "$.",
+            "$..a",
+            "$[x]",
+            "$.a b",
+            "$.\"\"",
+        ] {
+            let err = validate_json_path(path).unwrap_err();
+            assert_eq!(err.code(), "Validation", "{path}");
+        }
+    }
+
+    #[test]
+    fn test_open_booth_page_passes_stored_url() {
+        let (db, file_id) = db_with_file();
+        let url = "https://shop.booth.pm/items/12345";
+        db.update_file(
+            file_id,
+            FileUpdateFields {
+                product_id: None,
+                product_name: None,
+                author_name: None,
+                price: None,
+                description: None,
+                thumbnail_url: None,
+                product_url: Some(url.to_string()),
+            },
+        )
+        .unwrap();
+
+        let mut opened = None;
+        let result = open_booth_page(&db, file_id, |url| {
+            opened = Some(url.to_string());
+            Ok(())
+        });
+        assert_eq!(result.unwrap(), url);
+        assert_eq!(opened.as_deref(), Some(url));
+    }
+
+    #[test]
+    fn test_open_booth_page_without_url_is_validation_error() {
+        let (db, file_id) = db_with_file();
+
+        for id in [file_id, file_id + 1] {
+            let err = open_booth_page(&db, id, |_| panic!("must not open")).unwrap_err();
+            assert!(matches!(err, AppError::Validation { .. }), "{err:?}");
+        }
+    }
+
+    #[test]
+    fn test_open_booth_page_rejects_non_booth_urls() {
+        let (db, file_id) = db_with_file();
+
+        for url in [
+            "file:///etc/passwd",
+            "ms-settings://booth.pm/items/1",
+            "https://example.com/items/12345",
+            "not a url",
+        ] {
+            db.update_file(
+                file_id,
+                FileUpdateFields {
+                    product_id: None,
+                    product_name: None,
+                    author_name: None,
+                    price: None,
+                    description: None,
+                    thumbnail_url: None,
+                    product_url: Some(url.to_string()),
+                },
+            )
+            .unwrap();
+            let err = open_booth_page(&db, file_id, |_| panic!("must not open")).unwrap_err();
+            assert!(matches!(err, AppError::Validation { .. }), "{url}: {err:?}");
+        }
+    }
+
+    #[test]
+    fn test_update_file_note_rejects_over_length() {
+        let (db, file_id) = db_with_file();
+        update_file_note(&db, file_id, Some("keep")).unwrap();
+
+        // 全角文字も1文字として数える
+        let max_length = "あ".repeat(files::MAX_USER_NOTE_LENGTH);
+        assert!(update_file_note(&db, file_id, Some(&max_length)).unwrap());
+
+        let too_long = "a".repeat(files::MAX_USER_NOTE_LENGTH + 1);
+        let err = update_file_note(&db, file_id, Some(&too_long)).unwrap_err();
+        assert!(matches!(err, AppError::Validation { .. }), "{err:?}");
+        assert_eq!(note_of(&db, file_id), Some(max_length));
+    }
+}
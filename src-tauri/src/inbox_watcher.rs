@@ -0,0 +1,353 @@
+// 受信フォルダ監視 - 新しく置かれたZIPを書き込み完了後に自動で取り込む
+use crate::booth_client::BoothClient;
+use crate::config::watcher;
+use crate::db_pool::DatabasePool;
+use crate::process_commands::{failed_process_result, save_process_result};
+use crate::system_commands::{
+    stored_extraction_limits, stored_folder_template, stored_inbox_watch_settings,
+    stored_output_folder, stored_sanitize_options,
+};
+use crate::{process_zip_internal, AppError, AppResult, ExtractOptions, ProcessResult};
+use log::{error, info, warn};
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// 受信フォルダからの取り込み結果（1件ごとにイベントで通知）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InboxImportEvent {
+    pub zip_path: String,
+    pub result: ProcessResult,
+}
+
+/// サイズが一定時間変化しなくなったファイルを検出する
+///
+/// 時刻は呼び出し側から渡すため、テストでは任意の時刻で駆動できる。
+#[derive(Debug)]
+pub struct StabilityTracker {
+    settle: Duration,
+    pending: HashMap<PathBuf, PendingFile>,
+}
+
+#[derive(Debug)]
+struct PendingFile {
+    size: Option<u64>,
+    changed_at: Instant,
+}
+
+impl StabilityTracker {
+    pub fn new(settle: Duration) -> Self {
+        Self {
+            settle,
+            pending: HashMap::new(),
+        }
+    }
+
+    /// 変更イベントを受けたファイルを記録する（記録済みなら待ち時間をやり直す）
+    pub fn touch(&mut self, path: PathBuf, now: Instant) {
+        self.pending
+            .entry(path)
+            .and_modify(|pending| pending.changed_at = now)
+            .or_insert(PendingFile {
+                size: None,
+                changed_at: now,
+            });
+    }
+
+    /// 待機中のファイルのサイズを確認し、安定したものを待機リストから外して返す
+    ///
+    /// size_of が None を返したファイル（削除・移動済み）は破棄する。
+    /// 0バイトのファイルはダウンロード前の仮ファイルとみなして待ち続ける。
+    pub fn poll<F>(&mut self, now: Instant, size_of: F) -> Vec<PathBuf>
+    where
+        F: Fn(&Path) -> Option<u64>,
+    {
+        let settle = self.settle;
+        let mut ready = Vec::new();
+
+        self.pending.retain(|path, pending| {
+            let Some(size) = size_of(path) else {
+                return false;
+            };
+            if pending.size != Some(size) {
+                pending.size = Some(size);
+                pending.changed_at = now;
+                return true;
+            }
+            if size > 0 && now.duration_since(pending.changed_at) >= settle {
+                ready.push(path.clone());
+                return false;
+            }
+            true
+        });
+
+        ready.sort();
+        ready
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
+
+fn is_zip_path(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("zip"))
+}
+
+// 作成・変更（リネーム先を含む）イベントの対象になったZIPのパス
+fn zip_paths_of(event: &Event) -> Vec<PathBuf> {
+    match event.kind {
+        EventKind::Create(_) | EventKind::Modify(_) => event
+            .paths
+            .iter()
+            .filter(|path| is_zip_path(path))
+            .cloned()
+            .collect(),
+        _ => vec![],
+    }
+}
+
+fn file_size(path: &Path) -> Option<u64> {
+    std::fs::metadata(path)
+        .ok()
+        .filter(|meta| meta.is_file())
+        .map(|meta| meta.len())
+}
+
+/// 設定で有効になっていれば受信フォルダの監視を開始する
+///
+/// 監視はバックグラウンドスレッドで常駐し、取り込むたびに INBOX_IMPORT_EVENT を送る。
+pub fn start_inbox_watcher(
+    app: tauri::AppHandle,
+    db: Arc<DatabasePool>,
+    booth_client: Arc<BoothClient>,
+) {
+    let settings = stored_inbox_watch_settings();
+    if !settings.enabled {
+        return;
+    }
+    let Some(inbox_dir) = settings.inbox_dir.map(PathBuf::from) else {
+        warn!("Inbox watcher is enabled but no inbox directory is configured");
+        return;
+    };
+
+    std::thread::spawn(move || {
+        if let Err(e) = run_watch_loop(&app, &inbox_dir, &db, &booth_client) {
+            error!("Inbox watcher stopped: {e}");
+        }
+    });
+}
+
+fn run_watch_loop(
+    app: &tauri::AppHandle,
+    inbox_dir: &Path,
+    db: &DatabasePool,
+    booth_client: &BoothClient,
+) -> AppResult<()> {
+    use tauri::Emitter;
+
+    let (tx, rx) = mpsc::channel();
+    let mut fs_watcher = notify::recommended_watcher(tx)
+        .map_err(|e| AppError::custom(format!("Failed to create watcher: {e}")))?;
+    fs_watcher
+        .watch(inbox_dir, RecursiveMode::NonRecursive)
+        .map_err(|e| AppError::custom(format!("Failed to watch {}: {e}", inbox_dir.display())))?;
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .map_err(|e| AppError::custom(format!("Failed to start watcher runtime: {e}")))?;
+
+    let mut tracker = StabilityTracker::new(Duration::from_millis(watcher::SETTLE_DURATION_MS));
+
+    // 起動前に置かれたZIPも対象にする（取り込み済みのものはハッシュで省略される）
+    if let Ok(entries) = std::fs::read_dir(inbox_dir) {
+        for path in entries.flatten().map(|entry| entry.path()) {
+            if is_zip_path(&path) {
+                tracker.touch(path, Instant::now());
+            }
+        }
+    }
+
+    info!("Watching inbox: {}", inbox_dir.display());
+
+    loop {
+        match rx.recv_timeout(Duration::from_millis(watcher::POLL_INTERVAL_MS)) {
+            Ok(Ok(event)) => {
+                for path in zip_paths_of(&event) {
+                    tracker.touch(path, Instant::now());
+                }
+            }
+            Ok(Err(e)) => warn!("Inbox watcher error: {e}"),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+
+        if tracker.is_empty() {
+            continue;
+        }
+
+        for zip_path in tracker.poll(Instant::now(), file_size) {
+            let result = runtime.block_on(import_inbox_zip(&zip_path, db, booth_client));
+            let payload = InboxImportEvent {
+                zip_path: zip_path.to_string_lossy().to_string(),
+                result,
+            };
+            if let Err(e) = app.emit(watcher::INBOX_IMPORT_EVENT, payload) {
+                error!("Failed to emit inbox import: {e}");
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// 手動の取り込みと同じ設定で展開・登録する（BOOTH URLはファイル名から推定）
+async fn import_inbox_zip(
+    zip_path: &Path,
+    db: &DatabasePool,
+    booth_client: &BoothClient,
+) -> ProcessResult {
+    let options = ExtractOptions {
+        output_dir: stored_output_folder(),
+        folder_template: stored_folder_template(),
+        sanitize: stored_sanitize_options(),
+        overwrite_policy: Default::default(),
+        recursive: false,
+        limits: stored_extraction_limits(),
+        password: None,
+        filename_encoding: None,
+    };
+    let zip_path = zip_path.to_string_lossy().to_string();
+
+    match process_zip_internal(zip_path.clone(), None, booth_client, db, false, &options).await {
+        Ok(mut res) => {
+            if res.success {
+                match db.get().await {
+                    Ok(db) => save_process_result(&db, &zip_path, &mut res, None, None, true),
+                    Err(e) => error!("Database lock error: {e}"),
+                }
+            }
+            res
+        }
+        Err(e) => failed_process_result(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    const SETTLE: Duration = Duration::from_secs(3);
+
+    struct FakeFs {
+        sizes: RefCell<HashMap<PathBuf, u64>>,
+    }
+
+    impl FakeFs {
+        fn new() -> Self {
+            Self {
+                sizes: RefCell::new(HashMap::new()),
+            }
+        }
+
+        fn set(&self, path: &str, size: u64) {
+            self.sizes.borrow_mut().insert(PathBuf::from(path), size);
+        }
+
+        fn remove(&self, path: &str) {
+            self.sizes.borrow_mut().remove(Path::new(path));
+        }
+
+        fn size_of(&self, path: &Path) -> Option<u64> {
+            self.sizes.borrow().get(path).copied()
+        }
+    }
+
+    #[test]
+    fn test_growing_file_waits_until_size_settles() {
+        let fs = FakeFs::new();
+        let start = Instant::now();
+        let at = |secs: u64| start + Duration::from_secs(secs);
+        let mut tracker = StabilityTracker::new(SETTLE);
+
+        fs.set("/inbox/a.zip", 100);
+        tracker.touch(PathBuf::from("/inbox/a.zip"), at(0));
+        assert!(tracker.poll(at(0), |p| fs.size_of(p)).is_empty());
+
+        // 書き込みが続いている間は取り込まない
+        fs.set("/inbox/a.zip", 500);
+        assert!(tracker.poll(at(2), |p| fs.size_of(p)).is_empty());
+        assert!(tracker.poll(at(4), |p| fs.size_of(p)).is_empty());
+
+        // 最後にサイズが変わってから3秒経過で取り込み対象になる
+        assert_eq!(
+            tracker.poll(at(5), |p| fs.size_of(p)),
+            vec![PathBuf::from("/inbox/a.zip")]
+        );
+        assert!(tracker.is_empty());
+        assert!(tracker.poll(at(10), |p| fs.size_of(p)).is_empty());
+    }
+
+    #[test]
+    fn test_repeated_events_restart_the_wait() {
+        let fs = FakeFs::new();
+        let start = Instant::now();
+        let at = |secs: u64| start + Duration::from_secs(secs);
+        let mut tracker = StabilityTracker::new(SETTLE);
+
+        fs.set("/inbox/a.zip", 100);
+        tracker.touch(PathBuf::from("/inbox/a.zip"), at(0));
+        tracker.poll(at(0), |p| fs.size_of(p));
+
+        tracker.touch(PathBuf::from("/inbox/a.zip"), at(2));
+        assert!(tracker.poll(at(3), |p| fs.size_of(p)).is_empty());
+        assert!(tracker.poll(at(4), |p| fs.size_of(p)).is_empty());
+        assert_eq!(tracker.poll(at(5), |p| fs.size_of(p)).len(), 1);
+    }
+
+    #[test]
+    fn test_empty_and_removed_files_are_not_imported() {
+        let fs = FakeFs::new();
+        let start = Instant::now();
+        let at = |secs: u64| start + Duration::from_secs(secs);
+        let mut tracker = StabilityTracker::new(SETTLE);
+
+        fs.set("/inbox/placeholder.zip", 0);
+        fs.set("/inbox/gone.zip", 100);
+        tracker.touch(PathBuf::from("/inbox/placeholder.zip"), at(0));
+        tracker.touch(PathBuf::from("/inbox/gone.zip"), at(0));
+        tracker.poll(at(0), |p| fs.size_of(p));
+
+        fs.remove("/inbox/gone.zip");
+        assert!(tracker.poll(at(10), |p| fs.size_of(p)).is_empty());
+
+        // 仮ファイルに中身が書き込まれたら通常どおり待ってから取り込む
+        fs.set("/inbox/placeholder.zip", 2048);
+        assert!(tracker.poll(at(11), |p| fs.size_of(p)).is_empty());
+        assert_eq!(
+            tracker.poll(at(14), |p| fs.size_of(p)),
+            vec![PathBuf::from("/inbox/placeholder.zip")]
+        );
+        assert!(tracker.is_empty());
+    }
+
+    #[test]
+    fn test_zip_paths_of_filters_event_kinds_and_extensions() {
+        let event = Event::new(EventKind::Create(notify::event::CreateKind::File))
+            .add_path(PathBuf::from("/inbox/Item.ZIP"))
+            .add_path(PathBuf::from("/inbox/item.zip.part"));
+        assert_eq!(zip_paths_of(&event), vec![PathBuf::from("/inbox/Item.ZIP")]);
+
+        let removed = Event::new(EventKind::Remove(notify::event::RemoveKind::File))
+            .add_path(PathBuf::from("/inbox/item.zip"));
+        assert!(zip_paths_of(&removed).is_empty());
+    }
+}
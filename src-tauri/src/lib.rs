@@ -1,43 +1,95 @@
 use ::regex::Regex;
 use anyhow::{anyhow, Result};
-use encoding_rs::SHIFT_JIS;
+use encoding_rs::{Encoding, SHIFT_JIS, UTF_8};
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::io::BufReader;
 use std::path::{Path, PathBuf};
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 // Log imports will be added as needed in individual files
 
 mod api_types;
 pub mod booth_client;
 mod booth_commands;
+mod catalog_commands;
+mod collection_commands;
 mod config;
 mod database;
+mod db_backup;
+mod db_pool;
 pub mod errors;
 mod file_commands;
+mod inbox_watcher;
+mod logging;
 mod process_commands;
 mod sync_commands;
 mod system_commands;
 mod tag_commands;
+mod tag_query;
 mod tag_validator;
+#[cfg(test)]
+mod test_support;
 
-use crate::config::{app, files, regex};
-use booth_client::BoothClient;
-use database::Database;
+use crate::config::{app, booth, files, processing, regex};
+use booth_client::{normalize_booth_url, BoothClient, BoothClientConfig};
+use booth_commands::ThumbnailDownloads;
+use db_pool::DatabasePool;
 pub use errors::{AppError, AppResult};
 
 // アプリケーション状態管理
 pub struct AppState {
-    pub db: Arc<Mutex<Database>>,
+    pub db: Arc<DatabasePool>,
     pub booth_client: Arc<BoothClient>,
+    pub thumbnail_downloads: Arc<ThumbnailDownloads>,
+}
+
+/// アプリケーションのデータディレクトリ（データベース・ログの保存先）
+pub fn app_data_dir() -> PathBuf {
+    dirs::data_dir()
+        .or_else(dirs::home_dir)
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join(app::DATA_DIR_NAME)
+}
+
+// config.json の proxy_url / User-Agent / 再試行設定を反映したクライアント（不正な値はログを残して無視する）
+// proxy_url が未設定なら環境変数 HTTPS_PROXY のプロキシを使う
+fn booth_client_from_config() -> BoothClient {
+    let config = BoothClientConfig {
+        proxy_url: system_commands::stored_proxy_url()
+            .or_else(booth_client::proxy_url_from_env)
+            .filter(
+                |proxy_url| match booth_client::validate_proxy_url(proxy_url) {
+                    Ok(_) => true,
+                    Err(e) => {
+                        log::error!(
+                            "Ignoring invalid proxy {}: {e}",
+                            booth_client::redact_proxy(proxy_url)
+                        );
+                        false
+                    }
+                },
+            ),
+        user_agent: system_commands::stored_user_agent().filter(|user_agent| {
+            match booth_client::validate_user_agent(user_agent) {
+                Ok(_) => true,
+                Err(e) => {
+                    log::error!("Ignoring invalid user_agent {user_agent:?}: {e}");
+                    false
+                }
+            }
+        }),
+        retry_policy: system_commands::stored_retry_policy(),
+    };
+
+    BoothClient::from_config(&config).unwrap_or_else(|e| {
+        log::error!("Failed to create BOOTH client from config: {e}");
+        BoothClient::new().with_retry_policy(config.retry_policy)
+    })
 }
 
 impl AppState {
     pub fn new() -> Result<Self> {
-        let app_data_dir = dirs::data_dir()
-            .or_else(dirs::home_dir)
-            .unwrap_or_else(|| PathBuf::from("."))
-            .join(app::DATA_DIR_NAME);
+        let app_data_dir = app_data_dir();
 
         // ディレクトリが存在しない場合は作成
         if !app_data_dir.exists() {
@@ -45,17 +97,18 @@ impl AppState {
         }
 
         let db_path = app_data_dir.join(app::DATABASE_FILENAME);
-        let db = Database::new(&db_path.to_string_lossy())?;
-        let booth_client = BoothClient::new();
+        let db = DatabasePool::new(&db_path.to_string_lossy())?;
+        let booth_client = booth_client_from_config();
 
         Ok(AppState {
-            db: Arc::new(Mutex::new(db)),
+            db: Arc::new(db),
             booth_client: Arc::new(booth_client),
+            thumbnail_downloads: Arc::new(ThumbnailDownloads::default()),
         })
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProcessResult {
     pub success: bool,
     pub message: String,
@@ -63,6 +116,103 @@ pub struct ProcessResult {
     pub product_name: Option<String>,
     pub files_extracted: Vec<String>,
     pub output_path: Option<String>,
+    /// 元アーカイブのSHA-256（16進）
+    #[serde(default)]
+    pub file_hash: Option<String>,
+    /// 取り込み済みのため展開を省略した場合、既存ファイルのID
+    #[serde(default)]
+    pub already_imported_id: Option<i64>,
+    /// 新規作成または上書きしたファイル数
+    #[serde(default)]
+    pub files_written: usize,
+    /// 既存ファイルがあったため展開しなかったファイル数
+    #[serde(default)]
+    pub files_skipped: usize,
+    /// 既存ファイルと衝突したため別名で展開したファイル数
+    #[serde(default)]
+    pub files_renamed: usize,
+    /// BOOTHの商品ページに付いているタグ（自動タグ付け用）
+    #[serde(default)]
+    pub booth_tags: Vec<String>,
+    /// アーカイブ内のファイル名の文字コード
+    #[serde(default)]
+    pub encoding_info: Option<String>,
+    /// BOOTHの商品カテゴリ（最下層の名前）
+    #[serde(default)]
+    pub booth_category: Option<String>,
+    /// BOOTHで R-18 指定されている商品
+    #[serde(default)]
+    pub is_adult: bool,
+    /// 展開先フォルダを今回の取り込みで新規作成した（false なら既存のフォルダに展開した）
+    #[serde(default)]
+    pub output_created: bool,
+    /// 失敗時のエラーコード（"PasswordRequired" ならパスワード入力を促す）
+    #[serde(default)]
+    pub error_code: Option<String>,
+    /// false の場合はBOOTHに接続できず、商品情報をURLやファイル名から推測した（後で再取得できる）
+    #[serde(default = "default_network_available")]
+    pub network_available: bool,
+}
+
+fn default_network_available() -> bool {
+    true
+}
+
+/// BOOTH URLまたはファイル名から決定した商品情報
+#[derive(Debug, Clone, Default)]
+pub struct ProductLookup {
+    pub shop_name: Option<String>,
+    pub product_name: Option<String>,
+    pub booth_tags: Vec<String>,
+    pub booth_category: Option<String>,
+    pub is_adult: bool,
+    /// BOOTHに接続できず、商品情報を取得できなかった
+    pub booth_unreachable: bool,
+}
+
+/// 展開先に同名のファイルが既に存在する場合の扱い
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OverwritePolicy {
+    /// 既存ファイルを上書きする
+    #[default]
+    Overwrite,
+    /// 既存ファイルを残し、そのエントリは展開しない
+    Skip,
+    /// 拡張子の前に " (1)" などを付けた別名で展開する
+    Rename,
+}
+
+impl OverwritePolicy {
+    pub fn parse(policy: &str) -> AppResult<Self> {
+        match policy.trim().to_lowercase().as_str() {
+            "overwrite" => Ok(Self::Overwrite),
+            "skip" => Ok(Self::Skip),
+            "rename" => Ok(Self::Rename),
+            other => Err(AppError::validation(
+                "overwrite_policy",
+                format!("Unsupported overwrite policy: {other}"),
+            )),
+        }
+    }
+}
+
+/// 展開先の決定と書き込み方法に関するオプション
+#[derive(Debug, Clone, Default)]
+pub struct ExtractOptions {
+    pub output_dir: Option<String>,
+    /// 出力先フォルダの命名テンプレート（未指定時は "{shop}/{product}"）
+    pub folder_template: Option<String>,
+    pub overwrite_policy: OverwritePolicy,
+    /// フォルダ名に使えない文字の扱い
+    pub sanitize: SanitizeOptions,
+    /// 展開したファイルに含まれるZIPも、同名のフォルダに続けて展開する
+    pub recursive: bool,
+    /// 展開サイズの上限
+    pub limits: ExtractionLimits,
+    /// 暗号化されたZIPのパスワード（ZipCrypto のみ対応）
+    pub password: Option<String>,
+    /// ファイル名の文字コード（未指定時はアーカイブ内の名前から推定）
+    pub filename_encoding: Option<&'static Encoding>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -78,6 +228,9 @@ pub struct SyncResult {
     pub missing_files: Vec<MissingFile>,
     pub orphaned_files: usize,
     pub updated_files: usize,
+    /// 出力先にあるが、どのファイル情報にも対応しないフォルダ・ファイル
+    #[serde(default)]
+    pub orphaned_paths: Vec<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -98,8 +251,10 @@ pub struct MissingFile {
 pub async fn process_zip_internal(
     zip_path: String,
     booth_url: Option<String>,
-    output_dir: Option<String>,
     booth_client: &BoothClient,
+    db: &DatabasePool,
+    force: bool,
+    options: &ExtractOptions,
 ) -> Result<ProcessResult> {
     let zip_path = PathBuf::from(&zip_path);
 
@@ -109,24 +264,119 @@ pub async fn process_zip_internal(
         return Err(anyhow!("ZIPファイルが見つかりません: {path_display}"));
     }
 
+    // 同じ内容のアーカイブが取り込み済みなら展開しない
+    let file_hash = compute_file_hash(&zip_path)?;
+    if !force {
+        if let Some(res) = find_already_imported(db, &file_hash).await? {
+            return Ok(res);
+        }
+    }
+
+    let lookup = resolve_shop_and_product(
+        &zip_path,
+        booth_url.as_deref(),
+        booth_client,
+        &options.sanitize,
+    )
+    .await;
+
+    let product_id = product_id_from_booth_url(booth_url.as_deref(), booth_client);
+
+    let mut res = extract_to_output(
+        &zip_path,
+        lookup.shop_name,
+        lookup.product_name,
+        product_id,
+        options,
+    )?;
+    res.file_hash = Some(file_hash);
+    res.booth_tags = lookup.booth_tags;
+    res.booth_category = lookup.booth_category;
+    res.is_adult = lookup.is_adult;
+    res.network_available = !lookup.booth_unreachable;
+    Ok(res)
+}
+
+// ファイル内容のSHA-256を16進文字列で返す
+pub fn compute_file_hash(path: &Path) -> Result<String> {
+    use sha2::{Digest, Sha256};
+
+    let mut file = fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+// 同じハッシュのファイルが登録済みであれば、展開を省略した結果を返す
+pub async fn find_already_imported(
+    db: &DatabasePool,
+    file_hash: &str,
+) -> Result<Option<ProcessResult>> {
+    let db = db.get().await?;
+
+    let existing = db.get_file_by_hash(file_hash)?;
+    if let Some(existing) = &existing {
+        log::warn!(
+            "Skipping duplicate archive (same content as file {:?}: {})",
+            existing.id,
+            existing.file_path
+        );
+    }
+
+    Ok(existing.map(|existing| ProcessResult {
+        success: true,
+        message: format!(
+            "既にインポート済みです (ID: {})",
+            existing.id.unwrap_or_default()
+        ),
+        shop_name: existing.author_name,
+        product_name: existing.product_name,
+        files_extracted: vec![],
+        output_path: Some(existing.file_path),
+        file_hash: Some(file_hash.to_string()),
+        already_imported_id: existing.id,
+        files_written: 0,
+        files_skipped: 0,
+        files_renamed: 0,
+        booth_tags: vec![],
+        encoding_info: existing.encoding_info,
+        booth_category: existing.booth_category,
+        is_adult: existing.is_adult,
+        output_created: false,
+        error_code: None,
+        network_available: true,
+    }))
+}
+
+// BOOTH URLからショップ名・商品名・タグ・カテゴリを決定（ネットワークアクセスを伴う）
+// 名前は設定のサニタイズ規則で整える（フォルダ名と登録する名前を揃える）
+pub async fn resolve_shop_and_product(
+    zip_path: &Path,
+    booth_url: Option<&str>,
+    booth_client: &BoothClient,
+    sanitize_options: &SanitizeOptions,
+) -> ProductLookup {
+    let sanitize = |name: &str| sanitize_folder_name_with(name, sanitize_options);
     // BOOTH URLからショップ名と商品名を抽出（改善版）
-    let (shop_name, product_name) = if let Some(url) = &booth_url {
-        match extract_booth_info_with_api(url, booth_client).await {
-            Ok((shop, product)) => (
-                Some(sanitize_folder_name(&shop)),
-                Some(sanitize_folder_name(&product)),
+    if let Some(url) = booth_url {
+        let lookup = extract_booth_info_with_api(url, booth_client).await;
+        // URLからも分からなかった場合はファイル名から推測
+        let file_stem = zip_path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("unknown");
+        ProductLookup {
+            shop_name: Some(
+                lookup
+                    .shop_name
+                    .as_deref()
+                    .map(sanitize)
+                    .unwrap_or_else(|| "Unknown_Shop".to_string()),
             ),
-            Err(_) => {
-                // フォールバック
-                let file_stem = zip_path
-                    .file_stem()
-                    .and_then(|s| s.to_str())
-                    .unwrap_or("unknown");
-                (
-                    Some("Unknown_Shop".to_string()),
-                    Some(sanitize_folder_name(file_stem)),
-                )
-            }
+            product_name: Some(sanitize(
+                lookup.product_name.as_deref().unwrap_or(file_stem),
+            )),
+            ..lookup
         }
     } else {
         // URLが無い場合はファイル名から推測
@@ -134,71 +384,166 @@ pub async fn process_zip_internal(
             .file_stem()
             .and_then(|s| s.to_str())
             .unwrap_or("unknown");
-        (
-            Some("Unknown_Shop".to_string()),
-            Some(sanitize_folder_name(file_stem)),
-        )
-    };
+        ProductLookup {
+            shop_name: Some("Unknown_Shop".to_string()),
+            product_name: Some(sanitize(file_stem)),
+            ..ProductLookup::default()
+        }
+    }
+}
+
+// 出力先が未指定の場合に使うデスクトップのBOOTH_Organizedフォルダ
+pub fn default_output_base() -> PathBuf {
+    dirs::desktop_dir()
+        .unwrap_or_else(|| dirs::home_dir().unwrap_or_else(|| PathBuf::from(".")))
+        .join("BOOTH_Organized")
+}
+
+// BOOTH URLから商品IDを取得（テンプレートの {id} 用）
+pub fn product_id_from_booth_url(
+    booth_url: Option<&str>,
+    booth_client: &BoothClient,
+) -> Option<i64> {
+    let url = url::Url::parse(booth_url?).ok()?;
+    booth_client.extract_product_id(&url).ok()
+}
 
+// 出力先フォルダを作成してZIPを展開（ブロッキング処理）
+pub fn extract_to_output(
+    zip_path: &Path,
+    shop_name: Option<String>,
+    product_name: Option<String>,
+    product_id: Option<i64>,
+    options: &ExtractOptions,
+) -> Result<ProcessResult> {
     // 出力ディレクトリの決定
-    let output_base = if let Some(dir) = output_dir {
-        PathBuf::from(dir)
-    } else {
-        // デフォルトでデスクトップのBOOTH_Organizedフォルダに出力
-        dirs::desktop_dir()
-            .unwrap_or_else(|| dirs::home_dir().unwrap_or_else(|| PathBuf::from(".")))
-            .join("BOOTH_Organized")
-    };
+    let output_base = options
+        .output_dir
+        .as_ref()
+        .map(PathBuf::from)
+        .unwrap_or_else(default_output_base);
 
-    // ショップ名/商品名のフォルダ構造作成
-    let final_output_dir = match (&shop_name, &product_name) {
-        (Some(shop), Some(product)) => output_base.join(shop).join(product),
-        (Some(shop), None) => output_base.join(shop),
-        (None, Some(product)) => output_base.join("Unknown_Shop").join(product),
-        (None, None) => output_base.join("Unknown"),
-    };
+    // テンプレートに従ってショップ名/商品名のフォルダ構造作成
+    let template = options
+        .folder_template
+        .as_deref()
+        .unwrap_or(files::DEFAULT_FOLDER_TEMPLATE);
+    let final_output_dir = output_base.join(render_folder_template(
+        template,
+        shop_name.as_deref().unwrap_or("Unknown_Shop"),
+        product_name.as_deref().unwrap_or("Unknown"),
+        product_id,
+        &options.sanitize,
+    )?);
 
-    // ディレクトリ作成
+    // ディレクトリ作成（取り込みの取り消し時に削除してよいかの判定用に、新規作成かを記録する）
+    let output_created = !final_output_dir.exists();
     fs::create_dir_all(&final_output_dir)
         .map_err(|e| anyhow!("出力ディレクトリの作成に失敗: {}", e))?;
 
     // ZIP展開
-    let extracted_files = extract_zip_with_encoding(&zip_path, &final_output_dir)?;
+    let outcome = if options.recursive {
+        extract_zip_recursive(
+            zip_path,
+            &final_output_dir,
+            options.overwrite_policy,
+            &options.limits,
+            options.password.as_deref(),
+            options.filename_encoding,
+        )?
+    } else {
+        extract_zip_with_encoding(
+            zip_path,
+            &final_output_dir,
+            options.overwrite_policy,
+            &options.limits,
+            options.password.as_deref(),
+            options.filename_encoding,
+        )?
+    };
 
     Ok(ProcessResult {
         success: true,
         message: {
-            let count = extracted_files.len();
-            format!("{count}個のファイルを展開しました")
+            let count = outcome.files.len();
+            let mut message = format!("{count}個のファイルを展開しました");
+            if outcome.skipped > 0 {
+                message.push_str(&format!("（{}個は既存のためスキップ）", outcome.skipped));
+            }
+            if outcome.renamed > 0 {
+                message.push_str(&format!("（{}個は別名で保存）", outcome.renamed));
+            }
+            message
         },
         shop_name,
         product_name,
-        files_extracted: extracted_files,
+        files_extracted: outcome.files,
         output_path: Some(final_output_dir.to_string_lossy().to_string()),
+        file_hash: None,
+        already_imported_id: None,
+        files_written: outcome.written,
+        files_skipped: outcome.skipped,
+        files_renamed: outcome.renamed,
+        booth_tags: vec![],
+        encoding_info: outcome.encoding_info,
+        booth_category: None,
+        is_adult: false,
+        output_created,
+        error_code: None,
+        network_available: true,
     })
 }
 
 // 改善されたBOOTH情報取得（実際のAPIを使用）
-pub async fn extract_booth_info_with_api(
-    url: &str,
-    booth_client: &BoothClient,
-) -> Result<(String, String)> {
+// ショップ名・商品名・商品タグ・カテゴリを返す（名前はサニタイズ前）
+// 取得できない場合はURLから推測し、それでも分からない名前は None
+pub async fn extract_booth_info_with_api(url: &str, booth_client: &BoothClient) -> ProductLookup {
     // BOOTH APIを使って実際の商品情報を取得
     match booth_client.get_product_info(url).await {
-        Ok(info) => Ok((info.shop_name, info.product_name)),
-        Err(_) => {
-            // フォールバック: URLから推測
-            extract_booth_info_fallback(url)
+        Ok(info) => ProductLookup {
+            shop_name: Some(info.shop_name),
+            product_name: Some(info.product_name),
+            booth_tags: info.tags,
+            booth_category: info.category,
+            is_adult: info.is_adult,
+            booth_unreachable: false,
+        },
+        Err(e) => {
+            log::error!("Failed to fetch BOOTH product info for {url}: {e}");
+            // 年齢確認ページが返った商品は R-18 として扱う
+            let is_adult = matches!(e, booth_client::BoothClientError::AgeRestricted { .. });
+            // フォールバック: URLから推測（タグ・カテゴリは取得できない）
+            let (shop_name, product_name) = match extract_booth_info_fallback(url) {
+                Ok((shop, product)) => (Some(shop), Some(product)),
+                Err(fallback_error) => {
+                    log::warn!("Could not determine shop from {url}: {fallback_error}");
+                    (None, None)
+                }
+            };
+            ProductLookup {
+                shop_name,
+                product_name,
+                is_adult,
+                booth_unreachable: e.is_unreachable(),
+                ..ProductLookup::default()
+            }
         }
     }
 }
 
 pub fn extract_booth_info_fallback(url: &str) -> Result<(String, String)> {
     // BOOTH URLからショップ名を抽出（フォールバック）
-    let regex = Regex::new(r"https://([^.]+)\.booth\.pm/items/(\d+)")
+    // 表記ゆれ（http、大文字、言語プレフィックス、クエリなど）はそろえてから照合する
+    let regex = Regex::new(r"^https?://([a-z0-9-]+)\.booth\.pm/items/(\d+)$")
         .map_err(|e| anyhow!("正規表現の作成に失敗しました: {}", e))?;
 
-    if let Some(captures) = regex.captures(url) {
+    let normalized = normalize_booth_url(url);
+    let captures = regex.captures(&normalized).filter(|captures| {
+        !captures
+            .get(regex::GROUP_1)
+            .is_some_and(|shop| booth::NON_SHOP_SUBDOMAINS.contains(&shop.as_str()))
+    });
+    if let Some(captures) = captures {
         let shop_name = captures
             .get(regex::GROUP_1)
             .map(|m| m.as_str().to_string())
@@ -215,22 +560,73 @@ pub fn extract_booth_info_fallback(url: &str) -> Result<(String, String)> {
     }
 }
 
+/// フォルダ名のサニタイズ設定（既定値は従来どおりの置換）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SanitizeOptions {
+    /// 置換対象の文字。パス区切り文字（`/`, `\`）は常に置換される
+    pub invalid_chars: Vec<char>,
+    /// 置換後の文字。区切り文字や置換対象の文字を指定した場合は既定値を使う
+    pub replacement: char,
+    /// 先頭のドット（Unixの隠しファイル扱い）を除去する
+    pub strip_leading_dots: bool,
+}
+
+impl Default for SanitizeOptions {
+    fn default() -> Self {
+        Self {
+            invalid_chars: files::INVALID_FILENAME_CHARS.to_vec(),
+            replacement: files::DEFAULT_REPLACEMENT_CHAR,
+            strip_leading_dots: false,
+        }
+    }
+}
+
 // 安全なフォルダ名生成
 pub fn sanitize_folder_name(name: &str) -> String {
-    // Windows/Linux/macOSで使えない文字を置換
-    let invalid_chars = ['<', '>', ':', '"', '|', '?', '*', '/', '\\'];
-    let mut sanitized = name.to_string();
+    sanitize_folder_name_with(name, &SanitizeOptions::default())
+}
 
-    for ch in invalid_chars {
-        sanitized = sanitized.replace(ch, "_");
-    }
+// 設定に従った安全なフォルダ名生成
+pub fn sanitize_folder_name_with(name: &str, sanitize_options: &SanitizeOptions) -> String {
+    let is_invalid =
+        |c: char| matches!(c, '/' | '\\') || sanitize_options.invalid_chars.contains(&c);
+    let replacement = if is_invalid(sanitize_options.replacement) {
+        files::DEFAULT_REPLACEMENT_CHAR
+    } else {
+        sanitize_options.replacement
+    };
+
+    // Windows/Linux/macOSで使えない文字を置換
+    let mut sanitized: String = name
+        .chars()
+        .map(|c| if is_invalid(c) { replacement } else { c })
+        .collect();
 
     // 末尾のドットやスペースを削除
     sanitized = sanitized.trim_end_matches('.').trim().to_string();
 
-    // 長すぎる名前は切り詰める（255文字制限）
-    if sanitized.len() > files::MAX_FILENAME_LENGTH {
-        sanitized.truncate(files::MAX_FILENAME_LENGTH);
+    if sanitize_options.strip_leading_dots {
+        sanitized = sanitized.trim_start_matches('.').trim_start().to_string();
+    }
+
+    // 長すぎる名前は文字数とUTF-8のバイト数の両方で切り詰める（マルチバイト文字の途中で切らない）
+    // 日本語は1文字3バイトのため、文字数の上限より先にバイト数の上限に達する
+    if sanitized.chars().count() > files::MAX_FILENAME_LENGTH
+        || sanitized.len() > files::MAX_FILENAME_BYTES
+    {
+        let mut bytes = 0;
+        sanitized = sanitized
+            .chars()
+            .take(files::MAX_FILENAME_LENGTH)
+            .take_while(|c| {
+                bytes += c.len_utf8();
+                bytes <= files::MAX_FILENAME_BYTES
+            })
+            .collect::<String>()
+            .trim_end_matches('.')
+            .trim_end()
+            .to_string();
     }
 
     // 空文字列の場合はデフォルト名
@@ -238,39 +634,655 @@ pub fn sanitize_folder_name(name: &str) -> String {
         sanitized = "unnamed".to_string();
     }
 
+    // Windowsの予約デバイス名（CON, NUL.txt など）は先頭に "_" を付けて回避
+    // 末尾に付けると "PRN.txt_" のように拡張子付きの予約名のまま残るため
+    if is_windows_reserved_name(&sanitized) {
+        sanitized.insert(0, '_');
+    }
+
     sanitized
 }
 
-fn extract_zip_with_encoding(zip_path: &Path, output_dir: &Path) -> Result<Vec<String>> {
+// 拡張子を除いた部分がWindowsの予約デバイス名か（大文字小文字は区別しない）
+fn is_windows_reserved_name(name: &str) -> bool {
+    let stem = name.split('.').next().unwrap_or(name).trim_end();
+    files::WINDOWS_RESERVED_NAMES
+        .iter()
+        .any(|reserved| stem.eq_ignore_ascii_case(reserved))
+}
+
+/// 出力先フォルダの命名テンプレートを相対パスに展開する
+///
+/// 使用できるプレースホルダーは `{shop}`・`{product}`・`{id}`（別名 `{product_id}`）・
+/// `{date}`（取り込んだ日、YYYY-MM-DD）。`/` で区切ったセグメントごとに
+/// `sanitize_folder_name_with` を通すため、値に含まれる区切り文字で階層が増えることはない。
+/// 商品IDが不明な場合 `{id}` は空になり、テンプレートでその値を囲む `()`・`[]` も除く
+/// （値そのものに含まれる括弧は残す）。
+pub fn render_folder_template(
+    template: &str,
+    shop: &str,
+    product: &str,
+    product_id: Option<i64>,
+    sanitize_options: &SanitizeOptions,
+) -> AppResult<PathBuf> {
+    render_folder_template_on(
+        template,
+        shop,
+        product,
+        product_id,
+        chrono::Local::now().date_naive(),
+        sanitize_options,
+    )
+}
+
+// {date} に使う日付を指定して展開する（テスト用に日付を固定できる）
+fn render_folder_template_on(
+    template: &str,
+    shop: &str,
+    product: &str,
+    product_id: Option<i64>,
+    date: chrono::NaiveDate,
+    sanitize_options: &SanitizeOptions,
+) -> AppResult<PathBuf> {
+    let id = product_id.map(|id| id.to_string()).unwrap_or_default();
+    let date = date.format("%Y-%m-%d").to_string();
+    let mut path = PathBuf::new();
+
+    for segment in template.split('/').filter(|s| !s.trim().is_empty()) {
+        let mut rendered = String::new();
+        let mut rest = segment;
+
+        while let Some(start) = rest.find('{') {
+            rendered.push_str(&rest[..start]);
+            let end = rest[start..].find('}').ok_or_else(|| {
+                AppError::validation(
+                    "folder_template",
+                    format!("Unclosed placeholder in template: {template}"),
+                )
+            })?;
+            let value = match &rest[start + 1..start + end] {
+                "shop" => shop,
+                "product" => product,
+                "id" | "product_id" => &id,
+                "date" => &date,
+                other => {
+                    return Err(AppError::validation(
+                        "folder_template",
+                        format!("Unknown placeholder {{{other}}} in template: {template}"),
+                    ))
+                }
+            };
+            rendered.push_str(value);
+            rest = &rest[start + end + 1..];
+
+            // 空の値をテンプレート上で囲んでいた括弧は、空の括弧として残さない
+            if value.is_empty() {
+                let close = match rendered.chars().last() {
+                    Some('(') => Some(')'),
+                    Some('[') => Some(']'),
+                    _ => None,
+                };
+                if let Some(after) = close.and_then(|close| rest.strip_prefix(close)) {
+                    rendered.pop();
+                    rest = after;
+                }
+            }
+        }
+        rendered.push_str(rest);
+
+        path.push(sanitize_folder_name_with(&rendered, sanitize_options));
+    }
+
+    if path.as_os_str().is_empty() {
+        return Err(AppError::validation(
+            "folder_template",
+            "Folder template must not be empty",
+        ));
+    }
+
+    Ok(path)
+}
+
+/// ZIP展開時のサイズ上限（zip bomb対策、config.json の "extraction_limits" で変更可）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExtractionLimits {
+    /// 1つのアーカイブから書き出す合計バイト数（入れ子のZIPの中身も含む）
+    pub max_total_extracted_bytes: u64,
+    /// 1エントリあたりのバイト数
+    pub max_file_bytes: u64,
+    pub max_compression_ratio: u64,
+    /// ZIP内のZIPを展開する深さの上限（recursive 指定時）
+    pub max_nested_depth: usize,
+}
+
+impl Default for ExtractionLimits {
+    fn default() -> Self {
+        Self {
+            max_total_extracted_bytes: processing::MAX_TOTAL_UNCOMPRESSED_BYTES,
+            max_file_bytes: processing::MAX_ENTRY_UNCOMPRESSED_BYTES,
+            max_compression_ratio: processing::MAX_COMPRESSION_RATIO,
+            max_nested_depth: processing::MAX_NESTED_ARCHIVE_DEPTH,
+        }
+    }
+}
+
+type ZipReader = zip::ZipArchive<BufReader<fs::File>>;
+
+/// ZIP展開の結果（files は実際に書き込んだ相対パス）
+#[derive(Debug, Default)]
+struct ExtractionOutcome {
+    files: Vec<String>,
+    /// 実際に書き込んだバイト数
+    bytes_written: u64,
+    written: usize,
+    skipped: usize,
+    renamed: usize,
+    /// ファイル名の解釈に使った文字コード
+    encoding_info: Option<String>,
+}
+
+// ZIPを展開し、展開されたファイルに含まれるZIPも「アーカイブ名の拡張子を除いたフォルダ」に展開する
+// files は出力先からの相対パスで、内側のZIPの中身も含めて平坦化したもの
+// 深さやサイズの上限を超えるZIPがあればエラー（外側・内側を問わず、それまでに新規作成したファイルも削除する）
+// 内側のZIPも同じパスワードで展開する（暗号化されていないエントリには使われない）
+// 合計サイズの上限は外側と内側のZIPを通算して適用する
+fn extract_zip_recursive(
+    zip_path: &Path,
+    output_dir: &Path,
+    policy: OverwritePolicy,
+    limits: &ExtractionLimits,
+    password: Option<&str>,
+    filename_encoding: Option<&'static Encoding>,
+) -> Result<ExtractionOutcome> {
+    let output_root = output_dir.canonicalize()?;
+    let mut created = Vec::new();
+    let result = extract_nested_archives(
+        zip_path,
+        &output_root,
+        policy,
+        limits,
+        password,
+        filename_encoding,
+        &mut created,
+    );
+    if result.is_err() {
+        remove_partial_output(&output_root, &created);
+    }
+    result
+}
+
+// created には外側・内側のすべての階層で新規作成したパスを積む（後始末は呼び出し元で行う）
+fn extract_nested_archives(
+    zip_path: &Path,
+    output_root: &Path,
+    policy: OverwritePolicy,
+    limits: &ExtractionLimits,
+    password: Option<&str>,
+    filename_encoding: Option<&'static Encoding>,
+    created: &mut Vec<PathBuf>,
+) -> Result<ExtractionOutcome> {
+    let mut outcome = extract_zip_into(
+        zip_path,
+        output_root,
+        policy,
+        limits,
+        password,
+        filename_encoding,
+        created,
+    )?;
+    let mut pending: Vec<(PathBuf, usize)> = outcome
+        .files
+        .iter()
+        .filter(|file| is_zip_file_name(file))
+        .map(|file| (PathBuf::from(file), 1))
+        .collect();
+
+    while let Some((inner_zip, depth)) = pending.pop() {
+        if depth > limits.max_nested_depth {
+            return Err(size_limit_error(format!(
+                "ZIPの入れ子が深すぎます: {} (上限 {})",
+                inner_zip.display(),
+                limits.max_nested_depth
+            ))
+            .into());
+        }
+
+        let inner_dir = inner_zip.with_extension("");
+        let inner_root = output_root.join(&inner_dir);
+        if !inner_root.exists() {
+            fs::create_dir_all(&inner_root)?;
+            created.push(inner_root.clone());
+        }
+        let remaining = ExtractionLimits {
+            max_total_extracted_bytes: limits
+                .max_total_extracted_bytes
+                .saturating_sub(outcome.bytes_written),
+            ..*limits
+        };
+        let inner = extract_zip_into(
+            &output_root.join(&inner_zip),
+            &inner_root,
+            policy,
+            &remaining,
+            password,
+            filename_encoding,
+            created,
+        )?;
+
+        for file in inner.files {
+            let relative = inner_dir.join(&file);
+            if is_zip_file_name(&file) {
+                pending.push((relative.clone(), depth + 1));
+            }
+            outcome.files.push(relative.to_string_lossy().to_string());
+        }
+        outcome.bytes_written += inner.bytes_written;
+        outcome.written += inner.written;
+        outcome.skipped += inner.skipped;
+        outcome.renamed += inner.renamed;
+    }
+
+    Ok(outcome)
+}
+
+fn is_zip_file_name(name: &str) -> bool {
+    Path::new(name)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("zip"))
+}
+
+// 宣言サイズの検査と、書き込んだバイト数の累計の両方で上限を適用する
+// 上限を超えた場合は途中まで展開したファイルを削除してエラーを返す
+// 暗号化されたエントリがあるのにパスワードが無い場合は、何も書き込まずにエラーを返す
+// filename_encoding を指定した場合は推定せずにその文字コードでファイル名を読む
+fn extract_zip_with_encoding(
+    zip_path: &Path,
+    output_dir: &Path,
+    policy: OverwritePolicy,
+    limits: &ExtractionLimits,
+    password: Option<&str>,
+    filename_encoding: Option<&'static Encoding>,
+) -> Result<ExtractionOutcome> {
+    let output_root = output_dir.canonicalize()?;
+    let mut created = Vec::new();
+    let result = extract_zip_into(
+        zip_path,
+        &output_root,
+        policy,
+        limits,
+        password,
+        filename_encoding,
+        &mut created,
+    );
+    if result.is_err() {
+        // 途中まで展開したファイルを削除
+        remove_partial_output(&output_root, &created);
+    }
+    result
+}
+
+// 新規作成したパスを created に積みながら展開する（失敗時の後始末は呼び出し元で行う）
+fn extract_zip_into(
+    zip_path: &Path,
+    output_dir: &Path,
+    policy: OverwritePolicy,
+    limits: &ExtractionLimits,
+    password: Option<&str>,
+    filename_encoding: Option<&'static Encoding>,
+    created: &mut Vec<PathBuf>,
+) -> Result<ExtractionOutcome> {
     let file = fs::File::open(zip_path)?;
     let mut archive = zip::ZipArchive::new(BufReader::new(file))?;
-    let mut extracted_files = Vec::new();
+    let output_root = output_dir.canonicalize()?;
+
+    let encoding = match filename_encoding {
+        Some(encoding) => encoding,
+        None => detect_archive_encoding(&mut archive)?,
+    };
+
+    if password.is_none() && has_encrypted_entries(&mut archive)? {
+        return Err(AppError::PasswordRequired(archive_display_name(zip_path)).into());
+    }
+
+    // 書き込み前に宣言サイズと圧縮率を検査
+    check_declared_sizes(&mut archive, encoding, limits)?;
+
+    let mut outcome = extract_entries(
+        &mut archive,
+        encoding,
+        &output_root,
+        policy,
+        limits,
+        password,
+        created,
+    )
+    .map_err(|e| match e.downcast::<zip::result::ZipError>() {
+        Ok(zip::result::ZipError::InvalidPassword) => {
+            AppError::InvalidPassword(archive_display_name(zip_path)).into()
+        }
+        Ok(other) => other.into(),
+        Err(e) => e,
+    })?;
+    outcome.encoding_info = Some(encoding.name().to_string());
+    Ok(outcome)
+}
+
+fn check_declared_sizes(
+    archive: &mut ZipReader,
+    encoding: &'static Encoding,
+    limits: &ExtractionLimits,
+) -> Result<()> {
+    let mut total: u64 = 0;
+
+    for i in 0..archive.len() {
+        let file = archive.by_index_raw(i)?;
+        let name = decode_filename(file.name_raw(), encoding)?;
+        let size = file.size();
+
+        if size > limits.max_file_bytes {
+            return Err(size_limit_error(format!(
+                "エントリのサイズが上限 (max_file_bytes: {} bytes) を超えています: {name} ({size} bytes)",
+                limits.max_file_bytes
+            ))
+            .into());
+        }
+
+        let compressed = file.compressed_size();
+        if size >= processing::RATIO_CHECK_MIN_BYTES
+            && (compressed == 0 || size / compressed > limits.max_compression_ratio)
+        {
+            return Err(size_limit_error(format!(
+                "圧縮率が異常に高いエントリがあります: {name} ({compressed} -> {size} bytes)"
+            ))
+            .into());
+        }
+
+        total = total.saturating_add(size);
+        if total > limits.max_total_extracted_bytes {
+            return Err(size_limit_error(format!(
+                "展開後の合計サイズが上限 (max_total_extracted_bytes: {} bytes) を超えています",
+                limits.max_total_extracted_bytes
+            ))
+            .into());
+        }
+    }
+
+    Ok(())
+}
+
+fn extract_entries(
+    archive: &mut ZipReader,
+    encoding: &'static Encoding,
+    output_root: &Path,
+    policy: OverwritePolicy,
+    limits: &ExtractionLimits,
+    password: Option<&str>,
+    created: &mut Vec<PathBuf>,
+) -> Result<ExtractionOutcome> {
+    use std::io::Read;
+
+    let mut outcome = ExtractionOutcome::default();
 
     for i in 0..archive.len() {
-        let mut file = archive.by_index(i)?;
+        let mut file = match password {
+            Some(password) => archive.by_index_decrypt(i, password.as_bytes())?,
+            None => archive.by_index(i)?,
+        };
 
         // ファイル名のエンコーディング検出と変換
-        let file_name = detect_and_convert_filename(file.name_raw())?;
+        let file_name = decode_filename(file.name_raw(), encoding)?;
 
-        let output_path = output_dir.join(&file_name);
+        // 展開先が出力ディレクトリの外を指していないか検証（zip-slip対策）
+        let mut output_path = resolve_entry_path(output_root, &file_name)?;
 
         if file.is_dir() {
             // ディレクトリの場合
             fs::create_dir_all(&output_path)?;
+            ensure_within_output(output_root, &output_path, &file_name)?;
         } else {
             // ファイルの場合
             if let Some(parent) = output_path.parent() {
                 fs::create_dir_all(parent)?;
+                ensure_within_output(output_root, parent, &file_name)?;
+            }
+
+            // 既存ファイルとの衝突をポリシーに従って処理
+            let mut entry_name = file_name;
+            let existed = output_path.exists();
+            if existed {
+                match policy {
+                    OverwritePolicy::Overwrite => {}
+                    OverwritePolicy::Skip => {
+                        outcome.skipped += 1;
+                        continue;
+                    }
+                    OverwritePolicy::Rename => {
+                        output_path = next_available_path(&output_path);
+                        entry_name = output_path
+                            .strip_prefix(output_root)
+                            .unwrap_or(&output_path)
+                            .to_string_lossy()
+                            .to_string();
+                        outcome.renamed += 1;
+                    }
+                }
+            }
+
+            // 上書きは隣の一時ファイルに書き込み、サイズの検査を通ってから既存ファイルと置き換える
+            // （途中で失敗しても既存ファイルの中身は失われない）
+            let replace = existed && policy == OverwritePolicy::Overwrite;
+            let write_path = if replace {
+                partial_path(&output_path)
+            } else {
+                output_path.clone()
+            };
+            let mut output_file = fs::File::create(&write_path)?;
+            // 失敗時の後始末は新規作成したファイル（一時ファイルを含む）のみ
+            created.push(write_path.clone());
+
+            // 宣言サイズを偽装したエントリに備え、実際に書き込んだバイト数でも制限する
+            let remaining_total = limits
+                .max_total_extracted_bytes
+                .saturating_sub(outcome.bytes_written);
+            let remaining = limits.max_file_bytes.min(remaining_total);
+            // ZipCrypto の検査バイトは偶然一致することがあるため、復号後の破損も誤ったパスワードとみなす
+            let encrypted = file.encrypted();
+            let copied = std::io::copy(
+                &mut (&mut file).take(remaining.saturating_add(1)),
+                &mut output_file,
+            )
+            .map_err(|e| match e.kind() {
+                std::io::ErrorKind::InvalidData | std::io::ErrorKind::InvalidInput if encrypted => {
+                    anyhow::Error::from(zip::result::ZipError::InvalidPassword)
+                }
+                _ => e.into(),
+            })?;
+            if copied > remaining {
+                let (limit_name, limit) = if limits.max_file_bytes <= remaining_total {
+                    ("max_file_bytes", limits.max_file_bytes)
+                } else {
+                    (
+                        "max_total_extracted_bytes",
+                        limits.max_total_extracted_bytes,
+                    )
+                };
+                return Err(size_limit_error(format!(
+                    "展開中のデータがサイズ上限 ({limit_name}: {limit} bytes) を超えました: {entry_name}"
+                ))
+                .into());
+            }
+            outcome.bytes_written += copied;
+
+            if replace {
+                drop(output_file);
+                fs::rename(&write_path, &output_path)?;
+                created.pop();
+            }
+            if !existed || policy == OverwritePolicy::Overwrite {
+                outcome.written += 1;
+            }
+            outcome.files.push(entry_name);
+        }
+    }
+
+    Ok(outcome)
+}
+
+// 上書き用の一時ファイル（"name.ext" → ".name.ext.partial"）
+fn partial_path(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{name}.partial"))
+}
+
+// "name.ext" → "name (1).ext" のように、存在しない名前が見つかるまで連番を付ける
+fn next_available_path(path: &Path) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let extension = path.extension().map(|e| e.to_string_lossy().to_string());
+
+    (1..)
+        .map(|n| {
+            let name = match &extension {
+                Some(ext) => format!("{stem} ({n}).{ext}"),
+                None => format!("{stem} ({n})"),
+            };
+            path.with_file_name(name)
+        })
+        .find(|candidate| !candidate.exists())
+        .expect("unbounded counter always finds a free name")
+}
+
+// 新規作成したファイル・フォルダと、それにより空になったディレクトリを削除する
+fn remove_partial_output(output_root: &Path, created: &[PathBuf]) {
+    for path in created.iter().rev() {
+        let _ = fs::remove_file(path).or_else(|_| fs::remove_dir(path));
+        let mut dir = path.parent();
+        while let Some(current) = dir {
+            if current == output_root || fs::remove_dir(current).is_err() {
+                break;
+            }
+            dir = current.parent();
+        }
+    }
+}
+
+fn has_encrypted_entries(archive: &mut ZipReader) -> Result<bool> {
+    for i in 0..archive.len() {
+        if archive.by_index_raw(i)?.encrypted() {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+fn archive_display_name(zip_path: &Path) -> String {
+    zip_path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| zip_path.to_string_lossy().to_string())
+}
+
+fn size_limit_error(message: String) -> AppError {
+    AppError::validation("zip_archive", message)
+}
+
+// エントリ名を出力ディレクトリ配下のパスに解決する
+// 絶対パスや ".." で出力ディレクトリの外に出るエントリはエラーとする
+fn resolve_entry_path(output_root: &Path, entry_name: &str) -> AppResult<PathBuf> {
+    use std::path::Component;
+
+    let mut relative = PathBuf::new();
+    for component in Path::new(&entry_name.replace('\\', "/")).components() {
+        match component {
+            Component::Normal(part) => relative.push(part),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !relative.pop() {
+                    return Err(unsafe_entry_error(entry_name));
+                }
             }
+            Component::RootDir | Component::Prefix(_) => {
+                return Err(unsafe_entry_error(entry_name));
+            }
+        }
+    }
+
+    Ok(output_root.join(relative))
+}
+
+// 作成済みのディレクトリを正規化し、シンボリックリンク経由でも外に出ていないか確認する
+fn ensure_within_output(output_root: &Path, dir: &Path, entry_name: &str) -> AppResult<()> {
+    if dir.canonicalize()?.starts_with(output_root) {
+        Ok(())
+    } else {
+        Err(unsafe_entry_error(entry_name))
+    }
+}
 
-            let mut output_file = fs::File::create(&output_path)?;
-            std::io::copy(&mut file, &mut output_file)?;
+fn unsafe_entry_error(entry_name: &str) -> AppError {
+    AppError::validation(
+        "zip_entry",
+        format!("出力先の外を指すエントリは展開できません: {entry_name}"),
+    )
+}
+
+// アーカイブ内の全ファイル名からエンコーディングを推定する
+// すべて UTF-8 として読める場合は検出を省略し、それ以外は UTF-8 でない名前だけで判定する
+// Shift_JIS と EUC-JP のように両方で読めるバイト列も、より確からしい方を選ぶ
+fn detect_archive_encoding(archive: &mut ZipReader) -> Result<&'static Encoding> {
+    let mut detector = chardetng::EncodingDetector::new();
+    let mut all_utf8 = true;
 
-            extracted_files.push(file_name);
+    for i in 0..archive.len() {
+        let file = archive.by_index_raw(i)?;
+        let raw_name = file.name_raw();
+        if std::str::from_utf8(raw_name).is_err() {
+            all_utf8 = false;
+            detector.feed(raw_name, false);
+            detector.feed(b"\n", false);
         }
     }
 
-    Ok(extracted_files)
+    if all_utf8 {
+        return Ok(UTF_8);
+    }
+    detector.feed(&[], true);
+    Ok(detector.guess(None, false))
+}
+
+/// "euc-kr" や "gbk" などのラベルからファイル名の文字コードを決める
+pub fn parse_filename_encoding(label: &str) -> AppResult<&'static Encoding> {
+    Encoding::for_label(label.trim().as_bytes())
+        // "iso-2022-kr" などは全体を置換文字にする replacement エンコーディングになるため拒否する
+        .filter(|encoding| *encoding != encoding_rs::REPLACEMENT)
+        .ok_or_else(|| {
+            AppError::validation(
+                "filename_encoding",
+                format!("Unsupported filename encoding: {label}"),
+            )
+        })
+}
+
+// 推定したエンコーディングでファイル名を変換する
+// UTF-8 のまま読める名前（UTF-8 フラグ付きのエントリ）はそのまま、推定結果で読めない名前は従来の判定に任せる
+fn decode_filename(raw_bytes: &[u8], encoding: &'static Encoding) -> Result<String> {
+    if let Ok(utf8_str) = std::str::from_utf8(raw_bytes) {
+        return Ok(utf8_str.to_string());
+    }
+
+    let (decoded, had_errors) = encoding.decode_without_bom_handling(raw_bytes);
+    if !had_errors {
+        return Ok(decoded.into_owned());
+    }
+
+    detect_and_convert_filename(raw_bytes)
 }
 
 fn detect_and_convert_filename(raw_bytes: &[u8]) -> Result<String> {
@@ -305,50 +1317,1126 @@ fn detect_and_convert_filename(raw_bytes: &[u8]) -> Result<String> {
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
-    // Initialize logging
-    env_logger::init();
+    // ログファイルへの出力を開始（ハンドルは終了まで保持する）
+    let _logger = logging::init_logging();
 
     // アプリケーション状態を初期化
     let app_state = AppState::new().expect("Failed to initialize application state");
+    let watcher_db = app_state.db.clone();
+    let watcher_booth_client = app_state.booth_client.clone();
 
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_dialog::init())
         .manage(app_state)
+        .setup(move |app| {
+            // 受信フォルダの自動取り込み（設定で有効な場合のみ）
+            inbox_watcher::start_inbox_watcher(
+                app.handle().clone(),
+                watcher_db,
+                watcher_booth_client,
+            );
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             // 既存のコマンド（変更なし）
             system_commands::greet,
             process_commands::select_output_folder,
             process_commands::select_zip_files,
             process_commands::process_zip_file,
+            process_commands::process_zip_files_batch,
+            process_commands::process_zip_files,
+            process_commands::reextract_to_db,
+            process_commands::undo_last_import,
+            process_commands::refetch_booth_info_db,
             file_commands::save_file_to_db,
             file_commands::get_all_files_from_db,
             file_commands::get_files_with_tags_from_db,
+            file_commands::get_files_paginated_db,
+            file_commands::set_favorite_db,
+            file_commands::toggle_favorite_db,
+            file_commands::open_booth_page_db,
+            file_commands::get_favorites_db,
+            file_commands::update_file_note_db,
+            file_commands::update_file_metadata_db,
+            file_commands::search_metadata,
+            file_commands::mark_file_opened_db,
+            file_commands::get_recent_files_db,
+            file_commands::get_recently_opened_db,
+            file_commands::get_related_files_db,
             tag_commands::add_tag_to_file_db,
             tag_commands::remove_tag_from_file_db,
             tag_commands::get_all_tags_from_db,
+            tag_commands::get_tags_paginated_db,
+            tag_commands::update_tag_db,
             tag_commands::get_tags_for_file_db,
+            tag_commands::suggest_tags_for_file,
+            system_commands::load_settings,
+            system_commands::save_settings,
             system_commands::save_output_folder,
             system_commands::load_output_folder,
+            system_commands::save_folder_template,
+            system_commands::load_folder_template,
+            system_commands::save_inbox_watch_settings,
+            system_commands::load_inbox_watch_settings,
             booth_commands::validate_booth_url,
+            booth_commands::validate_booth_shop_url,
+            booth_commands::fetch_shop_items,
             booth_commands::fetch_booth_product_info,
+            booth_commands::check_booth_connectivity,
             system_commands::open_folder,
+            system_commands::open_file,
+            system_commands::reveal_file,
+            system_commands::get_log_path,
             booth_commands::download_booth_thumbnail,
+            booth_commands::get_thumbnail_data_url_db,
             sync_commands::search_files_db,
+            sync_commands::search_files_paginated_db,
+            sync_commands::rebuild_index_db,
+            sync_commands::repair_database,
+            sync_commands::compact_database,
+            sync_commands::backup_database,
+            sync_commands::verify_files_db,
+            sync_commands::search_files_fts_db,
             sync_commands::search_files_by_tags_db,
+            sync_commands::search_files_by_tags_paginated_db,
+            sync_commands::search_files_advanced_db,
+            sync_commands::search_files_by_tag_expr_db,
             sync_commands::find_duplicate_files_db,
+            sync_commands::find_duplicate_products_db,
+            sync_commands::merge_duplicate_files_db,
             tag_commands::batch_add_tag_to_files_db,
             tag_commands::batch_remove_tag_from_files_db,
             file_commands::delete_file_db,
             file_commands::delete_file_and_folder,
+            file_commands::move_file,
+            file_commands::get_deleted_files_db,
+            file_commands::get_unlinked_files_db,
+            file_commands::get_files_by_category_db,
+            file_commands::restore_file_db,
+            file_commands::purge_deleted_db,
             file_commands::batch_delete_files_db,
             file_commands::batch_update_files_db,
             sync_commands::get_batch_statistics_db,
+            sync_commands::get_shop_breakdown_db,
+            sync_commands::get_shop_summary_db,
+            sync_commands::get_library_stats_db,
+            file_commands::get_file_by_id_db,
             file_commands::get_files_with_tags_by_ids_db,
             booth_commands::update_file_booth_url_db,
             sync_commands::sync_file_system_db,
             sync_commands::remove_missing_files_db,
+            catalog_commands::export_catalog_db,
+            catalog_commands::import_catalog_db,
+            catalog_commands::export_csv,
+            catalog_commands::apply_tags_from_csv_db,
+            catalog_commands::export_library_db,
+            catalog_commands::import_library_db,
+            collection_commands::create_collection_db,
+            collection_commands::list_collections_db,
+            collection_commands::add_files_to_collection_db,
+            collection_commands::remove_files_from_collection_db,
+            collection_commands::get_collection_files_db,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::write_zip;
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+
+    // UTF-8 以外のファイル名を持つZIPを作る（同じ長さの仮名で書き込んでからバイト列を差し替える）
+    fn write_zip_with_raw_names(path: &Path, entries: &[(&[u8], &[u8])]) {
+        let placeholders: Vec<String> = entries
+            .iter()
+            .enumerate()
+            .map(|(i, (name, _))| format!("{i:@>width$}", width = name.len()))
+            .collect();
+        let named: Vec<(&str, &[u8])> = placeholders
+            .iter()
+            .zip(entries)
+            .map(|(placeholder, (_, content))| (placeholder.as_str(), *content))
+            .collect();
+        write_zip(path, &named);
+
+        let mut bytes = fs::read(path).unwrap();
+        for (placeholder, (name, _)) in placeholders.iter().zip(entries) {
+            let placeholder = placeholder.as_bytes();
+            let mut start = 0;
+            while let Some(pos) = bytes[start..]
+                .windows(placeholder.len())
+                .position(|w| w == placeholder)
+            {
+                let at = start + pos;
+                bytes[at..at + name.len()].copy_from_slice(name);
+                start = at + name.len();
+            }
+        }
+        fs::write(path, bytes).unwrap();
+    }
+
+    #[test]
+    fn test_detects_euc_jp_names_that_also_decode_as_shift_jis() {
+        let sandbox = tempfile::tempdir().unwrap();
+        let zip_path = sandbox.path().join("euc.zip");
+        let (name, _, _) = encoding_rs::EUC_JP.encode("アバター.unitypackage");
+        // 同じバイト列が Shift_JIS（半角カナ）としてもエラーなく読める
+        let (as_sjis, _, sjis_errors) = SHIFT_JIS.decode(&name);
+        assert!(!sjis_errors);
+        assert_ne!(as_sjis, "アバター.unitypackage");
+
+        write_zip_with_raw_names(&zip_path, &[(&name, b"pkg")]);
+        let output = sandbox.path().join("out");
+        fs::create_dir_all(&output).unwrap();
+
+        let outcome = extract_zip_with_encoding(
+            &zip_path,
+            &output,
+            OverwritePolicy::default(),
+            &ExtractionLimits::default(),
+            None,
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(outcome.encoding_info.as_deref(), Some("EUC-JP"));
+        assert_eq!(outcome.files, vec!["アバター.unitypackage".to_string()]);
+        assert!(output.join("アバター.unitypackage").exists());
+    }
+
+    #[test]
+    fn test_filename_encoding_override_decodes_korean_names() {
+        let sandbox = tempfile::tempdir().unwrap();
+        let zip_path = sandbox.path().join("euc_kr.zip");
+        let (name, _, _) = encoding_rs::EUC_KR.encode("아바타/텍스처.png");
+        write_zip_with_raw_names(&zip_path, &[(&name, b"png")]);
+        let output = sandbox.path().join("out");
+        fs::create_dir_all(&output).unwrap();
+
+        let outcome = extract_zip_with_encoding(
+            &zip_path,
+            &output,
+            OverwritePolicy::default(),
+            &ExtractionLimits::default(),
+            None,
+            Some(parse_filename_encoding("euc-kr").unwrap()),
+        )
+        .unwrap();
+
+        assert_eq!(outcome.encoding_info.as_deref(), Some("EUC-KR"));
+        assert_eq!(outcome.files, vec!["아바타/텍스처.png".to_string()]);
+        assert!(output.join("아바타/텍스처.png").exists());
+    }
+
+    #[test]
+    fn test_parse_filename_encoding_rejects_unknown_labels() {
+        assert_eq!(parse_filename_encoding(" GBK ").unwrap().name(), "GBK");
+        for label in ["klingon", "", "iso-2022-kr"] {
+            let err = parse_filename_encoding(label).unwrap_err();
+            assert!(matches!(err, AppError::Validation { .. }), "{err:?}");
+        }
+    }
+
+    #[test]
+    fn test_detects_shift_jis_and_utf8_names() {
+        let sandbox = tempfile::tempdir().unwrap();
+        let output = sandbox.path().join("out");
+        fs::create_dir_all(&output).unwrap();
+
+        let sjis_zip = sandbox.path().join("sjis.zip");
+        let (texture, _, _) = SHIFT_JIS.encode("テクスチャ/本体.png");
+        let (readme, _, _) = SHIFT_JIS.encode("説明書.txt");
+        write_zip_with_raw_names(&sjis_zip, &[(&texture, b"png"), (&readme, b"txt")]);
+
+        let outcome = extract_zip_with_encoding(
+            &sjis_zip,
+            &output,
+            OverwritePolicy::default(),
+            &ExtractionLimits::default(),
+            None,
+            None,
+        )
+        .unwrap();
+        assert_eq!(outcome.encoding_info.as_deref(), Some("Shift_JIS"));
+        assert!(output.join("テクスチャ/本体.png").exists());
+        assert!(output.join("説明書.txt").exists());
+
+        // UTF-8 のファイル名は検出を行わずそのまま使う
+        let utf8_zip = sandbox.path().join("utf8.zip");
+        write_zip(&utf8_zip, &[("衣装/夏.png", b"png")]);
+        let outcome = extract_zip_with_encoding(
+            &utf8_zip,
+            &output,
+            OverwritePolicy::default(),
+            &ExtractionLimits::default(),
+            None,
+            None,
+        )
+        .unwrap();
+        assert_eq!(outcome.encoding_info.as_deref(), Some("UTF-8"));
+        assert!(output.join("衣装/夏.png").exists());
+    }
+
+    #[test]
+    fn test_extract_keeps_nested_paths() {
+        let sandbox = tempfile::tempdir().unwrap();
+        let zip_path = sandbox.path().join("nested.zip");
+        write_zip(
+            &zip_path,
+            &[
+                ("Assets/Textures/body.png", b"png"),
+                ("./readme.txt", b"hi"),
+            ],
+        );
+        let output = sandbox.path().join("out");
+        fs::create_dir_all(&output).unwrap();
+
+        let outcome = extract_zip_with_encoding(
+            &zip_path,
+            &output,
+            OverwritePolicy::default(),
+            &ExtractionLimits::default(),
+            None,
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(outcome.files.len(), 2);
+        assert!(output.join("Assets/Textures/body.png").exists());
+        assert!(output.join("readme.txt").exists());
+    }
+
+    fn write_encrypted_zip(path: &Path, password: &str, entries: &[(&str, &[u8])]) {
+        use zip::unstable::write::FileOptionsExt;
+
+        let file = fs::File::create(path).unwrap();
+        let mut writer = zip::ZipWriter::new(file);
+        for (name, content) in entries {
+            let options =
+                SimpleFileOptions::default().with_deprecated_encryption(password.as_bytes());
+            writer.start_file(*name, options).unwrap();
+            writer.write_all(content).unwrap();
+        }
+        writer.finish().unwrap();
+    }
+
+    fn extract_error_code(err: anyhow::Error) -> &'static str {
+        err.downcast_ref::<AppError>()
+            .map(AppError::code)
+            .unwrap_or_else(|| panic!("unexpected error: {err}"))
+    }
+
+    #[test]
+    fn test_extract_encrypted_zip_with_password() {
+        let sandbox = tempfile::tempdir().unwrap();
+        let zip_path = sandbox.path().join("locked.zip");
+        write_encrypted_zip(
+            &zip_path,
+            "booth-pass",
+            &[("Avatar/model.fbx", b"fbx data"), ("readme.txt", b"hello")],
+        );
+        let output = sandbox.path().join("out");
+        fs::create_dir_all(&output).unwrap();
+        let extract = |password: Option<&str>| {
+            extract_zip_with_encoding(
+                &zip_path,
+                &output,
+                OverwritePolicy::default(),
+                &ExtractionLimits::default(),
+                password,
+                None,
+            )
+        };
+
+        // パスワード未指定・誤りの場合は何も残さない
+        let err = extract(None).unwrap_err();
+        assert_eq!(extract_error_code(err), "PasswordRequired");
+        let err = extract(Some("wrong")).unwrap_err();
+        assert_eq!(extract_error_code(err), "InvalidPassword");
+        assert_eq!(fs::read_dir(&output).unwrap().count(), 0);
+
+        let outcome = extract(Some("booth-pass")).unwrap();
+        assert_eq!(outcome.files.len(), 2);
+        assert_eq!(
+            fs::read(output.join("Avatar/model.fbx")).unwrap(),
+            b"fbx data"
+        );
+        assert_eq!(fs::read(output.join("readme.txt")).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn test_password_is_ignored_for_unencrypted_zip() {
+        let sandbox = tempfile::tempdir().unwrap();
+        let zip_path = sandbox.path().join("plain.zip");
+        write_zip(&zip_path, &[("model.fbx", b"fbx")]);
+        let output = sandbox.path().join("out");
+        fs::create_dir_all(&output).unwrap();
+
+        let outcome = extract_zip_with_encoding(
+            &zip_path,
+            &output,
+            OverwritePolicy::default(),
+            &ExtractionLimits::default(),
+            Some("unused"),
+            None,
+        )
+        .unwrap();
+        assert_eq!(outcome.files, vec!["model.fbx".to_string()]);
+    }
+
+    #[test]
+    fn test_extract_rejects_path_traversal() {
+        let sandbox = tempfile::tempdir().unwrap();
+        let zip_path = sandbox.path().join("evil.zip");
+        write_zip(&zip_path, &[("../../evil.txt", b"pwned")]);
+        let output = sandbox.path().join("a").join("out");
+        fs::create_dir_all(&output).unwrap();
+
+        let err = extract_zip_with_encoding(
+            &zip_path,
+            &output,
+            OverwritePolicy::default(),
+            &ExtractionLimits::default(),
+            None,
+            None,
+        )
+        .unwrap_err();
+
+        assert!(err.downcast_ref::<AppError>().is_some());
+        assert!(!sandbox.path().join("evil.txt").exists());
+        assert!(!sandbox.path().join("a").join("evil.txt").exists());
+        assert_eq!(fs::read_dir(&output).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_extract_rejects_oversize_declaration() {
+        let sandbox = tempfile::tempdir().unwrap();
+        let zip_path = sandbox.path().join("big.zip");
+        write_zip(
+            &zip_path,
+            &[("small.txt", b"ok"), ("big.bin", &[7u8; 4096])],
+        );
+        let output = sandbox.path().join("out");
+        fs::create_dir_all(&output).unwrap();
+
+        let limits = ExtractionLimits {
+            max_file_bytes: 1024,
+            ..ExtractionLimits::default()
+        };
+        let err = extract_zip_with_encoding(
+            &zip_path,
+            &output,
+            OverwritePolicy::default(),
+            &limits,
+            None,
+            None,
+        )
+        .unwrap_err();
+
+        assert!(err.to_string().contains("big.bin"));
+        assert!(err.to_string().contains("max_file_bytes"), "{err}");
+        assert_eq!(fs::read_dir(&output).unwrap().count(), 0);
+    }
+
+    // 指定したエントリの宣言サイズ（ローカルヘッダーと中央ディレクトリの展開後サイズ）を書き換える
+    fn forge_declared_size(path: &Path, entry_name: &str, declared: u32) {
+        let mut bytes = fs::read(path).unwrap();
+        // (シグネチャ, 展開後サイズの位置, ファイル名の位置)
+        let headers: [(&[u8], usize, usize); 2] =
+            [(b"PK\x03\x04", 22, 30), (b"PK\x01\x02", 24, 46)];
+        for (signature, size_at, name_at) in headers {
+            let mut patched = false;
+            for pos in 0..bytes.len().saturating_sub(name_at) {
+                let name_end = pos + name_at + entry_name.len();
+                if bytes[pos..].starts_with(signature)
+                    && bytes.get(pos + name_at..name_end) == Some(entry_name.as_bytes())
+                {
+                    bytes[pos + size_at..pos + size_at + 4]
+                        .copy_from_slice(&declared.to_le_bytes());
+                    patched = true;
+                }
+            }
+            assert!(patched, "header for {entry_name} not found");
+        }
+        fs::write(path, bytes).unwrap();
+    }
+
+    #[test]
+    fn test_extract_stops_entry_that_expands_beyond_declared_size() {
+        let sandbox = tempfile::tempdir().unwrap();
+        let zip_path = sandbox.path().join("forged.zip");
+        let file = fs::File::create(&zip_path).unwrap();
+        let mut writer = zip::ZipWriter::new(file);
+        writer
+            .start_file("readme.txt", SimpleFileOptions::default())
+            .unwrap();
+        writer.write_all(b"hello").unwrap();
+        writer
+            .start_file(
+                "payload.bin",
+                SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated),
+            )
+            .unwrap();
+        writer.write_all(&vec![0u8; 64 * 1024]).unwrap();
+        writer.finish().unwrap();
+        // 16バイトと申告しているので宣言サイズの検査は通過する
+        forge_declared_size(&zip_path, "payload.bin", 16);
+
+        let output = sandbox.path().join("out");
+        fs::create_dir_all(&output).unwrap();
+        let limits = ExtractionLimits {
+            max_file_bytes: 1024,
+            ..ExtractionLimits::default()
+        };
+        let err = extract_zip_with_encoding(
+            &zip_path,
+            &output,
+            OverwritePolicy::default(),
+            &limits,
+            None,
+            None,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("payload.bin"), "{err}");
+        assert!(err.to_string().contains("max_file_bytes"), "{err}");
+        // 先に展開したファイルも含めて削除される
+        assert_eq!(fs::read_dir(&output).unwrap().count(), 0);
+
+        // 合計サイズの上限が先に尽きる場合はそちらを報告する
+        let limits = ExtractionLimits {
+            max_total_extracted_bytes: 1024,
+            ..ExtractionLimits::default()
+        };
+        let err = extract_zip_with_encoding(
+            &zip_path,
+            &output,
+            OverwritePolicy::default(),
+            &limits,
+            None,
+            None,
+        )
+        .unwrap_err();
+        assert!(
+            err.to_string().contains("max_total_extracted_bytes"),
+            "{err}"
+        );
+        assert_eq!(fs::read_dir(&output).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_failed_overwrite_keeps_existing_file() {
+        let sandbox = tempfile::tempdir().unwrap();
+        let zip_path = sandbox.path().join("forged.zip");
+        let file = fs::File::create(&zip_path).unwrap();
+        let mut writer = zip::ZipWriter::new(file);
+        writer
+            .start_file(
+                "payload.bin",
+                SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated),
+            )
+            .unwrap();
+        writer.write_all(&vec![0u8; 64 * 1024]).unwrap();
+        writer.finish().unwrap();
+        forge_declared_size(&zip_path, "payload.bin", 16);
+
+        let output = sandbox.path().join("out");
+        fs::create_dir_all(&output).unwrap();
+        fs::write(output.join("payload.bin"), b"keep").unwrap();
+        let limits = ExtractionLimits {
+            max_file_bytes: 1024,
+            ..ExtractionLimits::default()
+        };
+        let err = extract_zip_with_encoding(
+            &zip_path,
+            &output,
+            OverwritePolicy::Overwrite,
+            &limits,
+            None,
+            None,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("max_file_bytes"), "{err}");
+
+        // 既存ファイルは元の内容のまま、一時ファイルも残らない
+        assert_eq!(fs::read(output.join("payload.bin")).unwrap(), b"keep");
+        assert_eq!(fs::read_dir(&output).unwrap().count(), 1);
+
+        // 検査を通れば置き換える
+        let outcome = extract_zip_with_encoding(
+            &zip_path,
+            &output,
+            OverwritePolicy::Overwrite,
+            &ExtractionLimits::default(),
+            None,
+            None,
+        )
+        .unwrap();
+        assert_eq!(outcome.written, 1);
+        assert_eq!(
+            fs::read(output.join("payload.bin")).unwrap().len(),
+            64 * 1024
+        );
+        assert_eq!(fs::read_dir(&output).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_recursive_extract_counts_total_size_across_nested_zips() {
+        let sandbox = tempfile::tempdir().unwrap();
+        let inner_path = sandbox.path().join("inner.zip");
+        write_zip(&inner_path, &[("inner.bin", &[3u8; 600])]);
+        let inner = fs::read(&inner_path).unwrap();
+        let outer_path = sandbox.path().join("outer.zip");
+        write_zip(&outer_path, &[("inner.zip", &inner)]);
+
+        let output = sandbox.path().join("out");
+        fs::create_dir_all(&output).unwrap();
+        // どちらのZIPも単体では上限に収まるが、通算すると超える
+        let limits = ExtractionLimits {
+            max_total_extracted_bytes: inner.len() as u64 + 100,
+            ..ExtractionLimits::default()
+        };
+        let err = extract_zip_recursive(
+            &outer_path,
+            &output,
+            OverwritePolicy::default(),
+            &limits,
+            None,
+            None,
+        )
+        .unwrap_err();
+        assert!(
+            err.to_string().contains("max_total_extracted_bytes"),
+            "{err}"
+        );
+        assert_eq!(fs::read_dir(&output).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_recursive_extract_unpacks_nested_zips_up_to_depth_limit() {
+        let sandbox = tempfile::tempdir().unwrap();
+        let inner_path = sandbox.path().join("inner.zip");
+        write_zip(&inner_path, &[("deep.txt", b"deep")]);
+        let inner = fs::read(&inner_path).unwrap();
+        let mid_path = sandbox.path().join("mid.zip");
+        write_zip(
+            &mid_path,
+            &[("Windows/inner.zip", &inner), ("mid.txt", b"mid")],
+        );
+        let mid = fs::read(&mid_path).unwrap();
+        let outer_path = sandbox.path().join("outer.zip");
+        write_zip(
+            &outer_path,
+            &[("Variants/mid.zip", &mid), ("readme.txt", b"hi")],
+        );
+
+        let output = sandbox.path().join("out");
+        fs::create_dir_all(&output).unwrap();
+        let limits = ExtractionLimits {
+            max_nested_depth: 2,
+            ..ExtractionLimits::default()
+        };
+        let outcome = extract_zip_recursive(
+            &outer_path,
+            &output,
+            OverwritePolicy::default(),
+            &limits,
+            None,
+            None,
+        )
+        .unwrap();
+
+        let mut files = outcome.files.clone();
+        files.sort();
+        let expected: Vec<String> = [
+            "Variants/mid.zip",
+            "Variants/mid/Windows/inner.zip",
+            "Variants/mid/Windows/inner/deep.txt",
+            "Variants/mid/mid.txt",
+            "readme.txt",
+        ]
+        .iter()
+        .map(|p| Path::new(p).to_string_lossy().to_string())
+        .collect();
+        assert_eq!(files, expected);
+        assert_eq!(outcome.written, 5);
+        assert_eq!(
+            fs::read(output.join("Variants/mid/Windows/inner/deep.txt")).unwrap(),
+            b"deep"
+        );
+
+        // 深さ1までに制限すると2段目のZIPでエラーになる
+        let shallow = sandbox.path().join("shallow");
+        fs::create_dir_all(&shallow).unwrap();
+        let limits = ExtractionLimits {
+            max_nested_depth: 1,
+            ..ExtractionLimits::default()
+        };
+        let err = extract_zip_recursive(
+            &outer_path,
+            &shallow,
+            OverwritePolicy::default(),
+            &limits,
+            None,
+            None,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("入れ子"), "{err}");
+        // 外側・内側のZIPから展開済みのファイルも残さない
+        assert_eq!(fs::read_dir(&shallow).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_extract_accepts_unbounded_limits() {
+        let sandbox = tempfile::tempdir().unwrap();
+        let zip_path = sandbox.path().join("plain.zip");
+        write_zip(&zip_path, &[("a/1.bin", &[1u8; 600]), ("b.txt", b"hello")]);
+        let output = sandbox.path().join("out");
+        fs::create_dir_all(&output).unwrap();
+
+        // 上限を実質無効にしても上限+1の計算で桁あふれしない
+        let limits = ExtractionLimits {
+            max_total_extracted_bytes: u64::MAX,
+            max_file_bytes: u64::MAX,
+            max_compression_ratio: u64::MAX,
+            ..ExtractionLimits::default()
+        };
+        let outcome = extract_zip_with_encoding(
+            &zip_path,
+            &output,
+            OverwritePolicy::default(),
+            &limits,
+            None,
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(outcome.written, 2);
+        assert_eq!(outcome.bytes_written, 605);
+        assert_eq!(fs::read(output.join("a/1.bin")).unwrap(), vec![1u8; 600]);
+        assert_eq!(fs::read(output.join("b.txt")).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn test_extract_rejects_total_size_over_limit() {
+        let sandbox = tempfile::tempdir().unwrap();
+        let zip_path = sandbox.path().join("many.zip");
+        write_zip(
+            &zip_path,
+            &[("a/1.bin", &[1u8; 600]), ("a/2.bin", &[2u8; 600])],
+        );
+        let output = sandbox.path().join("out");
+        fs::create_dir_all(&output).unwrap();
+
+        let limits = ExtractionLimits {
+            max_total_extracted_bytes: 1000,
+            ..ExtractionLimits::default()
+        };
+
+        let err = extract_zip_with_encoding(
+            &zip_path,
+            &output,
+            OverwritePolicy::default(),
+            &limits,
+            None,
+            None,
+        )
+        .unwrap_err();
+        assert!(
+            err.to_string().contains("max_total_extracted_bytes"),
+            "{err}"
+        );
+        assert_eq!(fs::read_dir(&output).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_extract_rejects_implausible_compression_ratio() {
+        let sandbox = tempfile::tempdir().unwrap();
+        let zip_path = sandbox.path().join("bomb.zip");
+        let file = fs::File::create(&zip_path).unwrap();
+        let mut writer = zip::ZipWriter::new(file);
+        writer
+            .start_file(
+                "zeros.bin",
+                SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated),
+            )
+            .unwrap();
+        writer.write_all(&vec![0u8; 4 * 1024 * 1024]).unwrap();
+        writer.finish().unwrap();
+        let output = sandbox.path().join("out");
+        fs::create_dir_all(&output).unwrap();
+
+        let err = extract_zip_with_encoding(
+            &zip_path,
+            &output,
+            OverwritePolicy::default(),
+            &ExtractionLimits::default(),
+            None,
+            None,
+        )
+        .unwrap_err();
+
+        assert!(err.to_string().contains("zeros.bin"));
+        assert!(!output.join("zeros.bin").exists());
+    }
+
+    // 既存の model.fbx がある出力先に、同名エントリを含むZIPを展開する
+    fn extract_over_existing(policy: OverwritePolicy) -> (tempfile::TempDir, ExtractionOutcome) {
+        let sandbox = tempfile::tempdir().unwrap();
+        let zip_path = sandbox.path().join("update.zip");
+        write_zip(
+            &zip_path,
+            &[("model.fbx", b"new"), ("readme.txt", b"readme")],
+        );
+        let output = sandbox.path().join("out");
+        fs::create_dir_all(&output).unwrap();
+        fs::write(output.join("model.fbx"), b"old").unwrap();
+
+        let outcome = extract_zip_with_encoding(
+            &zip_path,
+            &output,
+            policy,
+            &ExtractionLimits::default(),
+            None,
+            None,
+        )
+        .unwrap();
+        (sandbox, outcome)
+    }
+
+    #[test]
+    fn test_overwrite_policy_replaces_existing_file() {
+        let (sandbox, outcome) = extract_over_existing(OverwritePolicy::Overwrite);
+        let output = sandbox.path().join("out");
+
+        assert_eq!(fs::read(output.join("model.fbx")).unwrap(), b"new");
+        assert_eq!(outcome.written, 2);
+        assert_eq!(outcome.skipped, 0);
+        assert_eq!(outcome.renamed, 0);
+    }
+
+    #[test]
+    fn test_skip_policy_keeps_existing_file() {
+        let (sandbox, outcome) = extract_over_existing(OverwritePolicy::Skip);
+        let output = sandbox.path().join("out");
+
+        assert_eq!(fs::read(output.join("model.fbx")).unwrap(), b"old");
+        assert!(output.join("readme.txt").exists());
+        assert_eq!(outcome.files, vec!["readme.txt".to_string()]);
+        assert_eq!(outcome.written, 1);
+        assert_eq!(outcome.skipped, 1);
+        assert_eq!(outcome.renamed, 0);
+    }
+
+    #[test]
+    fn test_rename_policy_writes_alongside_existing_file() {
+        let (sandbox, outcome) = extract_over_existing(OverwritePolicy::Rename);
+        let output = sandbox.path().join("out");
+
+        assert_eq!(fs::read(output.join("model.fbx")).unwrap(), b"old");
+        assert_eq!(fs::read(output.join("model (1).fbx")).unwrap(), b"new");
+        assert!(outcome.files.contains(&"model (1).fbx".to_string()));
+        assert_eq!(outcome.written, 1);
+        assert_eq!(outcome.skipped, 0);
+        assert_eq!(outcome.renamed, 1);
+    }
+
+    #[test]
+    fn test_next_available_path_increments_counter() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a.txt"), b"").unwrap();
+        fs::write(dir.path().join("a (1).txt"), b"").unwrap();
+
+        assert_eq!(
+            next_available_path(&dir.path().join("a.txt")),
+            dir.path().join("a (2).txt")
+        );
+        assert_eq!(
+            next_available_path(&dir.path().join("noext")),
+            dir.path().join("noext (1)")
+        );
+        assert_eq!(
+            OverwritePolicy::parse(" Rename ").unwrap(),
+            OverwritePolicy::Rename
+        );
+        assert!(OverwritePolicy::parse("merge").is_err());
+    }
+
+    #[test]
+    fn test_resolve_entry_path_rejects_absolute_paths() {
+        let root = Path::new("/tmp/out");
+
+        assert!(resolve_entry_path(root, "/etc/passwd").is_err());
+        assert!(resolve_entry_path(root, "a/../../b").is_err());
+        assert!(resolve_entry_path(root, "..\\evil.txt").is_err());
+        assert_eq!(
+            resolve_entry_path(root, "a/../b/c.txt").unwrap(),
+            root.join("b/c.txt")
+        );
+    }
+
+    #[test]
+    fn test_render_default_template_nests_shop_and_product() {
+        let path = render_folder_template(
+            files::DEFAULT_FOLDER_TEMPLATE,
+            "Dolos Art",
+            "Avatar: v2",
+            None,
+            &SanitizeOptions::default(),
+        )
+        .unwrap();
+
+        assert_eq!(path, Path::new("Dolos Art").join("Avatar_ v2"));
+    }
+
+    #[test]
+    fn test_render_template_with_product_id() {
+        let template = "{shop} - {product} ({id})";
+
+        assert_eq!(
+            render_folder_template(
+                template,
+                "Shop",
+                "Item",
+                Some(5840141),
+                &SanitizeOptions::default()
+            )
+            .unwrap(),
+            PathBuf::from("Shop - Item (5840141)")
+        );
+        // 商品IDが不明な場合は空の括弧を残さない
+        assert_eq!(
+            render_folder_template(template, "Shop", "Item", None, &SanitizeOptions::default())
+                .unwrap(),
+            PathBuf::from("Shop - Item")
+        );
+    }
+
+    #[test]
+    fn test_render_template_keeps_brackets_in_names() {
+        // 値に含まれる括弧は、空の括弧でも商品IDが不明でもそのまま残す
+        assert_eq!(
+            render_folder_template(
+                "{shop}/{product} ({id})",
+                "[Sale] Shop",
+                "Avatar (Quest) []",
+                None,
+                &SanitizeOptions::default()
+            )
+            .unwrap(),
+            Path::new("[Sale] Shop").join("Avatar (Quest) []")
+        );
+        // テンプレートに書いた空の括弧も、プレースホルダーを囲んでいなければ残す
+        assert_eq!(
+            render_folder_template(
+                "{product} () [{id}]",
+                "Shop",
+                "Item",
+                None,
+                &SanitizeOptions::default()
+            )
+            .unwrap(),
+            PathBuf::from("Item ()")
+        );
+    }
+
+    #[test]
+    fn test_render_flat_template_keeps_separators_in_values() {
+        // 値に含まれる "/" は階層にならずサニタイズされる
+        assert_eq!(
+            render_folder_template(
+                "{product}",
+                "Shop",
+                "a/b",
+                None,
+                &SanitizeOptions::default()
+            )
+            .unwrap(),
+            PathBuf::from("a_b")
+        );
+        assert_eq!(
+            render_folder_template(
+                "BOOTH/{id}_{product}",
+                "Shop",
+                "..",
+                Some(7),
+                &SanitizeOptions::default()
+            )
+            .unwrap(),
+            Path::new("BOOTH").join("7_")
+        );
+    }
+
+    #[test]
+    fn test_render_template_with_product_id_alias_and_date() {
+        let date = chrono::NaiveDate::from_ymd_opt(2025, 3, 9).unwrap();
+        let render = |template: &str, product_id: Option<i64>| {
+            render_folder_template_on(
+                template,
+                "Shop",
+                "Item",
+                product_id,
+                date,
+                &SanitizeOptions::default(),
+            )
+            .unwrap()
+        };
+
+        assert_eq!(
+            render("{shop}_{product_id}", Some(42)),
+            PathBuf::from("Shop_42")
+        );
+        assert_eq!(
+            render("{date}/{shop} - {product} [{product_id}]", None),
+            Path::new("2025-03-09").join("Shop - Item")
+        );
+    }
+
+    #[test]
+    fn test_render_template_rejects_unknown_placeholders() {
+        let err = render_folder_template(
+            "{shop}/{author}",
+            "Shop",
+            "Item",
+            None,
+            &SanitizeOptions::default(),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("{author}"));
+
+        assert!(
+            render_folder_template("{shop", "Shop", "Item", None, &SanitizeOptions::default())
+                .is_err()
+        );
+        assert!(
+            render_folder_template(" / ", "Shop", "Item", None, &SanitizeOptions::default())
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_extract_to_output_uses_folder_template() {
+        let sandbox = tempfile::tempdir().unwrap();
+        let zip_path = sandbox.path().join("in.zip");
+        write_zip(&zip_path, &[("model.fbx", b"fbx")]);
+        let output = sandbox.path().join("out");
+
+        let options = ExtractOptions {
+            output_dir: Some(output.to_string_lossy().to_string()),
+            folder_template: Some("{shop} - {product} ({id})".to_string()),
+            ..ExtractOptions::default()
+        };
+        let res = extract_to_output(
+            &zip_path,
+            Some("Shop".to_string()),
+            Some("Item".to_string()),
+            Some(42),
+            &options,
+        )
+        .unwrap();
+
+        assert!(output.join("Shop - Item (42)/model.fbx").exists());
+        assert_eq!(
+            res.output_path,
+            Some(
+                output
+                    .join("Shop - Item (42)")
+                    .to_string_lossy()
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_sanitize_truncates_japanese_on_char_boundaries() {
+        let long_name = "ミルク専用衣装セット".repeat(30);
+
+        let sanitized = sanitize_folder_name(&long_name);
+
+        // 1文字3バイトのため、バイト数の上限ちょうどの80文字で切れる
+        assert_eq!(sanitized.len(), files::MAX_FILENAME_BYTES);
+        assert_eq!(sanitized.chars().count(), files::MAX_FILENAME_BYTES / 3);
+        assert!(long_name.starts_with(&sanitized));
+        assert_eq!(sanitize_folder_name("アバター：ミルク"), "アバター：ミルク");
+
+        // ASCII とマルチバイト文字が混ざっていても文字の途中では切らない
+        let mixed = format!("a{}", "衣装🎀".repeat(100));
+        let sanitized = sanitize_folder_name(&mixed);
+        assert!(sanitized.len() <= files::MAX_FILENAME_BYTES);
+        assert!(mixed.starts_with(&sanitized));
+
+        // ASCII のみなら文字数の上限で切れる
+        let ascii = "a".repeat(300);
+        assert_eq!(
+            sanitize_folder_name(&ascii).len(),
+            files::MAX_FILENAME_LENGTH
+        );
+    }
+
+    #[test]
+    fn test_sanitize_prefixes_windows_reserved_names() {
+        assert_eq!(sanitize_folder_name("CON"), "_CON");
+        assert_eq!(sanitize_folder_name("nul"), "_nul");
+        assert_eq!(sanitize_folder_name("COM1"), "_COM1");
+        assert_eq!(sanitize_folder_name("com1.txt"), "_com1.txt");
+        assert_eq!(sanitize_folder_name("PRN.tar.gz"), "_PRN.tar.gz");
+        assert_eq!(sanitize_folder_name("LPT9 "), "_LPT9");
+        assert_eq!(sanitize_folder_name("COM¹"), "_COM¹");
+        assert!(!is_windows_reserved_name(&sanitize_folder_name("aux.zip")));
+
+        assert_eq!(sanitize_folder_name("CONSOLE"), "CONSOLE");
+        assert_eq!(sanitize_folder_name("COM10"), "COM10");
+        assert_eq!(sanitize_folder_name("my CON"), "my CON");
+    }
+
+    #[test]
+    fn test_extract_booth_info_fallback_normalizes_url() {
+        let (shop, product) =
+            extract_booth_info_fallback("https://Shop.booth.pm/ja/items/123?x=1").unwrap();
+        assert_eq!(shop, "shop");
+        assert_eq!(product, "product_123");
+
+        assert!(extract_booth_info_fallback("https://www.booth.pm/items/1").is_err());
+        assert!(extract_booth_info_fallback("https://booth.pm/items/1").is_err());
+    }
+
+    #[test]
+    fn test_sanitize_default_options_match_plain_sanitize() {
+        let defaults = SanitizeOptions::default();
+
+        for name in ["a<b>c:d\"e|f?g*h/i\\j", "  .hidden. ", "#1 100%", ""] {
+            assert_eq!(
+                sanitize_folder_name_with(name, &defaults),
+                sanitize_folder_name(name)
+            );
+        }
+        assert_eq!(sanitize_folder_name("a<b>:c/d"), "a_b__c_d");
+        assert_eq!(sanitize_folder_name(".hidden"), ".hidden");
+    }
+
+    #[test]
+    fn test_sanitize_honors_custom_character_set() {
+        let options = SanitizeOptions {
+            invalid_chars: vec!['#', '%'],
+            replacement: '-',
+            strip_leading_dots: true,
+        };
+
+        assert_eq!(sanitize_folder_name_with("#1 100%", &options), "-1 100-");
+        assert_eq!(sanitize_folder_name_with("..config", &options), "config");
+        // 区切り文字は設定にかかわらず置換される
+        assert_eq!(sanitize_folder_name_with("a/b\\c", &options), "a-b-c");
+        // 設定にない文字は残る
+        assert_eq!(sanitize_folder_name_with("a<b", &options), "a<b");
+
+        // 置換後の文字が不正な場合は既定の "_" を使う
+        let options = SanitizeOptions {
+            replacement: '/',
+            ..options
+        };
+        assert_eq!(sanitize_folder_name_with("a#b", &options), "a_b");
+    }
+}
@@ -0,0 +1,90 @@
+// ログ出力 - アプリデータディレクトリ配下にローテーションするログファイルを書き出す
+use crate::app_data_dir;
+use crate::config::logging;
+use flexi_logger::{
+    detailed_format, Cleanup, Criterion, Duplicate, FileSpec, Logger, LoggerHandle, Naming,
+};
+use std::future::Future;
+use std::path::PathBuf;
+use std::time::Instant;
+
+/// ログファイルを置くディレクトリ
+pub fn log_dir() -> PathBuf {
+    app_data_dir().join(logging::LOG_DIR_NAME)
+}
+
+/// 書き込み中のログファイル（ローテーション済みのものは同じディレクトリに連番で残る）
+pub fn log_file_path() -> PathBuf {
+    log_dir().join(format!("{}_rCURRENT.log", logging::LOG_FILE_BASENAME))
+}
+
+/// ロガーを初期化する
+///
+/// 返り値はアプリ終了まで保持すること（破棄するとファイルへの書き込みが止まる）。
+/// デバッグビルドではコンソールにも出力する。ログファイルを開けない場合はコンソールのみ。
+pub fn init_logging() -> Option<LoggerHandle> {
+    let console = if cfg!(debug_assertions) {
+        Duplicate::All
+    } else {
+        Duplicate::None
+    };
+
+    let file_logger = Logger::try_with_env_or_str(logging::DEFAULT_LEVEL).and_then(|logger| {
+        logger
+            .log_to_file(
+                FileSpec::default()
+                    .directory(log_dir())
+                    .basename(logging::LOG_FILE_BASENAME)
+                    .suppress_timestamp(),
+            )
+            .rotate(
+                Criterion::Size(logging::MAX_LOG_FILE_BYTES),
+                Naming::Numbers,
+                Cleanup::KeepLogFiles(logging::KEEP_LOG_FILES),
+            )
+            .append()
+            .format_for_files(detailed_format)
+            .duplicate_to_stderr(console)
+            .format_for_stderr(detailed_format)
+            .start()
+    });
+
+    match file_logger {
+        Ok(handle) => Some(handle),
+        Err(e) => {
+            eprintln!("Failed to open log file, logging to console only: {e}");
+            Logger::try_with_env_or_str(logging::DEFAULT_LEVEL)
+                .and_then(|logger| logger.format(detailed_format).log_to_stderr().start())
+                .ok()
+        }
+    }
+}
+
+/// コマンドの開始・終了と所要時間を debug レベルで記録し、結果はそのまま返す
+///
+/// detail には処理対象を識別できる程度の情報だけを渡す（URLやパスワードは含めない）。
+pub async fn timed<F: Future>(command: &str, detail: &str, future: F) -> F::Output {
+    log::debug!("{command} started ({detail})");
+    let started = Instant::now();
+    let output = future.await;
+    log::debug!(
+        "{command} finished in {} ms ({detail})",
+        started.elapsed().as_millis()
+    );
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_timed_returns_wrapped_result_unchanged() {
+        let ok: Result<Vec<i32>, String> = timed("ok", "", async { Ok(vec![1, 2]) }).await;
+        assert_eq!(ok, Ok(vec![1, 2]));
+
+        let err: Result<(), String> =
+            timed("err", "query_len=3", async { Err("failed".to_string()) }).await;
+        assert_eq!(err, Err("failed".to_string()));
+    }
+}
@@ -1,10 +1,30 @@
-use crate::database::FileRecord;
-use crate::{process_zip_internal, AppError, AppState, FileSelectResult, ProcessResult};
+use crate::api_types::{ApiError, ProcessingStatus};
+use crate::booth_client::{normalize_booth_url, BoothClient};
+use crate::config::processing;
+use crate::database::{Database, FileRecord};
+use crate::db_pool::DatabasePool;
+use crate::file_commands::move_path_to_trash;
+use crate::logging;
+use crate::system_commands::{
+    stored_extraction_limits, stored_folder_template, stored_sanitize_options,
+};
+use crate::tag_validator::normalize_tag_name;
+use crate::{
+    compute_file_hash, extract_to_output, find_already_imported, parse_filename_encoding,
+    process_zip_internal, product_id_from_booth_url, resolve_shop_and_product, AppError, AppResult,
+    AppState, ExtractOptions, FileSelectResult, OverwritePolicy, ProcessResult,
+};
+use encoding_rs::Encoding;
 use log::error;
-use std::path::Path;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::sync::Semaphore;
 
 #[tauri::command]
-pub async fn select_zip_files(app: tauri::AppHandle) -> Result<FileSelectResult, String> {
+pub async fn select_zip_files(app: tauri::AppHandle) -> Result<FileSelectResult, ApiError> {
     use tauri_plugin_dialog::DialogExt;
 
     let (tx, rx) = std::sync::mpsc::channel();
@@ -21,7 +41,7 @@ pub async fn select_zip_files(app: tauri::AppHandle) -> Result<FileSelectResult,
 
     let files = rx
         .recv()
-        .map_err(|e| AppError::custom(format!("ファイル選択エラー: {e}")).to_string())?;
+        .map_err(|e| AppError::custom(format!("ファイル選択エラー: {e}")))?;
 
     match files {
         Some(paths) => {
@@ -42,19 +62,74 @@ pub async fn select_zip_files(app: tauri::AppHandle) -> Result<FileSelectResult,
 }
 
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 pub async fn process_zip_file(
     state: tauri::State<'_, AppState>,
     zip_path: String,
     booth_url: Option<String>,
     output_dir: Option<String>,
     tags: Option<Vec<String>>,
-) -> Result<ProcessResult, String> {
+    force: Option<bool>,
+    overwrite_policy: Option<String>,
+    auto_tag_from_booth: Option<bool>,
+    recursive: Option<bool>,
+    password: Option<String>,
+    filename_encoding: Option<String>,
+) -> Result<ProcessResult, ApiError> {
+    // BOOTH URL とパスワードはログに残さない
+    let detail = format!("zip_path={zip_path}");
+    logging::timed(
+        "process_zip_file",
+        &detail,
+        process_zip(
+            state,
+            zip_path,
+            booth_url,
+            output_dir,
+            tags,
+            force,
+            overwrite_policy,
+            auto_tag_from_booth,
+            recursive,
+            password,
+            filename_encoding,
+        ),
+    )
+    .await
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn process_zip(
+    state: tauri::State<'_, AppState>,
+    zip_path: String,
+    booth_url: Option<String>,
+    output_dir: Option<String>,
+    tags: Option<Vec<String>>,
+    force: Option<bool>,
+    overwrite_policy: Option<String>,
+    auto_tag_from_booth: Option<bool>,
+    recursive: Option<bool>,
+    password: Option<String>,
+    filename_encoding: Option<String>,
+) -> Result<ProcessResult, ApiError> {
+    let options = ExtractOptions {
+        output_dir,
+        folder_template: stored_folder_template(),
+        sanitize: stored_sanitize_options(),
+        overwrite_policy: parse_overwrite_policy(overwrite_policy.as_deref())?,
+        recursive: recursive.unwrap_or(false),
+        limits: stored_extraction_limits(),
+        password: password.filter(|p| !p.is_empty()),
+        filename_encoding: parse_filename_encoding_option(filename_encoding.as_deref())?,
+    };
     let booth_client = state.booth_client.clone();
     let result = process_zip_internal(
         zip_path.clone(),
         booth_url.clone(),
-        output_dir,
         &booth_client,
+        &state.db,
+        force.unwrap_or(false),
+        &options,
     )
     .await;
 
@@ -62,90 +137,840 @@ pub async fn process_zip_file(
         Ok(mut res) => {
             // データベースに保存を試行
             if res.success {
-                let file_name = Path::new(&zip_path)
-                    .file_name()
-                    .and_then(|name| name.to_str())
-                    .unwrap_or("unknown")
-                    .to_string();
-
-                let file_size = std::fs::metadata(&zip_path)
-                    .map(|meta| meta.len() as i64)
-                    .unwrap_or(0);
-
-                let modified_time = std::fs::metadata(&zip_path)
-                    .map(|meta| {
-                        meta.modified()
-                            .unwrap_or_else(|_| std::time::SystemTime::now())
-                            .duration_since(std::time::UNIX_EPOCH)
-                            .unwrap_or_default()
-                            .as_secs() as i64
-                    })
-                    .unwrap_or_else(|_| {
-                        std::time::SystemTime::now()
-                            .duration_since(std::time::UNIX_EPOCH)
-                            .unwrap_or_default()
-                            .as_secs() as i64
-                    });
-
-                let db = state.db.lock().map_err(|e| {
-                    AppError::database_lock(format!("Database lock error: {e}")).to_string()
-                })?;
-                let file_record = FileRecord {
-                    id: None,
-                    file_path: res.output_path.clone().unwrap_or_else(|| zip_path.clone()),
-                    file_name,
-                    file_size,
-                    modified_time,
-                    created_at: chrono::Utc::now().to_rfc3339(),
-                    updated_at: chrono::Utc::now().to_rfc3339(),
-                    product_id: None,
-                    product_name: res.product_name.clone(),
-                    author_name: res.shop_name.clone(), // shop_name を author_name として使用
-                    price: None,
-                    description: None,
-                    thumbnail_url: None,
-                    product_url: booth_url.clone(),
-                };
+                let db =
+                    state.db.get().await.map_err(|e| {
+                        AppError::database_lock(format!("Database lock error: {e}"))
+                    })?;
+                save_process_result(
+                    &db,
+                    &zip_path,
+                    &mut res,
+                    booth_url,
+                    tags.as_deref(),
+                    auto_tag_from_booth.unwrap_or(true),
+                );
+            }
+            Ok(res)
+        }
+        Err(e) => Ok(failed_process_result(e)),
+    }
+}
 
-                match db.add_file(file_record) {
-                    Ok(file_id) => {
-                        // ファイル保存後、タグを追加
-                        if let Some(tag_names) = &tags {
-                            for tag_name in tag_names {
-                                if !tag_name.trim().is_empty() {
-                                    if let Ok(tag_id) = db.add_tag(tag_name.trim()) {
-                                        let _ = db.add_file_tag(file_id, tag_id);
-                                    }
-                                }
-                            }
-                        }
-
-                        res.message = format!(
-                            "{} (ID: {file_id}でデータベースに保存されました)",
-                            res.message
-                        );
-                    }
-                    Err(e) => {
-                        error!("Failed to save to database: {e}");
-                        // データベース保存失敗はエラーとしない（ファイル処理は成功しているため）
+/// バッチ処理の進捗（1件完了ごとに通知）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchProgress {
+    pub index: usize,
+    pub total: usize,
+    pub completed: usize,
+    pub zip_path: String,
+    pub success: bool,
+    pub message: String,
+}
+
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn process_zip_files_batch(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+    paths: Vec<String>,
+    booth_urls: Vec<Option<String>>,
+    output_dir: Option<String>,
+    tags: Option<Vec<String>>,
+    force: Option<bool>,
+    overwrite_policy: Option<String>,
+    auto_tag_from_booth: Option<bool>,
+    recursive: Option<bool>,
+    filename_encoding: Option<String>,
+) -> Result<Vec<ProcessResult>, ApiError> {
+    use tauri::Emitter;
+
+    let jobs = paths
+        .into_iter()
+        .enumerate()
+        .map(|(i, path)| (path, booth_urls.get(i).cloned().flatten()))
+        .collect();
+
+    let options = BatchOptions {
+        extract: ExtractOptions {
+            output_dir,
+            folder_template: stored_folder_template(),
+            sanitize: stored_sanitize_options(),
+            overwrite_policy: parse_overwrite_policy(overwrite_policy.as_deref())?,
+            recursive: recursive.unwrap_or(false),
+            limits: stored_extraction_limits(),
+            password: None,
+            filename_encoding: parse_filename_encoding_option(filename_encoding.as_deref())?,
+        },
+        tags,
+        force: force.unwrap_or(false),
+        auto_tag_from_booth: auto_tag_from_booth.unwrap_or(true),
+    };
+
+    let results = process_zip_batch_internal(
+        jobs,
+        options,
+        state.booth_client.clone(),
+        state.db.clone(),
+        default_batch_workers(),
+        move |progress| {
+            if let Err(e) = app.emit(processing::BATCH_PROGRESS_EVENT, progress) {
+                error!("Failed to emit batch progress: {e}");
+            }
+        },
+    )
+    .await;
+
+    Ok(results)
+}
+
+/// 複数ZIPを順番に取り込んだ結果の集計
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BatchSummary {
+    pub total: usize,
+    pub success_count: usize,
+    pub error_count: usize,
+    /// ファイルごとの結果（入力順）
+    pub items: Vec<BatchItemResult>,
+}
+
+/// 順次取り込みでの1ファイル分の結果
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchItemResult {
+    pub zip_path: String,
+    pub success: bool,
+    pub message: String,
+}
+
+impl BatchSummary {
+    // 現在の集計から進捗イベントの内容を作る
+    fn status(&self, current_step: String) -> ProcessingStatus {
+        let processed_files = self.items.len();
+        ProcessingStatus {
+            current_step,
+            progress: if self.total == 0 {
+                100.0
+            } else {
+                processed_files as f64 * 100.0 / self.total as f64
+            },
+            total_files: self.total,
+            processed_files,
+            success_count: self.success_count,
+            error_count: self.error_count,
+            errors: self
+                .items
+                .iter()
+                .filter(|item| !item.success)
+                .map(|item| format!("{}: {}", item.zip_path, item.message))
+                .collect(),
+        }
+    }
+}
+
+#[tauri::command]
+pub async fn process_zip_files(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+    paths: Vec<String>,
+    output_dir: Option<String>,
+    tags: Option<Vec<String>>,
+    force: Option<bool>,
+    auto_tag_from_booth: Option<bool>,
+) -> Result<BatchSummary, ApiError> {
+    use tauri::Emitter;
+
+    let options = BatchOptions {
+        extract: ExtractOptions {
+            output_dir,
+            folder_template: stored_folder_template(),
+            sanitize: stored_sanitize_options(),
+            limits: stored_extraction_limits(),
+            ..ExtractOptions::default()
+        },
+        tags,
+        force: force.unwrap_or(false),
+        auto_tag_from_booth: auto_tag_from_booth.unwrap_or(true),
+    };
+
+    let summary =
+        process_zip_files_sequential(paths, &options, &state.booth_client, &state.db, |status| {
+            if let Err(e) = app.emit(processing::PROCESSING_STATUS_EVENT, status) {
+                error!("Failed to emit processing status: {e}");
+            }
+        })
+        .await;
+
+    Ok(summary)
+}
+
+/// 複数のZIPを1件ずつ順番に処理し、失敗しても残りを続けて集計を返す
+///
+/// 各ファイルの処理前後に `ProcessingStatus` で進捗を通知する。
+pub async fn process_zip_files_sequential<F>(
+    paths: Vec<String>,
+    options: &BatchOptions,
+    booth_client: &BoothClient,
+    db: &DatabasePool,
+    mut on_status: F,
+) -> BatchSummary
+where
+    F: FnMut(&ProcessingStatus),
+{
+    let locks = BatchLocks::default();
+    let mut summary = BatchSummary {
+        total: paths.len(),
+        ..BatchSummary::default()
+    };
+
+    for zip_path in paths {
+        on_status(&summary.status(format!("処理中: {zip_path}")));
+
+        let result = process_batch_job(&zip_path, None, options, booth_client, db, &locks).await;
+        if result.success {
+            summary.success_count += 1;
+        } else {
+            summary.error_count += 1;
+        }
+        summary.items.push(BatchItemResult {
+            zip_path,
+            success: result.success,
+            message: result.message,
+        });
+    }
+
+    on_status(&summary.status("完了".to_string()));
+    summary
+}
+
+/// 同時展開数の既定値（CPU数、上限あり）
+pub fn default_batch_workers() -> usize {
+    std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+        .min(processing::MAX_BATCH_WORKERS)
+}
+
+/// バッチ内の全ZIPに共通する処理オプション
+#[derive(Debug, Clone)]
+pub struct BatchOptions {
+    pub extract: ExtractOptions,
+    pub tags: Option<Vec<String>>,
+    /// 取り込み済みのアーカイブも再展開する
+    pub force: bool,
+    /// BOOTHの商品タグもファイルに付ける
+    pub auto_tag_from_booth: bool,
+}
+
+impl Default for BatchOptions {
+    fn default() -> Self {
+        Self {
+            extract: ExtractOptions::default(),
+            tags: None,
+            force: false,
+            auto_tag_from_booth: true,
+        }
+    }
+}
+
+// 未指定の場合は従来どおり上書き
+fn parse_overwrite_policy(policy: Option<&str>) -> AppResult<OverwritePolicy> {
+    policy
+        .map(OverwritePolicy::parse)
+        .transpose()
+        .map(Option::unwrap_or_default)
+}
+
+// 未指定・空文字の場合はファイル名から推定する
+fn parse_filename_encoding_option(label: Option<&str>) -> AppResult<Option<&'static Encoding>> {
+    label
+        .filter(|label| !label.trim().is_empty())
+        .map(parse_filename_encoding)
+        .transpose()
+}
+
+/// バッチ内のジョブで共有するロック
+#[derive(Default)]
+struct BatchLocks {
+    /// BOOTHへの問い合わせを1件ずつにする
+    lookup: tokio::sync::Mutex<()>,
+    /// 同じ内容（ハッシュ）のアーカイブを1件ずつ取り込む
+    archives: std::sync::Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
+}
+
+impl BatchLocks {
+    // ハッシュごとのロックを取得（無ければ作る）
+    fn archive_slot(&self, file_hash: &str) -> Arc<tokio::sync::Mutex<()>> {
+        let mut archives = self
+            .archives
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        archives.entry(file_hash.to_string()).or_default().clone()
+    }
+}
+
+/// 複数のZIPを最大 workers 件ずつ並列に処理し、入力順に結果を返す
+///
+/// BOOTHへの問い合わせは1件ずつ直列化し、展開のみを並列に行う。
+/// 同じ内容のアーカイブは先の1件の登録を待ってから取り込み済みとして扱う。
+/// データベースへの書き込みは共有の接続プールを通す。
+pub async fn process_zip_batch_internal<F>(
+    jobs: Vec<(String, Option<String>)>,
+    options: BatchOptions,
+    booth_client: Arc<BoothClient>,
+    db: Arc<DatabasePool>,
+    workers: usize,
+    on_progress: F,
+) -> Vec<ProcessResult>
+where
+    F: Fn(BatchProgress) + Send + Sync + 'static,
+{
+    let total = jobs.len();
+    let semaphore = Arc::new(Semaphore::new(workers.max(1)));
+    let locks = Arc::new(BatchLocks::default());
+    let completed = Arc::new(AtomicUsize::new(0));
+    let on_progress = Arc::new(on_progress);
+    let options = Arc::new(options);
+
+    let handles: Vec<_> = jobs
+        .into_iter()
+        .enumerate()
+        .map(|(index, (zip_path, booth_url))| {
+            let semaphore = semaphore.clone();
+            let locks = locks.clone();
+            let completed = completed.clone();
+            let on_progress = on_progress.clone();
+            let options = options.clone();
+            let booth_client = booth_client.clone();
+            let db = db.clone();
+
+            tokio::spawn(async move {
+                let result = match semaphore.acquire_owned().await {
+                    Ok(_permit) => {
+                        process_batch_job(
+                            &zip_path,
+                            booth_url,
+                            &options,
+                            &booth_client,
+                            &db,
+                            &locks,
+                        )
+                        .await
                     }
+                    Err(e) => failed_process_result(anyhow::anyhow!("{e}")),
+                };
+
+                on_progress(BatchProgress {
+                    index,
+                    total,
+                    completed: completed.fetch_add(1, Ordering::SeqCst) + 1,
+                    zip_path,
+                    success: result.success,
+                    message: result.message.clone(),
+                });
+
+                result
+            })
+        })
+        .collect();
+
+    let mut results = Vec::with_capacity(total);
+    for handle in handles {
+        results.push(
+            handle
+                .await
+                .unwrap_or_else(|e| failed_process_result(anyhow::anyhow!("{e}"))),
+        );
+    }
+    results
+}
+
+async fn process_batch_job(
+    zip_path: &str,
+    booth_url: Option<String>,
+    options: &BatchOptions,
+    booth_client: &BoothClient,
+    db: &DatabasePool,
+    locks: &BatchLocks,
+) -> ProcessResult {
+    let path = PathBuf::from(zip_path);
+    if !path.exists() {
+        return failed_process_result(anyhow::anyhow!(
+            "ZIPファイルが見つかりません: {}",
+            path.display()
+        ));
+    }
+
+    // 同じ内容のアーカイブが取り込み済みなら展開しない
+    let hash_path = path.clone();
+    let file_hash = match tokio::task::spawn_blocking(move || compute_file_hash(&hash_path)).await {
+        Ok(Ok(hash)) => hash,
+        Ok(Err(e)) => return failed_process_result(e),
+        Err(e) => return failed_process_result(anyhow::anyhow!("{e}")),
+    };
+    // 同じ内容のアーカイブを同時に取り込むと二重に登録されるため、登録まで順番に行う
+    let archive_slot = locks.archive_slot(&file_hash);
+    let _archive_guard = archive_slot.lock().await;
+    if !options.force {
+        match find_already_imported(db, &file_hash).await {
+            Ok(Some(res)) => return res,
+            Ok(None) => {}
+            Err(e) => return failed_process_result(e),
+        }
+    }
+
+    let lookup = {
+        let _guard = locks.lookup.lock().await;
+        resolve_shop_and_product(
+            &path,
+            booth_url.as_deref(),
+            booth_client,
+            &options.extract.sanitize,
+        )
+        .await
+    };
+
+    let product_id = product_id_from_booth_url(booth_url.as_deref(), booth_client);
+    let extract_options = options.extract.clone();
+    let extracted = tokio::task::spawn_blocking(move || {
+        extract_to_output(
+            &path,
+            lookup.shop_name,
+            lookup.product_name,
+            product_id,
+            &extract_options,
+        )
+    })
+    .await;
+
+    match extracted {
+        Ok(Ok(mut res)) => {
+            res.file_hash = Some(file_hash);
+            res.booth_tags = lookup.booth_tags;
+            res.booth_category = lookup.booth_category;
+            res.is_adult = lookup.is_adult;
+            res.network_available = !lookup.booth_unreachable;
+            match db.get().await {
+                Ok(db) => save_process_result(
+                    &db,
+                    zip_path,
+                    &mut res,
+                    booth_url,
+                    options.tags.as_deref(),
+                    options.auto_tag_from_booth,
+                ),
+                Err(e) => error!("Database lock error: {e}"),
+            }
+            res
+        }
+        Ok(Err(e)) => failed_process_result(e),
+        Err(e) => failed_process_result(anyhow::anyhow!("{e}")),
+    }
+}
+
+// 登録済みのBOOTH URLから商品情報を取り直すコマンド（BOOTHに接続できないときに取り込んだファイル用）
+// ショップ名・商品名・価格・カテゴリなどを更新し、BOOTHの商品タグを追加する（展開先のフォルダ名は変えない）
+#[tauri::command]
+pub async fn refetch_booth_info_db(
+    state: tauri::State<'_, AppState>,
+    file_id: i64,
+    auto_tag_from_booth: Option<bool>,
+) -> Result<FileRecord, ApiError> {
+    Ok(refetch_booth_info(
+        &state.db,
+        &state.booth_client,
+        file_id,
+        auto_tag_from_booth.unwrap_or(true),
+    )
+    .await?)
+}
+
+async fn refetch_booth_info(
+    db: &DatabasePool,
+    booth_client: &BoothClient,
+    file_id: i64,
+    auto_tag_from_booth: bool,
+) -> AppResult<FileRecord> {
+    let find = |db: &Database| {
+        db.get_file_by_id(file_id)?
+            .ok_or_else(|| AppError::validation("file_id", format!("File {file_id} not found")))
+    };
+
+    // 取得中はデータベースを使わない
+    let file = {
+        let db = db
+            .get()
+            .await
+            .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
+        find(&db)?
+    };
+    let booth_url = file
+        .product_url
+        .filter(|url| !url.trim().is_empty())
+        .ok_or_else(|| {
+            AppError::validation("booth_url", format!("File {file_id} has no BOOTH URL"))
+        })?;
+    let info = booth_client
+        .get_product_info(&booth_url)
+        .await
+        .map_err(AppError::from_booth)?;
+
+    let db = db
+        .get()
+        .await
+        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
+    db.in_transaction(|db| {
+        let mut file = find(db)?;
+        file.author_name = Some(info.shop_name.clone());
+        file.product_name = Some(info.product_name.clone());
+        file.product_id = info
+            .product_id
+            .map(|id| id.to_string())
+            .or(file.product_id.take());
+        file.price = info
+            .price
+            .and_then(|price| i32::try_from(price).ok())
+            .or(file.price);
+        file.description = file.description.take().or(info.description.clone());
+        file.thumbnail_url = file.thumbnail_url.take().or(info.thumbnail_url.clone());
+        file.booth_category = info.category.clone();
+        file.is_adult = info.is_adult;
+        db.overwrite_file(file_id, &file)?;
+
+        if auto_tag_from_booth {
+            for tag_name in merge_import_tags(&[], &info.tags) {
+                let tag_id = db.add_tag(&tag_name)?;
+                db.add_file_tag(file_id, tag_id)?;
+            }
+        }
+        find(db)
+    })
+}
+
+// 登録済みの元アーカイブを新しい出力先へ展開し直すコマンド（出力先フォルダを変更したとき用）
+// delete_old 指定時は、登録を更新した後に以前の展開先フォルダを .trash へ移動する
+#[tauri::command]
+pub async fn reextract_to_db(
+    state: tauri::State<'_, AppState>,
+    file_id: i64,
+    new_output_dir: String,
+    delete_old: Option<bool>,
+    password: Option<String>,
+) -> Result<FileRecord, ApiError> {
+    let options = ExtractOptions {
+        output_dir: Some(new_output_dir),
+        folder_template: stored_folder_template(),
+        sanitize: stored_sanitize_options(),
+        limits: stored_extraction_limits(),
+        password,
+        ..ExtractOptions::default()
+    };
+    Ok(reextract_to(&state.db, file_id, options, delete_old.unwrap_or(false)).await?)
+}
+
+async fn reextract_to(
+    db: &DatabasePool,
+    file_id: i64,
+    options: ExtractOptions,
+    delete_old: bool,
+) -> AppResult<FileRecord> {
+    let find = |db: &Database| {
+        db.get_file_by_id(file_id)?
+            .filter(|file| file.deleted_at.is_none())
+            .ok_or_else(|| AppError::validation("file_id", format!("File {file_id} not found")))
+    };
+
+    // 展開中はデータベースを使わない
+    let file = {
+        let db = db.get().await?;
+        find(&db)?
+    };
+    let archive = file
+        .archive_path
+        .clone()
+        .map(PathBuf::from)
+        .filter(|archive| archive.is_file())
+        .ok_or_else(|| {
+            AppError::validation(
+                "archive_path",
+                format!(
+                    "Archive not found: {}",
+                    file.archive_path.as_deref().unwrap_or_default()
+                ),
+            )
+        })?;
+
+    let product_id = file.product_id.as_deref().and_then(|id| id.parse().ok());
+    let (shop_name, product_name) = (file.author_name.clone(), file.product_name.clone());
+    let res = tokio::task::spawn_blocking(move || {
+        extract_to_output(&archive, shop_name, product_name, product_id, &options)
+    })
+    .await
+    .map_err(|e| AppError::custom(format!("展開に失敗しました: {e}")))?
+    .map_err(|e| {
+        e.downcast::<AppError>()
+            .unwrap_or_else(|e| AppError::custom(format!("展開に失敗しました: {e}")))
+    })?;
+    let new_path = res
+        .output_path
+        .ok_or_else(|| AppError::custom("展開先が分かりませんでした"))?;
+
+    let updated = {
+        let db = db.get().await?;
+        db.update_file_path(file_id, &new_path)
+            .map_err(|e| AppError::file_update(format!("Failed to update file path: {e}")))?;
+        find(&db)?
+    };
+
+    let old_path = Path::new(&file.file_path);
+    if delete_old && old_path.is_dir() {
+        // 新旧のどちらかが他方を含む場合は、新しい展開先ごと移動してしまうため残す
+        // （解決できない場合も重なっているものとして扱う）
+        let overlaps = match (old_path.canonicalize(), Path::new(&new_path).canonicalize()) {
+            (Ok(old), Ok(new)) => old.starts_with(&new) || new.starts_with(&old),
+            _ => true,
+        };
+        if overlaps {
+            log::warn!(
+                "Keeping {} because it overlaps the new output {new_path}",
+                file.file_path
+            );
+        } else if let Err(e) = move_path_to_trash(old_path, file_id) {
+            log::warn!("Failed to move {} to trash: {e}", file.file_path);
+        }
+    }
+    Ok(updated)
+}
+
+/// 取り込みの取り消し結果
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct UndoImportResult {
+    /// 取り消した場合 true（取り消せる取り込みがなかった場合 false）
+    pub undone: bool,
+    pub message: String,
+    /// 登録を削除したファイルのID
+    pub file_id: Option<i64>,
+    /// 削除した展開先フォルダ
+    pub deleted_path: Option<String>,
+}
+
+// 最後の取り込みを取り消すコマンド（登録とタグの関連付けを削除する）
+// delete_files 指定時は展開先フォルダも削除する（元アーカイブは残す）
+// 既存のフォルダに展開した取り込みは他のファイルも含むため、フォルダの削除は拒否する
+#[tauri::command]
+pub async fn undo_last_import(
+    state: tauri::State<'_, AppState>,
+    delete_files: Option<bool>,
+) -> Result<UndoImportResult, ApiError> {
+    let db = state
+        .db
+        .get()
+        .await
+        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
+
+    Ok(undo_import(&db, delete_files.unwrap_or(false))?)
+}
+
+fn undo_import(db: &Database, delete_files: bool) -> AppResult<UndoImportResult> {
+    let Some(import) = db.get_last_import()? else {
+        return Ok(UndoImportResult {
+            message: "取り消せる取り込みはありません".to_string(),
+            ..UndoImportResult::default()
+        });
+    };
+
+    // 取り込みで作成したフォルダのみ削除する（移動されていれば現在の場所）
+    let delete_path = if delete_files && import.output_path.is_some() {
+        if !import.output_created {
+            return Err(AppError::validation(
+                "delete_files",
+                format!(
+                    "{} は取り込み前から存在していたフォルダのため削除できません",
+                    import.file_path
+                ),
+            ));
+        }
+        Some(import.file_path.clone()).filter(|path| Path::new(path).exists())
+    } else {
+        None
+    };
+
+    db.in_transaction(|db| {
+        db.delete_file(import.file_id)?;
+        db.recalculate_usage_counts()
+    })
+    .map_err(|e| AppError::file_deletion(format!("Failed to undo import: {e}")))?;
+
+    if let Some(path) = &delete_path {
+        let removed = if Path::new(path).is_dir() {
+            std::fs::remove_dir_all(path)
+        } else {
+            std::fs::remove_file(path)
+        };
+        removed.map_err(|e| {
+            AppError::file_deletion(format!(
+                "Removed import {} but failed to delete {path}: {e}",
+                import.file_id
+            ))
+        })?;
+    }
+
+    Ok(UndoImportResult {
+        undone: true,
+        message: format!("ID: {} の取り込みを取り消しました", import.file_id),
+        file_id: Some(import.file_id),
+        deleted_path: delete_path,
+    })
+}
+
+// 展開結果をデータベースに登録し、メッセージにIDを追記する
+// データベース保存失敗はエラーとしない（ファイル処理は成功しているため）
+pub(crate) fn save_process_result(
+    db: &Database,
+    zip_path: &str,
+    res: &mut ProcessResult,
+    booth_url: Option<String>,
+    tags: Option<&[String]>,
+    auto_tag_from_booth: bool,
+) {
+    // 取り込み済みとして展開を省略した場合は登録しない
+    if res.already_imported_id.is_some() {
+        return;
+    }
+
+    let file_name = Path::new(zip_path)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or("unknown")
+        .to_string();
+
+    let file_size = std::fs::metadata(zip_path)
+        .map(|meta| meta.len() as i64)
+        .unwrap_or(0);
+
+    let modified_time = std::fs::metadata(zip_path)
+        .map(|meta| {
+            meta.modified()
+                .unwrap_or_else(|_| std::time::SystemTime::now())
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs() as i64
+        })
+        .unwrap_or_else(|_| {
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs() as i64
+        });
+
+    let file_record = FileRecord {
+        id: None,
+        file_path: res
+            .output_path
+            .clone()
+            .unwrap_or_else(|| zip_path.to_string()),
+        file_name,
+        file_size,
+        modified_time,
+        created_at: chrono::Utc::now().to_rfc3339(),
+        updated_at: chrono::Utc::now().to_rfc3339(),
+        product_id: None,
+        product_name: res.product_name.clone(),
+        author_name: res.shop_name.clone(), // shop_name を author_name として使用
+        price: None,
+        description: None,
+        thumbnail_url: None,
+        product_url: booth_url.as_deref().map(normalize_booth_url),
+        deleted_at: None,
+        file_hash: res.file_hash.clone(),
+        encoding_info: res.encoding_info.clone(),
+        archive_path: Some(zip_path.to_string()),
+        booth_category: res.booth_category.clone(),
+        is_adult: res.is_adult,
+        opened_at: None,
+        is_favorite: false,
+        user_note: None,
+        metadata: None,
+    };
+
+    match db.add_file(file_record) {
+        Ok(file_id) => {
+            if let Err(e) =
+                db.record_import(file_id, res.output_path.as_deref(), res.output_created)
+            {
+                error!("Failed to record import history: {e}");
+            }
+
+            // ファイル保存後、タグを追加
+            let booth_tags = if auto_tag_from_booth {
+                res.booth_tags.as_slice()
+            } else {
+                &[]
+            };
+            for tag_name in merge_import_tags(tags.unwrap_or_default(), booth_tags) {
+                if let Ok(tag_id) = db.add_tag(&tag_name) {
+                    let _ = db.add_file_tag(file_id, tag_id);
                 }
             }
-            Ok(res)
+
+            res.message = format!(
+                "{} (ID: {file_id}でデータベースに保存されました)",
+                res.message
+            );
         }
-        Err(e) => Ok(ProcessResult {
-            success: false,
-            message: format!("処理エラー: {e}"),
-            shop_name: None,
-            product_name: None,
-            files_extracted: vec![],
-            output_path: None,
-        }),
+        Err(e) => {
+            error!("Failed to save to database: {e}");
+        }
+    }
+}
+
+// 手動指定のタグとBOOTHの商品タグを、正規化・重複除去して1つにまとめる
+// タグ名として使えないもの（空・長すぎる・制御文字を含む）は取り込まない
+fn merge_import_tags(user_tags: &[String], booth_tags: &[String]) -> Vec<String> {
+    let mut merged: Vec<String> = Vec::new();
+    for tag_name in user_tags.iter().chain(booth_tags) {
+        let Ok(tag_name) = normalize_tag_name(tag_name) else {
+            continue;
+        };
+        if !merged.contains(&tag_name) {
+            merged.push(tag_name);
+        }
+    }
+    merged
+}
+
+pub(crate) fn failed_process_result(e: anyhow::Error) -> ProcessResult {
+    error!("ZIP processing failed: {e}");
+    // パスワードの入力を促せるよう、アプリのエラーはコードも返す
+    let app_error = e.downcast_ref::<AppError>();
+    let message = match app_error {
+        Some(err @ (AppError::PasswordRequired(_) | AppError::InvalidPassword(_))) => {
+            err.user_message()
+        }
+        _ => format!("処理エラー: {e}"),
+    };
+    ProcessResult {
+        success: false,
+        message,
+        shop_name: None,
+        product_name: None,
+        files_extracted: vec![],
+        output_path: None,
+        file_hash: None,
+        already_imported_id: None,
+        files_written: 0,
+        files_skipped: 0,
+        files_renamed: 0,
+        booth_tags: vec![],
+        encoding_info: None,
+        booth_category: None,
+        is_adult: false,
+        output_created: false,
+        error_code: app_error.map(|err| err.code().to_string()),
+        network_available: true,
     }
 }
 
 #[tauri::command]
-pub async fn select_output_folder(app: tauri::AppHandle) -> Result<Option<String>, String> {
+pub async fn select_output_folder(app: tauri::AppHandle) -> Result<Option<String>, ApiError> {
     use tauri_plugin_dialog::DialogExt;
 
     let (tx, rx) = std::sync::mpsc::channel();
@@ -161,7 +986,683 @@ pub async fn select_output_folder(app: tauri::AppHandle) -> Result<Option<String
 
     let folder = rx
         .recv()
-        .map_err(|e| AppError::custom(format!("フォルダ選択エラー: {e}")).to_string())?;
+        .map_err(|e| AppError::custom(format!("フォルダ選択エラー: {e}")))?;
 
     Ok(folder.map(|p| p.to_string()))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::booth_client::RetryPolicy;
+    use crate::test_support::write_zip;
+    use std::sync::Mutex;
+
+    #[tokio::test]
+    async fn test_batch_processes_zips_in_parallel() {
+        let input = tempfile::tempdir().unwrap();
+        let output = tempfile::tempdir().unwrap();
+
+        let names = ["alpha", "beta", "gamma"];
+        let jobs: Vec<(String, Option<String>)> = names
+            .iter()
+            .map(|name| {
+                let path = input.path().join(format!("{name}.zip"));
+                write_zip(
+                    &path,
+                    &[
+                        ("readme.txt", name.as_bytes()),
+                        ("Assets/model.fbx", b"fbx"),
+                    ],
+                );
+                (path.to_string_lossy().to_string(), None)
+            })
+            .collect();
+
+        let db = Arc::new(DatabasePool::new(":memory:").unwrap());
+        let progress = Arc::new(Mutex::new(Vec::new()));
+        let progress_sink = progress.clone();
+
+        let options = BatchOptions {
+            extract: ExtractOptions {
+                output_dir: Some(output.path().to_string_lossy().to_string()),
+                ..ExtractOptions::default()
+            },
+            tags: Some(vec!["VRChat".to_string()]),
+            ..BatchOptions::default()
+        };
+        let results = process_zip_batch_internal(
+            jobs,
+            options,
+            Arc::new(BoothClient::new()),
+            db.clone(),
+            3,
+            move |p| progress_sink.lock().unwrap().push(p),
+        )
+        .await;
+
+        // 結果は入力順に並ぶ
+        assert_eq!(results.len(), 3);
+        for (result, name) in results.iter().zip(names) {
+            assert!(result.success, "{}", result.message);
+            assert_eq!(result.product_name.as_deref(), Some(name));
+            assert_eq!(result.files_extracted.len(), 2);
+        }
+        assert!(output
+            .path()
+            .join("Unknown_Shop/beta/Assets/model.fbx")
+            .exists());
+
+        let db = db.get().await.unwrap();
+        let files = db.get_files_with_tags().unwrap();
+        assert_eq!(files.len(), 3);
+        assert!(files.iter().all(|f| f.tags.len() == 1));
+
+        let progress = progress.lock().unwrap();
+        assert_eq!(progress.len(), 3);
+        let mut completed: Vec<_> = progress.iter().map(|p| p.completed).collect();
+        completed.sort_unstable();
+        assert_eq!(completed, vec![1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn test_batch_reports_missing_zip_in_place() {
+        let output = tempfile::tempdir().unwrap();
+        let db = Arc::new(DatabasePool::new(":memory:").unwrap());
+
+        let results = process_zip_batch_internal(
+            vec![("/nonexistent/missing.zip".to_string(), None)],
+            BatchOptions {
+                extract: ExtractOptions {
+                    output_dir: Some(output.path().to_string_lossy().to_string()),
+                    ..ExtractOptions::default()
+                },
+                ..BatchOptions::default()
+            },
+            Arc::new(BoothClient::new()),
+            db.clone(),
+            2,
+            |_| {},
+        )
+        .await;
+
+        assert_eq!(results.len(), 1);
+        assert!(!results[0].success);
+        assert!(db.get().await.unwrap().get_all_files().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_sequential_import_continues_past_failures() {
+        let input = tempfile::tempdir().unwrap();
+        let output = tempfile::tempdir().unwrap();
+        let good = input.path().join("good.zip");
+        write_zip(&good, &[("readme.txt", b"hello")]);
+        let missing = input.path().join("missing.zip");
+
+        let db = DatabasePool::new(":memory:").unwrap();
+        let mut statuses = Vec::new();
+        let summary = process_zip_files_sequential(
+            vec![
+                missing.to_string_lossy().to_string(),
+                good.to_string_lossy().to_string(),
+            ],
+            &BatchOptions {
+                extract: ExtractOptions {
+                    output_dir: Some(output.path().to_string_lossy().to_string()),
+                    ..ExtractOptions::default()
+                },
+                ..BatchOptions::default()
+            },
+            &BoothClient::new(),
+            &db,
+            |status| statuses.push((status.processed_files, status.error_count)),
+        )
+        .await;
+
+        assert_eq!(summary.total, 2);
+        assert_eq!(summary.success_count, 1);
+        assert_eq!(summary.error_count, 1);
+        assert!(!summary.items[0].success);
+        assert!(summary.items[0].message.contains("missing.zip"));
+        assert!(summary.items[1].success, "{}", summary.items[1].message);
+        assert_eq!(db.get().await.unwrap().get_all_files().unwrap().len(), 1);
+
+        // 各ファイルの処理前と最後に通知する
+        assert_eq!(statuses, vec![(0, 0), (1, 1), (2, 1)]);
+    }
+
+    #[tokio::test]
+    async fn test_batch_registers_identical_archives_once() {
+        let input = tempfile::tempdir().unwrap();
+        let output = tempfile::tempdir().unwrap();
+        let jobs: Vec<(String, Option<String>)> = ["avatar.zip", "avatar_copy.zip"]
+            .iter()
+            .map(|name| {
+                let path = input.path().join(name);
+                write_zip(&path, &[("model.fbx", b"fbx")]);
+                (path.to_string_lossy().to_string(), None)
+            })
+            .collect();
+        let db = Arc::new(DatabasePool::new(":memory:").unwrap());
+
+        let results = process_zip_batch_internal(
+            jobs,
+            BatchOptions {
+                extract: ExtractOptions {
+                    output_dir: Some(output.path().to_string_lossy().to_string()),
+                    ..ExtractOptions::default()
+                },
+                ..BatchOptions::default()
+            },
+            Arc::new(BoothClient::new()),
+            db.clone(),
+            2,
+            |_| {},
+        )
+        .await;
+
+        // 並列に処理しても、後の1件は取り込み済みとして報告される
+        let files = db.get().await.unwrap().get_all_files().unwrap();
+        assert_eq!(files.len(), 1);
+        assert!(results.iter().all(|res| res.success));
+        let duplicates: Vec<_> = results
+            .iter()
+            .filter_map(|res| res.already_imported_id)
+            .collect();
+        assert_eq!(duplicates, vec![files[0].id.unwrap()]);
+    }
+
+    async fn import_once(
+        zip_path: &Path,
+        output: &Path,
+        db: &DatabasePool,
+        force: bool,
+    ) -> ProcessResult {
+        let mut res = process_zip_internal(
+            zip_path.to_string_lossy().to_string(),
+            None,
+            &BoothClient::new(),
+            db,
+            force,
+            &ExtractOptions {
+                output_dir: Some(output.to_string_lossy().to_string()),
+                ..ExtractOptions::default()
+            },
+        )
+        .await
+        .unwrap();
+        save_process_result(
+            &db.get().await.unwrap(),
+            &zip_path.to_string_lossy(),
+            &mut res,
+            None,
+            None,
+            true,
+        );
+        res
+    }
+
+    #[tokio::test]
+    async fn test_undo_last_import_removes_row_and_folder() {
+        let input = tempfile::tempdir().unwrap();
+        let output = tempfile::tempdir().unwrap();
+        let db = DatabasePool::new(":memory:").unwrap();
+
+        let earlier = input.path().join("earlier.zip");
+        write_zip(&earlier, &[("model.fbx", b"earlier")]);
+        import_once(&earlier, output.path(), &db, false).await;
+        let zip_path = input.path().join("avatar.zip");
+        write_zip(&zip_path, &[("model.fbx", b"fbx")]);
+        let res = import_once(&zip_path, output.path(), &db, false).await;
+        let output_path = res.output_path.unwrap();
+
+        let db = db.get().await.unwrap();
+        let file_id = db.get_last_import().unwrap().unwrap().file_id;
+        let tag_id = db.add_tag("VRChat").unwrap();
+        db.add_file_tag(file_id, tag_id).unwrap();
+
+        let undone = undo_import(&db, true).unwrap();
+        assert!(undone.undone);
+        assert_eq!(undone.file_id, Some(file_id));
+        assert_eq!(undone.deleted_path.as_deref(), Some(output_path.as_str()));
+        assert!(db.get_file_by_id(file_id).unwrap().is_none());
+        assert!(!Path::new(&output_path).exists());
+        assert_eq!(db.get_all_tags().unwrap()[0].usage_count, 0);
+        // 元アーカイブは残す
+        assert!(zip_path.exists());
+
+        // 次はその前の取り込みが対象になる（フォルダは残す）
+        let previous = undo_import(&db, false).unwrap();
+        assert!(previous.undone);
+        assert!(previous.deleted_path.is_none());
+        assert!(db.get_all_files().unwrap().is_empty());
+        assert!(output.path().join("Unknown_Shop/earlier").exists());
+
+        let nothing = undo_import(&db, true).unwrap();
+        assert!(!nothing.undone);
+        assert!(nothing.file_id.is_none());
+        assert!(!nothing.message.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_undo_import_follows_moves_and_keeps_merged_folders() {
+        let input = tempfile::tempdir().unwrap();
+        let output = tempfile::tempdir().unwrap();
+        let db = DatabasePool::new(":memory:").unwrap();
+
+        // 取り込み後に移動したフォルダは移動先を削除する
+        let zip_path = input.path().join("avatar.zip");
+        write_zip(&zip_path, &[("model.fbx", b"fbx")]);
+        let res = import_once(&zip_path, output.path(), &db, false).await;
+        let moved = output.path().join("Moved");
+        std::fs::rename(res.output_path.as_deref().unwrap(), &moved).unwrap();
+        {
+            let db = db.get().await.unwrap();
+            let file_id = db.get_last_import().unwrap().unwrap().file_id;
+            db.update_file_path(file_id, &moved.to_string_lossy())
+                .unwrap();
+            let undone = undo_import(&db, true).unwrap();
+            assert_eq!(
+                undone.deleted_path.as_deref(),
+                Some(&*moved.to_string_lossy())
+            );
+            assert!(!moved.exists());
+        }
+
+        // 既存のフォルダに展開した取り込みはフォルダを削除しない
+        let existing = output.path().join("Unknown_Shop/merged");
+        std::fs::create_dir_all(&existing).unwrap();
+        std::fs::write(existing.join("mine.txt"), b"keep").unwrap();
+        let zip_path = input.path().join("merged.zip");
+        write_zip(&zip_path, &[("model.fbx", b"merged")]);
+        let res = import_once(&zip_path, output.path(), &db, false).await;
+        assert!(!res.output_created);
+
+        let db = db.get().await.unwrap();
+        let err = undo_import(&db, true).unwrap_err();
+        assert!(matches!(err, AppError::Validation { .. }), "{err:?}");
+        assert!(existing.join("mine.txt").exists());
+        assert!(db.get_last_import().unwrap().is_some());
+
+        // 登録だけなら取り消せる
+        assert!(undo_import(&db, false).unwrap().undone);
+        assert!(existing.join("mine.txt").exists());
+    }
+
+    #[tokio::test]
+    async fn test_reimport_of_same_archive_is_skipped() {
+        let input = tempfile::tempdir().unwrap();
+        let output = tempfile::tempdir().unwrap();
+        let zip_path = input.path().join("avatar.zip");
+        write_zip(&zip_path, &[("model.fbx", b"fbx")]);
+        let db = DatabasePool::new(":memory:").unwrap();
+
+        let first = import_once(&zip_path, output.path(), &db, false).await;
+        assert!(first.already_imported_id.is_none());
+        assert_eq!(first.files_extracted.len(), 1);
+
+        // 別名でコピーしても内容が同じなら取り込み済みと判定される
+        let copy_path = input.path().join("avatar_copy.zip");
+        std::fs::copy(&zip_path, &copy_path).unwrap();
+        let second = import_once(&copy_path, output.path(), &db, false).await;
+
+        assert!(second.success);
+        assert!(second.message.contains("既にインポート済み"));
+        assert!(second.files_extracted.is_empty());
+        let files = db.get().await.unwrap().get_all_files().unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(second.already_imported_id, files[0].id);
+        assert_eq!(files[0].file_hash, second.file_hash);
+    }
+
+    #[tokio::test]
+    async fn test_import_names_follow_configured_sanitize_options() {
+        let input = tempfile::tempdir().unwrap();
+        let output = tempfile::tempdir().unwrap();
+        let zip_path = input.path().join("Avatar: v2 #1.zip");
+        write_zip(&zip_path, &[("model.fbx", b"fbx")]);
+        let db = DatabasePool::new(":memory:").unwrap();
+
+        let res = process_zip_internal(
+            zip_path.to_string_lossy().to_string(),
+            None,
+            &BoothClient::new(),
+            &db,
+            false,
+            &ExtractOptions {
+                output_dir: Some(output.path().to_string_lossy().to_string()),
+                sanitize: crate::SanitizeOptions {
+                    invalid_chars: vec!['#'],
+                    replacement: '-',
+                    strip_leading_dots: false,
+                },
+                ..ExtractOptions::default()
+            },
+        )
+        .await
+        .unwrap();
+
+        // 既定の規則（":" を "_" に置換）を先に通さず、設定の規則だけで整える
+        assert_eq!(res.product_name.as_deref(), Some("Avatar: v2 -1"));
+        assert_eq!(
+            res.output_path.map(PathBuf::from),
+            Some(output.path().join("Unknown_Shop").join("Avatar: v2 -1"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_import_records_filename_encoding() {
+        let input = tempfile::tempdir().unwrap();
+        let output = tempfile::tempdir().unwrap();
+        let zip_path = input.path().join("avatar.zip");
+        write_zip(&zip_path, &[("テクスチャ/本体.png", b"png")]);
+        let db = DatabasePool::new(":memory:").unwrap();
+
+        let res = import_once(&zip_path, output.path(), &db, false).await;
+
+        assert_eq!(res.encoding_info.as_deref(), Some("UTF-8"));
+        let files = db.get().await.unwrap().get_all_files().unwrap();
+        assert_eq!(files[0].encoding_info.as_deref(), Some("UTF-8"));
+    }
+
+    #[tokio::test]
+    async fn test_import_keeps_archive_path_separate_from_output() {
+        let input = tempfile::tempdir().unwrap();
+        let output = tempfile::tempdir().unwrap();
+        let zip_path = input.path().join("avatar.zip");
+        write_zip(&zip_path, &[("model.fbx", b"fbx")]);
+        let db = DatabasePool::new(":memory:").unwrap();
+
+        import_once(&zip_path, output.path(), &db, false).await;
+
+        let files = db.get().await.unwrap().get_all_files().unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(
+            files[0].file_path,
+            output.path().join("Unknown_Shop/avatar").to_string_lossy()
+        );
+        assert_eq!(
+            files[0].archive_path.as_deref(),
+            Some(zip_path.to_string_lossy().as_ref())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_force_reextracts_imported_archive() {
+        let input = tempfile::tempdir().unwrap();
+        let output = tempfile::tempdir().unwrap();
+        let zip_path = input.path().join("avatar.zip");
+        write_zip(&zip_path, &[("model.fbx", b"fbx")]);
+        let db = DatabasePool::new(":memory:").unwrap();
+
+        import_once(&zip_path, output.path(), &db, false).await;
+        std::fs::remove_file(output.path().join("Unknown_Shop/avatar/model.fbx")).unwrap();
+
+        let forced = import_once(&zip_path, output.path(), &db, true).await;
+
+        assert!(forced.success);
+        assert!(forced.already_imported_id.is_none());
+        assert_eq!(forced.files_extracted, vec!["model.fbx".to_string()]);
+        assert!(output.path().join("Unknown_Shop/avatar/model.fbx").exists());
+        assert_eq!(db.get().await.unwrap().get_all_files().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_different_archive_is_imported() {
+        let input = tempfile::tempdir().unwrap();
+        let output = tempfile::tempdir().unwrap();
+        let first_zip = input.path().join("first.zip");
+        let second_zip = input.path().join("second.zip");
+        write_zip(&first_zip, &[("a.txt", b"first")]);
+        write_zip(&second_zip, &[("a.txt", b"second")]);
+        let db = DatabasePool::new(":memory:").unwrap();
+
+        import_once(&first_zip, output.path(), &db, false).await;
+        let res = import_once(&second_zip, output.path(), &db, false).await;
+
+        assert!(res.already_imported_id.is_none());
+        assert_eq!(res.files_extracted.len(), 1);
+        let files = db.get().await.unwrap().get_all_files().unwrap();
+        assert_eq!(files.len(), 2);
+        assert_ne!(files[0].file_hash, files[1].file_hash);
+    }
+
+    const MILK_BOOTH_URL: &str = "http://dolosart.booth.pm/items/3456789";
+
+    // BOOTHの商品JSONを返すだけのローカルサーバーを立て、そこをプロキシとして使うクライアントを返す
+    // （BOOTH以外のURLは問い合わせないため、商品URLは booth.pm のまま送らせる）
+    async fn serve_booth_product_json(body: &'static str) -> BoothClient {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy_url = serve_booth_product_json_on(listener, body);
+        BoothClient::with_proxy(&proxy_url).unwrap()
+    }
+
+    fn serve_booth_product_json_on(
+        listener: tokio::net::TcpListener,
+        body: &'static str,
+    ) -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut request = [0u8; 4096];
+                let _ = socket.read(&mut request).await;
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\
+                     Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                );
+                let _ = socket.write_all(response.as_bytes()).await;
+            }
+        });
+
+        format!("http://{addr}")
+    }
+
+    const MILK_PRODUCT_JSON: &str = r#"{"id": 3456789, "name": "Milk", "price": "¥ 5,500",
+        "tags": [{"name": "VRChat"}, {"name": "Avatar"}],
+        "shop": {"name": "Dolos Art"},
+        "category": {"name": "3Dキャラクター", "parent": {"name": "3Dモデル"}}}"#;
+
+    async fn import_with_booth_tags(auto_tag_from_booth: bool) -> Vec<String> {
+        let client = serve_booth_product_json(MILK_PRODUCT_JSON).await;
+        let booth_url = MILK_BOOTH_URL.to_string();
+        let input = tempfile::tempdir().unwrap();
+        let output = tempfile::tempdir().unwrap();
+        let zip_path = input.path().join("milk.zip");
+        write_zip(&zip_path, &[("model.fbx", b"fbx")]);
+        let db = DatabasePool::new(":memory:").unwrap();
+
+        let mut res = process_zip_internal(
+            zip_path.to_string_lossy().to_string(),
+            Some(booth_url.clone()),
+            &client,
+            &db,
+            false,
+            &ExtractOptions {
+                output_dir: Some(output.path().to_string_lossy().to_string()),
+                ..ExtractOptions::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(res.shop_name.as_deref(), Some("Dolos Art"));
+
+        let db = db.get().await.unwrap();
+        save_process_result(
+            &db,
+            &zip_path.to_string_lossy(),
+            &mut res,
+            Some(booth_url),
+            Some(&["avatar".to_string(), " Avatar ".to_string()]),
+            auto_tag_from_booth,
+        );
+
+        let files = db.get_files_with_tags().unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(
+            files[0].file.booth_category.as_deref(),
+            Some("3Dキャラクター")
+        );
+        let mut tags: Vec<_> = files[0].tags.iter().map(|t| t.name.clone()).collect();
+        tags.sort();
+        tags
+    }
+
+    #[tokio::test]
+    async fn test_booth_tags_are_added_on_import() {
+        // 手動タグと重複する "Avatar" は1つにまとまる
+        assert_eq!(
+            import_with_booth_tags(true).await,
+            vec!["Avatar", "VRChat", "avatar"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_booth_tags_can_be_disabled() {
+        assert_eq!(
+            import_with_booth_tags(false).await,
+            vec!["Avatar", "avatar"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reextract_moves_item_to_new_output_dir() {
+        let input = tempfile::tempdir().unwrap();
+        let old_output = tempfile::tempdir().unwrap();
+        let new_output = tempfile::tempdir().unwrap();
+        let zip_path = input.path().join("avatar.zip");
+        write_zip(&zip_path, &[("Assets/model.fbx", b"fbx")]);
+        let db = DatabasePool::new(":memory:").unwrap();
+        import_once(&zip_path, old_output.path(), &db, false).await;
+        let file_id = db.get().await.unwrap().get_all_files().unwrap()[0]
+            .id
+            .unwrap();
+        let old_folder = old_output.path().join("Unknown_Shop/avatar");
+        assert!(old_folder.join("Assets/model.fbx").exists());
+
+        let options = ExtractOptions {
+            output_dir: Some(new_output.path().to_string_lossy().to_string()),
+            ..ExtractOptions::default()
+        };
+        let updated = reextract_to(&db, file_id, options.clone(), true)
+            .await
+            .unwrap();
+
+        let new_folder = new_output.path().join("Unknown_Shop/avatar");
+        assert_eq!(updated.file_path, new_folder.to_string_lossy());
+        assert_eq!(
+            std::fs::read(new_folder.join("Assets/model.fbx")).unwrap(),
+            b"fbx"
+        );
+        assert!(!old_folder.exists());
+        let trash = old_output
+            .path()
+            .join("Unknown_Shop")
+            .join(crate::config::files::TRASH_DIR_NAME);
+        assert_eq!(std::fs::read_dir(&trash).unwrap().count(), 1);
+
+        // 新しい展開先が以前の展開先の中にある場合は以前の展開先を残す
+        let nested_options = ExtractOptions {
+            output_dir: Some(new_folder.join("nested").to_string_lossy().to_string()),
+            ..ExtractOptions::default()
+        };
+        let nested = reextract_to(&db, file_id, nested_options, true)
+            .await
+            .unwrap();
+        assert!(Path::new(&nested.file_path)
+            .join("Assets/model.fbx")
+            .exists());
+        assert!(new_folder.join("Assets/model.fbx").exists());
+
+        // 元のアーカイブが無くなっていればエラーにし、登録は変えない
+        std::fs::remove_file(&zip_path).unwrap();
+        let err = reextract_to(&db, file_id, options, true).await.unwrap_err();
+        assert!(matches!(err, AppError::Validation { .. }), "{err:?}");
+        assert_eq!(
+            db.get()
+                .await
+                .unwrap()
+                .get_file_by_id(file_id)
+                .unwrap()
+                .unwrap()
+                .file_path,
+            nested.file_path
+        );
+    }
+
+    #[tokio::test]
+    async fn test_offline_import_can_be_refetched_later() {
+        // BOOTHに接続できない状態（閉じたポート）で取り込む
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let booth_url = MILK_BOOTH_URL.to_string();
+        let client = BoothClient::with_proxy(&format!("http://{addr}"))
+            .unwrap()
+            .with_retry_policy(RetryPolicy {
+                max_retries: 1,
+                base_backoff_ms: 0,
+                ..RetryPolicy::default()
+            });
+        let input = tempfile::tempdir().unwrap();
+        let output = tempfile::tempdir().unwrap();
+        let zip_path = input.path().join("milk.zip");
+        write_zip(&zip_path, &[("model.fbx", b"fbx")]);
+        let db = DatabasePool::new(":memory:").unwrap();
+
+        let mut res = process_zip_internal(
+            zip_path.to_string_lossy().to_string(),
+            Some(booth_url.clone()),
+            &client,
+            &db,
+            false,
+            &ExtractOptions {
+                output_dir: Some(output.path().to_string_lossy().to_string()),
+                ..ExtractOptions::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert!(!res.network_available);
+        // 名前は商品URLから推測する
+        assert_eq!(res.shop_name.as_deref(), Some("dolosart"));
+        assert_eq!(res.product_name.as_deref(), Some("product_3456789"));
+        save_process_result(
+            &db.get().await.unwrap(),
+            &zip_path.to_string_lossy(),
+            &mut res,
+            Some(booth_url),
+            None,
+            true,
+        );
+        let imported = db.get().await.unwrap().get_all_files().unwrap().remove(0);
+        let file_id = imported.id.unwrap();
+
+        // 接続できるようになってから取り直す
+        let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
+        serve_booth_product_json_on(listener, MILK_PRODUCT_JSON);
+        let file = refetch_booth_info(&db, &client, file_id, true)
+            .await
+            .unwrap();
+
+        assert_eq!(file.author_name.as_deref(), Some("Dolos Art"));
+        assert_eq!(file.product_name.as_deref(), Some("Milk"));
+        assert_eq!(file.product_id.as_deref(), Some("3456789"));
+        assert_eq!(file.price, Some(5500));
+        assert_eq!(file.booth_category.as_deref(), Some("3Dキャラクター"));
+        assert_eq!(file.file_path, imported.file_path);
+        let tags: Vec<String> = db
+            .get()
+            .await
+            .unwrap()
+            .get_tags_for_file(file_id)
+            .unwrap()
+            .into_iter()
+            .map(|tag| tag.name)
+            .collect();
+        assert!(tags.contains(&"VRChat".to_string()) && tags.contains(&"Avatar".to_string()));
+    }
+}
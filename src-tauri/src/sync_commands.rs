@@ -1,23 +1,118 @@
-use crate::database::{BatchStatistics, FileWithTags};
-use crate::{AppError, AppState, MissingFile, SyncResult};
+use crate::api_types::{
+    ApiError, LibraryStats, PaginationResponse, SearchByTagsPaginatedRequest,
+    SearchFilesPaginatedRequest, ShopFileSummary,
+};
+use crate::config::{files, tags};
+use crate::database::{
+    build_fts_query, exclude_adult_files, AdvancedSearchFilter, BatchStatistics, Database,
+    FileRecord, FileWithTags, RepairReport, ShopSummary,
+};
+use crate::db_backup;
+use crate::db_pool::DatabasePool;
+use crate::file_commands::move_path_to_trash;
+use crate::logging;
+use crate::system_commands::stored_output_folder;
+use crate::tag_query;
+use crate::{
+    compute_file_hash, default_output_base, AppError, AppResult, AppState, MissingFile, SyncResult,
+};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
 
 // Phase 3: 検索・重複検出コマンド
 #[tauri::command]
 pub async fn search_files_db(
     state: tauri::State<'_, AppState>,
     query: String,
-) -> Result<Vec<FileWithTags>, String> {
+    hide_adult: Option<bool>,
+) -> Result<Vec<FileWithTags>, ApiError> {
+    let detail = format!("query_len={}", query.chars().count());
+    logging::timed(
+        "search_files_db",
+        &detail,
+        search_files(state, query, hide_adult),
+    )
+    .await
+}
+
+async fn search_files(
+    state: tauri::State<'_, AppState>,
+    query: String,
+    hide_adult: Option<bool>,
+) -> Result<Vec<FileWithTags>, ApiError> {
     let db = state
         .db
-        .lock()
-        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")).to_string())?;
+        .get()
+        .await
+        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
 
     // 新しいスキーマでは簡単な検索を実装（ファイル名でフィルタリング）
-    let all_files_with_tags = db.get_files_with_tags().map_err(|e| {
-        AppError::file_retrieval(format!("Failed to get files with tags: {e}")).to_string()
-    })?;
+    let all_files_with_tags = db
+        .get_files_with_tags()
+        .map_err(|e| AppError::file_retrieval(format!("Failed to get files with tags: {e}")))?;
+
+    Ok(exclude_adult_files(
+        filter_files_by_query(all_files_with_tags, &query),
+        hide_adult.unwrap_or(false),
+    ))
+}
+
+#[tauri::command]
+pub async fn search_files_paginated_db(
+    state: tauri::State<'_, AppState>,
+    request: SearchFilesPaginatedRequest,
+) -> Result<PaginationResponse<FileWithTags>, ApiError> {
+    let pagination = request.pagination.clamped();
+    let db = state
+        .db
+        .get()
+        .await
+        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
 
-    let filtered_files = all_files_with_tags
+    let (items, total_count) = db
+        .search_files_page(&request.query, &pagination, request.hide_adult)
+        .map_err(|e| match e {
+            AppError::Database(e) => {
+                AppError::file_retrieval(format!("Failed to search files: {e}"))
+            }
+            e => e,
+        })?;
+
+    Ok(PaginationResponse::new(items, total_count, &pagination))
+}
+
+// 全文検索（関連度順）。trigram で扱えない短い語を含む場合は部分一致検索にフォールバック
+#[tauri::command]
+pub async fn search_files_fts_db(
+    state: tauri::State<'_, AppState>,
+    query: String,
+    hide_adult: Option<bool>,
+) -> Result<Vec<FileWithTags>, ApiError> {
+    let db = state
+        .db
+        .get()
+        .await
+        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
+
+    let files = match build_fts_query(&query) {
+        Some(fts_query) => db
+            .search_files_fts(&fts_query)
+            .map_err(|e| AppError::file_retrieval(format!("Failed to search files: {e}")))?,
+        None => {
+            let all_files_with_tags = db.get_files_with_tags().map_err(|e| {
+                AppError::file_retrieval(format!("Failed to get files with tags: {e}"))
+            })?;
+            filter_files_by_query(all_files_with_tags, &query)
+        }
+    };
+    Ok(exclude_adult_files(files, hide_adult.unwrap_or(false)))
+}
+
+// ファイル名・パス・商品名・ショップ名の部分一致で絞り込む
+fn filter_files_by_query(files_with_tags: Vec<FileWithTags>, query: &str) -> Vec<FileWithTags> {
+    let query = query.to_lowercase();
+
+    files_with_tags
         .into_iter()
         .filter(|file_with_tags| {
             let file_name = &file_with_tags.file.file_name;
@@ -25,32 +120,130 @@ pub async fn search_files_db(
             let product_name = file_with_tags.file.product_name.as_deref().unwrap_or("");
             let author_name = file_with_tags.file.author_name.as_deref().unwrap_or("");
 
-            file_name.to_lowercase().contains(&query.to_lowercase())
-                || file_path.to_lowercase().contains(&query.to_lowercase())
-                || product_name.to_lowercase().contains(&query.to_lowercase())
-                || author_name.to_lowercase().contains(&query.to_lowercase())
+            file_name.to_lowercase().contains(&query)
+                || file_path.to_lowercase().contains(&query)
+                || product_name.to_lowercase().contains(&query)
+                || author_name.to_lowercase().contains(&query)
         })
-        .collect();
-
-    Ok(filtered_files)
+        .collect()
 }
 
 #[tauri::command]
 pub async fn search_files_by_tags_db(
     state: tauri::State<'_, AppState>,
     tag_names: Vec<String>,
-) -> Result<Vec<FileWithTags>, String> {
+    match_all: Option<bool>,
+    hide_adult: Option<bool>,
+) -> Result<Vec<FileWithTags>, ApiError> {
     let db = state
         .db
-        .lock()
-        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")).to_string())?;
+        .get()
+        .await
+        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
 
-    // タグでフィルタリングする新しい実装
-    let all_files_with_tags = db.get_files_with_tags().map_err(|e| {
-        AppError::file_retrieval(format!("Failed to get files with tags: {e}")).to_string()
-    })?;
+    let files = if match_all.unwrap_or(false) {
+        // AND検索はSQLで絞り込む
+        db.get_files_with_all_tags(&tag_names)
+            .map_err(|e| AppError::file_retrieval(format!("Failed to search files by tags: {e}")))?
+    } else {
+        // タグでフィルタリングする新しい実装
+        let all_files_with_tags = db
+            .get_files_with_tags()
+            .map_err(|e| AppError::file_retrieval(format!("Failed to get files with tags: {e}")))?;
+        filter_files_by_any_tag(all_files_with_tags, &tag_names)
+    };
+    Ok(exclude_adult_files(files, hide_adult.unwrap_or(false)))
+}
+
+#[tauri::command]
+pub async fn search_files_by_tags_paginated_db(
+    state: tauri::State<'_, AppState>,
+    request: SearchByTagsPaginatedRequest,
+) -> Result<PaginationResponse<FileWithTags>, ApiError> {
+    let pagination = request.pagination.clamped();
+    let db = state
+        .db
+        .get()
+        .await
+        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
+
+    let (items, total_count) = db
+        .get_files_with_any_tags_page(&request.tag_names, &pagination, request.hide_adult)
+        .map_err(|e| match e {
+            AppError::Database(e) => {
+                AppError::file_retrieval(format!("Failed to search files by tags: {e}"))
+            }
+            e => e,
+        })?;
+
+    Ok(PaginationResponse::new(items, total_count, &pagination))
+}
+
+// テキスト・タグ・サイズ・登録日時を組み合わせた詳細検索
+// created_after / created_before は RFC3339（例: 2024-01-10T00:00:00+09:00）
+#[tauri::command]
+pub async fn search_files_advanced_db(
+    state: tauri::State<'_, AppState>,
+    filter: AdvancedSearchFilter,
+) -> Result<Vec<FileWithTags>, ApiError> {
+    let filter = AdvancedSearchFilter {
+        created_after: normalize_created_bound("created_after", filter.created_after)?,
+        created_before: normalize_created_bound("created_before", filter.created_before)?,
+        ..filter
+    };
 
-    let filtered_files = all_files_with_tags
+    let db = state
+        .db
+        .get()
+        .await
+        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
+
+    db.search_files_advanced(&filter)
+        .map_err(|e| AppError::file_retrieval(format!("Failed to search files: {e}")).into())
+}
+
+// "VRChat AND (Avatar OR Cloth) AND NOT WIP" のようなタグの論理式で検索
+#[tauri::command]
+pub async fn search_files_by_tag_expr_db(
+    state: tauri::State<'_, AppState>,
+    expr: String,
+    hide_adult: Option<bool>,
+) -> Result<Vec<FileWithTags>, ApiError> {
+    let expr = tag_query::parse(&expr)?;
+
+    let db = state
+        .db
+        .get()
+        .await
+        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
+
+    db.search_files_by_tag_expr(&expr)
+        .map(|files| exclude_adult_files(files, hide_adult.unwrap_or(false)))
+        .map_err(|e| AppError::file_retrieval(format!("Failed to search files: {e}")).into())
+}
+
+// RFC3339 の日時を created_at と同じ UTC の "YYYY-MM-DD HH:MM:SS" 表記に変換する
+fn normalize_created_bound(field: &str, value: Option<String>) -> AppResult<Option<String>> {
+    value
+        .filter(|value| !value.trim().is_empty())
+        .map(|value| {
+            chrono::DateTime::parse_from_rfc3339(value.trim())
+                .map(|dt| {
+                    dt.with_timezone(&chrono::Utc)
+                        .format("%Y-%m-%d %H:%M:%S%.f")
+                        .to_string()
+                })
+                .map_err(|e| AppError::validation(field, format!("RFC3339形式ではありません: {e}")))
+        })
+        .transpose()
+}
+
+// 指定されたタグのいずれかを持つファイルに絞り込む（OR検索）
+fn filter_files_by_any_tag(
+    files_with_tags: Vec<FileWithTags>,
+    tag_names: &[String],
+) -> Vec<FileWithTags> {
+    files_with_tags
         .into_iter()
         .filter(|file_with_tags| {
             // ファイルが指定されたタグのいずれかを持っているかチェック
@@ -64,24 +257,23 @@ pub async fn search_files_by_tags_db(
                 .iter()
                 .any(|tag_name| file_tag_names.iter().any(|file_tag| file_tag == tag_name))
         })
-        .collect();
-
-    Ok(filtered_files)
+        .collect()
 }
 
 #[tauri::command]
 pub async fn find_duplicate_files_db(
     state: tauri::State<'_, AppState>,
-) -> Result<Vec<Vec<FileWithTags>>, String> {
+) -> Result<Vec<Vec<FileWithTags>>, ApiError> {
     let db = state
         .db
-        .lock()
-        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")).to_string())?;
+        .get()
+        .await
+        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
 
     // 重複ファイル検出の新しい実装（ファイルサイズと名前で比較）
-    let all_files_with_tags = db.get_files_with_tags().map_err(|e| {
-        AppError::file_retrieval(format!("Failed to get files with tags: {e}")).to_string()
-    })?;
+    let all_files_with_tags = db
+        .get_files_with_tags()
+        .map_err(|e| AppError::file_retrieval(format!("Failed to get files with tags: {e}")))?;
 
     let mut duplicate_groups: Vec<Vec<FileWithTags>> = Vec::new();
     let mut processed_files: std::collections::HashSet<i64> = std::collections::HashSet::new();
@@ -120,20 +312,200 @@ pub async fn find_duplicate_files_db(
     Ok(duplicate_groups)
 }
 
+#[tauri::command]
+pub async fn find_duplicate_products_db(
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<Vec<FileWithTags>>, ApiError> {
+    let db = state
+        .db
+        .get()
+        .await
+        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
+
+    let all_files_with_tags = db
+        .get_files_with_tags()
+        .map_err(|e| AppError::file_retrieval(format!("Failed to get files with tags: {e}")))?;
+
+    Ok(group_by_product_id(all_files_with_tags))
+}
+
+// 同じBOOTH商品IDを持つファイルを、最初に現れた順にまとめる（商品ID未設定は対象外）
+// アーカイブ名が違っても同じ商品を二重に取り込んだものを見つけるため
+fn group_by_product_id(files: Vec<FileWithTags>) -> Vec<Vec<FileWithTags>> {
+    let mut groups: Vec<(String, Vec<FileWithTags>)> = Vec::new();
+
+    for file in files {
+        let Some(product_id) = file
+            .file
+            .product_id
+            .as_deref()
+            .map(str::trim)
+            .filter(|id| !id.is_empty())
+            .map(str::to_string)
+        else {
+            continue;
+        };
+        match groups.iter_mut().find(|(id, _)| *id == product_id) {
+            Some((_, group)) => group.push(file),
+            None => groups.push((product_id, vec![file])),
+        }
+    }
+
+    groups
+        .into_iter()
+        .map(|(_, group)| group)
+        .filter(|group| group.len() > 1)
+        .collect()
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct MergeDuplicatesResult {
+    /// 残したファイルに新しく付いたタグ数
+    pub tags_added: usize,
+    /// 削除したファイル情報の数
+    pub files_removed: usize,
+    /// ゴミ箱へ移動できなかった・移動を見送った展開先フォルダ（ファイル情報の削除は完了している）
+    pub failed_paths: Vec<String>,
+}
+
+// 重複ファイルを1つにまとめるコマンド
+// remove_ids のタグを keep_id に付け替えてからファイル情報を削除し、
+// delete_folders 指定時は展開先フォルダも .trash へ移動する
+#[tauri::command]
+pub async fn merge_duplicate_files_db(
+    state: tauri::State<'_, AppState>,
+    keep_id: i64,
+    remove_ids: Vec<i64>,
+    delete_folders: Option<bool>,
+) -> Result<MergeDuplicatesResult, ApiError> {
+    let db = state
+        .db
+        .get()
+        .await
+        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
+
+    let (mut result, removed) = merge_duplicate_files(&db, keep_id, &remove_ids)?;
+    if delete_folders.unwrap_or(false) {
+        let kept_path = db
+            .get_file_by_id(keep_id)
+            .map_err(|e| AppError::file_retrieval(format!("Failed to get file: {e}")))?
+            .map(|file| PathBuf::from(file.file_path));
+        result.failed_paths = remove_merged_folders(&db, &removed, kept_path.as_deref());
+    }
+    Ok(result)
+}
+
+// タグの付け替えとファイル情報の削除を1トランザクションで行い、削除したファイル情報を返す
+// 対象のファイルが1つでも見つからない場合は何も変更しない
+fn merge_duplicate_files(
+    db: &Database,
+    keep_id: i64,
+    remove_ids: &[i64],
+) -> AppResult<(MergeDuplicatesResult, Vec<FileRecord>)> {
+    if remove_ids.contains(&keep_id) {
+        return Err(AppError::validation(
+            "remove_ids",
+            format!("File {keep_id} cannot be both kept and removed"),
+        ));
+    }
+
+    db.in_transaction(|db| {
+        let find = |id: i64| {
+            db.get_file_by_id(id)?
+                .ok_or_else(|| AppError::validation("file_id", format!("File {id} not found")))
+        };
+        find(keep_id)?;
+        let tags_before = db.get_tags_for_file(keep_id)?.len();
+
+        let mut removed = Vec::new();
+        for &id in remove_ids {
+            if removed.iter().any(|file: &FileRecord| file.id == Some(id)) {
+                continue;
+            }
+            let file = find(id)?;
+            for tag in db.get_tags_for_file(id)? {
+                if let Some(tag_id) = tag.id {
+                    db.add_file_tag(keep_id, tag_id)?;
+                }
+            }
+            db.delete_file(id)?;
+            removed.push(file);
+        }
+        db.recalculate_usage_counts()?;
+
+        let result = MergeDuplicatesResult {
+            tags_added: db.get_tags_for_file(keep_id)?.len() - tags_before,
+            files_removed: removed.len(),
+            failed_paths: Vec::new(),
+        };
+        Ok((result, removed))
+    })
+}
+
+// 削除したファイルの展開先を .trash へ移動し、移動しなかったパスを返す
+// 残したファイルと同じ場所や、他の登録済みファイルを含む共有フォルダは移動しない
+fn remove_merged_folders(
+    db: &Database,
+    removed: &[FileRecord],
+    kept_path: Option<&Path>,
+) -> Vec<String> {
+    let kept = kept_path.and_then(|path| path.canonicalize().ok());
+    let mut failed = Vec::new();
+
+    for file in removed {
+        let path = Path::new(&file.file_path);
+        let Ok(canonical) = path.canonicalize() else {
+            continue;
+        };
+        if kept
+            .as_ref()
+            .is_some_and(|kept| kept.starts_with(&canonical))
+        {
+            continue;
+        }
+        let shared = [file.file_path.as_str(), &canonical.to_string_lossy()]
+            .iter()
+            .map(|path| db.has_files_under(path))
+            .collect::<Result<Vec<_>, _>>();
+        match shared {
+            Ok(shared) if !shared.contains(&true) => {}
+            Ok(_) => {
+                log::warn!(
+                    "Keeping {} because it contains other registered files",
+                    file.file_path
+                );
+                failed.push(file.file_path.clone());
+                continue;
+            }
+            Err(e) => {
+                log::warn!("Failed to check files under {}: {e}", file.file_path);
+                failed.push(file.file_path.clone());
+                continue;
+            }
+        }
+        if let Err(e) = move_path_to_trash(&canonical, file.id.unwrap_or_default()) {
+            log::warn!("Failed to move {} to trash: {e}", file.file_path);
+            failed.push(file.file_path.clone());
+        }
+    }
+    failed
+}
+
 #[tauri::command]
 pub async fn get_batch_statistics_db(
     state: tauri::State<'_, AppState>,
     file_ids: Vec<i64>,
-) -> Result<BatchStatistics, String> {
+) -> Result<BatchStatistics, ApiError> {
     let db = state
         .db
-        .lock()
-        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")).to_string())?;
+        .get()
+        .await
+        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
 
     // バッチ統計の新しい実装
-    let all_files = db.get_all_files().map_err(|e| {
-        AppError::file_retrieval(format!("Failed to get all files: {e}")).to_string()
-    })?;
+    let all_files = db
+        .get_all_files()
+        .map_err(|e| AppError::file_retrieval(format!("Failed to get all files: {e}")))?;
 
     let selected_files = all_files
         .into_iter()
@@ -153,28 +525,132 @@ pub async fn get_batch_statistics_db(
     })
 }
 
+// ショップ別の購入数・合計金額・合計サイズ（合計金額の多い順）
+#[tauri::command]
+pub async fn get_shop_breakdown_db(
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<ShopSummary>, ApiError> {
+    let db = state
+        .db
+        .get()
+        .await
+        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
+
+    db.get_shop_breakdown()
+        .map_err(|e| AppError::file_retrieval(format!("Failed to get shop breakdown: {e}")).into())
+}
+
+// ショップ別のファイル数・合計サイズ（ファイル数の多い順）
+#[tauri::command]
+pub async fn get_shop_summary_db(
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<ShopFileSummary>, ApiError> {
+    let db = state
+        .db
+        .get()
+        .await
+        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
+
+    db.get_shop_summary()
+        .map_err(|e| AppError::file_retrieval(format!("Failed to get shop summary: {e}")).into())
+}
+
+// ライブラリ全体の統計（ダッシュボード用、top_tags は上位タグの件数）
+#[tauri::command]
+pub async fn get_library_stats_db(
+    state: tauri::State<'_, AppState>,
+    top_tags: Option<u32>,
+) -> Result<LibraryStats, ApiError> {
+    let db = state
+        .db
+        .get()
+        .await
+        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
+
+    db.get_library_stats(top_tags.unwrap_or(tags::STATS_TOP_TAGS))
+        .map_err(|e| AppError::file_retrieval(format!("Failed to get library stats: {e}")).into())
+}
+
 #[tauri::command]
-pub async fn sync_file_system_db(state: tauri::State<'_, AppState>) -> Result<SyncResult, String> {
+pub async fn sync_file_system_db(
+    state: tauri::State<'_, AppState>,
+    output_dir: Option<String>,
+    list_orphaned: Option<bool>,
+) -> Result<SyncResult, ApiError> {
+    let detail = format!("output_dir={output_dir:?}");
+    logging::timed(
+        "sync_file_system_db",
+        &detail,
+        sync_file_system(state, output_dir, list_orphaned),
+    )
+    .await
+}
+
+async fn sync_file_system(
+    state: tauri::State<'_, AppState>,
+    output_dir: Option<String>,
+    list_orphaned: Option<bool>,
+) -> Result<SyncResult, ApiError> {
     let db = state
         .db
-        .lock()
-        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")).to_string())?;
+        .get()
+        .await
+        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
 
     // データベースから全ファイルを取得
-    let all_files = db.get_all_files().map_err(|e| {
-        AppError::file_retrieval(format!("Failed to get all files: {e}")).to_string()
-    })?;
+    let all_files = db
+        .get_all_files()
+        .map_err(|e| AppError::file_retrieval(format!("Failed to get all files: {e}")))?;
+
+    // 出力先（未指定なら保存済みの設定、それもなければ既定の場所）にある未登録の項目
+    let output_base = output_dir
+        .or_else(stored_output_folder)
+        .map(PathBuf::from)
+        .unwrap_or_else(default_output_base);
+    let tracked_paths: Vec<&Path> = all_files
+        .iter()
+        .map(|file| Path::new(&file.file_path))
+        .collect();
+    let orphaned = find_orphaned_entries(&output_base, &tracked_paths)
+        .map_err(|e| AppError::file_retrieval(format!("Failed to scan output directory: {e}")))?;
 
     let mut sync_result = SyncResult {
         total_files: all_files.len(),
         missing_files: Vec::new(),
-        orphaned_files: 0,
+        orphaned_files: orphaned.len(),
         updated_files: 0,
+        orphaned_paths: if list_orphaned.unwrap_or(true) {
+            orphaned
+                .iter()
+                .map(|path| path.to_string_lossy().to_string())
+                .collect()
+        } else {
+            Vec::new()
+        },
     };
 
+    check_tracked_files(&db, &all_files, &mut sync_result)
+        .map_err(|e| AppError::file_update(format!("Failed to update file stats: {e}")))?;
+
+    // ファイル同期後にタグのカウントを再計算
+    db.recalculate_usage_counts()
+        .map_err(|e| AppError::custom(format!("Failed to recalculate tag usage count: {e}")))?;
+
+    Ok(sync_result)
+}
+
+// 登録済みの各ファイルについて、存在しないものを missing_files に加え、
+// サイズ・更新日時がディスク上と異なるものはデータベースを更新して updated_files に数える
+// フォルダとして展開したものはアーカイブのサイズを記録しているため比較しない
+fn check_tracked_files(
+    db: &Database,
+    all_files: &[FileRecord],
+    sync_result: &mut SyncResult,
+) -> AppResult<()> {
     for file_record in all_files {
+        let path = Path::new(&file_record.file_path);
         // ファイルの存在確認
-        if !std::path::Path::new(&file_record.file_path).exists() {
+        if !path.exists() {
             sync_result.missing_files.push(MissingFile {
                 id: file_record.id.unwrap_or(0),
                 file_name: file_record.file_name.clone(),
@@ -182,26 +658,87 @@ pub async fn sync_file_system_db(state: tauri::State<'_, AppState>) -> Result<Sy
                 booth_shop_name: file_record.author_name.clone(), // author_name を使用
                 booth_product_name: file_record.product_name.clone(),
             });
+            continue;
+        }
+
+        let (Some(id), Ok(metadata)) = (file_record.id, std::fs::metadata(path)) else {
+            continue;
+        };
+        if !metadata.is_file() {
+            continue;
         }
+        let file_size = metadata.len() as i64;
+        let modified_time = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|duration| duration.as_secs() as i64)
+            .unwrap_or(file_record.modified_time);
+        if file_size != file_record.file_size || modified_time != file_record.modified_time {
+            db.update_file_stats(id, file_size, modified_time)?;
+            sync_result.updated_files += 1;
+        }
+    }
+    Ok(())
+}
+
+// 出力先にあるフォルダ・ファイルのうち、どの登録済みパスとも対応しないものを名前順に返す
+// 登録済みパスを含むフォルダ（ショップフォルダなど）は中まで調べ、未登録の商品フォルダを個別に報告する
+// 未登録のフォルダの中身は調べない。ゴミ箱フォルダは対象外。出力先が存在しない場合は空
+pub fn find_orphaned_entries(
+    output_dir: &Path,
+    tracked_paths: &[&Path],
+) -> std::io::Result<Vec<PathBuf>> {
+    let mut orphaned = Vec::new();
+    if output_dir.is_dir() {
+        collect_orphaned_entries(output_dir, tracked_paths, &mut orphaned)?;
     }
+    orphaned.sort();
+    Ok(orphaned)
+}
 
-    // ファイル同期後にタグのカウントを再計算
-    db.recalculate_usage_counts().map_err(|e| {
-        AppError::custom(format!("Failed to recalculate tag usage count: {e}")).to_string()
-    })?;
+fn collect_orphaned_entries(
+    dir: &Path,
+    tracked_paths: &[&Path],
+    orphaned: &mut Vec<PathBuf>,
+) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.file_name() == Some(std::ffi::OsStr::new(files::TRASH_DIR_NAME)) {
+            continue;
+        }
 
-    Ok(sync_result)
+        // 登録済みのフォルダ自身とその中身は対象外
+        if tracked_paths
+            .iter()
+            .any(|tracked| path.starts_with(tracked))
+        {
+            continue;
+        }
+
+        if path.is_dir()
+            && tracked_paths
+                .iter()
+                .any(|tracked| tracked.starts_with(&path))
+        {
+            collect_orphaned_entries(&path, tracked_paths, orphaned)?;
+        } else {
+            orphaned.push(path);
+        }
+    }
+    Ok(())
 }
 
 #[tauri::command]
 pub async fn remove_missing_files_db(
     state: tauri::State<'_, AppState>,
     file_ids: Vec<i64>,
-) -> Result<usize, String> {
+) -> Result<usize, ApiError> {
     let db = state
         .db
-        .lock()
-        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")).to_string())?;
+        .get()
+        .await
+        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
 
     let mut removed_count = 0;
     for file_id in file_ids {
@@ -211,15 +748,853 @@ pub async fn remove_missing_files_db(
             }
             Err(e) => {
                 // Log error but continue with other files
-                eprintln!("Failed to delete file {file_id}: {e}");
+                log::error!("Failed to delete file {file_id}: {e}");
             }
         }
     }
 
     // ファイル削除後にタグのカウントを再計算
-    db.recalculate_usage_counts().map_err(|e| {
-        AppError::custom(format!("Failed to recalculate tag usage count: {e}")).to_string()
-    })?;
+    db.recalculate_usage_counts()
+        .map_err(|e| AppError::custom(format!("Failed to recalculate tag usage count: {e}")))?;
 
     Ok(removed_count)
 }
+
+/// 保存済みハッシュとの照合結果
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VerifyStatus {
+    /// 保存済みのハッシュと一致
+    Ok,
+    /// 内容が変わっている（破損・差し替え）
+    Mismatch,
+    /// ファイルが見つからない、または読めない
+    Missing,
+    /// ハッシュが保存されていないため照合できない
+    Unhashed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileVerification {
+    pub file_id: i64,
+    /// ハッシュを計算したパス（展開先フォルダの場合は元のZIP）
+    pub path: String,
+    pub status: VerifyStatus,
+}
+
+// ファイルの内容が取り込み時から変わっていないか、保存済みのハッシュで確認するコマンド
+// file_ids を省略するとゴミ箱以外の全ファイルが対象。ハッシュ計算中はDBを使わない
+#[tauri::command]
+pub async fn verify_files_db(
+    state: tauri::State<'_, AppState>,
+    file_ids: Option<Vec<i64>>,
+) -> Result<Vec<FileVerification>, ApiError> {
+    let files = {
+        let db = state
+            .db
+            .get()
+            .await
+            .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
+        match file_ids {
+            Some(ids) => {
+                let mut files = Vec::new();
+                for id in ids {
+                    let file = db
+                        .get_file_by_id(id)
+                        .map_err(|e| AppError::file_retrieval(format!("Failed to get file: {e}")))?
+                        .ok_or_else(|| {
+                            AppError::validation("file_ids", format!("File {id} not found"))
+                        })?;
+                    files.push(file);
+                }
+                files
+            }
+            None => db
+                .get_all_files_ordered_by_id()
+                .map_err(|e| AppError::file_retrieval(format!("Failed to get all files: {e}")))?,
+        }
+    };
+
+    tokio::task::spawn_blocking(move || files.iter().map(verify_file).collect())
+        .await
+        .map_err(|e| AppError::custom(format!("Failed to verify files: {e}")).into())
+}
+
+// ハッシュの元になるファイル。ZIPとして登録されたファイルはそのもの、展開先フォルダは元のZIP
+// （元のZIPが登録されていないフォルダは None）
+fn hash_source(file: &FileRecord) -> Option<&str> {
+    if Path::new(&file.file_path).is_dir() {
+        file.archive_path.as_deref()
+    } else {
+        Some(&file.file_path)
+    }
+}
+
+fn verify_file(file: &FileRecord) -> FileVerification {
+    let path = hash_source(file).unwrap_or(&file.file_path);
+    let status = match file.file_hash.as_deref() {
+        None => VerifyStatus::Unhashed,
+        Some(expected) => match compute_file_hash(Path::new(path)) {
+            Ok(hash) if hash == expected => VerifyStatus::Ok,
+            Ok(_) => VerifyStatus::Mismatch,
+            Err(_) => VerifyStatus::Missing,
+        },
+    };
+
+    FileVerification {
+        file_id: file.id.unwrap_or_default(),
+        path: path.to_string(),
+        status,
+    }
+}
+
+/// 派生データ再構築の結果（種類ごとの件数）
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct RebuildIndexResult {
+    /// ハッシュを計算し直したファイル数
+    pub hashes_recomputed: usize,
+    /// そのうち保存済みの値と異なっていたファイル数
+    pub hashes_changed: usize,
+    /// usage_count を補正したタグ数
+    pub usage_counts_corrected: usize,
+    /// どのファイルにも付いていなかったため削除したタグ数
+    pub orphaned_tags_removed: usize,
+    /// ディスク上に見つからない（または読めない）ためスキップしたファイルのID
+    pub missing_file_ids: Vec<i64>,
+    /// 元のZIPが登録されていない展開先フォルダのため、ハッシュを計算できなかったファイルのID
+    pub unhashable_file_ids: Vec<i64>,
+}
+
+// ハッシュ・タグ使用数などの派生データを一括で再計算するコマンド
+#[tauri::command]
+pub async fn rebuild_index_db(
+    state: tauri::State<'_, AppState>,
+) -> Result<RebuildIndexResult, ApiError> {
+    rebuild_index(&state.db)
+        .await
+        .map_err(|e| AppError::custom(format!("Failed to rebuild index: {e}")).into())
+}
+
+// 壊れた関連付け・タグ使用数を修復し、整合性チェックの結果とあわせて返すコマンド
+#[tauri::command]
+pub async fn repair_database(state: tauri::State<'_, AppState>) -> Result<RepairReport, ApiError> {
+    let db = state
+        .db
+        .get()
+        .await
+        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
+
+    db.repair()
+        .map_err(|e| AppError::custom(format!("Failed to repair database: {e}")).into())
+}
+
+// データベースのバックアップを作成し、書き出したファイルのパスを返すコマンド
+// dest 未指定時はデータベースと同じフォルダの backups/ に書き出す
+#[tauri::command]
+pub async fn backup_database(
+    state: tauri::State<'_, AppState>,
+    dest: Option<String>,
+) -> Result<String, ApiError> {
+    let db = state
+        .db
+        .get()
+        .await
+        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
+
+    let backup = db_backup::backup_database(&db, state.db.path(), dest.as_deref().map(Path::new))?;
+    Ok(backup.to_string_lossy().into_owned())
+}
+
+/// データベース最適化の結果（実行前後のファイルサイズ、バイト単位）
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CompactResult {
+    pub size_before: u64,
+    pub size_after: u64,
+}
+
+// VACUUM でデータベースファイルを詰め直し、前後のサイズを返すコマンド
+#[tauri::command]
+pub async fn compact_database(
+    state: tauri::State<'_, AppState>,
+) -> Result<CompactResult, ApiError> {
+    compact(&state.db)
+        .await
+        .map_err(|e| AppError::custom(format!("Failed to compact database: {e}")).into())
+}
+
+// 書き込み中の処理と並行しないよう、他の接続がすべて返却されてから実行する
+// 実行前にバックアップを作成する（作成できなければ最適化しない）
+// VACUUM は時間がかかるため、返却待ちから最適化まで別スレッドで行う
+pub async fn compact(pool: &DatabasePool) -> AppResult<CompactResult> {
+    let pool = pool.clone();
+    tokio::task::spawn_blocking(move || compact_blocking(&pool))
+        .await
+        .map_err(|e| AppError::custom(format!("Failed to compact database: {e}")))?
+}
+
+fn compact_blocking(pool: &DatabasePool) -> AppResult<CompactResult> {
+    let db = pool.get_exclusive_blocking()?;
+
+    db_backup::backup_before_destructive(&db, pool.path(), "compact")?;
+    db.checkpoint()?;
+    let size_before = database_file_size(pool.path())?;
+    db.vacuum()?;
+    let size_after = database_file_size(pool.path())?;
+
+    log::info!("Compacted database: {size_before} -> {size_after} bytes");
+    Ok(CompactResult {
+        size_before,
+        size_after,
+    })
+}
+
+// ":memory:" はファイルを持たないため 0 とする
+fn database_file_size(path: &str) -> AppResult<u64> {
+    if path == ":memory:" {
+        return Ok(0);
+    }
+    Ok(std::fs::metadata(path)?.len())
+}
+
+// ファイルのハッシュ計算は接続を返却してから別スレッドで行い、更新は1トランザクションで行う
+// 展開先フォルダとして登録されたファイルは元アーカイブのハッシュを計算する
+pub async fn rebuild_index(pool: &DatabasePool) -> AppResult<RebuildIndexResult> {
+    let files = pool.get().await?.get_all_files_ordered_by_id()?;
+
+    let scan = tokio::task::spawn_blocking(move || hash_files(&files))
+        .await
+        .map_err(|e| AppError::custom(format!("Failed to hash files: {e}")))?;
+
+    let db = pool.get().await?;
+    apply_rebuild(&db, scan)
+}
+
+// ハッシュの再計算結果（データベースへの反映前）
+#[derive(Debug, Default)]
+struct HashScan {
+    result: RebuildIndexResult,
+    changed_hashes: Vec<(i64, String)>,
+}
+
+fn hash_files(files: &[FileRecord]) -> HashScan {
+    let mut scan = HashScan::default();
+
+    for file in files {
+        let Some(file_id) = file.id else {
+            continue;
+        };
+        if !Path::new(&file.file_path).exists() {
+            scan.result.missing_file_ids.push(file_id);
+            continue;
+        }
+        let Some(source) = hash_source(file) else {
+            scan.result.unhashable_file_ids.push(file_id);
+            continue;
+        };
+
+        match compute_file_hash(Path::new(source)) {
+            Ok(hash) => {
+                scan.result.hashes_recomputed += 1;
+                if file.file_hash.as_deref() != Some(hash.as_str()) {
+                    scan.changed_hashes.push((file_id, hash));
+                }
+            }
+            Err(e) => {
+                log::warn!("Failed to hash file {file_id}: {e}");
+                scan.result.missing_file_ids.push(file_id);
+            }
+        }
+    }
+
+    scan
+}
+
+fn apply_rebuild(db: &Database, scan: HashScan) -> AppResult<RebuildIndexResult> {
+    let HashScan {
+        mut result,
+        changed_hashes,
+    } = scan;
+
+    db.in_transaction(|db| {
+        for (file_id, hash) in &changed_hashes {
+            db.set_file_hash(*file_id, hash)?;
+        }
+        result.usage_counts_corrected = db.recalculate_usage_counts()?;
+        result.orphaned_tags_removed = db.delete_orphaned_tags()?;
+        Ok::<_, AppError>(())
+    })?;
+    result.hashes_changed = changed_hashes.len();
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api_types::PaginationRequest;
+    use crate::database::Database;
+    use crate::test_support::{sample_file, tagged_db};
+
+    #[test]
+    fn test_group_by_product_id_finds_reimported_products() {
+        let db = Database::new(":memory:").unwrap();
+        for (path, product_id) in [
+            ("/Shop/Avatar", Some("12345")),
+            ("/Shop/Cloth", Some("67890")),
+            ("/Downloads/Avatar_v2", Some("12345")),
+            ("/Unknown/Hair", None),
+            ("/Unknown/Shoes", None),
+        ] {
+            db.add_file(FileRecord {
+                product_id: product_id.map(str::to_string),
+                ..sample_file(path)
+            })
+            .unwrap();
+        }
+
+        let groups = group_by_product_id(db.get_files_with_tags().unwrap());
+        assert_eq!(groups.len(), 1);
+        let mut paths: Vec<&str> = groups[0]
+            .iter()
+            .map(|file| file.file.file_path.as_str())
+            .collect();
+        paths.sort();
+        assert_eq!(paths, vec!["/Downloads/Avatar_v2", "/Shop/Avatar"]);
+    }
+
+    fn tag_names(db: &Database, file_id: i64) -> Vec<String> {
+        let mut names: Vec<String> = db
+            .get_tags_for_file(file_id)
+            .unwrap()
+            .into_iter()
+            .map(|tag| tag.name)
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_merge_duplicate_files_unions_tags_and_removes_others() {
+        let (db, both, vrchat_only, paid_only) = tagged_db();
+        let extra = db.add_tag("Quest").unwrap();
+        db.add_file_tag(paid_only, extra).unwrap();
+
+        let (result, removed) =
+            merge_duplicate_files(&db, vrchat_only, &[paid_only, both]).unwrap();
+
+        assert_eq!(result.tags_added, 2);
+        assert_eq!(result.files_removed, 2);
+        assert_eq!(removed.len(), 2);
+        assert_eq!(tag_names(&db, vrchat_only), vec!["Quest", "VRChat", "有料"]);
+        assert!(db.get_file_by_id(paid_only).unwrap().is_none());
+        assert!(db.get_file_by_id(both).unwrap().is_none());
+        let usage = |name: &str| {
+            db.get_all_tags()
+                .unwrap()
+                .into_iter()
+                .find(|tag| tag.name == name)
+                .map(|tag| tag.usage_count)
+        };
+        assert_eq!(usage("有料"), Some(1));
+        assert_eq!(usage("Quest"), Some(1));
+    }
+
+    #[test]
+    fn test_merge_duplicate_files_rejects_invalid_requests_without_changes() {
+        let (db, both, vrchat_only, paid_only) = tagged_db();
+
+        let err = merge_duplicate_files(&db, both, &[vrchat_only, both]).unwrap_err();
+        assert!(matches!(err, AppError::Validation { .. }), "{err:?}");
+
+        // 存在しないファイルが含まれていればロールバックする
+        let err = merge_duplicate_files(&db, both, &[vrchat_only, 9999]).unwrap_err();
+        assert!(matches!(err, AppError::Validation { .. }), "{err:?}");
+        assert!(db.get_file_by_id(vrchat_only).unwrap().is_some());
+        assert!(db.get_file_by_id(paid_only).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_remove_merged_folders_trashes_only_unshared_locations() {
+        let dir = tempfile::tempdir().unwrap();
+        let kept = dir.path().join("Shop/Avatar");
+        let duplicate = dir.path().join("Shop/Avatar (1)");
+        let other = dir.path().join("Shared/Other");
+        std::fs::create_dir_all(&kept).unwrap();
+        std::fs::create_dir_all(&duplicate).unwrap();
+        std::fs::create_dir_all(&other).unwrap();
+        std::fs::write(duplicate.join("model.fbx"), b"fbx").unwrap();
+
+        let db = Database::new(":memory:").unwrap();
+        db.add_file(sample_file(&kept.to_string_lossy())).unwrap();
+        db.add_file(sample_file(&other.to_string_lossy())).unwrap();
+
+        // 他の登録済みファイルを含む共有フォルダは移動しない
+        let shared = dir.path().join("Shared");
+        let removed = [
+            sample_file(&duplicate.to_string_lossy()),
+            sample_file(&kept.to_string_lossy()),
+            sample_file(&shared.to_string_lossy()),
+            sample_file("/nonexistent/folder"),
+        ];
+        assert_eq!(
+            remove_merged_folders(&db, &removed, Some(&kept)),
+            vec![shared.to_string_lossy().to_string()]
+        );
+        assert!(!duplicate.exists());
+        assert!(kept.exists() && other.exists());
+
+        // 削除ではなくゴミ箱へ移動している
+        let trash = dir.path().join("Shop").join(files::TRASH_DIR_NAME);
+        let entry = std::fs::read_dir(&trash).unwrap().next().unwrap().unwrap();
+        assert!(entry.path().join("model.fbx").exists());
+    }
+
+    fn sorted_ids(files: &[FileWithTags]) -> Vec<i64> {
+        let mut ids: Vec<i64> = files.iter().filter_map(|f| f.file.id).collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    fn page(page: u32, page_size: u32) -> PaginationRequest {
+        PaginationRequest {
+            page,
+            page_size,
+            sort_by: Some("file_name".to_string()),
+            sort_order: Some("asc".to_string()),
+            sort: Vec::new(),
+        }
+    }
+
+    // 25件すべてに「VRChat」タグ、名前に "avatar" を含むファイル
+    fn paged_db() -> Database {
+        let db = Database::new(":memory:").unwrap();
+        let tag = db.add_tag("VRChat").unwrap();
+        for i in 0..25 {
+            let id = db
+                .add_file(sample_file(&format!("/avatar_{i:02}")))
+                .unwrap();
+            db.add_file_tag(id, tag).unwrap();
+        }
+        db.add_file(sample_file("/other")).unwrap();
+        db
+    }
+
+    fn assert_three_pages(fetch: impl Fn(&PaginationRequest) -> (Vec<FileWithTags>, u32)) {
+        let expected = [
+            (1, 10, true, false),
+            (2, 10, true, true),
+            (3, 5, false, true),
+        ];
+        for (page_no, len, has_next, has_prev) in expected {
+            let request = page(page_no, 10);
+            let (items, total) = fetch(&request);
+            let response = PaginationResponse::new(items, total, &request);
+
+            assert_eq!(response.total_count, 25);
+            assert_eq!(response.total_pages, 3);
+            assert_eq!(response.items.len(), len);
+            assert_eq!(response.has_next_page, has_next);
+            assert_eq!(response.has_prev_page, has_prev);
+            assert_eq!(
+                response.items[0].file.file_name,
+                format!("/avatar_{:02}", (page_no - 1) * 10)
+            );
+        }
+    }
+
+    #[test]
+    fn test_get_files_paginated_boundaries() {
+        let db = paged_db();
+        db.soft_delete_file(db.get_file_by_path("/other").unwrap().unwrap().id.unwrap())
+            .unwrap();
+
+        assert_three_pages(|request| db.get_files_page(request, false).unwrap());
+    }
+
+    #[test]
+    fn test_search_files_paginated_boundaries() {
+        let db = paged_db();
+
+        assert_three_pages(|request| db.search_files_page("avatar", request, false).unwrap());
+        // LIKE のワイルドカードは文字として扱う
+        assert_eq!(db.search_files_page("%", &page(1, 10), false).unwrap().1, 0);
+    }
+
+    #[test]
+    fn test_search_files_by_tags_paginated_boundaries() {
+        let db = paged_db();
+        let tag_names = vec!["VRChat".to_string(), "未使用".to_string()];
+
+        assert_three_pages(|request| {
+            db.get_files_with_any_tags_page(&tag_names, request, false)
+                .unwrap()
+        });
+        let (items, _) = db
+            .get_files_with_any_tags_page(&tag_names, &page(1, 10), false)
+            .unwrap();
+        assert!(items.iter().all(|f| f.tags.len() == 1));
+    }
+
+    fn fetch_clamped(
+        db: &Database,
+        request: PaginationRequest,
+    ) -> PaginationResponse<FileWithTags> {
+        let pagination = request.clamped();
+        let (items, total) = db.get_files_page(&pagination, false).unwrap();
+        PaginationResponse::new(items, total, &pagination)
+    }
+
+    #[test]
+    fn test_zero_page_size_is_clamped_to_one() {
+        let response = fetch_clamped(&paged_db(), page(1, 0));
+
+        assert_eq!(response.page_size, 1);
+        assert_eq!(response.items.len(), 1);
+        assert_eq!(response.total_pages, 26);
+        assert!(response.has_next_page);
+    }
+
+    #[test]
+    fn test_huge_page_size_is_clamped_to_max() {
+        let response = fetch_clamped(&paged_db(), page(1, 100_000));
+
+        assert_eq!(response.page_size, crate::config::pagination::MAX_PAGE_SIZE);
+        assert_eq!(response.items.len(), 26);
+        assert_eq!(response.total_pages, 1);
+        assert!(!response.has_next_page);
+    }
+
+    #[test]
+    fn test_page_zero_is_treated_as_first_page() {
+        let response = fetch_clamped(&paged_db(), page(0, 10));
+
+        assert_eq!(response.page, 1);
+        assert_eq!(response.items[0].file.file_name, "/avatar_00");
+        assert!(!response.has_prev_page);
+        assert!(response.has_next_page);
+    }
+
+    #[test]
+    fn test_tag_search_or_semantics() {
+        let (db, both, vrchat_only, paid_only) = tagged_db();
+        let tag_names = vec!["VRChat".to_string(), "有料".to_string()];
+
+        let files = filter_files_by_any_tag(db.get_files_with_tags().unwrap(), &tag_names);
+        assert_eq!(sorted_ids(&files), vec![both, vrchat_only, paid_only]);
+    }
+
+    #[test]
+    fn test_substring_search_fallback() {
+        let (db, _, _, _) = tagged_db();
+
+        let files = filter_files_by_query(db.get_files_with_tags().unwrap(), "VRCH");
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].file.file_path, "/vrchat");
+    }
+
+    #[test]
+    fn test_tag_search_and_semantics() {
+        let (db, both, _, _) = tagged_db();
+        let tag_names = vec!["VRChat".to_string(), "有料".to_string()];
+
+        let files = db.get_files_with_all_tags(&tag_names).unwrap();
+        assert_eq!(sorted_ids(&files), vec![both]);
+    }
+
+    #[test]
+    fn test_verify_file_reports_modified_and_missing_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Database::new(":memory:").unwrap();
+        let add_hashed = |name: &str, is_folder: bool| {
+            let archive = dir.path().join(format!("{name}.zip"));
+            std::fs::write(&archive, name.as_bytes()).unwrap();
+            let mut file = if is_folder {
+                let folder = dir.path().join(name);
+                std::fs::create_dir_all(&folder).unwrap();
+                sample_file(&folder.to_string_lossy())
+            } else {
+                sample_file(&archive.to_string_lossy())
+            };
+            file.archive_path = Some(archive.to_string_lossy().to_string());
+            file.file_hash = Some(compute_file_hash(&archive).unwrap());
+            (db.add_file(file).unwrap(), archive)
+        };
+        let (untouched, _) = add_hashed("untouched", true);
+        let (modified, modified_archive) = add_hashed("modified", false);
+        let (removed, removed_archive) = add_hashed("removed", true);
+        let unhashed = db.add_file(sample_file("/no/hash")).unwrap();
+
+        std::fs::write(&modified_archive, b"swapped contents").unwrap();
+        std::fs::remove_file(&removed_archive).unwrap();
+
+        let statuses: Vec<(i64, VerifyStatus)> = db
+            .get_all_files_ordered_by_id()
+            .unwrap()
+            .iter()
+            .map(verify_file)
+            .map(|result| (result.file_id, result.status))
+            .collect();
+        assert_eq!(
+            statuses,
+            vec![
+                (untouched, VerifyStatus::Ok),
+                (modified, VerifyStatus::Mismatch),
+                (removed, VerifyStatus::Missing),
+                (unhashed, VerifyStatus::Unhashed),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sync_updates_resized_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Database::new(":memory:").unwrap();
+        let archive = dir.path().join("avatar.zip");
+        std::fs::write(&archive, b"zip").unwrap();
+        let folder = dir.path().join("Shop").join("avatar");
+        std::fs::create_dir_all(&folder).unwrap();
+        let file_id = db
+            .add_file(sample_file(&archive.to_string_lossy()))
+            .unwrap();
+        db.add_file(sample_file(&folder.to_string_lossy())).unwrap();
+        db.add_file(sample_file("/nonexistent/gone.zip")).unwrap();
+
+        let sync = |db: &Database| {
+            let mut result = SyncResult {
+                total_files: 0,
+                missing_files: Vec::new(),
+                orphaned_files: 0,
+                updated_files: 0,
+                orphaned_paths: Vec::new(),
+            };
+            check_tracked_files(db, &db.get_all_files().unwrap(), &mut result).unwrap();
+            result
+        };
+
+        // 取り込み時の記録と揃えた後、ディスク上でサイズを変える
+        sync(&db);
+        std::fs::write(&archive, b"resized archive").unwrap();
+
+        let result = sync(&db);
+        assert_eq!(result.updated_files, 1);
+        assert_eq!(result.missing_files.len(), 1);
+        let file = db.get_file_by_id(file_id).unwrap().unwrap();
+        assert_eq!(file.file_size, 15);
+
+        // 変化がなければ更新しない
+        assert_eq!(sync(&db).updated_files, 0);
+    }
+
+    #[tokio::test]
+    async fn test_rebuild_index_hashes_extracted_folders_by_archive() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("avatar.zip");
+        std::fs::write(&archive, b"archive contents").unwrap();
+        let extracted = dir.path().join("Shop/Avatar");
+        let unlinked = dir.path().join("Shop/Manual");
+        std::fs::create_dir_all(&extracted).unwrap();
+        std::fs::create_dir_all(&unlinked).unwrap();
+
+        let pool = DatabasePool::new(":memory:").unwrap();
+        let (extracted_id, unlinked_id) = {
+            let db = pool.get().await.unwrap();
+            let extracted_id = db
+                .add_file(FileRecord {
+                    file_hash: Some("wrong".to_string()),
+                    archive_path: Some(archive.to_string_lossy().to_string()),
+                    ..sample_file(&extracted.to_string_lossy())
+                })
+                .unwrap();
+            let unlinked_id = db
+                .add_file(sample_file(&unlinked.to_string_lossy()))
+                .unwrap();
+            (extracted_id, unlinked_id)
+        };
+
+        let result = rebuild_index(&pool).await.unwrap();
+
+        assert_eq!((result.hashes_recomputed, result.hashes_changed), (1, 1));
+        assert_eq!(result.unhashable_file_ids, vec![unlinked_id]);
+        assert!(result.missing_file_ids.is_empty());
+        assert_eq!(
+            pool.get()
+                .await
+                .unwrap()
+                .get_file_by_id(extracted_id)
+                .unwrap()
+                .unwrap()
+                .file_hash,
+            Some(compute_file_hash(&archive).unwrap())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rebuild_index_corrects_derived_data() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("avatar.zip");
+        std::fs::write(&archive, b"archive contents").unwrap();
+
+        let pool = DatabasePool::new(":memory:").unwrap();
+        let (present_id, missing_id) = {
+            let db = pool.get().await.unwrap();
+            let mut present = sample_file(&archive.to_string_lossy());
+            present.file_hash = Some("stale".to_string());
+            let present_id = db.add_file(present).unwrap();
+            let missing_id = db.add_file(sample_file("/nonexistent/gone.zip")).unwrap();
+
+            let kept = db.add_tag("VRChat").unwrap();
+            let dropped = db.add_tag("有料").unwrap();
+            db.add_file_tag(present_id, kept).unwrap();
+            db.add_file_tag(missing_id, kept).unwrap();
+            db.add_file_tag(present_id, dropped).unwrap();
+            // usage_count を更新せずに関連付けを外し、値をずらしておく
+            db.clear_file_tags(present_id).unwrap();
+            db.add_tag("未使用").unwrap();
+            (present_id, missing_id)
+        };
+
+        // ハッシュ計算中は接続を保持しないため、1本だけのプールでも完了する
+        let result = rebuild_index(&pool).await.unwrap();
+
+        assert_eq!(result.missing_file_ids, vec![missing_id]);
+        assert_eq!(result.hashes_recomputed, 1);
+        assert_eq!(result.hashes_changed, 1);
+        assert_eq!(result.usage_counts_corrected, 2);
+        assert_eq!(result.orphaned_tags_removed, 2);
+
+        {
+            let db = pool.get().await.unwrap();
+            let present = db.get_file_by_id(present_id).unwrap().unwrap();
+            assert_eq!(
+                present.file_hash,
+                Some(compute_file_hash(&archive).unwrap())
+            );
+            assert_eq!(
+                db.get_file_by_id(missing_id).unwrap().unwrap().file_hash,
+                None
+            );
+
+            let tags = db.get_all_tags().unwrap();
+            assert_eq!(tags.len(), 1);
+            assert_eq!((tags[0].name.as_str(), tags[0].usage_count), ("VRChat", 1));
+        }
+
+        // 2回目は補正するものがない
+        let again = rebuild_index(&pool).await.unwrap();
+        assert_eq!(
+            (
+                again.hashes_changed,
+                again.usage_counts_corrected,
+                again.orphaned_tags_removed
+            ),
+            (0, 0, 0)
+        );
+    }
+
+    #[test]
+    fn test_find_orphaned_entries_reports_untracked_top_level_items() {
+        let output = tempfile::tempdir().unwrap();
+        let tracked = output.path().join("Dolos Art").join("Milk");
+        std::fs::create_dir_all(&tracked).unwrap();
+        std::fs::create_dir_all(output.path().join("Unknown_Shop").join("old")).unwrap();
+        std::fs::write(output.path().join("stray.zip"), b"zip").unwrap();
+        std::fs::create_dir_all(output.path().join(files::TRASH_DIR_NAME)).unwrap();
+
+        let orphaned = find_orphaned_entries(output.path(), &[tracked.as_path()]).unwrap();
+
+        assert_eq!(
+            orphaned,
+            vec![
+                output.path().join("Unknown_Shop"),
+                output.path().join("stray.zip")
+            ]
+        );
+        assert!(find_orphaned_entries(&output.path().join("missing"), &[])
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_find_orphaned_entries_reports_untracked_product_in_tracked_shop() {
+        let output = tempfile::tempdir().unwrap();
+        let shop = output.path().join("Dolos Art");
+        let tracked = shop.join("Milk");
+        std::fs::create_dir_all(tracked.join("Assets")).unwrap();
+        std::fs::create_dir_all(shop.join("Cocoa").join("Assets")).unwrap();
+
+        let orphaned = find_orphaned_entries(output.path(), &[tracked.as_path()]).unwrap();
+
+        assert_eq!(orphaned, vec![shop.join("Cocoa")]);
+    }
+
+    #[test]
+    fn test_normalize_created_bound_converts_to_utc() {
+        assert_eq!(
+            normalize_created_bound("created_after", Some("2024-01-10T09:00:00+09:00".into()))
+                .unwrap(),
+            Some("2024-01-10 00:00:00".to_string())
+        );
+        assert_eq!(
+            normalize_created_bound("created_before", Some("2024-01-10T00:00:00.5Z".into()))
+                .unwrap(),
+            Some("2024-01-10 00:00:00.500".to_string())
+        );
+        assert_eq!(
+            normalize_created_bound("created_after", None).unwrap(),
+            None
+        );
+        assert_eq!(
+            normalize_created_bound("created_after", Some("  ".into())).unwrap(),
+            None
+        );
+
+        let err = normalize_created_bound("created_before", Some("2024-01-10".into())).unwrap_err();
+        assert_eq!(err.code(), "Validation");
+    }
+
+    #[tokio::test]
+    async fn test_compact_shrinks_file_after_deletes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("library.db");
+        let pool = DatabasePool::new(&path.to_string_lossy()).unwrap();
+
+        {
+            let db = pool.get().await.unwrap();
+            let ids = db
+                .in_transaction(|db| {
+                    (0..500)
+                        .map(|i| {
+                            let mut file = sample_file(&format!("/tmp/compact/{i}"));
+                            file.description = Some("x".repeat(2000));
+                            db.add_file(file)
+                        })
+                        .collect::<Result<Vec<_>, _>>()
+                })
+                .unwrap();
+            for id in ids {
+                db.delete_file(id).unwrap();
+            }
+        }
+
+        let result = compact(&pool).await.unwrap();
+        assert!(
+            result.size_after < result.size_before,
+            "{} should be smaller than {}",
+            result.size_after,
+            result.size_before
+        );
+        assert_eq!(result.size_after, std::fs::metadata(&path).unwrap().len());
+
+        // 最適化の前にバックアップを作成している
+        assert_eq!(
+            std::fs::read_dir(dir.path().join("backups"))
+                .unwrap()
+                .count(),
+            1
+        );
+
+        // 最適化後もそのまま使える
+        let db = pool.get().await.unwrap();
+        assert!(db.get_all_files().unwrap().is_empty());
+    }
+}
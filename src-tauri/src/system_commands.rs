@@ -1,6 +1,15 @@
-use crate::config::app;
-use crate::AppError;
-use std::path::PathBuf;
+use crate::api_types::ApiError;
+use crate::booth_client::RetryPolicy;
+use crate::config::{app, booth, files};
+use crate::database::Database;
+use crate::{
+    default_output_base, render_folder_template, AppError, AppResult, AppState, ExtractionLimits,
+    SanitizeOptions,
+};
+use log::warn;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
 
 // Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
 #[tauri::command]
@@ -8,57 +17,333 @@ pub fn greet(name: &str) -> String {
     format!("Hello, {name}! You've been greeted from Rust!")
 }
 
-// 設定保存・読み込みコマンド
-#[tauri::command]
-pub async fn save_output_folder(output_folder: String) -> Result<(), String> {
-    let app_data_dir = dirs::data_dir()
+// 設定ファイル（アプリデータディレクトリの config.json）のパス
+fn config_path() -> PathBuf {
+    dirs::data_dir()
         .unwrap_or_else(|| dirs::home_dir().unwrap_or_else(|| PathBuf::from(".")))
-        .join(app::DATA_DIR_NAME);
+        .join(app::DATA_DIR_NAME)
+        .join(app::CONFIG_FILENAME)
+}
 
-    if !app_data_dir.exists() {
-        std::fs::create_dir_all(&app_data_dir).map_err(|e| {
-            AppError::custom(format!("Failed to create app data directory: {e}")).to_string()
-        })?;
+// 設定ファイルを読み込む（存在しない場合は空のオブジェクト）
+fn read_config() -> AppResult<serde_json::Value> {
+    read_config_file(&config_path())
+}
+
+fn read_config_file(config_path: &Path) -> AppResult<serde_json::Value> {
+    if !config_path.exists() {
+        return Ok(serde_json::json!({}));
     }
 
-    let config_path = app_data_dir.join("config.json");
-    let config = serde_json::json!({
-        "output_folder": output_folder
-    });
+    let config_content = std::fs::read_to_string(config_path)
+        .map_err(|e| AppError::custom(format!("Failed to read config: {e}")))?;
 
-    std::fs::write(&config_path, config.to_string())
-        .map_err(|e| AppError::custom(format!("Failed to save config: {e}")).to_string())?;
+    serde_json::from_str(&config_content)
+        .map_err(|e| AppError::custom(format!("Failed to parse config: {e}")))
+}
+
+// 指定したキーだけを書き換え、他の設定は保持する
+fn write_config_value(key: &str, value: serde_json::Value) -> AppResult<()> {
+    let mut values = serde_json::Map::new();
+    values.insert(key.to_string(), value);
+    merge_config_file(&config_path(), values)
+}
+
+fn merge_config_file(
+    config_path: &Path,
+    values: serde_json::Map<String, serde_json::Value>,
+) -> AppResult<()> {
+    if let Some(app_data_dir) = config_path.parent() {
+        if !app_data_dir.exists() {
+            std::fs::create_dir_all(app_data_dir).map_err(|e| {
+                AppError::custom(format!("Failed to create app data directory: {e}"))
+            })?;
+        }
+    }
+
+    let mut config = match read_config_file(config_path)? {
+        serde_json::Value::Object(map) => map,
+        _ => serde_json::Map::new(),
+    };
+    config.extend(values);
+
+    std::fs::write(config_path, serde_json::Value::Object(config).to_string())
+        .map_err(|e| AppError::custom(format!("Failed to save config: {e}")))?;
 
     Ok(())
 }
 
+fn read_config_string(key: &str) -> AppResult<Option<String>> {
+    Ok(read_config()?
+        .get(key)
+        .and_then(|v| v.as_str())
+        .map(|s| s.to_string()))
+}
+
+/// アプリ設定（config.json の内容を型付きで扱う）
+///
+/// 各項目は config.json の同名のキーに対応し、未設定・不正な値の項目は既定値（従来の動作）になる。
+/// BOOTHへの接続と受信フォルダ監視の設定はアプリの次回起動時に反映される。
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Settings {
+    pub output_folder: Option<String>,
+    pub folder_template: String,
+    pub sanitize_options: SanitizeOptions,
+    pub extraction_limits: ExtractionLimits,
+    pub proxy_url: Option<String>,
+    pub user_agent: Option<String>,
+    pub use_browser_user_agent: bool,
+    pub booth_retry: RetryPolicy,
+    pub inbox_watch: InboxWatchSettings,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            output_folder: None,
+            folder_template: files::DEFAULT_FOLDER_TEMPLATE.to_string(),
+            sanitize_options: SanitizeOptions::default(),
+            extraction_limits: ExtractionLimits::default(),
+            proxy_url: None,
+            user_agent: None,
+            use_browser_user_agent: false,
+            booth_retry: RetryPolicy::default(),
+            inbox_watch: InboxWatchSettings::default(),
+        }
+    }
+}
+
+impl Settings {
+    // 項目ごとに読み込み、1つの項目が不正でも他の項目は保持する
+    fn from_config(config: &serde_json::Value) -> Self {
+        fn field<T: DeserializeOwned>(config: &serde_json::Value, key: &str, default: T) -> T {
+            config
+                .get(key)
+                .cloned()
+                .and_then(|value| serde_json::from_value(value).ok())
+                .unwrap_or(default)
+        }
+
+        let defaults = Self::default();
+        Self {
+            output_folder: field(config, "output_folder", defaults.output_folder),
+            folder_template: field(config, "folder_template", defaults.folder_template),
+            sanitize_options: field(config, "sanitize_options", defaults.sanitize_options),
+            extraction_limits: field(config, "extraction_limits", defaults.extraction_limits),
+            proxy_url: field(config, "proxy_url", defaults.proxy_url),
+            user_agent: field(config, "user_agent", defaults.user_agent),
+            use_browser_user_agent: field(
+                config,
+                "use_browser_user_agent",
+                defaults.use_browser_user_agent,
+            ),
+            booth_retry: field(config, "booth_retry", defaults.booth_retry),
+            inbox_watch: field(config, "inbox_watch", defaults.inbox_watch),
+        }
+    }
+
+    fn to_config_map(&self) -> AppResult<serde_json::Map<String, serde_json::Value>> {
+        match serde_json::to_value(self) {
+            Ok(serde_json::Value::Object(map)) => Ok(map),
+            Ok(_) => Err(AppError::custom("Settings must serialize to an object")),
+            Err(e) => Err(AppError::custom(format!(
+                "Failed to serialize settings: {e}"
+            ))),
+        }
+    }
+
+    fn validate(&self) -> AppResult<()> {
+        validate_folder_template(&self.folder_template)?;
+        validate_inbox_watch_settings(&self.inbox_watch)
+    }
+}
+
+// 設定を読み込む（旧形式の設定ファイルは不足している項目を既定値で補って書き戻す）
+fn load_settings_from(config_path: &Path) -> AppResult<Settings> {
+    let config = read_config_file(config_path)?;
+    let settings = Settings::from_config(&config);
+
+    if config_path.exists() {
+        let missing: serde_json::Map<_, _> = settings
+            .to_config_map()?
+            .into_iter()
+            .filter(|(key, _)| config.get(key).is_none())
+            .collect();
+        if !missing.is_empty() {
+            merge_config_file(config_path, missing)?;
+        }
+    }
+    Ok(settings)
+}
+
+// 設定を保存する（Settings にないキーはそのまま残す）
+fn save_settings_to(config_path: &Path, settings: &Settings) -> AppResult<()> {
+    settings.validate()?;
+    merge_config_file(config_path, settings.to_config_map()?)
+}
+
 #[tauri::command]
-pub async fn load_output_folder() -> Result<Option<String>, String> {
-    let app_data_dir = dirs::data_dir()
-        .unwrap_or_else(|| dirs::home_dir().unwrap_or_else(|| PathBuf::from(".")))
-        .join(app::DATA_DIR_NAME);
+pub async fn load_settings() -> Result<Settings, ApiError> {
+    Ok(load_settings_from(&config_path())?)
+}
 
-    let config_path = app_data_dir.join("config.json");
+#[tauri::command]
+pub async fn save_settings(settings: Settings) -> Result<(), ApiError> {
+    Ok(save_settings_to(&config_path(), &settings)?)
+}
 
-    if !config_path.exists() {
-        return Ok(None);
+// 保存済みの設定（読み込み失敗時は既定値、設定ファイルは書き換えない）
+pub fn stored_settings() -> Settings {
+    read_config()
+        .map(|config| Settings::from_config(&config))
+        .unwrap_or_default()
+}
+
+// 設定保存・読み込みコマンド
+#[tauri::command]
+pub async fn save_output_folder(output_folder: String) -> Result<(), ApiError> {
+    Ok(write_config_value(
+        "output_folder",
+        serde_json::json!(output_folder),
+    )?)
+}
+
+#[tauri::command]
+pub async fn load_output_folder() -> Result<Option<String>, ApiError> {
+    Ok(read_config_string("output_folder")?)
+}
+
+// 出力先フォルダの命名テンプレート（例: "{shop}/{product}", "{shop} - {product} ({id})", "{date}/{shop}_{product_id}"）
+#[tauri::command]
+pub async fn save_folder_template(folder_template: String) -> Result<(), ApiError> {
+    validate_folder_template(&folder_template)?;
+
+    Ok(write_config_value(
+        "folder_template",
+        serde_json::json!(folder_template),
+    )?)
+}
+
+#[tauri::command]
+pub async fn load_folder_template() -> Result<String, ApiError> {
+    Ok(read_config_string("folder_template")?
+        .unwrap_or_else(|| files::DEFAULT_FOLDER_TEMPLATE.to_string()))
+}
+
+// 不正なテンプレートは保存前に弾く
+fn validate_folder_template(folder_template: &str) -> AppResult<()> {
+    render_folder_template(
+        folder_template,
+        "shop",
+        "product",
+        Some(1),
+        &SanitizeOptions::default(),
+    )?;
+    Ok(())
+}
+
+// 保存済みの出力先フォルダ（未設定・読み込み失敗時は None）
+pub fn stored_output_folder() -> Option<String> {
+    stored_settings().output_folder
+}
+
+// ZIP処理で使用するフォルダ名のサニタイズ設定（config.json の "sanitize_options"）
+// 未設定・不正な値の場合は従来どおりの既定値
+pub fn stored_sanitize_options() -> SanitizeOptions {
+    stored_settings().sanitize_options
+}
+
+// ZIP展開のサイズ上限（config.json の "extraction_limits"）
+// 未設定の項目・不正な値の場合は既定値
+pub fn stored_extraction_limits() -> ExtractionLimits {
+    stored_settings().extraction_limits
+}
+
+// BOOTHへのリクエストに使うプロキシ（config.json の "proxy_url"、起動時に読み込む）
+pub fn stored_proxy_url() -> Option<String> {
+    stored_settings()
+        .proxy_url
+        .filter(|url| !url.trim().is_empty())
+}
+
+// BOOTHへのリクエストに使う User-Agent（起動時に読み込む）
+// config.json の "use_browser_user_agent" が true ならブラウザ相当、次に "user_agent"、未設定なら None
+pub fn stored_user_agent() -> Option<String> {
+    let settings = stored_settings();
+    if settings.use_browser_user_agent {
+        return Some(booth::BROWSER_USER_AGENT.to_string());
     }
+    settings.user_agent.filter(|ua| !ua.trim().is_empty())
+}
 
-    let config_content = std::fs::read_to_string(&config_path)
-        .map_err(|e| AppError::custom(format!("Failed to read config: {e}")).to_string())?;
+// BOOTHへのリクエストの再試行設定（config.json の "booth_retry"、起動時に読み込む）
+pub fn stored_retry_policy() -> RetryPolicy {
+    stored_settings().booth_retry
+}
 
-    let config: serde_json::Value = serde_json::from_str(&config_content)
-        .map_err(|e| AppError::custom(format!("Failed to parse config: {e}")).to_string())?;
+// ZIP処理で使用する保存済みテンプレート（未設定・読み込み失敗時は既定値）
+pub fn stored_folder_template() -> Option<String> {
+    Some(stored_settings().folder_template)
+}
 
-    Ok(config
-        .get("output_folder")
-        .and_then(|v| v.as_str())
-        .map(|s| s.to_string()))
+/// 受信フォルダ監視の設定（config.json の "inbox_watch"）
+/// 変更はアプリの次回起動時に反映される
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InboxWatchSettings {
+    pub enabled: bool,
+    pub inbox_dir: Option<String>,
+}
+
+#[tauri::command]
+pub async fn save_inbox_watch_settings(settings: InboxWatchSettings) -> Result<(), ApiError> {
+    validate_inbox_watch_settings(&settings)?;
+
+    let value = serde_json::to_value(&settings)
+        .map_err(|e| AppError::custom(format!("Failed to serialize settings: {e}")))?;
+    Ok(write_config_value("inbox_watch", value)?)
+}
+
+#[tauri::command]
+pub async fn load_inbox_watch_settings() -> Result<InboxWatchSettings, ApiError> {
+    Ok(stored_inbox_watch_settings())
+}
+
+fn validate_inbox_watch_settings(settings: &InboxWatchSettings) -> AppResult<()> {
+    if settings.enabled {
+        let is_dir = settings
+            .inbox_dir
+            .as_deref()
+            .is_some_and(|dir| Path::new(dir).is_dir());
+        if !is_dir {
+            return Err(AppError::validation(
+                "inbox_dir",
+                "監視するフォルダが存在しません",
+            ));
+        }
+    }
+    Ok(())
+}
+
+// 保存済みの受信フォルダ監視設定（未設定・不正な値の場合は無効）
+pub fn stored_inbox_watch_settings() -> InboxWatchSettings {
+    stored_settings().inbox_watch
+}
+
+// 現在のログファイルのパス（「ログを開く」用）
+#[tauri::command]
+pub async fn get_log_path() -> Result<String, ApiError> {
+    Ok(crate::logging::log_file_path()
+        .to_string_lossy()
+        .to_string())
 }
 
 // フォルダを開くコマンド
 #[tauri::command]
-pub async fn open_folder(folder_path: String) -> Result<(), String> {
+pub async fn open_folder(
+    state: tauri::State<'_, AppState>,
+    folder_path: String,
+) -> Result<(), ApiError> {
     use std::process::Command;
 
     let path = std::path::Path::new(&folder_path);
@@ -75,7 +360,7 @@ pub async fn open_folder(folder_path: String) -> Result<(), String> {
         Command::new("explorer")
             .arg(folder_to_open)
             .spawn()
-            .map_err(|e| AppError::custom(format!("Failed to open folder: {e}")).to_string())?;
+            .map_err(|e| AppError::custom(format!("Failed to open folder: {e}")))?;
     }
 
     #[cfg(target_os = "macos")]
@@ -83,7 +368,7 @@ pub async fn open_folder(folder_path: String) -> Result<(), String> {
         Command::new("open")
             .arg(folder_to_open)
             .spawn()
-            .map_err(|e| AppError::custom(format!("Failed to open folder: {e}")).to_string())?;
+            .map_err(|e| AppError::custom(format!("Failed to open folder: {e}")))?;
     }
 
     #[cfg(target_os = "linux")]
@@ -91,8 +376,400 @@ pub async fn open_folder(folder_path: String) -> Result<(), String> {
         Command::new("xdg-open")
             .arg(folder_to_open)
             .spawn()
-            .map_err(|e| AppError::custom(format!("Failed to open folder: {e}")).to_string())?;
+            .map_err(|e| AppError::custom(format!("Failed to open folder: {e}")))?;
+    }
+
+    // 登録済みファイルのフォルダなら「最近開いた」に記録する（失敗しても開く操作は成功扱い）
+    match state.db.get().await {
+        Ok(db) => {
+            if let Err(e) = record_opened_path(&db, path) {
+                warn!("Failed to record opened folder: {e}");
+            }
+        }
+        Err(e) => warn!("Database lock error: {e}"),
+    }
+
+    Ok(())
+}
+
+// 開いたパスに対応するファイルの opened_at を更新し、記録できたか返す
+// 登録時と表記が異なるパス（"./" や "..", シンボリックリンク経由など）は正規化して照合する
+fn record_opened_path(db: &Database, path: &Path) -> rusqlite::Result<bool> {
+    if db.mark_file_opened_by_path(&path.to_string_lossy())? {
+        return Ok(true);
+    }
+
+    let (Ok(canonical), Some(name)) = (std::fs::canonicalize(path), path.file_name()) else {
+        return Ok(false);
+    };
+    let mut recorded = false;
+    for (id, file_path) in db.find_file_paths_containing(&name.to_string_lossy())? {
+        if std::fs::canonicalize(&file_path).is_ok_and(|p| p == canonical) {
+            recorded |= db.mark_file_opened(id)?;
+        }
     }
+    Ok(recorded)
+}
+
+// ファイルを選択した状態でエクスプローラー / Finder を開くコマンド
+// Linux では選択表示の手段が統一されていないため、親フォルダを開く
+#[tauri::command]
+pub async fn reveal_file(path: String) -> Result<(), ApiError> {
+    use std::process::Command;
+
+    let path = Path::new(&path);
+    if !path.exists() {
+        return Err(
+            AppError::validation("path", format!("File not found: {}", path.display())).into(),
+        );
+    }
+
+    let (program, args) = reveal_command(path);
+    let mut command = Command::new(program);
+    #[cfg(target_os = "windows")]
+    {
+        // explorer は "/select,<path>" 全体を引用符で囲むと解釈できないため、そのまま渡す
+        use std::os::windows::process::CommandExt;
+        for arg in &args {
+            command.raw_arg(arg);
+        }
+    }
+    #[cfg(not(target_os = "windows"))]
+    command.args(&args);
+
+    command
+        .spawn()
+        .map_err(|e| AppError::custom(format!("Failed to reveal file: {e}")))?;
+    Ok(())
+}
+
+// 選択表示に使うプログラムと引数（空白や日本語を含むパスもそのまま1つの引数にする）
+#[cfg(target_os = "windows")]
+fn reveal_command(path: &Path) -> (&'static str, Vec<std::ffi::OsString>) {
+    let mut select = std::ffi::OsString::from("/select,\"");
+    select.push(path.as_os_str());
+    select.push("\"");
+    ("explorer", vec![select])
+}
+
+#[cfg(target_os = "macos")]
+fn reveal_command(path: &Path) -> (&'static str, Vec<std::ffi::OsString>) {
+    ("open", vec!["-R".into(), path.as_os_str().to_owned()])
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+fn reveal_command(path: &Path) -> (&'static str, Vec<std::ffi::OsString>) {
+    let folder = if path.is_file() {
+        path.parent().unwrap_or(path)
+    } else {
+        path
+    };
+    ("xdg-open", vec![folder.as_os_str().to_owned()])
+}
+
+// 展開したファイルを既定のアプリで開くコマンド
+#[tauri::command]
+pub async fn open_file(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+    path: String,
+) -> Result<(), ApiError> {
+    use tauri_plugin_opener::OpenerExt;
 
+    // 出力先・受信フォルダと、登録済みファイルのフォルダ以外は開かない
+    let mut managed_roots = vec![stored_output_folder()
+        .map(PathBuf::from)
+        .unwrap_or_else(default_output_base)];
+    managed_roots.extend(stored_inbox_watch_settings().inbox_dir.map(PathBuf::from));
+
+    let file = {
+        let db = state
+            .db
+            .get()
+            .await
+            .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
+        resolve_openable_file(&db, Path::new(&path), &managed_roots)?
+    };
+    app.opener()
+        .open_path(file.to_string_lossy(), None::<&str>)
+        .map_err(|e| AppError::custom(format!("Failed to open file: {e}")))?;
     Ok(())
 }
+
+// 開いてよいファイルか検証し、正規化したパスを返す
+// 存在しない・ファイルでない・管理対象のフォルダの外にある場合は Validation エラー
+// 管理対象のフォルダの外でも、登録済みのファイル・フォルダ（ドライブ直下を除く）の中なら開ける
+fn resolve_openable_file(
+    db: &Database,
+    path: &Path,
+    managed_roots: &[PathBuf],
+) -> AppResult<PathBuf> {
+    let file = std::fs::canonicalize(path)
+        .map_err(|_| AppError::validation("path", format!("File not found: {}", path.display())))?;
+    if !file.is_file() {
+        return Err(AppError::validation(
+            "path",
+            format!("Not a file: {}", path.display()),
+        ));
+    }
+
+    let managed = managed_roots
+        .iter()
+        .filter_map(|root| std::fs::canonicalize(root).ok())
+        .any(|root| file.starts_with(root));
+    if !managed && !is_inside_registered_file(db, &file)? {
+        return Err(AppError::validation(
+            "path",
+            format!("File is outside the managed folders: {}", path.display()),
+        ));
+    }
+    Ok(file)
+}
+
+// 祖先のパスを1つずつ索引で照合する（ルートディレクトリの登録は許可の根拠にしない）
+fn is_inside_registered_file(db: &Database, file: &Path) -> AppResult<bool> {
+    for ancestor in file.ancestors().filter(|path| path.parent().is_some()) {
+        let registered = db
+            .get_file_by_path(&ancestor.to_string_lossy())
+            .map_err(|e| AppError::file_retrieval(format!("Failed to get file: {e}")))?;
+        if registered.is_some_and(|file| file.deleted_at.is_none()) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::sample_file;
+
+    #[cfg(target_os = "windows")]
+    #[test]
+    fn test_reveal_command_selects_file_in_explorer() {
+        let path = Path::new(r"C:\Booth Files\ショップ\アバター.unitypackage");
+        let (program, args) = reveal_command(path);
+        assert_eq!(program, "explorer");
+        assert_eq!(
+            args,
+            vec![std::ffi::OsString::from(
+                r#"/select,"C:\Booth Files\ショップ\アバター.unitypackage""#
+            )]
+        );
+    }
+
+    #[cfg(target_os = "macos")]
+    #[test]
+    fn test_reveal_command_selects_file_in_finder() {
+        let path = Path::new("/Users/me/Booth Files/ショップ/アバター.unitypackage");
+        let (program, args) = reveal_command(path);
+        assert_eq!(program, "open");
+        assert_eq!(args, vec!["-R".into(), path.as_os_str().to_owned()]);
+    }
+
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    #[test]
+    fn test_reveal_command_opens_parent_folder() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().join("Booth Files").join("ショップ");
+        std::fs::create_dir_all(&folder).unwrap();
+        let package = folder.join("アバター.unitypackage");
+        std::fs::write(&package, b"pkg").unwrap();
+
+        let (program, args) = reveal_command(&package);
+        assert_eq!(program, "xdg-open");
+        assert_eq!(args, vec![folder.as_os_str().to_owned()]);
+        // フォルダはそのまま開く
+        assert_eq!(reveal_command(&folder).1, vec![folder.into_os_string()]);
+    }
+
+    #[test]
+    fn test_resolve_openable_file_validates_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let managed = dir.path().join("output");
+        let outside = dir.path().join("elsewhere");
+        std::fs::create_dir_all(managed.join("Shop/Avatar")).unwrap();
+        std::fs::create_dir_all(&outside).unwrap();
+        let package = managed.join("Shop/Avatar/Avatar.unitypackage");
+        std::fs::write(&package, b"pkg").unwrap();
+        std::fs::write(outside.join("script.bat"), b"echo").unwrap();
+        let roots = [managed.clone()];
+        let db = Database::new(":memory:").unwrap();
+        // ルートディレクトリの登録があっても任意のパスは開けない
+        db.add_file(sample_file("/")).unwrap();
+
+        assert_eq!(
+            resolve_openable_file(&db, &package, &roots).unwrap(),
+            package.canonicalize().unwrap()
+        );
+
+        for path in [
+            managed.join("Shop/Avatar/missing.png"),
+            managed.join("Shop/Avatar"),
+            outside.join("script.bat"),
+            managed.join("..").join("elsewhere").join("script.bat"),
+        ] {
+            let err = resolve_openable_file(&db, &path, &roots).unwrap_err();
+            assert!(matches!(err, AppError::Validation { .. }), "{path:?}");
+        }
+
+        // 登録済みのフォルダの中は出力先の外でも開ける（ゴミ箱内の登録は除く）
+        let registered = outside.canonicalize().unwrap();
+        let id = db
+            .add_file(sample_file(&registered.to_string_lossy()))
+            .unwrap();
+        let script = outside.join("script.bat");
+        assert!(resolve_openable_file(&db, &script, &roots).is_ok());
+        db.soft_delete_file(id).unwrap();
+        assert!(resolve_openable_file(&db, &script, &roots).is_err());
+    }
+
+    #[test]
+    fn test_load_settings_without_file_returns_defaults() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("config.json");
+
+        assert_eq!(
+            load_settings_from(&config_path).unwrap(),
+            Settings::default()
+        );
+        // 読み込みだけでは設定ファイルを作成しない
+        assert!(!config_path.exists());
+        assert_eq!(
+            Settings::default().folder_template,
+            files::DEFAULT_FOLDER_TEMPLATE
+        );
+    }
+
+    #[test]
+    fn test_settings_round_trip_keeps_unknown_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("app").join("config.json");
+        write_test_config(&config_path, r#"{"future_option": 42}"#);
+
+        let settings = Settings {
+            output_folder: Some("/library".to_string()),
+            folder_template: "{shop} - {product} ({id})".to_string(),
+            extraction_limits: ExtractionLimits {
+                max_file_bytes: 1024,
+                ..ExtractionLimits::default()
+            },
+            proxy_url: Some("http://127.0.0.1:8080".to_string()),
+            use_browser_user_agent: true,
+            booth_retry: RetryPolicy {
+                max_retries: 5,
+                base_backoff_ms: 250,
+                max_backoff_ms: 5000,
+            },
+            ..Settings::default()
+        };
+        save_settings_to(&config_path, &settings).unwrap();
+
+        assert_eq!(load_settings_from(&config_path).unwrap(), settings);
+        let config = read_config_file(&config_path).unwrap();
+        assert_eq!(config["future_option"], 42);
+        assert_eq!(config["output_folder"], "/library");
+    }
+
+    #[test]
+    fn test_legacy_single_key_config_is_migrated() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("config.json");
+        write_test_config(&config_path, r#"{"output_folder": "/old/library"}"#);
+
+        let settings = load_settings_from(&config_path).unwrap();
+        assert_eq!(settings.output_folder.as_deref(), Some("/old/library"));
+        assert_eq!(settings.folder_template, files::DEFAULT_FOLDER_TEMPLATE);
+
+        // 不足していた項目が既定値で書き込まれる
+        let config = read_config_file(&config_path).unwrap();
+        assert_eq!(config["output_folder"], "/old/library");
+        assert_eq!(config["folder_template"], files::DEFAULT_FOLDER_TEMPLATE);
+        assert_eq!(config["inbox_watch"]["enabled"], false);
+    }
+
+    #[test]
+    fn test_invalid_setting_falls_back_without_losing_others() {
+        let config = serde_json::json!({
+            "output_folder": "/library",
+            "extraction_limits": "not an object",
+            "use_browser_user_agent": true,
+        });
+
+        let settings = Settings::from_config(&config);
+        assert_eq!(settings.output_folder.as_deref(), Some("/library"));
+        assert_eq!(settings.extraction_limits, ExtractionLimits::default());
+        assert!(settings.use_browser_user_agent);
+    }
+
+    #[test]
+    fn test_save_settings_rejects_invalid_template() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("config.json");
+        let settings = Settings {
+            folder_template: "{unknown}".to_string(),
+            ..Settings::default()
+        };
+
+        let err = save_settings_to(&config_path, &settings).unwrap_err();
+        assert!(matches!(err, AppError::Validation { .. }), "{err:?}");
+        assert!(!config_path.exists());
+    }
+
+    fn add_test_file(db: &Database, file_path: &Path) -> i64 {
+        db.add_file(crate::database::FileRecord {
+            file_name: "item".to_string(),
+            ..sample_file(&file_path.to_string_lossy())
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_record_opened_path_matches_canonicalized_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let shop = dir.path().join("Shop");
+        for name in ["Avatar", "Cloth", "Hair"] {
+            std::fs::create_dir_all(shop.join(name)).unwrap();
+        }
+        let db = Database::new(":memory:").unwrap();
+        let avatar = add_test_file(&db, &shop.join("Avatar"));
+        let cloth = add_test_file(&db, &shop.join("Cloth"));
+        add_test_file(&db, &shop.join("Hair"));
+
+        let open = |path: PathBuf| {
+            // opened_at はミリ秒単位のため、開いた順序が区別できるよう間隔をあける
+            std::thread::sleep(std::time::Duration::from_millis(5));
+            record_opened_path(&db, &path).unwrap()
+        };
+
+        assert!(open(shop.join("Cloth")));
+        assert!(open(shop.join("Avatar")));
+        // 表記の異なるパスでも同じフォルダなら記録される
+        assert!(open(shop.join(".").join("Cloth")));
+        assert!(!open(shop.join("Missing")));
+        assert!(!open(dir.path().to_path_buf()));
+        let opened_at = |id: i64| db.get_file_by_id(id).unwrap().unwrap().opened_at;
+        assert!(opened_at(cloth) > opened_at(avatar));
+
+        assert!(open(shop.join("Cloth").join("..").join("Avatar")));
+        assert!(opened_at(avatar) > opened_at(cloth));
+        let opened = |limit: u32| -> Vec<String> {
+            db.get_recently_opened(limit)
+                .unwrap()
+                .into_iter()
+                .map(|f| f.file.file_path)
+                .collect()
+        };
+        let path_of = |name: &str| shop.join(name).to_string_lossy().to_string();
+        assert_eq!(
+            opened(10),
+            vec![path_of("Avatar"), path_of("Cloth"), path_of("Hair")]
+        );
+        assert_eq!(opened(1), vec![path_of("Avatar")]);
+    }
+
+    fn write_test_config(config_path: &Path, content: &str) {
+        std::fs::create_dir_all(config_path.parent().unwrap()).unwrap();
+        std::fs::write(config_path, content).unwrap();
+    }
+}
@@ -1,26 +1,35 @@
-use crate::database::Tag;
-use crate::{AppError, AppState};
+use crate::api_types::{ApiError, GetTagsPaginatedRequest, PaginationResponse, TagUsage};
+use crate::config::tags;
+use crate::database::{Database, Tag, TagUpdateFields};
+use crate::tag_validator::{normalize_tag_name, validate_color};
+use crate::{AppError, AppResult, AppState};
+use serde::{Deserialize, Serialize};
+
+/// バッチでのタグ付け結果（ファイルごとの成否）
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BatchTagResult {
+    pub succeeded: Vec<i64>,
+    /// 関連付けできなかったファイルIDと理由
+    pub failed: Vec<(i64, String)>,
+}
 
 #[tauri::command]
 pub async fn add_tag_to_file_db(
     state: tauri::State<'_, AppState>,
     file_id: i64,
     tag_name: String,
-    _tag_color: Option<String>,
-) -> Result<(), String> {
+    tag_color: Option<String>,
+) -> Result<(), ApiError> {
     let db = state
         .db
-        .lock()
-        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")).to_string())?;
+        .get()
+        .await
+        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
 
-    // タグを取得または作成
-    let tag_id = db
-        .add_tag(&tag_name)
-        .map_err(|e| AppError::tag_creation(format!("Failed to add tag: {e}")).to_string())?;
+    let tag_id = get_or_create_tag(&db, &tag_name, tag_color.as_deref())?;
 
-    db.add_file_tag(file_id, tag_id).map_err(|e| {
-        AppError::tag_operation(format!("Failed to add tag to file: {e}")).to_string()
-    })?;
+    db.add_file_tag(file_id, tag_id)
+        .map_err(|e| AppError::tag_operation(format!("Failed to add tag to file: {e}")))?;
 
     Ok(())
 }
@@ -30,54 +39,157 @@ pub async fn remove_tag_from_file_db(
     state: tauri::State<'_, AppState>,
     _file_id: i64,
     tag_name: String,
-) -> Result<(), String> {
+) -> Result<(), ApiError> {
     let db = state
         .db
-        .lock()
-        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")).to_string())?;
+        .get()
+        .await
+        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
 
     // タグ名からIDを取得して削除を実行
     let all_tags = db
         .get_all_tags()
-        .map_err(|e| AppError::tag_operation(format!("Failed to get tags: {e}")).to_string())?;
+        .map_err(|e| AppError::tag_operation(format!("Failed to get tags: {e}")))?;
 
     if let Some(tag) = all_tags.iter().find(|t| t.name == tag_name) {
         if let Some(_tag_id) = tag.id {
             // 直接的な削除メソッドがないため、手動で実装する必要があります
             // ここでは簡単なエラーメッセージを返します
-            return Err("Tag removal not implemented in new schema".to_string());
+            return Err("Tag removal not implemented in new schema"
+                .to_string()
+                .into());
         }
     }
     Ok(())
 }
 
 #[tauri::command]
-pub async fn get_all_tags_from_db(state: tauri::State<'_, AppState>) -> Result<Vec<Tag>, String> {
+pub async fn get_all_tags_from_db(state: tauri::State<'_, AppState>) -> Result<Vec<Tag>, ApiError> {
     let db = state
         .db
-        .lock()
-        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")).to_string())?;
+        .get()
+        .await
+        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
 
-    let tags = db.get_all_tags().map_err(|e| {
-        AppError::file_retrieval(format!("Failed to get tags from database: {e}")).to_string()
-    })?;
+    let tags = db
+        .get_all_tags()
+        .map_err(|e| AppError::file_retrieval(format!("Failed to get tags from database: {e}")))?;
 
     Ok(tags)
 }
 
+#[tauri::command]
+pub async fn get_tags_paginated_db(
+    state: tauri::State<'_, AppState>,
+    request: GetTagsPaginatedRequest,
+) -> Result<PaginationResponse<Tag>, ApiError> {
+    let pagination = request.pagination.clamped();
+    let db = state
+        .db
+        .get()
+        .await
+        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
+
+    let (items, total_count) = db.get_tags_page(&pagination).map_err(|e| match e {
+        AppError::Database(e) => {
+            AppError::file_retrieval(format!("Failed to get tags from database: {e}"))
+        }
+        e => e,
+    })?;
+
+    Ok(PaginationResponse::new(items, total_count, &pagination))
+}
+
 #[tauri::command]
 pub async fn get_tags_for_file_db(
     state: tauri::State<'_, AppState>,
     file_id: i64,
-) -> Result<Vec<Tag>, String> {
+) -> Result<Vec<Tag>, ApiError> {
     let db = state
         .db
-        .lock()
-        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")).to_string())?;
+        .get()
+        .await
+        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
 
-    db.get_tags_for_file(file_id).map_err(|e| {
-        AppError::tag_operation(format!("Failed to get tags for file: {e}")).to_string()
-    })
+    db.get_tags_for_file(file_id)
+        .map_err(|e| AppError::tag_operation(format!("Failed to get tags for file: {e}")).into())
+}
+
+// 同じショップ・共通のタグを持つファイルに多く付いているタグを提案する
+#[tauri::command]
+pub async fn suggest_tags_for_file(
+    state: tauri::State<'_, AppState>,
+    file_id: i64,
+    limit: Option<u32>,
+) -> Result<Vec<TagUsage>, ApiError> {
+    let db = state
+        .db
+        .get()
+        .await
+        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
+
+    db.suggest_tags_for_file(file_id, limit.unwrap_or(tags::SUGGESTED_TAGS))
+        .map_err(|e| AppError::tag_operation(format!("Failed to suggest tags: {e}")).into())
+}
+
+#[tauri::command]
+pub async fn update_tag_db(
+    state: tauri::State<'_, AppState>,
+    tag_id: i64,
+    color: Option<String>,
+    category: Option<String>,
+    parent_tag_id: Option<i64>,
+) -> Result<Tag, ApiError> {
+    let db = state
+        .db
+        .get()
+        .await
+        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
+
+    let fields = TagUpdateFields {
+        color,
+        category,
+        parent_tag_id,
+    };
+    Ok(update_tag(&db, tag_id, &fields)?)
+}
+
+// 色の形式と親子関係の循環を検証してからタグを更新し、更新後のタグを返す
+fn update_tag(db: &Database, tag_id: i64, fields: &TagUpdateFields) -> AppResult<Tag> {
+    if let Some(color) = &fields.color {
+        validate_color(color)?;
+    }
+
+    let find_tag = |id: i64| {
+        db.get_tag_by_id(id)
+            .map_err(|e| AppError::tag_operation(format!("Failed to get tag: {e}")))
+    };
+    if find_tag(tag_id)?.is_none() {
+        return Err(AppError::tag_operation(format!("Tag not found: {tag_id}")));
+    }
+
+    if let Some(parent_id) = fields.parent_tag_id {
+        if find_tag(parent_id)?.is_none() {
+            return Err(AppError::validation(
+                "parent_tag_id",
+                format!("Parent tag not found: {parent_id}"),
+            ));
+        }
+        // 親（またはその祖先）が自身になる設定は循環になる
+        let ancestors = db
+            .get_tag_ancestor_ids(parent_id)
+            .map_err(|e| AppError::tag_operation(format!("Failed to get parent tags: {e}")))?;
+        if parent_id == tag_id || ancestors.contains(&tag_id) {
+            return Err(AppError::validation(
+                "parent_tag_id",
+                "A tag cannot be its own ancestor",
+            ));
+        }
+    }
+
+    db.update_tag(tag_id, fields)
+        .map_err(|e| AppError::tag_operation(format!("Failed to update tag: {e}")))?;
+    find_tag(tag_id)?.ok_or_else(|| AppError::tag_operation(format!("Tag not found: {tag_id}")))
 }
 
 #[tauri::command]
@@ -85,22 +197,60 @@ pub async fn batch_add_tag_to_files_db(
     state: tauri::State<'_, AppState>,
     file_ids: Vec<i64>,
     tag_name: String,
-    _tag_color: Option<String>,
-) -> Result<(), String> {
+    tag_color: Option<String>,
+) -> Result<BatchTagResult, ApiError> {
     let db = state
         .db
-        .lock()
-        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")).to_string())?;
+        .get()
+        .await
+        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
 
-    // バッチでタグを追加（新しいスキーマでは個別実行）
-    let tag_id = db
-        .add_tag(&tag_name)
-        .map_err(|e| AppError::tag_creation(format!("Failed to add tag: {e}")).to_string())?;
+    Ok(batch_add_tag(
+        &db,
+        &file_ids,
+        &tag_name,
+        tag_color.as_deref(),
+    )?)
+}
 
-    for file_id in file_ids {
-        let _ = db.add_file_tag(file_id, tag_id);
+// タグを取得または作成する（名前は正規化し、色は新規作成時のみ使用して未指定なら既定の色）
+fn get_or_create_tag(db: &Database, tag_name: &str, color: Option<&str>) -> AppResult<i64> {
+    let tag_name = normalize_tag_name(tag_name)?;
+    if let Some(color) = color {
+        validate_color(color)?;
     }
-    Ok(())
+    db.add_tag_with_color(&tag_name, color.unwrap_or(tags::DEFAULT_TAG_COLOR))
+        .map_err(|e| AppError::tag_creation(format!("Failed to add tag: {e}")))
+}
+
+// 1つのトランザクション内でファイルごとにタグを付け、失敗したファイルは理由とともに記録する
+// 存在しない・ゴミ箱内のファイルは失敗扱い（他のファイルの処理は続ける）
+fn batch_add_tag(
+    db: &Database,
+    file_ids: &[i64],
+    tag_name: &str,
+    color: Option<&str>,
+) -> AppResult<BatchTagResult> {
+    db.in_transaction(|db| {
+        let tag_id = get_or_create_tag(db, tag_name, color)?;
+
+        let mut result = BatchTagResult::default();
+        for &file_id in file_ids {
+            let outcome = match db.get_file_by_id(file_id) {
+                Ok(Some(file)) if file.deleted_at.is_none() => db
+                    .add_file_tag(file_id, tag_id)
+                    .map_err(|e| format!("Failed to add tag to file: {e}")),
+                Ok(_) => Err(format!("File not found: {file_id}")),
+                Err(e) => Err(format!("Failed to get file: {e}")),
+            };
+
+            match outcome {
+                Ok(()) => result.succeeded.push(file_id),
+                Err(message) => result.failed.push((file_id, message)),
+            }
+        }
+        Ok(result)
+    })
 }
 
 #[tauri::command]
@@ -108,13 +258,250 @@ pub async fn batch_remove_tag_from_files_db(
     state: tauri::State<'_, AppState>,
     _file_ids: Vec<i64>,
     _tag_id: i64,
-) -> Result<(), String> {
+) -> Result<(), ApiError> {
     let _db = state
         .db
-        .lock()
-        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")).to_string())?;
+        .get()
+        .await
+        .map_err(|e| AppError::database_lock(format!("Database lock error: {e}")))?;
 
     // バッチでタグを削除（新しいスキーマでは直接的な方法がない）
     // ここでは簡単なエラーメッセージを返します
-    Err("Batch tag removal not implemented in new schema".to_string())
+    Err("Batch tag removal not implemented in new schema"
+        .to_string()
+        .into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::sample_file;
+
+    #[test]
+    fn test_get_tags_paginated_boundaries() {
+        let db = Database::new(":memory:").unwrap();
+        for i in 0..12 {
+            db.add_tag(&format!("tag_{i:02}")).unwrap();
+        }
+        let popular = db.add_file(sample_file("/tmp/popular")).unwrap();
+        db.add_file_tag(popular, db.add_tag("tag_11").unwrap())
+            .unwrap();
+
+        let fetch = |page: u32, page_size: u32| {
+            let pagination = crate::api_types::PaginationRequest {
+                page,
+                page_size,
+                sort_by: None,
+                sort_order: None,
+                sort: Vec::new(),
+            }
+            .clamped();
+            let (items, total) = db.get_tags_page(&pagination).unwrap();
+            PaginationResponse::new(items, total, &pagination)
+        };
+
+        let first = fetch(1, 5);
+        assert_eq!(first.total_count, 12);
+        assert_eq!(first.total_pages, 3);
+        assert!(first.has_next_page && !first.has_prev_page);
+        // 既定では使用回数の多いタグが先頭
+        assert_eq!(first.items[0].name, "tag_11");
+        assert_eq!(first.items[1].name, "tag_00");
+
+        let last = fetch(3, 5);
+        assert_eq!(last.items.len(), 2);
+        assert!(!last.has_next_page && last.has_prev_page);
+        assert_eq!(last.items[1].name, "tag_10");
+
+        // ページ番号0は1ページ目として扱う
+        assert_eq!(fetch(0, 5).page, 1);
+        assert!(fetch(4, 5).items.is_empty());
+    }
+
+    #[test]
+    fn test_update_tag_sets_color_category_and_parent() {
+        let db = Database::new(":memory:").unwrap();
+        let parent = db.add_tag("3Dモデル").unwrap();
+        let child = db.add_tag("3D衣装").unwrap();
+        assert_eq!(
+            db.get_tag_by_id(child).unwrap().unwrap().color,
+            crate::config::tags::DEFAULT_TAG_COLOR
+        );
+
+        let fields = TagUpdateFields {
+            color: Some("#FF8800".to_string()),
+            category: Some("種類".to_string()),
+            parent_tag_id: Some(parent),
+        };
+        let updated = update_tag(&db, child, &fields).unwrap();
+        assert_eq!(updated.color, "#FF8800");
+        assert_eq!(updated.category.as_deref(), Some("種類"));
+        assert_eq!(updated.parent_tag_id, Some(parent));
+
+        // 指定しなかった項目は変更せず、空文字列のカテゴリは解除する
+        let fields = TagUpdateFields {
+            category: Some(String::new()),
+            ..Default::default()
+        };
+        let updated = update_tag(&db, child, &fields).unwrap();
+        assert_eq!(updated.color, "#FF8800");
+        assert_eq!(updated.category, None);
+        assert_eq!(updated.parent_tag_id, Some(parent));
+    }
+
+    #[test]
+    fn test_update_tag_rejects_invalid_color() {
+        let db = Database::new(":memory:").unwrap();
+        let tag_id = db.add_tag("VRChat").unwrap();
+
+        for color in ["red", "#GG0000", "007ACC", "#12345", "#"] {
+            let fields = TagUpdateFields {
+                color: Some(color.to_string()),
+                ..Default::default()
+            };
+            let err = update_tag(&db, tag_id, &fields).unwrap_err();
+            assert!(
+                matches!(err, AppError::Validation { .. }),
+                "{color:?} -> {err:?}"
+            );
+        }
+        assert_eq!(
+            db.get_tag_by_id(tag_id).unwrap().unwrap().color,
+            crate::config::tags::DEFAULT_TAG_COLOR
+        );
+    }
+
+    #[test]
+    fn test_validate_color_accepts_short_and_long_hex() {
+        for color in ["#FFF", "#fff8", "#007ACC", "#007acc80"] {
+            assert!(validate_color(color).is_ok(), "{color:?}");
+        }
+        for color in [
+            "",
+            "FFF",
+            "#FF",
+            "#FFFFF",
+            "#GGGGGG",
+            "#007ACC0",
+            "#ＦＦＦ",
+            "blue",
+        ] {
+            assert!(
+                matches!(validate_color(color), Err(AppError::Validation { .. })),
+                "{color:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_tag_creation_validates_color_and_falls_back_to_default() {
+        let db = Database::new(":memory:").unwrap();
+        let file_id = db.add_file(sample_file("/tmp/item")).unwrap();
+        let color_of = |name: &str| {
+            let id = db.add_tag(name).unwrap();
+            db.get_tag_by_id(id).unwrap().unwrap().color
+        };
+
+        get_or_create_tag(&db, "Default", None).unwrap();
+        assert_eq!(color_of("Default"), tags::DEFAULT_TAG_COLOR);
+
+        get_or_create_tag(&db, "Orange", Some("#F80")).unwrap();
+        assert_eq!(color_of("Orange"), "#F80");
+        // 既存タグの色は変更しない
+        get_or_create_tag(&db, "Orange", Some("#000000")).unwrap();
+        assert_eq!(color_of("Orange"), "#F80");
+
+        let err = batch_add_tag(&db, &[file_id], "Broken", Some("not-a-color")).unwrap_err();
+        assert!(matches!(err, AppError::Validation { .. }));
+        assert!(db
+            .get_all_tags()
+            .unwrap()
+            .iter()
+            .all(|t| t.name != "Broken"));
+        assert!(db.get_tags_for_file(file_id).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_tag_names_are_normalized_and_validated() {
+        let db = Database::new(":memory:").unwrap();
+        let file_id = db.add_file(sample_file("/tmp/item")).unwrap();
+
+        let id = get_or_create_tag(&db, "  3D   衣装 ", None).unwrap();
+        assert_eq!(db.get_tag_by_id(id).unwrap().unwrap().name, "3D 衣装");
+        assert_eq!(get_or_create_tag(&db, "3D 衣装", None).unwrap(), id);
+
+        // 全角文字も1文字として数える
+        let max_length = "あ".repeat(tags::MAX_TAG_LENGTH);
+        assert!(get_or_create_tag(&db, &max_length, None).is_ok());
+
+        for invalid in [
+            "a".repeat(tags::MAX_TAG_LENGTH + 1),
+            "   ".to_string(),
+            "\u{3000}".to_string(),
+            "VR\nChat".to_string(),
+            "tab\there".to_string(),
+        ] {
+            let err = get_or_create_tag(&db, &invalid, None).unwrap_err();
+            assert!(
+                matches!(err, AppError::Validation { .. }),
+                "{invalid:?} -> {err:?}"
+            );
+        }
+
+        let err = batch_add_tag(&db, &[file_id], "VR\nChat", None).unwrap_err();
+        assert!(err.to_string().contains("control characters"), "{err}");
+        assert_eq!(db.get_all_tags().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_update_tag_rejects_parent_cycles() {
+        let db = Database::new(":memory:").unwrap();
+        let (a, b, c) = (
+            db.add_tag("A").unwrap(),
+            db.add_tag("B").unwrap(),
+            db.add_tag("C").unwrap(),
+        );
+        let set_parent = |tag_id: i64, parent_id: i64| {
+            let fields = TagUpdateFields {
+                parent_tag_id: Some(parent_id),
+                ..Default::default()
+            };
+            update_tag(&db, tag_id, &fields)
+        };
+
+        assert!(matches!(
+            set_parent(a, a).unwrap_err(),
+            AppError::Validation { .. }
+        ));
+
+        // A <- B <- C の後に A の親を C にすると循環する
+        set_parent(b, a).unwrap();
+        set_parent(c, b).unwrap();
+        assert!(matches!(
+            set_parent(a, c).unwrap_err(),
+            AppError::Validation { .. }
+        ));
+        assert_eq!(db.get_tag_by_id(a).unwrap().unwrap().parent_tag_id, None);
+    }
+
+    #[test]
+    fn test_batch_add_tag_reports_per_file_results() {
+        let db = Database::new(":memory:").unwrap();
+        let valid = db.add_file(sample_file("/tmp/valid")).unwrap();
+        let trashed = db.add_file(sample_file("/tmp/trashed")).unwrap();
+        db.soft_delete_file(trashed).unwrap();
+        let missing = valid + 100;
+
+        let result = batch_add_tag(&db, &[valid, missing, trashed], "VRChat", None).unwrap();
+
+        assert_eq!(result.succeeded, vec![valid]);
+        let failed_ids: Vec<i64> = result.failed.iter().map(|(id, _)| *id).collect();
+        assert_eq!(failed_ids, vec![missing, trashed]);
+        assert!(result.failed[0].1.contains("not found"));
+
+        let tags = db.get_tags_for_file(valid).unwrap();
+        assert_eq!(tags.len(), 1);
+        assert_eq!(tags[0].name, "VRChat");
+        assert_eq!(tags[0].usage_count, 1);
+    }
 }
@@ -0,0 +1,301 @@
+// タグの論理式検索 - "VRChat AND (Avatar OR Cloth) AND NOT WIP" のような式を解析してSQL条件に変換する
+//
+// 演算子は大文字の AND / OR / NOT（優先順位は NOT > AND > OR）。
+// 空白や括弧を含むタグ名はダブルクォートで囲む（例: "Cloth (Summer)"）。
+use crate::config::tags;
+use crate::{AppError, AppResult};
+use rusqlite::types::Value;
+
+/// タグ条件の構文木
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TagExpr {
+    Tag(String),
+    And(Box<TagExpr>, Box<TagExpr>),
+    Or(Box<TagExpr>, Box<TagExpr>),
+    Not(Box<TagExpr>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Tag(String),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+/// 式を構文木に変換する（構文エラーは Validation エラー）
+pub fn parse(input: &str) -> AppResult<TagExpr> {
+    let tokens = tokenize(input)?;
+    let mut parser = Parser {
+        tokens,
+        pos: 0,
+        depth: 0,
+    };
+
+    let expr = parser.parse_or()?;
+    match parser.peek() {
+        None => Ok(expr),
+        Some(Token::Close) => Err(syntax_error("対応する '(' がない ')' があります")),
+        Some(_) => Err(syntax_error("タグ同士は AND / OR でつないでください")),
+    }
+}
+
+impl TagExpr {
+    /// files テーブルの別名 f に対する WHERE 条件を組み立てる（タグ名は params に追加）
+    ///
+    /// 存在しないタグは単に一致しないため、常に偽として扱われる。
+    pub fn to_sql(&self, params: &mut Vec<Value>) -> String {
+        match self {
+            TagExpr::Tag(name) => {
+                params.push(Value::Text(name.clone()));
+                format!(
+                    "EXISTS (SELECT 1 FROM file_tags ft JOIN tags t ON t.id = ft.tag_id
+                             WHERE ft.file_id = f.id AND t.name = ?{})",
+                    params.len()
+                )
+            }
+            TagExpr::And(lhs, rhs) => {
+                format!("({} AND {})", lhs.to_sql(params), rhs.to_sql(params))
+            }
+            TagExpr::Or(lhs, rhs) => {
+                format!("({} OR {})", lhs.to_sql(params), rhs.to_sql(params))
+            }
+            TagExpr::Not(inner) => format!("(NOT {})", inner.to_sql(params)),
+        }
+    }
+}
+
+fn tokenize(input: &str) -> AppResult<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' => {
+                chars.next();
+                tokens.push(Token::Open);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::Close);
+            }
+            '"' => {
+                chars.next();
+                let mut name = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => name.push(c),
+                        None => return Err(syntax_error("ダブルクォートが閉じられていません")),
+                    }
+                }
+                if name.trim().is_empty() {
+                    return Err(syntax_error("空のタグ名は指定できません"));
+                }
+                tokens.push(Token::Tag(name.trim().to_string()));
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || matches!(c, '(' | ')' | '"') {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                tokens.push(match word.as_str() {
+                    "AND" => Token::And,
+                    "OR" => Token::Or,
+                    "NOT" => Token::Not,
+                    _ => Token::Tag(word),
+                });
+            }
+        }
+    }
+
+    if tokens.is_empty() {
+        return Err(syntax_error("検索式が空です"));
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    // 現在の括弧・NOT の入れ子の深さ（深すぎる式でスタックを使い切らないよう制限する）
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    // or := and ("OR" and)*
+    fn parse_or(&mut self) -> AppResult<TagExpr> {
+        let mut expr = self.parse_and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.next();
+            expr = TagExpr::Or(Box::new(expr), Box::new(self.parse_and()?));
+        }
+        Ok(expr)
+    }
+
+    // and := unary ("AND" unary)*
+    fn parse_and(&mut self) -> AppResult<TagExpr> {
+        let mut expr = self.parse_unary()?;
+        while self.peek() == Some(&Token::And) {
+            self.next();
+            expr = TagExpr::And(Box::new(expr), Box::new(self.parse_unary()?));
+        }
+        Ok(expr)
+    }
+
+    // 入れ子を1段深くして解析する
+    fn nested(&mut self, parse: fn(&mut Self) -> AppResult<TagExpr>) -> AppResult<TagExpr> {
+        if self.depth >= tags::MAX_QUERY_NESTING {
+            return Err(syntax_error(&format!(
+                "括弧・NOT の入れ子は {} 段までです",
+                tags::MAX_QUERY_NESTING
+            )));
+        }
+        self.depth += 1;
+        let expr = parse(self);
+        self.depth -= 1;
+        expr
+    }
+
+    // unary := "NOT" unary | "(" or ")" | tag
+    fn parse_unary(&mut self) -> AppResult<TagExpr> {
+        match self.next() {
+            Some(Token::Not) => Ok(TagExpr::Not(Box::new(self.nested(Self::parse_unary)?))),
+            Some(Token::Open) => {
+                let expr = self.nested(Self::parse_or)?;
+                match self.next() {
+                    Some(Token::Close) => Ok(expr),
+                    _ => Err(syntax_error("')' が閉じられていません")),
+                }
+            }
+            Some(Token::Tag(name)) => Ok(TagExpr::Tag(name)),
+            Some(Token::And) | Some(Token::Or) => {
+                Err(syntax_error("AND / OR の前にタグがありません"))
+            }
+            Some(Token::Close) => Err(syntax_error("括弧の中にタグがありません")),
+            None => Err(syntax_error("式の途中で終わっています")),
+        }
+    }
+}
+
+fn syntax_error(message: &str) -> AppError {
+    AppError::validation("expr", message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tag(name: &str) -> Box<TagExpr> {
+        Box::new(TagExpr::Tag(name.to_string()))
+    }
+
+    #[test]
+    fn test_and_binds_tighter_than_or() {
+        assert_eq!(
+            parse("A OR B AND C").unwrap(),
+            TagExpr::Or(tag("A"), Box::new(TagExpr::And(tag("B"), tag("C"))))
+        );
+        assert_eq!(
+            parse("A AND B OR C").unwrap(),
+            TagExpr::Or(Box::new(TagExpr::And(tag("A"), tag("B"))), tag("C"))
+        );
+    }
+
+    #[test]
+    fn test_not_binds_tighter_than_and() {
+        assert_eq!(
+            parse("NOT A AND B").unwrap(),
+            TagExpr::And(Box::new(TagExpr::Not(tag("A"))), tag("B"))
+        );
+        assert_eq!(
+            parse("NOT NOT A").unwrap(),
+            TagExpr::Not(Box::new(TagExpr::Not(tag("A"))))
+        );
+    }
+
+    #[test]
+    fn test_parentheses_and_quoted_names() {
+        assert_eq!(
+            parse(r#"VRChat AND ("3D衣装" OR "Cloth (Summer)") AND NOT WIP"#).unwrap(),
+            TagExpr::And(
+                Box::new(TagExpr::And(
+                    tag("VRChat"),
+                    Box::new(TagExpr::Or(tag("3D衣装"), tag("Cloth (Summer)")))
+                )),
+                Box::new(TagExpr::Not(tag("WIP")))
+            )
+        );
+        // 小文字の and はタグ名として扱う
+        assert_eq!(parse("and").unwrap(), *tag("and"));
+    }
+
+    #[test]
+    fn test_syntax_errors_are_validation_errors() {
+        for input in [
+            "",
+            "   ",
+            "A AND",
+            "OR A",
+            "(A OR B",
+            "A OR B)",
+            "A B",
+            "()",
+            "\"unterminated",
+            "NOT",
+        ] {
+            let err = parse(input).unwrap_err();
+            assert!(
+                matches!(err, AppError::Validation { .. }),
+                "{input:?} -> {err:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_deep_nesting_is_rejected_without_overflow() {
+        let limit = tags::MAX_QUERY_NESTING;
+        let parens = |depth: usize| format!("{}A{}", "(".repeat(depth), ")".repeat(depth));
+        let nots = |depth: usize| format!("{}A", "NOT ".repeat(depth));
+
+        assert!(parse(&parens(limit)).is_ok());
+        assert!(parse(&nots(limit)).is_ok());
+
+        // 上限を超える入れ子や、スタックを使い切るほどの入れ子は構文エラーになる
+        for input in [parens(limit + 1), nots(limit + 1), "(".repeat(100_000)] {
+            let err = parse(&input).unwrap_err();
+            assert!(matches!(err, AppError::Validation { .. }), "{err:?}");
+        }
+    }
+
+    #[test]
+    fn test_to_sql_numbers_params_in_order() {
+        let mut params = Vec::new();
+        let sql = parse("A AND NOT B").unwrap().to_sql(&mut params);
+        assert_eq!(
+            params,
+            vec![Value::Text("A".to_string()), Value::Text("B".to_string())]
+        );
+        assert!(sql.contains("?1") && sql.contains("(NOT EXISTS"));
+        assert!(sql.find("?1") < sql.find("?2"));
+    }
+}
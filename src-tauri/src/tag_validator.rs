@@ -1,11 +1,53 @@
 // タグバリデーション機能のモジュール
-// 現在は未使用ですが、将来の拡張のため基本構造を保持
 
 use crate::config::tags;
+use crate::{AppError, AppResult};
 
-/// メインのバリデーション関数（将来の実装用にスタブとして保持）
-#[allow(dead_code)]
-pub fn is_valid_tag(tag_text: &str) -> bool {
-    // 基本的な検証のみ実装（現在は未使用）
-    !tag_text.trim().is_empty() && tag_text.trim().len() <= tags::MAX_TAG_LENGTH
+/// タグ名を検証し、正規化した名前を返す
+///
+/// 前後の空白を除去して内部の連続する空白を1つにまとめる。
+/// 空の名前、改行などの制御文字を含む名前、MAX_TAG_LENGTH 文字を超える名前はエラー。
+pub fn normalize_tag_name(tag_text: &str) -> AppResult<String> {
+    let trimmed = tag_text.trim();
+    if trimmed.chars().any(char::is_control) {
+        return Err(AppError::validation(
+            "tag_name",
+            "Tag name must not contain line breaks or control characters",
+        ));
+    }
+
+    let normalized = trimmed.split_whitespace().collect::<Vec<_>>().join(" ");
+    if normalized.is_empty() {
+        return Err(AppError::validation(
+            "tag_name",
+            "Tag name must not be empty",
+        ));
+    }
+
+    let length = normalized.chars().count();
+    if length > tags::MAX_TAG_LENGTH {
+        return Err(AppError::validation(
+            "tag_name",
+            format!(
+                "Tag name must be at most {} characters ({length} given)",
+                tags::MAX_TAG_LENGTH
+            ),
+        ));
+    }
+    Ok(normalized)
+}
+
+/// タグの色が16進カラーコード（#RGB / #RGBA / #RRGGBB / #RRGGBBAA）か検証する
+pub fn validate_color(color: &str) -> AppResult<()> {
+    let valid = color.strip_prefix('#').is_some_and(|hex| {
+        matches!(hex.len(), 3 | 4 | 6 | 8) && hex.chars().all(|c| c.is_ascii_hexdigit())
+    });
+    if valid {
+        Ok(())
+    } else {
+        Err(AppError::validation(
+            "color",
+            format!("Color must be a hex string like #RRGGBB: {color}"),
+        ))
+    }
 }
@@ -0,0 +1,47 @@
+// 複数のモジュールのテストで使う共通のフィクスチャ
+
+use crate::database::{Database, FileRecord};
+use std::io::Write;
+use std::path::Path;
+use zip::write::SimpleFileOptions;
+
+// パスだけを指定した登録用のファイル（ほかの項目は構造体更新構文で上書きする）
+pub(crate) fn sample_file(path: &str) -> FileRecord {
+    FileRecord {
+        file_path: path.to_string(),
+        file_name: path.to_string(),
+        file_size: 1,
+        ..FileRecord::default()
+    }
+}
+
+// 「VRChat」「有料」の両方、片方ずつ、タグなしの4ファイル
+pub(crate) fn tagged_db() -> (Database, i64, i64, i64) {
+    let db = Database::new(":memory:").unwrap();
+    let both = db.add_file(sample_file("/both")).unwrap();
+    let vrchat_only = db.add_file(sample_file("/vrchat")).unwrap();
+    let paid_only = db.add_file(sample_file("/paid")).unwrap();
+    db.add_file(sample_file("/untagged")).unwrap();
+
+    let vrchat = db.add_tag("VRChat").unwrap();
+    let paid = db.add_tag("有料").unwrap();
+    db.add_file_tag(both, vrchat).unwrap();
+    db.add_file_tag(both, paid).unwrap();
+    db.add_file_tag(vrchat_only, vrchat).unwrap();
+    db.add_file_tag(paid_only, paid).unwrap();
+
+    (db, both, vrchat_only, paid_only)
+}
+
+// 非圧縮のエントリだけを持つZIPを書き出す
+pub(crate) fn write_zip(path: &Path, entries: &[(&str, &[u8])]) {
+    let file = std::fs::File::create(path).unwrap();
+    let mut writer = zip::ZipWriter::new(file);
+    for (name, content) in entries {
+        writer
+            .start_file(*name, SimpleFileOptions::default())
+            .unwrap();
+        writer.write_all(content).unwrap();
+    }
+    writer.finish().unwrap();
+}
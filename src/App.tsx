@@ -5,6 +5,7 @@ import { UrlInputSection, BoothProductInfo } from "./components/UrlInputSection"
 import { FileSearchSection } from "./components/FileSearchSection";
 import { ProcessingQueueSection } from "./components/ProcessingQueueSection";
 import { FileSyncSection } from "./components/FileSyncSection";
+import { errorMessage, isApiError } from "./errors";
 
 
 interface Tag {
@@ -247,7 +248,7 @@ function App() {
   const [outputDir, setOutputDir] = useState<string | null>(null);
   const [availableTags, setAvailableTags] = useState<Tag[]>([]);
   const [isValidatingUrl, setIsValidatingUrl] = useState(false);
-  const [urlValidationResult, setUrlValidationResult] = useState<'valid' | 'invalid' | null>(null);
+  const [urlValidationResult, setUrlValidationResult] = useState<'valid' | 'invalid' | 'age_restricted' | null>(null);
   const [boothProductInfo, setBoothProductInfo] = useState<BoothProductInfo | null>(null);
   
   
@@ -301,7 +302,7 @@ function App() {
       }
     } catch (error) {
       console.error('フォルダ選択エラー:', error);
-      alert('フォルダの選択に失敗しました: ' + error);
+      alert('フォルダの選択に失敗しました: ' + errorMessage(error));
     }
   };
 
@@ -366,7 +367,9 @@ function App() {
       }
     } catch (error) {
       console.error('URL validation error:', error);
-      setUrlValidationResult('invalid');
+      setUrlValidationResult(
+        isApiError(error) && error.code === 'AgeRestricted' ? 'age_restricted' : 'invalid'
+      );
     } finally {
       setIsValidatingUrl(false);
     }
@@ -380,7 +383,7 @@ function App() {
       await invoke('open_folder', { folderPath: filePath });
     } catch (error) {
       console.error('Failed to open folder:', error);
-      alert('フォルダを開くことができませんでした: ' + String(error));
+      alert('フォルダを開くことができませんでした: ' + errorMessage(error));
     }
   };
 
@@ -400,7 +403,7 @@ function App() {
       alert(`タグ「${tagName}」を追加しました。`);
     } catch (error) {
       console.error('Failed to add tag:', error);
-      alert('タグの追加に失敗しました: ' + error);
+      alert('タグの追加に失敗しました: ' + errorMessage(error));
     }
   };
 
@@ -414,7 +417,7 @@ function App() {
       alert(`タグ「${tagName}」を削除しました。`);
     } catch (error) {
       console.error('Failed to remove tag:', error);
-      alert('タグの削除に失敗しました: ' + error);
+      alert('タグの削除に失敗しました: ' + errorMessage(error));
     }
   };
 
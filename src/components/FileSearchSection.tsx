@@ -3,6 +3,7 @@
 
 import { useState, useEffect } from 'react';
 import { invoke } from "@tauri-apps/api/core";
+import { errorMessage } from '../errors';
 
 
 // 型定義
@@ -29,6 +30,12 @@ interface FileRecord {
   booth_price?: number;
   booth_thumbnail_path?: string;
   encoding_info?: string;
+  archive_path?: string;
+  booth_category?: string;
+  is_adult?: boolean;
+  opened_at?: string;
+  is_favorite?: boolean;
+  user_note?: string;
   created_at?: string;
   updated_at?: string;
   metadata?: string;
@@ -39,6 +46,11 @@ interface FileWithTags {
   tags: Tag[];
 }
 
+interface BatchTagResult {
+  succeeded: number[];
+  failed: [number, string][];
+}
+
 interface GeneralModal {
   isOpen: boolean;
   type: 'url-edit' | 'tag-add' | 'tag-manage' | 'delete-confirm';
@@ -241,14 +253,19 @@ export function FileSearchSection({
     alert(`🏷️ タグ追加処理開始\n\nタグ名: "${tagName}"\n対象ファイル: ${fileCount}件\n\n処理中...`);
 
     try {
-      await invoke('batch_add_tag_to_files_db', {
+      const result = await invoke<BatchTagResult>('batch_add_tag_to_files_db', {
         file_ids: selectedSearchFileIds,
         tag_name: tagName,
         tag_color: newTagColor
       });
 
-      // 成功のフィードバック
-      alert(`✅ タグ追加完了！\n\n追加したタグ: "${tagName}"\n対象ファイル: ${fileCount}件\n\n⚠️ 注意: 既にタグが付いているファイルは自動的にスキップされます。`);
+      // 成功のフィードバック（一部失敗した場合はファイルIDと理由を表示）
+      if (result.failed.length > 0) {
+        const failures = result.failed.map(([id, reason]) => `ID ${id}: ${reason}`).join('\n');
+        alert(`⚠️ 一部のファイルにタグを追加できませんでした\n\n追加したタグ: "${tagName}"\n成功: ${result.succeeded.length}件 / 失敗: ${result.failed.length}件\n\n${failures}`);
+      } else {
+        alert(`✅ タグ追加完了！\n\n追加したタグ: "${tagName}"\n対象ファイル: ${fileCount}件\n\n⚠️ 注意: 既にタグが付いているファイルは自動的にスキップされます。`);
+      }
 
       // 検索結果を更新
       if (searchQuery.trim() || selectedTags.length > 0) {
@@ -268,7 +285,7 @@ export function FileSearchSection({
       setSelectedSearchFileIds([]);
     } catch (error) {
       console.error('バッチタグ追加エラー:', error);
-      alert(`❌ タグ追加に失敗しました\n\nエラー詳細: ${error}\n\nもう一度お試しください。`);
+      alert(`❌ タグ追加に失敗しました\n\nエラー詳細: ${errorMessage(error)}\n\nもう一度お試しください。`);
     }
   };
 
@@ -302,7 +319,7 @@ export function FileSearchSection({
           console.error('バッチ削除エラー:', error);
           // エラー時もモーダルを閉じる
           onSetGeneralModal({ isOpen: false, type: 'delete-confirm', title: '', onConfirm: () => {} });
-          alert(`❌ ファイルの削除に失敗しました: ${error}`);
+          alert(`❌ ファイルの削除に失敗しました: ${errorMessage(error)}`);
         }
       }
     });
@@ -566,7 +583,7 @@ export function FileSearchSection({
                                   console.error('削除エラー:', error);
                                   // エラー時もモーダルを閉じる
                                   onSetGeneralModal({ isOpen: false, type: 'delete-confirm', title: '', onConfirm: () => {} });
-                                  alert('❌ ファイルの削除に失敗しました: ' + error);
+                                  alert('❌ ファイルの削除に失敗しました: ' + errorMessage(error));
                                 }
                               }
                             });
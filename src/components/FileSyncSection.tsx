@@ -3,12 +3,14 @@
 
 import { useState } from 'react';
 import { invoke } from "@tauri-apps/api/core";
+import { errorMessage } from '../errors';
 
 // 型定義
 interface SyncResult {
   total_files: number;
   missing_files: MissingFile[];
   orphaned_files: number;
+  orphaned_paths?: string[];
   updated_files: number;
 }
 
@@ -45,7 +47,7 @@ export function FileSyncSection({ onSetDeleteConfirmModal }: FileSyncSectionProp
       setSyncResult(result);
     } catch (error) {
       console.error('Sync failed:', error);
-      alert('ファイル同期に失敗しました: ' + String(error));
+      alert('ファイル同期に失敗しました: ' + errorMessage(error));
     } finally {
       setIsSyncing(false);
     }
@@ -82,7 +84,7 @@ export function FileSyncSection({ onSetDeleteConfirmModal }: FileSyncSectionProp
           alert(`${removedCount}個のファイル情報をデータベースから削除しました。`);
         } catch (error) {
           console.error('Remove missing files failed:', error);
-          alert('ファイル情報の削除に失敗しました: ' + String(error));
+          alert('ファイル情報の削除に失敗しました: ' + errorMessage(error));
         }
       }
     });
@@ -3,6 +3,7 @@
 
 import { useState, useEffect } from 'react';
 import { invoke } from "@tauri-apps/api/core";
+import { errorMessage } from '../errors';
 
 // 型定義
 interface ProcessingFile {
@@ -14,6 +15,7 @@ interface ProcessingFile {
   productName?: string;
   progress: number;
   error?: string;
+  notice?: string;
   fullPath?: string;
   tags?: string[];
   tagInput?: string;
@@ -85,18 +87,33 @@ export function ProcessingQueueSection({
           }));
         }, 200);
 
-        const result = await invoke<{
+        const runProcess = (password: string | null) => invoke<{
           success: boolean;
           message?: string;
           shop_name?: string;
           product_name?: string;
+          error_code?: string | null;
+          network_available?: boolean;
+          already_imported_id?: number | null;
         }>('process_zip_file', {
           zipPath: file.fullPath || file.name,
           boothUrl: boothUrl || null,
           outputDir: outputDir,
           tags: file.tags || null,
+          password,
         });
 
+        let result = await runProcess(null);
+        // パスワード付きZIPは入力を求めて再試行（キャンセルでエラーとして扱う）
+        while (
+          !result.success &&
+          (result.error_code === 'PasswordRequired' || result.error_code === 'InvalidPassword')
+        ) {
+          const password = window.prompt(result.message ?? 'ZIPのパスワードを入力してください');
+          if (!password) break;
+          result = await runProcess(password);
+        }
+
         clearInterval(progressInterval);
 
         if (result.success) {
@@ -106,7 +123,13 @@ export function ProcessingQueueSection({
               status: 'completed', 
               progress: 100,
               shopName: result.shop_name,
-              productName: result.product_name
+              productName: result.product_name,
+              // オフライン時はURLから推定した情報で登録済み。後で再取得できる
+              notice: result.already_imported_id != null
+                ? `同じ内容のZIPが既に登録されているため追加しませんでした (ID: ${result.already_imported_id})`
+                : result.network_available === false
+                  ? 'BOOTHに接続できなかったため、商品情報は後で再取得してください'
+                  : undefined
             } : f
           ));
           
@@ -122,7 +145,7 @@ export function ProcessingQueueSection({
         }
       } catch (error) {
         setFiles(prev => prev.map(f => 
-          f.id === file.id ? { ...f, status: 'error', error: String(error) } : f
+          f.id === file.id ? { ...f, status: 'error', error: errorMessage(error) } : f
         ));
       }
     }
@@ -160,7 +183,7 @@ export function ProcessingQueueSection({
       }
     } catch (error) {
       console.error('File selection failed:', error);
-      alert('ファイルの選択に失敗しました: ' + String(error));
+      alert('ファイルの選択に失敗しました: ' + errorMessage(error));
     }
   };
 
@@ -324,6 +347,9 @@ export function ProcessingQueueSection({
                 </div>
               )}
               
+              {file.notice && (
+                <div className="notice-message">{file.notice}</div>
+              )}
               {file.error && (
                 <div className="error-message">{file.error}</div>
               )}
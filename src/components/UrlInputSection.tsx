@@ -12,11 +12,14 @@ export interface BoothProductInfo {
   thumbnail_url?: string;
   is_free: boolean;
   tags: string[];
+  category?: string;
+  category_path: string[];
+  is_adult: boolean;
 }
 
 export interface UrlInputSectionProps {
   boothUrl: string;
-  urlValidationResult: 'valid' | 'invalid' | null;
+  urlValidationResult: 'valid' | 'invalid' | 'age_restricted' | null;
   isValidatingUrl: boolean;
   boothProductInfo: BoothProductInfo | null;
   onUrlChange: (url: string) => void;
@@ -69,6 +72,13 @@ export function UrlInputSection({
         </div>
       )}
 
+      {urlValidationResult === 'age_restricted' && (
+        <div className="url-validation-error">
+          🔞 R-18 指定の商品のため、年齢確認が必要です。<br/>
+          ブラウザで商品ページを開いて年齢確認を済ませてください。
+        </div>
+      )}
+
       {boothProductInfo && (
         <div className="booth-product-info">
           <h3>商品情報</h3>
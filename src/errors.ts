@@ -0,0 +1,21 @@
+// Tauri コマンドが返す構造化エラー（src-tauri/src/api_types.rs の ApiError）
+export interface ApiError {
+  code: string;
+  message: string;
+  details?: unknown;
+  retryable: boolean;
+}
+
+export function isApiError(error: unknown): error is ApiError {
+  return (
+    typeof error === 'object' &&
+    error !== null &&
+    typeof (error as ApiError).code === 'string' &&
+    typeof (error as ApiError).message === 'string'
+  );
+}
+
+// 表示用のエラーメッセージ（構造化エラー以外はそのまま文字列化）
+export function errorMessage(error: unknown): string {
+  return isApiError(error) ? error.message : String(error);
+}